@@ -0,0 +1,635 @@
+use crate::archive::header::{self, invalid_data, Compression, Format, Header};
+use crate::archive::plain;
+use crate::archive::primitives::{ArchiveReader, ArchiveWriter, DataOffset};
+use crate::archive::toc::{self, DataLocation, TocEntry};
+use crate::errors::AnonymiserError;
+use crate::parsers::row_parser;
+use crate::parsers::state::State;
+use crate::parsers::strategies::Strategies;
+use crate::shuffle;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use rand::rngs::SmallRng;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const BLOCK_DATA: u8 = 1;
+const BLOCK_BLOBS: u8 = 3;
+const CHUNK_SIZE: usize = 4096;
+
+/// Anonymises a custom-format archive into plain SQL, laid out the same way pg_restore would
+/// print it
+pub fn to_plain<R: Read>(
+    input: R,
+    writer: &mut dyn Write,
+    strategies: &Strategies,
+    rng: &mut SmallRng,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    let (header, mut reader, entries) = open(input)?;
+    let mut state = collect_types(&entries, strategies, rng)?;
+
+    plain::write_preamble(writer, &header, &entries)?;
+    for entry in &entries {
+        plain::write_entry(writer, entry)?;
+        if !entry.has_data() {
+            continue;
+        }
+
+        let block_type = read_block_header(&mut reader, entry)?;
+        plain::write_data_comment(writer, entry)?;
+        if block_type == BLOCK_BLOBS {
+            writer.write_all(b"BEGIN;\n\n")?;
+            loop {
+                let oid = reader.read_int()?;
+                if oid == 0 {
+                    break;
+                }
+                let mut chunks = ChunkReader::new(&mut reader);
+                let mut data = Vec::new();
+                decompressor(header.compression, &mut chunks)?.read_to_end(&mut data)?;
+                chunks.drain()?;
+                plain::write_blob(writer, oid, &data)?;
+            }
+            writer.write_all(b"COMMIT;\n\n")?;
+        } else {
+            if let Some(copy_stmt) = &entry.copy_stmt {
+                writer.write_all(copy_stmt.as_bytes())?;
+            }
+            let mut chunks = ChunkReader::new(&mut reader);
+            transform_data(
+                decompressor(header.compression, &mut chunks)?,
+                writer,
+                entry.copy_stmt.as_deref(),
+                &mut state,
+                strategies,
+                rng,
+                spill_dir,
+            )?;
+            chunks.drain()?;
+        }
+    }
+    plain::write_footer(writer)?;
+    Ok(())
+}
+
+/// Anonymises a custom-format archive into another custom-format archive, using the same
+/// compression as the input. Once all the data is written the table of contents is rewritten
+/// with each entry's data offset so the archive can be restored in parallel
+pub fn to_custom<R: Read, W: Write + Seek>(
+    input: R,
+    output: W,
+    strategies: &Strategies,
+    rng: &mut SmallRng,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    let mut written = write_custom(input, output, strategies, rng, spill_dir)?;
+
+    written
+        .writer
+        .inner_mut()
+        .seek(SeekFrom::Start(written.toc_start))?;
+    written.writer.set_position(written.toc_start);
+    toc::write(&written.header, &written.entries, &mut written.writer)?;
+    written.writer.flush()?;
+    Ok(())
+}
+
+/// The same as `to_custom` for output which can't be seeked, such as stdout. The data offsets
+/// are left unset, as pg_dump does when writing to a pipe, so the archive can be restored but
+/// not in parallel
+pub fn to_custom_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    strategies: &Strategies,
+    rng: &mut SmallRng,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    write_custom(input, output, strategies, rng, spill_dir)?
+        .writer
+        .flush()?;
+    Ok(())
+}
+
+struct WrittenArchive<W: Write> {
+    header: Header,
+    entries: Vec<TocEntry>,
+    toc_start: u64,
+    writer: ArchiveWriter<BufWriter<W>>,
+}
+
+fn write_custom<R: Read, W: Write>(
+    input: R,
+    output: W,
+    strategies: &Strategies,
+    rng: &mut SmallRng,
+    spill_dir: Option<&Path>,
+) -> Result<WrittenArchive<W>, AnonymiserError> {
+    let (header, mut reader, mut entries) = open(input)?;
+    let mut state = collect_types(&entries, strategies, rng)?;
+
+    let mut writer = ArchiveWriter::new(
+        BufWriter::new(output),
+        header.int_size as usize,
+        header.off_size as usize,
+    );
+    header::write(&header, &mut writer)?;
+    let toc_start = writer.position();
+    for entry in entries.iter_mut().filter(|entry| entry.has_data()) {
+        entry.data_location = DataLocation::Offset(DataOffset::NotSet);
+    }
+    toc::write(&header, &entries, &mut writer)?;
+
+    for entry in entries.iter_mut().filter(|entry| entry.has_data()) {
+        let block_type = read_block_header(&mut reader, entry)?;
+        entry.data_location = DataLocation::Offset(DataOffset::Set(writer.position()));
+        writer.write_byte(block_type)?;
+        writer.write_int(entry.dump_id)?;
+
+        if block_type == BLOCK_DATA && entry.is_table_data() {
+            let mut chunks = ChunkReader::new(&mut reader);
+            let mut encoder = Encoder::new(
+                header.compression,
+                BufWriter::with_capacity(CHUNK_SIZE, ChunkWriter::new(&mut writer)),
+            )?;
+            transform_data(
+                decompressor(header.compression, &mut chunks)?,
+                &mut encoder,
+                entry.copy_stmt.as_deref(),
+                &mut state,
+                strategies,
+                rng,
+                spill_dir,
+            )?;
+            encoder
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .finish()?;
+            chunks.drain()?;
+        } else {
+            copy_block(&mut reader, &mut writer, block_type)?;
+        }
+    }
+
+    Ok(WrittenArchive {
+        header,
+        entries,
+        toc_start,
+        writer,
+    })
+}
+
+fn open<R: Read>(input: R) -> std::io::Result<(Header, ArchiveReader<R>, Vec<TocEntry>)> {
+    let (header, mut reader) = header::read(input)?;
+    if header.format != Format::Custom {
+        return Err(invalid_data("Expected a custom-format archive".to_string()));
+    }
+    if header.compression == Compression::Lz4 {
+        return Err(invalid_data(
+            "LZ4 compressed archives are not supported, re-run pg_dump with gzip or zstd compression".to_string(),
+        ));
+    }
+    let entries = toc::read(&header, &mut reader)?;
+    Ok((header, reader, entries))
+}
+
+/// Runs each table definition in the archive through the row parser so the column types are
+/// known before any data is transformed
+pub fn collect_types(
+    entries: &[TocEntry],
+    strategies: &Strategies,
+    rng: &mut SmallRng,
+) -> Result<State, AnonymiserError> {
+    let mut state = State::new();
+    for defn in entries
+        .iter()
+        .filter(|entry| entry.desc() == "TABLE")
+        .filter_map(|entry| entry.defn.as_deref())
+    {
+        for line in defn.split_inclusive('\n') {
+            row_parser::parse(rng, line, &mut state, strategies)?;
+        }
+    }
+    state.check_transformers()?;
+    Ok(state)
+}
+
+/// Transforms one table's data, which in an archive is the rows of a COPY block (including the
+/// terminating `\.`) with the COPY statement kept separately in the table of contents. If any
+/// of the table's columns are shuffled the rows are held back until the end of the block.
+/// Errors are given the line of the table's data they were found on, counting the COPY
+/// statement as line 1
+pub fn transform_data<R: Read>(
+    input: R,
+    writer: &mut dyn Write,
+    copy_stmt: Option<&str>,
+    state: &mut State,
+    strategies: &Strategies,
+    rng: &mut SmallRng,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    let mut shuffler = None;
+    let mut line_number = 0;
+    if let Some(copy_stmt) = copy_stmt {
+        line_number += 1;
+        row_parser::parse(rng, copy_stmt, state, strategies)
+            .map_err(|err| err.at_line(line_number))?;
+        shuffler = shuffle::for_copy_block(state, rng, spill_dir);
+    }
+
+    let mut reader = BufReader::new(input);
+    let mut line = String::new();
+    loop {
+        line_number += 1;
+        let bytes_read = reader
+            .read_line(&mut line)
+            .map_err(|err| AnonymiserError::from(err).at_line(line_number))?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        let is_copy_block_row = row_parser::is_copy_block_row(&line, state);
+        let transformed_row = row_parser::parse(rng, &line, state, strategies)
+            .map_err(|err| err.at_line(line_number))?;
+        match shuffler.as_mut() {
+            Some(shuffler) if is_copy_block_row => shuffler.push(&transformed_row)?,
+            _ => {
+                if let Some(shuffler) = shuffler.take() {
+                    shuffler.finish(writer)?;
+                }
+                writer.write_all(transformed_row.as_bytes())?;
+            }
+        }
+        line.clear();
+    }
+    if let Some(shuffler) = shuffler {
+        shuffler.finish(writer)?;
+    }
+    Ok(())
+}
+
+fn read_block_header<R: Read>(
+    reader: &mut ArchiveReader<R>,
+    entry: &TocEntry,
+) -> std::io::Result<u8> {
+    let block_type = reader.read_byte()?;
+    let dump_id = reader.read_int()?;
+    if dump_id != entry.dump_id {
+        return Err(invalid_data(format!(
+            "Expected the data for '{}' (dump id {}) but found dump id {}",
+            entry.tag.as_deref().unwrap_or("-"),
+            entry.dump_id,
+            dump_id
+        )));
+    }
+    if block_type != BLOCK_DATA && block_type != BLOCK_BLOBS {
+        return Err(invalid_data(format!(
+            "Unknown data block type in archive: {}",
+            block_type
+        )));
+    }
+    Ok(block_type)
+}
+
+fn copy_block<R: Read, W: Write>(
+    reader: &mut ArchiveReader<R>,
+    writer: &mut ArchiveWriter<W>,
+    block_type: u8,
+) -> std::io::Result<()> {
+    if block_type != BLOCK_BLOBS {
+        return copy_chunks(reader, writer);
+    }
+    loop {
+        let oid = reader.read_int()?;
+        writer.write_int(oid)?;
+        if oid == 0 {
+            return Ok(());
+        }
+        copy_chunks(reader, writer)?;
+    }
+}
+
+fn copy_chunks<R: Read, W: Write>(
+    reader: &mut ArchiveReader<R>,
+    writer: &mut ArchiveWriter<W>,
+) -> std::io::Result<()> {
+    loop {
+        let length = reader.read_int()?;
+        writer.write_int(length)?;
+        if length <= 0 {
+            return Ok(());
+        }
+        let copied = std::io::copy(&mut reader.by_ref().take(length as u64), writer)?;
+        if copied != length as u64 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
+
+fn decompressor<'a, R: Read + 'a>(
+    compression: Compression,
+    input: R,
+) -> std::io::Result<Box<dyn Read + 'a>> {
+    match compression {
+        Compression::None => Ok(Box::new(input)),
+        Compression::Gzip(_) => Ok(Box::new(ZlibDecoder::new(input))),
+        Compression::Zstd => Ok(Box::new(zstd::Decoder::new(input)?)),
+        Compression::Lz4 => Err(invalid_data(
+            "LZ4 compressed archives are not supported".to_string(),
+        )),
+    }
+}
+
+/// pg_dump records -1 when the default compression level was used
+pub fn gzip_level(level: i64) -> flate2::Compression {
+    u32::try_from(level)
+        .ok()
+        .filter(|level| *level <= 9)
+        .map_or_else(flate2::Compression::default, flate2::Compression::new)
+}
+
+/// Reads the length-prefixed chunks a data block is split into as one continuous stream
+struct ChunkReader<'a, R> {
+    reader: &'a mut ArchiveReader<R>,
+    remaining: u64,
+    finished: bool,
+}
+
+impl<'a, R: Read> ChunkReader<'a, R> {
+    fn new(reader: &'a mut ArchiveReader<R>) -> Self {
+        ChunkReader {
+            reader,
+            remaining: 0,
+            finished: false,
+        }
+    }
+
+    /// Skips anything left in the block, such as the terminating empty chunk
+    fn drain(&mut self) -> std::io::Result<()> {
+        std::io::copy(self, &mut std::io::sink()).map(|_| ())
+    }
+}
+
+impl<R: Read> Read for ChunkReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.remaining == 0 {
+            if self.finished {
+                return Ok(0);
+            }
+            let length = self.reader.read_int()?;
+            if length <= 0 {
+                self.finished = true;
+            } else {
+                self.remaining = length as u64;
+            }
+        }
+
+        let max = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let bytes_read = self.reader.read(&mut buf[..max])?;
+        if bytes_read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+/// Writes each buffer it is given as a length-prefixed chunk
+struct ChunkWriter<'a, W: Write> {
+    writer: &'a mut ArchiveWriter<W>,
+}
+
+impl<'a, W: Write> ChunkWriter<'a, W> {
+    fn new(writer: &'a mut ArchiveWriter<W>) -> Self {
+        ChunkWriter { writer }
+    }
+
+    /// Ends the block with an empty chunk
+    fn finish(self) -> std::io::Result<()> {
+        self.writer.write_int(0)
+    }
+}
+
+impl<W: Write> Write for ChunkWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.writer.write_int(buf.len() as i64)?;
+        self.writer.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+enum Encoder<W: Write> {
+    None(W),
+    Zlib(ZlibEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(compression: Compression, inner: W) -> std::io::Result<Self> {
+        match compression {
+            Compression::None => Ok(Encoder::None(inner)),
+            Compression::Gzip(level) => {
+                Ok(Encoder::Zlib(ZlibEncoder::new(inner, gzip_level(level))))
+            }
+            Compression::Zstd => Ok(Encoder::Zstd(zstd::Encoder::new(inner, 0)?)),
+            Compression::Lz4 => Err(invalid_data(
+                "LZ4 compressed archives are not supported".to_string(),
+            )),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<W> {
+        match self {
+            Encoder::None(inner) => Ok(inner),
+            Encoder::Zlib(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Encoder::None(inner) => inner.write(buf),
+            Encoder::Zlib(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Encoder::None(inner) => inner.flush(),
+            Encoder::Zlib(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::rng;
+    use crate::parsers::state::Types;
+    use crate::parsers::strategies::Strategies;
+    use crate::parsers::strategy_structs::{ColumnInfo, TransformerType};
+    use crate::parsers::types::SubType;
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn identity_strategies() -> Strategies {
+        let mut strategies = Strategies::new();
+        for (table, columns) in [
+            ("public.extra_data", vec!["id", "data"]),
+            ("public.orders", vec!["id", "user_id", "product_id"]),
+            (
+                "public.products",
+                vec!["id", "description", "price", "details", "tags"],
+            ),
+            (
+                "public.users",
+                vec![
+                    "id",
+                    "email",
+                    "password",
+                    "last_login",
+                    "inserted_at",
+                    "updated_at",
+                    "first_name",
+                    "last_name",
+                    "deactivated",
+                    "phone_number",
+                ],
+            ),
+        ] {
+            strategies
+                .insert(
+                    table.to_string(),
+                    columns
+                        .into_iter()
+                        .map(|name| {
+                            (
+                                name.to_string(),
+                                ColumnInfo::builder().with_name(name).build(),
+                            )
+                        })
+                        .collect::<HashMap<_, _>>(),
+                )
+                .unwrap();
+        }
+        strategies
+    }
+
+    fn as_plain(archive: &[u8], strategies: &Strategies) -> String {
+        let mut plain = Vec::new();
+        to_plain(archive, &mut plain, strategies, &mut rng::get(), None).unwrap();
+        String::from_utf8(plain).unwrap()
+    }
+
+    #[test]
+    fn stream_output_leaves_data_offsets_unset() {
+        let original = fs::read("test_files/dump_file.dump").unwrap();
+        let strategies = identity_strategies();
+
+        let mut streamed = Vec::new();
+        to_custom_stream(
+            original.as_slice(),
+            &mut streamed,
+            &strategies,
+            &mut rng::get(),
+            None,
+        )
+        .unwrap();
+
+        let (header, mut reader) = header::read(streamed.as_slice()).unwrap();
+        let entries = toc::read(&header, &mut reader).unwrap();
+        assert!(entries
+            .iter()
+            .filter(|entry| entry.has_data())
+            .all(|entry| entry.data_location == DataLocation::Offset(DataOffset::NotSet)));
+        assert_eq!(
+            as_plain(&original, &strategies),
+            as_plain(&streamed, &strategies)
+        );
+    }
+
+    #[test]
+    fn archives_that_do_not_parse_are_invalid_dumps() {
+        let mut archive = fs::read("test_files/dump_file.dump").unwrap();
+        // The archive format, which is 1 for custom archives
+        archive[10] = 9;
+
+        let err = to_plain(
+            archive.as_slice(),
+            &mut Vec::new(),
+            &identity_strategies(),
+            &mut rng::get(),
+            None,
+        )
+        .unwrap_err();
+
+        assert!(
+            matches!(err, AnonymiserError::InvalidDump { .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.exit_code(), 7);
+    }
+
+    #[test]
+    fn errors_in_table_data_are_given_their_line() {
+        let mut strategies = identity_strategies();
+        strategies
+            .insert(
+                "public.extra_data".to_string(),
+                HashMap::from([
+                    (
+                        "id".to_string(),
+                        ColumnInfo::builder().with_name("id").build(),
+                    ),
+                    (
+                        "data".to_string(),
+                        ColumnInfo::builder()
+                            .with_name("data")
+                            .with_transformer(TransformerType::Error, None)
+                            .build(),
+                    ),
+                ]),
+            )
+            .unwrap();
+
+        let err = transform_data(
+            "1\tjank\n\\.\n".as_bytes(),
+            &mut Vec::new(),
+            Some("COPY public.extra_data (id, data) FROM stdin;\n"),
+            &mut State {
+                types: Types::builder()
+                    .add_type("public.extra_data", "id", SubType::Integer)
+                    .add_type("public.extra_data", "data", SubType::Text)
+                    .build(),
+                ..State::new()
+            },
+            &strategies,
+            &mut rng::get(),
+            None,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Unable to transform public.extra_data.data on line 2: Error transform still in place"
+        );
+    }
+}
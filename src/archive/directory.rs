@@ -0,0 +1,217 @@
+use crate::archive::custom;
+use crate::archive::header::{self, invalid_data, Compression, Format};
+use crate::archive::toc::{self, DataLocation};
+use crate::errors::AnonymiserError;
+use crate::parsers::rng;
+use crate::parsers::state::State;
+use crate::parsers::strategies::Strategies;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const TOC_FILE: &str = "toc.dat";
+
+struct TableFile {
+    dump_id: i64,
+    file_name: String,
+    compression: Compression,
+    copy_stmt: Option<String>,
+}
+
+/// Anonymises a directory-format archive. Each table's data file is transformed on a worker
+/// thread and written under the same name in `output_dir`, alongside an unchanged copy of
+/// everything else, so the output can be restored with `pg_restore -j`
+pub fn anonymise(
+    input_dir: &Path,
+    output_dir: &Path,
+    strategies: &Strategies,
+    threads: usize,
+    seed: Option<u64>,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    let toc_bytes = fs::read(input_dir.join(TOC_FILE)).map_err(|err| {
+        Error::new(
+            err.kind(),
+            format!(
+                "Could not read '{}', is '{}' a directory format archive?",
+                TOC_FILE,
+                input_dir.display()
+            ),
+        )
+    })?;
+    let (header, mut reader) = header::read(toc_bytes.as_slice())?;
+    if header.format != Format::Directory {
+        return Err(invalid_data(format!(
+            "Expected '{}' to be from a directory format archive",
+            input_dir.join(TOC_FILE).display()
+        ))
+        .into());
+    }
+    let entries = toc::read(&header, &mut reader)?;
+    let state = custom::collect_types(&entries, strategies, &mut rng::get())?;
+
+    let table_files = entries
+        .iter()
+        .filter(|entry| entry.is_table_data() && entry.has_data())
+        .filter_map(|entry| match &entry.data_location {
+            DataLocation::File(Some(file_name)) => Some(
+                find_table_file(input_dir, file_name, header.compression).map(
+                    |(file_name, compression)| TableFile {
+                        dump_id: entry.dump_id,
+                        file_name,
+                        compression,
+                        copy_stmt: entry.copy_stmt.clone(),
+                    },
+                ),
+            ),
+            _ => None,
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    fs::create_dir_all(output_dir)?;
+    let table_file_names: HashSet<&str> = table_files
+        .iter()
+        .map(|table_file| table_file.file_name.as_str())
+        .collect();
+    for dir_entry in fs::read_dir(input_dir)? {
+        let dir_entry = dir_entry?;
+        let file_name = dir_entry.file_name();
+        if dir_entry.file_type()?.is_file()
+            && !table_file_names.contains(file_name.to_string_lossy().as_ref())
+        {
+            fs::copy(dir_entry.path(), output_dir.join(file_name))?;
+        }
+    }
+
+    let next_file = AtomicUsize::new(0);
+    let workers = threads.min(table_files.len()).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| loop {
+                    match table_files.get(next_file.fetch_add(1, Ordering::Relaxed)) {
+                        Some(table_file) => {
+                            transform_table_file(
+                                table_file, input_dir, output_dir, &state, strategies, seed,
+                                spill_dir,
+                            )?;
+                        }
+                        None => return Ok(()),
+                    }
+                })
+            })
+            .collect();
+
+        handles.into_iter().try_for_each(|handle| {
+            handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    })
+}
+
+/// The table of contents only records the uncompressed name of each data file, pg_dump adds an
+/// extension to it for the compression used
+fn find_table_file(
+    input_dir: &Path,
+    file_name: &str,
+    compression: Compression,
+) -> std::io::Result<(String, Compression)> {
+    let gzip = match compression {
+        Compression::Gzip(level) => Compression::Gzip(level),
+        _ => Compression::Gzip(-1),
+    };
+    let candidates = [
+        (file_name.to_string(), Compression::None),
+        (format!("{}.gz", file_name), gzip),
+        (format!("{}.zst", file_name), Compression::Zstd),
+    ];
+    for (candidate, compression) in candidates {
+        if input_dir.join(&candidate).is_file() {
+            return Ok((candidate, compression));
+        }
+    }
+
+    if input_dir.join(format!("{}.lz4", file_name)).is_file() {
+        return Err(invalid_data(format!(
+            "'{}.lz4' is LZ4 compressed, which is not supported",
+            file_name
+        )));
+    }
+    Err(Error::new(
+        ErrorKind::NotFound,
+        format!(
+            "Data file '{}' is missing from '{}'",
+            file_name,
+            input_dir.display()
+        ),
+    ))
+}
+
+fn transform_table_file(
+    table_file: &TableFile,
+    input_dir: &Path,
+    output_dir: &Path,
+    state: &State,
+    strategies: &Strategies,
+    seed: Option<u64>,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    // Seeding each table separately keeps the output reproducible whichever worker picks it up
+    let mut rng = seed.map_or_else(rng::get, |seed| {
+        rng::derived(seed, "table data", table_file.dump_id as u64)
+    });
+    let mut state = state.clone();
+    let input = BufReader::new(File::open(input_dir.join(&table_file.file_name))?);
+    let output = BufWriter::new(File::create(output_dir.join(&table_file.file_name))?);
+    let copy_stmt = table_file.copy_stmt.as_deref();
+
+    match table_file.compression {
+        Compression::Gzip(level) => {
+            let mut encoder = GzEncoder::new(output, custom::gzip_level(level));
+            custom::transform_data(
+                MultiGzDecoder::new(input),
+                &mut encoder,
+                copy_stmt,
+                &mut state,
+                strategies,
+                &mut rng,
+                spill_dir,
+            )?;
+            encoder.finish()?.flush()?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, 0)?;
+            custom::transform_data(
+                zstd::Decoder::new(input)?,
+                &mut encoder,
+                copy_stmt,
+                &mut state,
+                strategies,
+                &mut rng,
+                spill_dir,
+            )?;
+            encoder.finish()?.flush()?;
+        }
+        _ => {
+            let mut output = output;
+            custom::transform_data(
+                input,
+                &mut output,
+                copy_stmt,
+                &mut state,
+                strategies,
+                &mut rng,
+                spill_dir,
+            )?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
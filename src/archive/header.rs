@@ -0,0 +1,205 @@
+use crate::archive::primitives::{ArchiveReader, ArchiveWriter};
+use std::io::{Error, ErrorKind, Read, Write};
+
+pub const MAGIC: &[u8] = b"PGDMP";
+
+const MIN_SUPPORTED_VERSION: Version = (1, 12, 0);
+const MAX_SUPPORTED_VERSION: Version = (1, 16, 0);
+const COMPRESSION_ALGORITHM_VERSION: Version = (1, 15, 0);
+const TABLE_ACCESS_METHOD_VERSION: Version = (1, 14, 0);
+const RELKIND_VERSION: Version = (1, 16, 0);
+
+pub type Version = (u8, u8, u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Custom,
+    Directory,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip(i64),
+    Lz4,
+    Zstd,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: Version,
+    pub int_size: u8,
+    pub off_size: u8,
+    pub format: Format,
+    pub compression: Compression,
+    pub created: [i64; 7],
+    pub database_name: Option<String>,
+    pub remote_version: Option<String>,
+    pub dump_version: Option<String>,
+}
+
+impl Header {
+    pub fn has_table_access_method(&self) -> bool {
+        self.version >= TABLE_ACCESS_METHOD_VERSION
+    }
+
+    pub fn has_relkind(&self) -> bool {
+        self.version >= RELKIND_VERSION
+    }
+}
+
+pub fn is_archive(start_of_file: &[u8]) -> bool {
+    start_of_file.starts_with(MAGIC)
+}
+
+pub fn read<R: Read>(mut reader: R) -> std::io::Result<(Header, ArchiveReader<R>)> {
+    let mut magic = [0; 5];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_data("File is not a pg_dump archive".to_string()));
+    }
+
+    let mut fixed = [0; 6];
+    reader.read_exact(&mut fixed)?;
+    let [major, minor, revision, int_size, off_size, format] = fixed;
+    let version = (major, minor, revision);
+    if !(MIN_SUPPORTED_VERSION..=MAX_SUPPORTED_VERSION).contains(&version) {
+        return Err(invalid_data(format!(
+            "Unsupported archive version {}.{}.{}",
+            major, minor, revision
+        )));
+    }
+
+    let format = match format {
+        1 => Format::Custom,
+        3 => Format::Directory,
+        _ => {
+            return Err(invalid_data(format!(
+                "Unsupported archive format '{}', only custom (-Fc) and directory (-Fd) archives can be read",
+                format
+            )))
+        }
+    };
+
+    let mut archive_reader = ArchiveReader::new(reader, int_size as usize, off_size as usize);
+
+    let compression = if version >= COMPRESSION_ALGORITHM_VERSION {
+        match archive_reader.read_byte()? {
+            0 => Compression::None,
+            1 => Compression::Gzip(-1),
+            2 => Compression::Lz4,
+            3 => Compression::Zstd,
+            algorithm => {
+                return Err(invalid_data(format!(
+                    "Unknown compression algorithm in archive: {}",
+                    algorithm
+                )))
+            }
+        }
+    } else {
+        match archive_reader.read_int()? {
+            0 => Compression::None,
+            level => Compression::Gzip(level),
+        }
+    };
+
+    let mut created = [0; 7];
+    for part in created.iter_mut() {
+        *part = archive_reader.read_int()?;
+    }
+
+    let header = Header {
+        version,
+        int_size,
+        off_size,
+        format,
+        compression,
+        created,
+        database_name: archive_reader.read_str()?,
+        remote_version: archive_reader.read_str()?,
+        dump_version: archive_reader.read_str()?,
+    };
+
+    Ok((header, archive_reader))
+}
+
+pub fn write<W: Write>(header: &Header, writer: &mut ArchiveWriter<W>) -> std::io::Result<()> {
+    writer.write_all(MAGIC)?;
+    let (major, minor, revision) = header.version;
+    let format = match header.format {
+        Format::Custom => 1,
+        Format::Directory => 3,
+    };
+    writer.write_all(&[
+        major,
+        minor,
+        revision,
+        header.int_size,
+        header.off_size,
+        format,
+    ])?;
+
+    if header.version >= COMPRESSION_ALGORITHM_VERSION {
+        writer.write_byte(match header.compression {
+            Compression::None => 0,
+            Compression::Gzip(_) => 1,
+            Compression::Lz4 => 2,
+            Compression::Zstd => 3,
+        })?;
+    } else {
+        writer.write_int(match header.compression {
+            Compression::Gzip(level) => level,
+            _ => 0,
+        })?;
+    }
+
+    for part in header.created {
+        writer.write_int(part)?;
+    }
+    writer.write_str(header.database_name.as_deref())?;
+    writer.write_str(header.remote_version.as_deref())?;
+    writer.write_str(header.dump_version.as_deref())
+}
+
+pub fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn reads_the_header_of_a_custom_archive() {
+        let bytes = fs::read("test_files/dump_file.dump").unwrap();
+        let (header, _) = read(bytes.as_slice()).unwrap();
+
+        assert_eq!(header.format, Format::Custom);
+        assert_eq!(header.int_size, 4);
+        assert_eq!(header.off_size, 8);
+        assert_eq!(header.compression, Compression::Gzip(-1));
+        assert_eq!(header.database_name, Some("fixture_src".to_string()));
+    }
+
+    #[test]
+    fn rejects_files_which_are_not_archives() {
+        let error = read(b"CREATE TABLE".as_slice()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn writes_the_header_back_byte_for_byte() {
+        let bytes = fs::read("test_files/dump_file.dump").unwrap();
+        let (header, _) = read(bytes.as_slice()).unwrap();
+
+        let mut written = Vec::new();
+        write(
+            &header,
+            &mut ArchiveWriter::new(&mut written, header.int_size as usize, 8),
+        )
+        .unwrap();
+
+        assert_eq!(written, bytes[..written.len()]);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod custom;
+pub mod directory;
+pub mod header;
+pub mod plain;
+pub mod primitives;
+pub mod toc;
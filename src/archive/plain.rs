@@ -0,0 +1,97 @@
+use crate::archive::header::Header;
+use crate::archive::toc::TocEntry;
+use std::io::Write;
+
+const SPECIAL_ENTRIES: [&str; 3] = ["ENCODING", "STDSTRINGS", "SEARCHPATH"];
+// Only restored by pg_restore when run with '--create'
+const DATABASE_ENTRIES: [&str; 2] = ["DATABASE", "DATABASE PROPERTIES"];
+
+/// Writes the same preamble pg_restore does, taking the encoding, string and search path
+/// settings from the archive's special entries
+pub fn write_preamble(
+    writer: &mut dyn Write,
+    header: &Header,
+    entries: &[TocEntry],
+) -> std::io::Result<()> {
+    writer.write_all(b"--\n-- PostgreSQL database dump\n--\n\n")?;
+    if let Some(remote_version) = &header.remote_version {
+        writeln!(writer, "-- Dumped from database version {}", remote_version)?;
+    }
+    if let Some(dump_version) = &header.dump_version {
+        writeln!(writer, "-- Dumped by pg_dump version {}", dump_version)?;
+    }
+    writer.write_all(b"\n")?;
+
+    writer.write_all(b"SET statement_timeout = 0;\n")?;
+    writer.write_all(b"SET lock_timeout = 0;\n")?;
+    writer.write_all(b"SET idle_in_transaction_session_timeout = 0;\n")?;
+    for special in SPECIAL_ENTRIES {
+        if let Some(defn) = entries
+            .iter()
+            .find(|entry| entry.desc() == special)
+            .and_then(|entry| entry.defn.as_deref())
+        {
+            writer.write_all(defn.as_bytes())?;
+        }
+    }
+    writer.write_all(b"SET check_function_bodies = false;\n")?;
+    writer.write_all(b"SET xmloption = content;\n")?;
+    writer.write_all(b"SET client_min_messages = warning;\n")?;
+    writer.write_all(b"SET row_security = off;\n\n")
+}
+
+/// Writes an entry's definition with the same comment header pg_restore gives it. Ownership is
+/// left out, the same as `pg_restore --no-owner`
+pub fn write_entry(writer: &mut dyn Write, entry: &TocEntry) -> std::io::Result<()> {
+    if SPECIAL_ENTRIES.contains(&entry.desc()) || DATABASE_ENTRIES.contains(&entry.desc()) {
+        return Ok(());
+    }
+    match entry.defn.as_deref() {
+        Some(defn) if !defn.is_empty() => {
+            write_comment(writer, "Name", entry)?;
+            writer.write_all(defn.as_bytes())?;
+            writer.write_all(b"\n\n")
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn write_data_comment(writer: &mut dyn Write, entry: &TocEntry) -> std::io::Result<()> {
+    write_comment(writer, "Data for Name", entry)
+}
+
+pub fn write_blob(writer: &mut dyn Write, oid: i64, data: &[u8]) -> std::io::Result<()> {
+    writeln!(writer, "SELECT pg_catalog.lo_open('{}', 131072);", oid)?;
+    if !data.is_empty() {
+        writer.write_all(b"SELECT pg_catalog.lowrite(0, '\\x")?;
+        for byte in data {
+            write!(writer, "{:02x}", byte)?;
+        }
+        writer.write_all(b"');\n")?;
+    }
+    writer.write_all(b"SELECT pg_catalog.lo_close(0);\n\n")
+}
+
+pub fn write_footer(writer: &mut dyn Write) -> std::io::Result<()> {
+    writer.write_all(b"--\n-- PostgreSQL database dump complete\n--\n\n")
+}
+
+fn write_comment(writer: &mut dyn Write, label: &str, entry: &TocEntry) -> std::io::Result<()> {
+    write!(
+        writer,
+        "--\n-- {}: {}; Type: {}; Schema: {}; Owner: {}\n--\n\n",
+        label,
+        entry.tag.as_deref().unwrap_or("-"),
+        entry.desc(),
+        entry
+            .namespace
+            .as_deref()
+            .filter(|n| !n.is_empty())
+            .unwrap_or("-"),
+        entry
+            .owner
+            .as_deref()
+            .filter(|o| !o.is_empty())
+            .unwrap_or("-"),
+    )
+}
@@ -0,0 +1,222 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+const OFFSET_POS_NOT_SET: u8 = 1;
+const OFFSET_POS_SET: u8 = 2;
+const OFFSET_NO_DATA: u8 = 3;
+
+/// Where a table's data lives in a custom-format archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataOffset {
+    NotSet,
+    Set(u64),
+    NoData,
+}
+
+/// Reads the integers, strings and offsets pg_dump writes, which are sized according to the
+/// archive header
+pub struct ArchiveReader<R> {
+    inner: R,
+    int_size: usize,
+    off_size: usize,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    pub fn new(inner: R, int_size: usize, off_size: usize) -> Self {
+        ArchiveReader {
+            inner,
+            int_size,
+            off_size,
+        }
+    }
+
+    pub fn read_byte(&mut self) -> std::io::Result<u8> {
+        let mut byte = [0; 1];
+        self.inner.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    pub fn read_int(&mut self) -> std::io::Result<i64> {
+        let is_negative = self.read_byte()? != 0;
+        let mut value: i64 = 0;
+        for i in 0..self.int_size {
+            value |= (self.read_byte()? as i64) << (i * 8);
+        }
+        Ok(if is_negative { -value } else { value })
+    }
+
+    pub fn read_str(&mut self) -> std::io::Result<Option<String>> {
+        let length = self.read_int()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; length as usize];
+        self.inner.read_exact(&mut bytes)?;
+        String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub fn read_offset(&mut self) -> std::io::Result<DataOffset> {
+        let flag = self.read_byte()?;
+        let mut offset: u64 = 0;
+        for i in 0..self.off_size {
+            offset |= (self.read_byte()? as u64) << (i * 8);
+        }
+        match flag {
+            OFFSET_POS_NOT_SET => Ok(DataOffset::NotSet),
+            OFFSET_POS_SET => Ok(DataOffset::Set(offset)),
+            OFFSET_NO_DATA => Ok(DataOffset::NoData),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unexpected data offset flag in archive: {}", flag),
+            )),
+        }
+    }
+}
+
+impl<R: Read> Read for ArchiveReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Writes values in the same format `ArchiveReader` reads them, keeping track of the position so
+/// data offsets can be recorded
+pub struct ArchiveWriter<W> {
+    inner: W,
+    int_size: usize,
+    off_size: usize,
+    position: u64,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(inner: W, int_size: usize, off_size: usize) -> Self {
+        ArchiveWriter {
+            inner,
+            int_size,
+            off_size,
+            position: 0,
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.write_all(&[byte])
+    }
+
+    pub fn write_int(&mut self, value: i64) -> std::io::Result<()> {
+        self.write_byte(u8::from(value < 0))?;
+        let magnitude = value.unsigned_abs();
+        for i in 0..self.int_size {
+            self.write_byte((magnitude >> (i * 8)) as u8)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_str(&mut self, value: Option<&str>) -> std::io::Result<()> {
+        match value {
+            Some(value) => {
+                self.write_int(value.len() as i64)?;
+                self.write_all(value.as_bytes())
+            }
+            None => self.write_int(-1),
+        }
+    }
+
+    pub fn write_offset(&mut self, offset: DataOffset) -> std::io::Result<()> {
+        let (flag, value) = match offset {
+            DataOffset::NotSet => (OFFSET_POS_NOT_SET, 0),
+            DataOffset::Set(value) => (OFFSET_POS_SET, value),
+            DataOffset::NoData => (OFFSET_NO_DATA, 0),
+        };
+        self.write_byte(flag)?;
+        for i in 0..self.off_size {
+            self.write_byte((value >> (i * 8)) as u8)?;
+        }
+        Ok(())
+    }
+
+    pub fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+}
+
+impl<W: Write> Write for ArchiveWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T, F, G>(write: F, read: G) -> T
+    where
+        F: Fn(&mut ArchiveWriter<&mut Vec<u8>>) -> std::io::Result<()>,
+        G: Fn(&mut ArchiveReader<&[u8]>) -> std::io::Result<T>,
+    {
+        let mut bytes = Vec::new();
+        write(&mut ArchiveWriter::new(&mut bytes, 4, 8)).unwrap();
+        read(&mut ArchiveReader::new(bytes.as_slice(), 4, 8)).unwrap()
+    }
+
+    #[test]
+    fn reads_ints_as_a_sign_byte_followed_by_little_endian_bytes() {
+        let mut reader = ArchiveReader::new([1, 0x2b, 0x01, 0, 0].as_slice(), 4, 8);
+        assert_eq!(reader.read_int().unwrap(), -299);
+    }
+
+    #[test]
+    fn can_round_trip_ints() {
+        for value in [0, 1, -1, 299, i32::MAX as i64] {
+            assert_eq!(round_trip(|w| w.write_int(value), |r| r.read_int()), value);
+        }
+    }
+
+    #[test]
+    fn can_round_trip_strings_including_null() {
+        for value in [Some("COPY public.users (id) FROM stdin;\n"), Some(""), None] {
+            assert_eq!(
+                round_trip(|w| w.write_str(value), |r| r.read_str()),
+                value.map(|v| v.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn can_round_trip_offsets() {
+        for offset in [
+            DataOffset::NotSet,
+            DataOffset::Set(8284),
+            DataOffset::NoData,
+        ] {
+            assert_eq!(
+                round_trip(|w| w.write_offset(offset), |r| r.read_offset()),
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn writer_tracks_its_position() {
+        let mut bytes = Vec::new();
+        let mut writer = ArchiveWriter::new(&mut bytes, 4, 8);
+        writer.write_int(1).unwrap();
+        writer.write_offset(DataOffset::NotSet).unwrap();
+        assert_eq!(writer.position(), 5 + 9);
+    }
+}
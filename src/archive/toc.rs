@@ -0,0 +1,215 @@
+use crate::archive::header::{Format, Header};
+use crate::archive::primitives::{ArchiveReader, ArchiveWriter, DataOffset};
+use std::io::{Read, Write};
+
+/// Where an entry's data is stored, which depends on the archive format
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataLocation {
+    Offset(DataOffset),
+    File(Option<String>),
+}
+
+/// A table of contents entry. Every field is kept so the table of contents can be written back
+/// out exactly as pg_dump wrote it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TocEntry {
+    pub dump_id: i64,
+    pub had_dumper: i64,
+    pub table_oid: Option<String>,
+    pub oid: Option<String>,
+    pub tag: Option<String>,
+    pub desc: Option<String>,
+    pub section: i64,
+    pub defn: Option<String>,
+    pub drop_stmt: Option<String>,
+    pub copy_stmt: Option<String>,
+    pub namespace: Option<String>,
+    pub tablespace: Option<String>,
+    pub table_access_method: Option<String>,
+    pub relkind: Option<i64>,
+    pub owner: Option<String>,
+    pub with_oids: Option<String>,
+    pub dependencies: Vec<String>,
+    pub data_location: DataLocation,
+}
+
+impl TocEntry {
+    pub fn desc(&self) -> &str {
+        self.desc.as_deref().unwrap_or("")
+    }
+
+    pub fn is_table_data(&self) -> bool {
+        self.desc() == "TABLE DATA"
+    }
+
+    pub fn has_data(&self) -> bool {
+        match &self.data_location {
+            DataLocation::Offset(offset) => self.had_dumper != 0 && *offset != DataOffset::NoData,
+            DataLocation::File(file) => self.had_dumper != 0 && file.is_some(),
+        }
+    }
+}
+
+pub fn read<R: Read>(
+    header: &Header,
+    reader: &mut ArchiveReader<R>,
+) -> std::io::Result<Vec<TocEntry>> {
+    let count = reader.read_int()?;
+    let mut entries = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        entries.push(read_entry(header, reader)?);
+    }
+    Ok(entries)
+}
+
+fn read_entry<R: Read>(
+    header: &Header,
+    reader: &mut ArchiveReader<R>,
+) -> std::io::Result<TocEntry> {
+    let dump_id = reader.read_int()?;
+    let had_dumper = reader.read_int()?;
+    let table_oid = reader.read_str()?;
+    let oid = reader.read_str()?;
+    let tag = reader.read_str()?;
+    let desc = reader.read_str()?;
+    let section = reader.read_int()?;
+    let defn = reader.read_str()?;
+    let drop_stmt = reader.read_str()?;
+    let copy_stmt = reader.read_str()?;
+    let namespace = reader.read_str()?;
+    let tablespace = reader.read_str()?;
+    let table_access_method = if header.has_table_access_method() {
+        reader.read_str()?
+    } else {
+        None
+    };
+    let relkind = if header.has_relkind() {
+        Some(reader.read_int()?)
+    } else {
+        None
+    };
+    let owner = reader.read_str()?;
+    let with_oids = reader.read_str()?;
+
+    let mut dependencies = Vec::new();
+    while let Some(dependency) = reader.read_str()? {
+        dependencies.push(dependency);
+    }
+
+    let data_location = match header.format {
+        Format::Custom => DataLocation::Offset(reader.read_offset()?),
+        Format::Directory => DataLocation::File(reader.read_str()?),
+    };
+
+    Ok(TocEntry {
+        dump_id,
+        had_dumper,
+        table_oid,
+        oid,
+        tag,
+        desc,
+        section,
+        defn,
+        drop_stmt,
+        copy_stmt,
+        namespace,
+        tablespace,
+        table_access_method,
+        relkind,
+        owner,
+        with_oids,
+        dependencies,
+        data_location,
+    })
+}
+
+pub fn write<W: Write>(
+    header: &Header,
+    entries: &[TocEntry],
+    writer: &mut ArchiveWriter<W>,
+) -> std::io::Result<()> {
+    writer.write_int(entries.len() as i64)?;
+    for entry in entries {
+        write_entry(header, entry, writer)?;
+    }
+    Ok(())
+}
+
+fn write_entry<W: Write>(
+    header: &Header,
+    entry: &TocEntry,
+    writer: &mut ArchiveWriter<W>,
+) -> std::io::Result<()> {
+    writer.write_int(entry.dump_id)?;
+    writer.write_int(entry.had_dumper)?;
+    writer.write_str(entry.table_oid.as_deref())?;
+    writer.write_str(entry.oid.as_deref())?;
+    writer.write_str(entry.tag.as_deref())?;
+    writer.write_str(entry.desc.as_deref())?;
+    writer.write_int(entry.section)?;
+    writer.write_str(entry.defn.as_deref())?;
+    writer.write_str(entry.drop_stmt.as_deref())?;
+    writer.write_str(entry.copy_stmt.as_deref())?;
+    writer.write_str(entry.namespace.as_deref())?;
+    writer.write_str(entry.tablespace.as_deref())?;
+    if header.has_table_access_method() {
+        writer.write_str(entry.table_access_method.as_deref())?;
+    }
+    if header.has_relkind() {
+        writer.write_int(entry.relkind.unwrap_or(0))?;
+    }
+    writer.write_str(entry.owner.as_deref())?;
+    writer.write_str(entry.with_oids.as_deref())?;
+    for dependency in &entry.dependencies {
+        writer.write_str(Some(dependency))?;
+    }
+    writer.write_str(None)?;
+
+    match &entry.data_location {
+        DataLocation::Offset(offset) => writer.write_offset(*offset),
+        DataLocation::File(file) => writer.write_str(file.as_deref()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::header;
+    use std::fs;
+
+    #[test]
+    fn reads_the_table_of_contents() {
+        let bytes = fs::read("test_files/dump_file.dump").unwrap();
+        let (header, mut reader) = header::read(bytes.as_slice()).unwrap();
+        let entries = read(&header, &mut reader).unwrap();
+
+        let orders = entries
+            .iter()
+            .find(|e| e.is_table_data() && e.tag.as_deref() == Some("orders"))
+            .unwrap();
+
+        assert!(orders.has_data());
+        assert_eq!(orders.namespace, Some("public".to_string()));
+        assert!(orders
+            .copy_stmt
+            .as_deref()
+            .unwrap()
+            .starts_with("COPY public.orders ("));
+    }
+
+    #[test]
+    fn writes_the_table_of_contents_back_byte_for_byte() {
+        let bytes = fs::read("test_files/dump_file.dump").unwrap();
+        let mut remaining = bytes.as_slice();
+        let (header, mut reader) = header::read(&mut remaining).unwrap();
+        let entries = read(&header, &mut reader).unwrap();
+        let toc_end = bytes.len() - remaining.len();
+
+        let mut written = Vec::new();
+        let mut writer = ArchiveWriter::new(&mut written, 4, 8);
+        header::write(&header, &mut writer).unwrap();
+        write(&header, &entries, &mut writer).unwrap();
+
+        assert_eq!(written, bytes[..toc_end]);
+    }
+}
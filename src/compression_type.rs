@@ -1,6 +1,7 @@
+use crate::errors::AnonymiserError;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CompressionType {
     Zstd,
     Gzip,
@@ -17,3 +18,52 @@ impl FromStr for CompressionType {
         }
     }
 }
+
+impl CompressionType {
+    /// Works out how a file is compressed from its first few bytes
+    pub fn detect(start_of_file: &[u8]) -> Result<Option<CompressionType>, AnonymiserError> {
+        match start_of_file {
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Ok(Some(CompressionType::Zstd)),
+            [0x1f, 0x8b, ..] => Ok(Some(CompressionType::Gzip)),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Err(AnonymiserError::Usage(
+                "xz compressed input is not supported, decompress it with 'xz -dc' and pass it in on stdin with '-i -'".to_string(),
+            )),
+            [b'B', b'Z', b'h', ..] => Err(AnonymiserError::Usage(
+                "bzip2 compressed input is not supported, decompress it with 'bzip2 -dc' and pass it in on stdin with '-i -'".to_string(),
+            )),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_zstd_and_gzip() {
+        assert_eq!(
+            CompressionType::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]).unwrap(),
+            Some(CompressionType::Zstd)
+        );
+        assert_eq!(
+            CompressionType::detect(&[0x1f, 0x8b, 0x08]).unwrap(),
+            Some(CompressionType::Gzip)
+        );
+    }
+
+    #[test]
+    fn detects_uncompressed_input() {
+        assert_eq!(CompressionType::detect(b"--\n-- PostgreSQL").unwrap(), None);
+        assert_eq!(CompressionType::detect(b"PGDMP").unwrap(), None);
+        assert_eq!(CompressionType::detect(b"").unwrap(), None);
+    }
+
+    #[test]
+    fn errors_for_xz_input() {
+        let err = CompressionType::detect(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("xz compressed input is not supported"));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::parsers::strategy_errors::{IncompatibleTransformers, ValidationErrors};
+use std::fmt;
+
+/// Everything that can stop a dump from being anonymised. Each class of failure exits with its
+/// own code (see `exit_code`) so scripts can tell them apart. Variants in the same class share a
+/// code: a missing input file is an I/O failure, a strategy file that can't be read is as unusable
+/// as a missing one, and transformers that don't suit their columns fail validation
+#[derive(Debug)]
+pub enum AnonymiserError {
+    /// The command line options can't be used together, or with this input
+    Usage(String),
+    InputFileMissing {
+        path: String,
+    },
+    /// Reading the dump or writing the output failed
+    Io(std::io::Error),
+    StrategyFileMissing {
+        path: String,
+    },
+    /// The strategy file couldn't be read or isn't valid JSON
+    InvalidStrategyFile {
+        path: String,
+        message: String,
+    },
+    /// The strategy file was read but failed validation
+    InvalidStrategies(Box<ValidationErrors>),
+    /// Some transformers in the strategy file can't be used on their columns' types in the dump
+    IncompatibleTransformers(IncompatibleTransformers),
+    /// The dump has a table, or a column, that isn't in the strategy file
+    MissingStrategy {
+        table: String,
+        column: Option<String>,
+        line: Option<usize>,
+    },
+    /// The dump itself couldn't be parsed
+    InvalidDump {
+        message: String,
+        line: Option<usize>,
+    },
+    /// A transformer couldn't be applied to a value
+    Transform {
+        table: String,
+        column: Option<String>,
+        message: String,
+        line: Option<usize>,
+    },
+}
+
+impl AnonymiserError {
+    pub fn invalid_dump(message: String) -> Self {
+        AnonymiserError::InvalidDump {
+            message,
+            line: None,
+        }
+    }
+
+    pub fn transform(table: &str, message: String) -> Self {
+        AnonymiserError::Transform {
+            table: table.to_string(),
+            column: None,
+            message,
+            line: None,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AnonymiserError::Usage(_) => 2,
+            AnonymiserError::InputFileMissing { .. } | AnonymiserError::Io(_) => 3,
+            AnonymiserError::StrategyFileMissing { .. }
+            | AnonymiserError::InvalidStrategyFile { .. } => 4,
+            AnonymiserError::InvalidStrategies(_)
+            | AnonymiserError::IncompatibleTransformers(_) => 5,
+            AnonymiserError::MissingStrategy { .. } => 6,
+            AnonymiserError::InvalidDump { .. } => 7,
+            AnonymiserError::Transform { .. } => 8,
+        }
+    }
+
+    /// Records the line of the dump the error was found on, unless it already has one
+    pub fn at_line(mut self, line_number: usize) -> Self {
+        if let AnonymiserError::MissingStrategy { line, .. }
+        | AnonymiserError::InvalidDump { line, .. }
+        | AnonymiserError::Transform { line, .. } = &mut self
+        {
+            line.get_or_insert(line_number);
+        }
+        self
+    }
+
+    /// Records the column being transformed when the error happened, unless it already has one
+    pub fn in_column(mut self, column_name: &str) -> Self {
+        if let AnonymiserError::Transform { column, .. } = &mut self {
+            column.get_or_insert_with(|| column_name.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for AnonymiserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnonymiserError::Usage(message) => write!(f, "{}", message),
+            AnonymiserError::InputFileMissing { path } => {
+                write!(f, "Input file '{}' does not exist", path)
+            }
+            AnonymiserError::Io(err) => write!(f, "{}", err),
+            AnonymiserError::StrategyFileMissing { path } => {
+                write!(f, "Strategy file '{}' does not exist", path)
+            }
+            AnonymiserError::InvalidStrategyFile { path, message } => {
+                write!(f, "Invalid strategy file at '{}': {}", path, message)
+            }
+            AnonymiserError::InvalidStrategies(errors) => write!(f, "{}", errors),
+            AnonymiserError::IncompatibleTransformers(errors) => write!(f, "{}", errors),
+            AnonymiserError::MissingStrategy {
+                table,
+                column: Some(column),
+                line,
+            } => {
+                write!(
+                    f,
+                    "No transform found for column: {:?} in table: {:?}",
+                    column, table
+                )?;
+                write_line(f, line)
+            }
+            AnonymiserError::MissingStrategy {
+                table,
+                column: None,
+                line,
+            } => {
+                write!(f, "No transforms found for table: {:?}", table)?;
+                write_line(f, line)
+            }
+            AnonymiserError::InvalidDump { message, line } => {
+                write!(f, "{}", message)?;
+                write_line(f, line)
+            }
+            AnonymiserError::Transform {
+                table,
+                column,
+                message,
+                line,
+            } => {
+                match column {
+                    Some(column) => write!(f, "Unable to transform {}.{}", table, column)?,
+                    None => write!(f, "Unable to transform {}", table)?,
+                }
+                write_line(f, line)?;
+                write!(f, ": {}", message)
+            }
+        }
+    }
+}
+
+fn write_line(f: &mut fmt::Formatter, line: &Option<usize>) -> fmt::Result {
+    match line {
+        Some(line) => write!(f, " on line {}", line),
+        None => Ok(()),
+    }
+}
+
+impl std::error::Error for AnonymiserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnonymiserError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AnonymiserError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            // What's read is corrupt rather than unreadable, e.g. an archive that doesn't parse, a
+            // dump that isn't UTF-8 or compressed data that won't decompress
+            std::io::ErrorKind::InvalidData => AnonymiserError::invalid_dump(err.to_string()),
+            _ => AnonymiserError::Io(err),
+        }
+    }
+}
+
+impl From<ValidationErrors> for AnonymiserError {
+    fn from(errors: ValidationErrors) -> Self {
+        AnonymiserError::InvalidStrategies(Box::new(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_is_added_to_transform_errors() {
+        let err = AnonymiserError::transform("public.users", "Invalid date".to_string())
+            .in_column("inserted_at")
+            .at_line(12);
+
+        assert_eq!(
+            err.to_string(),
+            "Unable to transform public.users.inserted_at on line 12: Invalid date"
+        );
+    }
+
+    #[test]
+    fn the_first_line_and_column_recorded_are_kept() {
+        let err = AnonymiserError::transform("public.users", "Invalid date".to_string())
+            .in_column("inserted_at")
+            .at_line(12)
+            .in_column("updated_at")
+            .at_line(40);
+
+        assert_eq!(
+            err.to_string(),
+            "Unable to transform public.users.inserted_at on line 12: Invalid date"
+        );
+    }
+
+    #[test]
+    fn corrupt_input_is_an_invalid_dump_rather_than_an_io_error() {
+        let err = AnonymiserError::from(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Unexpected data offset flag in archive: 9",
+        ));
+
+        assert!(matches!(err, AnonymiserError::InvalidDump { .. }));
+        assert_eq!(err.exit_code(), 7);
+        assert_eq!(err.to_string(), "Unexpected data offset flag in archive: 9");
+    }
+
+    #[test]
+    fn every_error_exits_with_the_documented_code_for_its_class() {
+        let documented_exit_codes = [
+            (AnonymiserError::Usage("bad options".to_string()), 2),
+            (
+                AnonymiserError::InputFileMissing {
+                    path: "dump.sql".to_string(),
+                },
+                3,
+            ),
+            (AnonymiserError::Io(std::io::Error::other("disk full")), 3),
+            (
+                AnonymiserError::StrategyFileMissing {
+                    path: "strategy.json".to_string(),
+                },
+                4,
+            ),
+            (
+                AnonymiserError::InvalidStrategyFile {
+                    path: "strategy.json".to_string(),
+                    message: "expected value".to_string(),
+                },
+                4,
+            ),
+            (AnonymiserError::InvalidStrategies(Box::default()), 5),
+            (
+                AnonymiserError::IncompatibleTransformers(IncompatibleTransformers(vec![])),
+                5,
+            ),
+            (
+                AnonymiserError::MissingStrategy {
+                    table: "public.users".to_string(),
+                    column: None,
+                    line: None,
+                },
+                6,
+            ),
+            (AnonymiserError::invalid_dump("bad COPY".to_string()), 7),
+            (
+                AnonymiserError::transform("public.users", "Invalid date".to_string()),
+                8,
+            ),
+        ];
+
+        for (error, exit_code) in documented_exit_codes {
+            assert_eq!(error.exit_code(), exit_code, "{:?}", error);
+        }
+    }
+}
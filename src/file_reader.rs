@@ -1,62 +1,235 @@
+use crate::anonymiser::AnonymiseOptions;
+use crate::archive::{custom, directory, header};
 use crate::compression_type::CompressionType;
+use crate::errors::AnonymiserError;
+use crate::output_format::OutputFormat;
 use crate::parsers::rng;
-use crate::parsers::row_parser;
-use crate::parsers::state::State;
 use crate::parsers::strategies::Strategies;
+use crate::parsers::subset;
+use crate::pipeline;
+use crate::shuffle;
+use flate2::read::MultiGzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::path::Path;
+
+/// Passed as the input or output file to read from stdin or write to stdout
+const STDIO: &str = "-";
+
+const SUBSET_ERROR: &str =
+    "'--subset' reads the dump more than once, so it can only be used with a plain dump read from a file";
+
+const DIRECTORY_OUTPUT_ERROR: &str =
+    "Directory format output can only be written when the input is a directory format archive";
+
+/// Where the anonymised dump is written. A file can be seeked, which lets a custom archive have
+/// its data offsets filled in
+pub enum Output<'a> {
+    File(File),
+    Stream(Box<dyn Write + Send + 'a>),
+}
 
 pub fn read(
     input_file_path: String,
     output_file_path: String,
     strategies: &Strategies,
-    compress_output: Option<Option<CompressionType>>,
-) -> Result<(), std::io::Error> {
-    let output_file = File::create(output_file_path)?;
-    let mut file_writer: Box<dyn Write> = match compress_output {
-        Some(Some(CompressionType::Zstd)) => {
-            Box::new(zstd::Encoder::new(output_file, 1)?.auto_finish())
+    options: &AnonymiseOptions,
+) -> Result<(), AnonymiserError> {
+    if input_file_path != STDIO && Path::new(&input_file_path).is_dir() {
+        if options.subset {
+            return Err(AnonymiserError::Usage(SUBSET_ERROR.to_string()));
+        }
+        if options.compress_output.is_some()
+            || options.output_format.unwrap_or(OutputFormat::Directory) != OutputFormat::Directory
+        {
+            return Err(AnonymiserError::Usage("Directory format archives can only be written out as another directory, '--output-format' and '--compress-output' cannot be used with them".to_string()));
         }
-        Some(Some(CompressionType::Gzip)) => {
-            Box::new(GzEncoder::new(output_file, Compression::best()))
+        if output_file_path == STDIO {
+            return Err(AnonymiserError::Usage(
+                "Directory format archives can't be written to stdout".to_string(),
+            ));
         }
-        Some(None) => Box::new(zstd::Encoder::new(output_file, 1)?.auto_finish()),
+        return directory::anonymise(
+            Path::new(&input_file_path),
+            Path::new(&output_file_path),
+            &strategies.seeded(options.seed),
+            pipeline::threads(options.threads),
+            options.seed,
+            shuffle::spill_dir(options).as_deref(),
+        );
+    }
 
-        None => Box::new(BufWriter::new(output_file)),
+    let subset_strategies;
+    let strategies = match options.subset {
+        true => {
+            subset_strategies = find_subset(&input_file_path, strategies, options)?;
+            &subset_strategies
+        }
+        false => strategies,
     };
 
-    let file_reader = File::open(&input_file_path)
-        .unwrap_or_else(|_| panic!("Input file '{}' does not exist", input_file_path));
+    let output = match output_file_path.as_str() {
+        STDIO => Output::Stream(Box::new(std::io::stdout())),
+        path => Output::File(File::create(path)?),
+    };
 
-    let mut reader = BufReader::new(file_reader);
-    let mut line = String::new();
+    let input: Box<dyn Read> = match input_file_path.as_str() {
+        STDIO => Box::new(std::io::stdin().lock()),
+        path => Box::new(open(path)?),
+    };
 
-    let mut row_parser_state = State::new();
+    transform(input, output, strategies, options)
+}
 
-    let mut rng = rng::get();
+fn open(path: &str) -> Result<File, AnonymiserError> {
+    File::open(path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => AnonymiserError::InputFileMissing {
+            path: path.to_string(),
+        },
+        _ => AnonymiserError::Io(err),
+    })
+}
 
-    loop {
-        let bytes_read = reader.read_line(&mut line)?;
-        if bytes_read == 0 {
-            break;
-        }
+/// The strategies with the subset of rows to keep, found by reading through the whole dump
+/// before it's anonymised
+fn find_subset(
+    input_file_path: &str,
+    strategies: &Strategies,
+    options: &AnonymiseOptions,
+) -> Result<Strategies, AnonymiserError> {
+    if input_file_path == STDIO {
+        return Err(AnonymiserError::Usage(SUBSET_ERROR.to_string()));
+    }
+    let mut reader = decompress(Box::new(open(input_file_path)?), options)?;
+    if header::is_archive(reader.fill_buf()?) {
+        return Err(AnonymiserError::Usage(SUBSET_ERROR.to_string()));
+    }
+    let mut reader = Some(reader);
+    let subset = subset::find(
+        &mut || match reader.take() {
+            Some(reader) => Ok(reader),
+            None => decompress(Box::new(open(input_file_path)?), options),
+        },
+        strategies,
+    )?;
+    Ok(strategies.with_subset(subset))
+}
 
-        let transformed_row = row_parser::parse(&mut rng, &line, &mut row_parser_state, strategies);
-        file_writer.write_all(transformed_row.as_bytes())?;
-        line.clear();
+/// Anonymises a plain dump or a custom archive, working out from the first few bytes of `input`
+/// which it is and how it's compressed
+pub fn transform<'a>(
+    input: Box<dyn Read + 'a>,
+    output: Output<'a>,
+    strategies: &Strategies,
+    options: &AnonymiseOptions,
+) -> Result<(), AnonymiserError> {
+    if options.subset && strategies.subset().is_none() {
+        return Err(AnonymiserError::Usage(SUBSET_ERROR.to_string()));
+    }
+    let mut reader = decompress(input, options)?;
+    let mut rng = options.seed.map_or_else(rng::get, rng::seeded);
+    let strategies = &*strategies.seeded(options.seed);
+    let spill_dir = shuffle::spill_dir(options);
+    let spill_dir = spill_dir.as_deref();
+
+    if header::is_archive(reader.fill_buf()?) {
+        return match options.output_format.unwrap_or(OutputFormat::Custom) {
+            OutputFormat::Plain => custom::to_plain(
+                reader,
+                writer(output, options.compress_output)?.as_mut(),
+                strategies,
+                &mut rng,
+                spill_dir,
+            ),
+            OutputFormat::Custom => {
+                if options.compress_output.is_some() {
+                    return Err(AnonymiserError::Usage("Custom format archives are already compressed, '--compress-output' can only be used with plain output".to_string()));
+                }
+                match output {
+                    Output::File(output_file) => {
+                        custom::to_custom(reader, output_file, strategies, &mut rng, spill_dir)
+                    }
+                    Output::Stream(output) => {
+                        custom::to_custom_stream(reader, output, strategies, &mut rng, spill_dir)
+                    }
+                }
+            }
+            OutputFormat::Directory => {
+                Err(AnonymiserError::Usage(DIRECTORY_OUTPUT_ERROR.to_string()))
+            }
+        };
+    }
+
+    match options.output_format {
+        Some(OutputFormat::Custom) => return Err(AnonymiserError::Usage(
+            "Custom format output can only be written when the input is a custom format archive"
+                .to_string(),
+        )),
+        Some(OutputFormat::Directory) => {
+            return Err(AnonymiserError::Usage(DIRECTORY_OUTPUT_ERROR.to_string()))
+        }
+        _ => (),
     }
-    Ok(())
+
+    let mut file_writer = writer(output, options.compress_output)?;
+    pipeline::transform(
+        &mut reader,
+        file_writer.as_mut(),
+        strategies,
+        pipeline::threads(options.threads),
+        options.seed,
+        spill_dir,
+    )
+}
+
+fn decompress<'a>(
+    input: Box<dyn Read + 'a>,
+    options: &AnonymiseOptions,
+) -> Result<Box<dyn BufRead + 'a>, AnonymiserError> {
+    let mut reader = BufReader::new(input);
+    let input_compression = match options.input_compression {
+        Some(input_compression) => Some(input_compression),
+        None => CompressionType::detect(reader.fill_buf()?)?,
+    };
+    Ok(match input_compression {
+        Some(CompressionType::Zstd) => {
+            Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+        }
+        Some(CompressionType::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        None => Box::new(reader),
+    })
+}
+
+fn writer<'a>(
+    output: Output<'a>,
+    compress_output: Option<Option<CompressionType>>,
+) -> Result<Box<dyn Write + Send + 'a>, std::io::Error> {
+    let output: Box<dyn Write + Send + 'a> = match output {
+        Output::File(output_file) => Box::new(output_file),
+        Output::Stream(output) => output,
+    };
+    let file_writer: Box<dyn Write + Send + 'a> = match compress_output {
+        Some(Some(CompressionType::Zstd)) => Box::new(zstd::Encoder::new(output, 1)?.auto_finish()),
+        Some(Some(CompressionType::Gzip)) => Box::new(GzEncoder::new(output, Compression::best())),
+        Some(None) => Box::new(zstd::Encoder::new(output, 1)?.auto_finish()),
+
+        None => Box::new(BufWriter::new(output)),
+    };
+    Ok(file_writer)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::archive::primitives::DataOffset;
+    use crate::archive::toc::{self, DataLocation};
     use crate::parsers::strategy_structs::*;
     use crate::uncompress::uncompress;
+    use flate2::read::MultiGzDecoder;
     use pretty_assertions::assert_eq;
     use std::collections::HashMap;
     use std::fs;
@@ -64,45 +237,53 @@ mod tests {
 
     fn default_strategies() -> Strategies {
         let mut strategies = Strategies::new();
-        strategies.insert(
-            "public.orders".to_string(),
-            HashMap::from([
-                strategy_tuple("id"),
-                strategy_tuple("user_id"),
-                strategy_tuple("product_id"),
-            ]),
-        );
-        strategies.insert(
-            "public.products".to_string(),
-            HashMap::from([
-                strategy_tuple("id"),
-                strategy_tuple("description"),
-                strategy_tuple("price"),
-                strategy_tuple("details"),
-                strategy_tuple("tags"),
-            ]),
-        );
+        strategies
+            .insert(
+                "public.orders".to_string(),
+                HashMap::from([
+                    strategy_tuple("id"),
+                    strategy_tuple("user_id"),
+                    strategy_tuple("product_id"),
+                ]),
+            )
+            .unwrap();
+        strategies
+            .insert(
+                "public.products".to_string(),
+                HashMap::from([
+                    strategy_tuple("id"),
+                    strategy_tuple("description"),
+                    strategy_tuple("price"),
+                    strategy_tuple("details"),
+                    strategy_tuple("tags"),
+                ]),
+            )
+            .unwrap();
 
-        strategies.insert(
-            "public.users".to_string(),
-            HashMap::from([
-                strategy_tuple("id"),
-                strategy_tuple("email"),
-                strategy_tuple("password"),
-                strategy_tuple("last_login"),
-                strategy_tuple("inserted_at"),
-                strategy_tuple("updated_at"),
-                strategy_tuple("first_name"),
-                strategy_tuple("last_name"),
-                strategy_tuple("deactivated"),
-                strategy_tuple("phone_number"),
-            ]),
-        );
+        strategies
+            .insert(
+                "public.users".to_string(),
+                HashMap::from([
+                    strategy_tuple("id"),
+                    strategy_tuple("email"),
+                    strategy_tuple("password"),
+                    strategy_tuple("last_login"),
+                    strategy_tuple("inserted_at"),
+                    strategy_tuple("updated_at"),
+                    strategy_tuple("first_name"),
+                    strategy_tuple("last_name"),
+                    strategy_tuple("deactivated"),
+                    strategy_tuple("phone_number"),
+                ]),
+            )
+            .unwrap();
 
-        strategies.insert(
-            "public.extra_data".to_string(),
-            HashMap::from([strategy_tuple("id"), strategy_tuple("data")]),
-        );
+        strategies
+            .insert(
+                "public.extra_data".to_string(),
+                HashMap::from([strategy_tuple("id"), strategy_tuple("data")]),
+            )
+            .unwrap();
 
         strategies
     }
@@ -114,7 +295,36 @@ mod tests {
         let _ = fs::remove_file(&output_file).ok();
         let strategies = default_strategies();
 
-        assert!(read(input_file.clone(), output_file.clone(), &strategies, None).is_ok());
+        assert!(read(
+            input_file.clone(),
+            output_file.clone(),
+            &strategies,
+            &AnonymiseOptions::default()
+        )
+        .is_ok());
+
+        let original =
+            fs::read_to_string(&input_file).expect("Something went wrong reading the file");
+
+        let processed =
+            fs::read_to_string(&output_file).expect("Something went wrong reading the file");
+
+        assert_eq!(original, processed);
+    }
+
+    #[test]
+    fn can_read_inserts() {
+        let input_file = "test_files/dump_file_inserts.sql".to_string();
+        let output_file = "test_files/file_reader_inserts_test_results.sql".to_string();
+        let strategies = default_strategies();
+
+        assert!(read(
+            input_file.clone(),
+            output_file.clone(),
+            &strategies,
+            &AnonymiseOptions::default()
+        )
+        .is_ok());
 
         let original =
             fs::read_to_string(&input_file).expect("Something went wrong reading the file");
@@ -141,7 +351,10 @@ mod tests {
             input_file.clone(),
             compressed_file.clone(),
             &strategies,
-            Some(None)
+            &AnonymiseOptions {
+                compress_output: Some(None),
+                ..Default::default()
+            }
         )
         .is_ok());
 
@@ -174,7 +387,10 @@ mod tests {
             input_file.clone(),
             compressed_file.clone(),
             &strategies,
-            Some(Some(CompressionType::Zstd))
+            &AnonymiseOptions {
+                compress_output: Some(Some(CompressionType::Zstd)),
+                ..Default::default()
+            }
         )
         .is_ok());
 
@@ -193,6 +409,303 @@ mod tests {
         assert_eq!(original, processed);
     }
 
+    #[test]
+    fn can_read_compressed_input() {
+        let input_file = "test_files/dump_file.sql".to_string();
+        let strategies = default_strategies();
+
+        for (compression_type, extension) in [
+            (CompressionType::Zstd, "zst"),
+            (CompressionType::Gzip, "gz"),
+        ] {
+            let compressed_file =
+                format!("test_files/file_reader_compressed_input_test_results.sql.{extension}");
+            let output_file =
+                "test_files/file_reader_compressed_input_test_results.sql".to_string();
+
+            assert!(read(
+                input_file.clone(),
+                compressed_file.clone(),
+                &strategies,
+                &AnonymiseOptions {
+                    compress_output: Some(Some(compression_type)),
+                    ..Default::default()
+                }
+            )
+            .is_ok());
+
+            assert!(read(
+                compressed_file,
+                output_file.clone(),
+                &strategies,
+                &AnonymiseOptions::default()
+            )
+            .is_ok());
+
+            let original =
+                fs::read_to_string(&input_file).expect("Something went wrong reading the file");
+
+            let processed =
+                fs::read_to_string(&output_file).expect("Something went wrong reading the file");
+
+            assert_eq!(original, processed);
+        }
+    }
+
+    #[test]
+    fn gives_identical_output_for_the_same_seed() {
+        let input_file = "test_files/dump_file.sql".to_string();
+        let first_output_file = "test_files/file_reader_seeded_first_results.sql".to_string();
+        let second_output_file = "test_files/file_reader_seeded_second_results.sql".to_string();
+        let mut strategies = default_strategies();
+        strategies
+            .insert(
+                "public.users".to_string(),
+                HashMap::from([
+                    strategy_tuple("id"),
+                    strategy_tuple_with_transformer("email", TransformerType::FakeEmail),
+                    strategy_tuple_with_transformer("password", TransformerType::Scramble),
+                    strategy_tuple("last_login"),
+                    strategy_tuple("inserted_at"),
+                    strategy_tuple("updated_at"),
+                    strategy_tuple_with_transformer("first_name", TransformerType::FakeFirstName),
+                    strategy_tuple_with_transformer("last_name", TransformerType::FakeLastName),
+                    strategy_tuple("deactivated"),
+                    strategy_tuple_with_transformer(
+                        "phone_number",
+                        TransformerType::FakePhoneNumber,
+                    ),
+                ]),
+            )
+            .unwrap();
+
+        for output_file in [&first_output_file, &second_output_file] {
+            assert!(read(
+                input_file.clone(),
+                output_file.clone(),
+                &strategies,
+                &AnonymiseOptions {
+                    seed: Some(1234),
+                    ..Default::default()
+                }
+            )
+            .is_ok());
+        }
+
+        let original =
+            fs::read_to_string(&input_file).expect("Something went wrong reading the file");
+        let first =
+            fs::read_to_string(&first_output_file).expect("Something went wrong reading the file");
+        let second =
+            fs::read_to_string(&second_output_file).expect("Something went wrong reading the file");
+
+        assert!(original != first);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn can_read_a_custom_archive_and_output_plain_sql() {
+        let output_file = "test_files/file_reader_archive_plain_results.sql".to_string();
+        let strategies = default_strategies();
+
+        assert!(read(
+            "test_files/dump_file.dump".to_string(),
+            output_file.clone(),
+            &strategies,
+            &AnonymiseOptions {
+                output_format: Some(OutputFormat::Plain),
+                ..Default::default()
+            }
+        )
+        .is_ok());
+
+        let original = fs::read_to_string("test_files/dump_file.sql")
+            .expect("Something went wrong reading the file");
+        let processed =
+            fs::read_to_string(&output_file).expect("Something went wrong reading the file");
+
+        for table in ["extra_data", "orders", "products", "users"] {
+            assert_eq!(copy_block(&original, table), copy_block(&processed, table));
+        }
+    }
+
+    #[test]
+    fn can_read_a_custom_archive_and_output_a_custom_archive() {
+        let output_file = "test_files/file_reader_archive_custom_results.dump".to_string();
+        let original_as_plain = "test_files/file_reader_archive_original_results.sql".to_string();
+        let output_as_plain = "test_files/file_reader_archive_output_results.sql".to_string();
+        let strategies = default_strategies();
+
+        assert!(read(
+            "test_files/dump_file.dump".to_string(),
+            output_file.clone(),
+            &strategies,
+            &AnonymiseOptions::default()
+        )
+        .is_ok());
+
+        let bytes = fs::read(&output_file).expect("Something went wrong reading the file");
+        let (header, mut reader) = header::read(bytes.as_slice()).unwrap();
+        let entries = toc::read(&header, &mut reader).unwrap();
+        assert!(entries
+            .iter()
+            .filter(|entry| entry.has_data())
+            .all(|entry| matches!(
+                entry.data_location,
+                DataLocation::Offset(DataOffset::Set(_))
+            )));
+
+        for (input_file, plain_file) in [
+            ("test_files/dump_file.dump", &original_as_plain),
+            (&output_file, &output_as_plain),
+        ] {
+            assert!(read(
+                input_file.to_string(),
+                plain_file.clone(),
+                &strategies,
+                &AnonymiseOptions {
+                    output_format: Some(OutputFormat::Plain),
+                    ..Default::default()
+                }
+            )
+            .is_ok());
+        }
+
+        let original =
+            fs::read_to_string(&original_as_plain).expect("Something went wrong reading the file");
+        let processed =
+            fs::read_to_string(&output_as_plain).expect("Something went wrong reading the file");
+
+        assert_eq!(original, processed);
+    }
+
+    #[test]
+    fn errors_if_custom_output_is_requested_for_plain_input() {
+        let strategies = default_strategies();
+
+        let result = read(
+            "test_files/dump_file.sql".to_string(),
+            "test_files/file_reader_custom_from_plain_results.dump".to_string(),
+            &strategies,
+            &AnonymiseOptions {
+                output_format: Some(OutputFormat::Custom),
+                ..Default::default()
+            },
+        );
+
+        let err = result.unwrap_err();
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(
+            err.to_string(),
+            "Custom format output can only be written when the input is a custom format archive"
+        );
+    }
+
+    #[test]
+    fn can_read_a_directory_archive_and_output_a_directory_archive() {
+        let input_dir = PathBuf::from("test_files/dump_file_directory");
+        let output_dir = PathBuf::from("test_files/file_reader_directory_results");
+        let _ = fs::remove_dir_all(&output_dir);
+        let strategies = default_strategies();
+
+        assert!(read(
+            input_dir.to_string_lossy().to_string(),
+            output_dir.to_string_lossy().to_string(),
+            &strategies,
+            &AnonymiseOptions::default()
+        )
+        .is_ok());
+
+        let mut file_names: Vec<_> = fs::read_dir(&input_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        file_names.sort();
+        for file_name in file_names {
+            let original = fs::read(input_dir.join(&file_name)).unwrap();
+            let processed = fs::read(output_dir.join(&file_name)).unwrap();
+            if file_name.to_string_lossy().ends_with(".gz") {
+                let mut original_data = String::new();
+                let mut processed_data = String::new();
+                MultiGzDecoder::new(original.as_slice())
+                    .read_to_string(&mut original_data)
+                    .unwrap();
+                MultiGzDecoder::new(processed.as_slice())
+                    .read_to_string(&mut processed_data)
+                    .unwrap();
+                assert_eq!(original_data, processed_data);
+            } else {
+                assert_eq!(original, processed);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_inserts_in_function_bodies_alone() {
+        let dump = "CREATE TABLE public.extra_data (
+    id bigint NOT NULL,
+    data character varying(255) NOT NULL
+);
+
+CREATE FUNCTION public.audit_extra_data() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $_$
+BEGIN
+    INSERT INTO audit (id) VALUES (NEW.id);
+INSERT INTO audit (id, note) VALUES (NEW.id, '$$');
+    RETURN NEW;
+END;
+$_$;
+
+INSERT INTO public.extra_data VALUES (1, 'data');
+";
+        let strategies = default_strategies();
+        let subset = subset::find(
+            &mut || Ok(Box::new(BufReader::new(dump.as_bytes()))),
+            &strategies,
+        )
+        .unwrap();
+
+        for (strategies, subset) in [
+            (strategies.clone(), false),
+            (strategies.with_subset(subset), true),
+        ] {
+            let mut output = Vec::new();
+            transform(
+                Box::new(dump.as_bytes()),
+                Output::Stream(Box::new(&mut output)),
+                &strategies,
+                &AnonymiseOptions {
+                    subset,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), dump);
+        }
+    }
+
+    fn copy_block<'a>(sql: &'a str, table: &str) -> &'a str {
+        let start = sql
+            .find(&format!("COPY public.{} ", table))
+            .expect("COPY block should exist");
+        let end = start + sql[start..].find("\\.\n").unwrap();
+        &sql[start..end]
+    }
+
+    fn strategy_tuple_with_transformer(
+        column_name: &str,
+        transformer_type: TransformerType,
+    ) -> (String, ColumnInfo) {
+        (
+            column_name.to_string(),
+            ColumnInfo::builder()
+                .with_name(column_name)
+                .with_transformer(transformer_type, None)
+                .build(),
+        )
+    }
+
     fn strategy_tuple(column_name: &str) -> (String, ColumnInfo) {
         (
             column_name.to_string(),
@@ -33,6 +33,8 @@ fn add_missing(current: Vec<StrategyInFile>, missing: &[SimpleColumn]) -> Vec<St
             None => {
                 let mut new_table = StrategyInFile {
                     truncate: false,
+                    locale: None,
+                    filter: None,
                     table_name: table.clone(),
                     description: "".to_string(),
                     columns: vec![],
@@ -97,6 +99,8 @@ mod tests {
             table_name: "public.person".to_string(),
             description: "".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![ColumnInFile::new("id"), ColumnInFile::new("first_name")],
         }];
 
@@ -122,6 +126,8 @@ mod tests {
                 table_name: "public.person".to_string(),
                 description: "".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![
                     ColumnInFile::new("id"),
                     ColumnInFile::new("first_name"),
@@ -132,6 +138,8 @@ mod tests {
                 table_name: "public.location".to_string(),
                 description: "".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![ColumnInFile::new("id"), ColumnInFile::new("post_code")],
             },
         ];
@@ -146,12 +154,16 @@ mod tests {
                 table_name: "public.location".to_string(),
                 description: "".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![ColumnInFile::new("id"), ColumnInFile::new("post_code")],
             },
             StrategyInFile {
                 table_name: "public.person".to_string(),
                 description: "".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![
                     ColumnInFile::new("id"),
                     ColumnInFile::new("first_name"),
@@ -181,6 +193,8 @@ mod tests {
             table_name: "public.person".to_string(),
             description: "".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![ColumnInFile::new("id"), ColumnInFile::new("first_name")],
         }];
 
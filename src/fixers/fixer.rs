@@ -1,3 +1,4 @@
+use crate::errors::AnonymiserError;
 use crate::fixers::{db_mismatch, validation};
 use crate::parsers::strategy_errors::StrategyFileError;
 use crate::parsers::strategy_file;
@@ -19,38 +20,37 @@ pub enum SortResult {
     NoChange,
 }
 
-pub fn just_sort(strategy_file: &str) -> SortResult {
-    let initial_hash = sha256_digest(strategy_file);
-    let current_file_contents = strategy_file::read(strategy_file).unwrap_or_else(|_| Vec::new());
-    strategy_file::write(strategy_file, current_file_contents).expect("Unable to write to file :(");
-    let post_sort_hash = sha256_digest(strategy_file);
+pub fn just_sort(strategy_file: &str) -> Result<SortResult, AnonymiserError> {
+    let initial_hash = sha256_digest(strategy_file)?;
+    let current_file_contents = strategy_file::read_or_empty(strategy_file)?;
+    strategy_file::write(strategy_file, current_file_contents)?;
+    let post_sort_hash = sha256_digest(strategy_file)?;
     if initial_hash == post_sort_hash {
-        SortResult::NoChange
+        Ok(SortResult::NoChange)
     } else {
-        SortResult::Sorted
+        Ok(SortResult::Sorted)
     }
 }
 
-pub fn fix(strategy_file: &str, error: StrategyFileError) {
-    let current_file_contents = strategy_file::read(strategy_file).unwrap_or_else(|_| Vec::new());
+pub fn fix(strategy_file: &str, error: StrategyFileError) -> Result<(), AnonymiserError> {
+    let current_file_contents = strategy_file::read_or_empty(strategy_file)?;
     match error {
         StrategyFileError::ValidationError(validation_error) => {
-            let new_file_contents = validation::fix(current_file_contents, validation_error);
+            let new_file_contents = validation::fix(current_file_contents, *validation_error);
 
-            strategy_file::write(strategy_file, new_file_contents)
-                .expect("Unable to write to file :(");
+            strategy_file::write(strategy_file, new_file_contents)?;
         }
         StrategyFileError::DbMismatchError(db_mismatch_error) => {
             let new_file_contents = db_mismatch::fix(current_file_contents, db_mismatch_error);
 
-            strategy_file::write(strategy_file, new_file_contents)
-                .expect("Unable to write to file :(");
+            strategy_file::write(strategy_file, new_file_contents)?;
         }
     }
+    Ok(())
 }
-fn sha256_digest(strategy_file: &str) -> String {
-    let bytes = std::fs::read(strategy_file).unwrap();
-    sha256::digest(bytes.as_slice())
+fn sha256_digest(strategy_file: &str) -> std::io::Result<String> {
+    let bytes = std::fs::read(strategy_file)?;
+    Ok(sha256::digest(bytes.as_slice()))
 }
 
 #[cfg(test)]
@@ -103,15 +103,17 @@ mod tests {
     }
     #[test]
     fn cannot_fix_validation_error_if_no_errors() {
-        assert!(!can_fix(&StrategyFileError::ValidationError(
+        assert!(!can_fix(&StrategyFileError::ValidationError(Box::new(
             ValidationErrors {
                 unknown_data_categories: Vec::new(),
                 error_transformer_types: Vec::new(),
                 unanonymised_pii: Vec::new(),
                 duplicate_columns: Vec::new(),
                 duplicate_tables: Vec::new(),
+                invalid_transformer_args: Vec::new(),
+                invalid_filters: Vec::new(),
             }
-        )));
+        ))));
     }
 
     #[test]
@@ -120,15 +122,17 @@ mod tests {
             column_name: "column".to_string(),
             table_name: "table".to_string(),
         }];
-        assert!(!can_fix(&StrategyFileError::ValidationError(
+        assert!(!can_fix(&StrategyFileError::ValidationError(Box::new(
             ValidationErrors {
                 unknown_data_categories: error.clone(),
                 error_transformer_types: error.clone(),
                 unanonymised_pii: error,
                 duplicate_columns: Vec::new(),
                 duplicate_tables: Vec::new(),
+                invalid_transformer_args: Vec::new(),
+                invalid_filters: Vec::new(),
             }
-        )));
+        ))));
     }
 
     #[test]
@@ -137,28 +141,55 @@ mod tests {
             table_name: "table_name".to_string(),
             column_name: "column".to_string(),
         }];
-        assert!(can_fix(&StrategyFileError::ValidationError(
+        assert!(can_fix(&StrategyFileError::ValidationError(Box::new(
             ValidationErrors {
                 unknown_data_categories: Vec::new(),
                 error_transformer_types: Vec::new(),
                 unanonymised_pii: Vec::new(),
                 duplicate_columns: error,
                 duplicate_tables: Vec::new(),
+                invalid_transformer_args: Vec::new(),
+                invalid_filters: Vec::new(),
             }
-        )));
+        ))));
     }
 
     #[test]
     fn cannot_currently_fix_duplicate_tables() {
         let error = vec!["table_name".to_string()];
-        assert!(!can_fix(&StrategyFileError::ValidationError(
+        assert!(!can_fix(&StrategyFileError::ValidationError(Box::new(
             ValidationErrors {
                 unknown_data_categories: Vec::new(),
                 error_transformer_types: Vec::new(),
                 unanonymised_pii: Vec::new(),
                 duplicate_columns: Vec::new(),
                 duplicate_tables: error,
+                invalid_transformer_args: Vec::new(),
+                invalid_filters: Vec::new(),
             }
-        )));
+        ))));
+    }
+
+    #[test]
+    fn returns_an_error_if_the_strategy_file_cannot_be_read() {
+        let strategy_file = std::env::temp_dir().join("anonymiser-fixer-invalid-strategy.json");
+        std::fs::write(&strategy_file, "not json").unwrap();
+        let strategy_file = strategy_file.to_str().unwrap();
+
+        assert!(matches!(
+            just_sort(strategy_file),
+            Err(AnonymiserError::InvalidStrategyFile { .. })
+        ));
+        assert!(matches!(
+            fix(
+                strategy_file,
+                StrategyFileError::DbMismatchError(DbErrors {
+                    missing_from_db: Vec::new(),
+                    missing_from_strategy_file: Vec::new(),
+                })
+            ),
+            Err(AnonymiserError::InvalidStrategyFile { .. })
+        ));
+        std::fs::remove_file(strategy_file).unwrap();
     }
 }
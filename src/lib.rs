@@ -0,0 +1,56 @@
+//! Anonymises database backups created with pg_dump, based on a strategy file saying how each
+//! column should be transformed.
+//!
+//! ```no_run
+//! use anonymiser::{anonymise_stream, load_strategies, AnonymiseOptions, TransformerOverrides};
+//!
+//! let strategies = load_strategies("strategy.json", &TransformerOverrides::none())?;
+//! anonymise_stream(
+//!     std::io::stdin(),
+//!     std::io::stdout(),
+//!     &strategies,
+//!     &AnonymiseOptions::default(),
+//! )?;
+//! # Ok::<(), anonymiser::AnonymiserError>(())
+//! ```
+
+mod anonymiser;
+mod archive;
+mod compression_type;
+mod errors;
+mod file_reader;
+mod fixers;
+mod output_format;
+mod parsers;
+mod pipeline;
+mod shuffle;
+mod uncompress;
+
+pub use crate::anonymiser::{anonymise, anonymise_stream, load_strategies, AnonymiseOptions};
+pub use crate::compression_type::CompressionType;
+pub use crate::errors::AnonymiserError;
+pub use crate::output_format::OutputFormat;
+pub use crate::parsers::rng;
+pub use crate::parsers::strategies::{Strategies, TableStrategy};
+pub use crate::parsers::strategy_errors::{IncompatibleTransformers, ValidationErrors};
+pub use crate::parsers::strategy_structs::{
+    ColumnInFile, ColumnInfo, DataCategory, StrategyInFile, Transformer, TransformerArg,
+    TransformerArgs, TransformerOverrides, TransformerType,
+};
+pub use crate::parsers::transformer::{
+    compile, transform, transform_deterministically, CompiledTransformer,
+};
+pub use crate::parsers::types::{SubType, Type};
+pub use crate::uncompress::uncompress;
+
+/// What the command line tool needs beyond the library's API, to check a strategy file against a
+/// database and fix it. Not part of the public API, so it can change in any release
+#[doc(hidden)]
+pub mod cli {
+    pub use crate::fixers::fixer;
+    pub use crate::parsers::strategy_errors::StrategyFileError;
+    pub use crate::parsers::{db_schema, strategy_file};
+}
+
+#[cfg(test)]
+mod test_builders;
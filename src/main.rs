@@ -1,21 +1,16 @@
-mod anonymiser;
-mod compression_type;
-mod file_reader;
-mod fixers;
 mod opts;
-mod parsers;
-mod uncompress;
 
-use crate::fixers::fixer;
-use crate::fixers::fixer::SortResult;
 use crate::opts::{Anonymiser, Opts};
-use crate::parsers::strategies::Strategies;
-use crate::parsers::strategy_errors::StrategyFileError;
-use crate::parsers::strategy_structs::{StrategyInFile, TransformerOverrides};
+use anonymiser::cli::fixer;
+use anonymiser::cli::fixer::SortResult;
+use anonymiser::cli::StrategyFileError;
+use anonymiser::{
+    AnonymiseOptions, AnonymiserError, Strategies, StrategyInFile, TransformerOverrides,
+};
 use native_tls::TlsConnector;
 use postgres_native_tls::MakeTlsConnector;
 
-use parsers::{db_schema, strategy_file};
+use anonymiser::cli::{db_schema, strategy_file};
 use structopt::StructOpt;
 
 use mimalloc::MiMalloc;
@@ -23,7 +18,14 @@ use mimalloc::MiMalloc;
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
-fn main() -> Result<(), std::io::Error> {
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run() -> Result<(), AnonymiserError> {
     let opt = Opts::from_args();
 
     match opt.commands {
@@ -32,22 +34,40 @@ fn main() -> Result<(), std::io::Error> {
             output_file,
             strategy_file,
             compress_output,
+            input_compression,
+            output_format,
             allow_potential_pii,
             allow_commercially_sensitive,
             scramble_blank,
+            deterministic_key,
+            seed,
+            subset,
+            threads,
+            spill_dir,
+            no_spill,
         } => {
             let transformer_overrides = TransformerOverrides {
                 allow_potential_pii,
                 allow_commercially_sensitive,
                 scramble_blank,
+                deterministic_key,
             };
 
             anonymiser::anonymise(
                 input_file,
                 output_file,
                 strategy_file,
-                compress_output,
                 transformer_overrides,
+                &AnonymiseOptions {
+                    compress_output,
+                    input_compression,
+                    output_format,
+                    threads,
+                    seed,
+                    subset,
+                    spill_dir,
+                    no_spill,
+                },
             )?
         }
         Anonymiser::ToCsv {
@@ -58,7 +78,7 @@ fn main() -> Result<(), std::io::Error> {
             strategy_file,
             db_url,
         } => {
-            let strategies = strategy_file::read(&strategy_file).unwrap_or_else(|_| Vec::new());
+            let strategies = strategy_file::read_or_empty(&strategy_file)?;
 
             match strategy_differences(strategies, db_url) {
                 Ok(()) => println!("All up to date"),
@@ -78,10 +98,10 @@ fn main() -> Result<(), std::io::Error> {
             strategy_file,
             db_url,
         } => {
-            let strategies = strategy_file::read(&strategy_file).unwrap_or_else(|_| Vec::new());
+            let strategies = strategy_file::read_or_empty(&strategy_file)?;
 
             match strategy_differences(strategies, db_url) {
-                Ok(()) => match fixer::just_sort(&strategy_file) {
+                Ok(()) => match fixer::just_sort(&strategy_file)? {
                     SortResult::Sorted => {
                         println!("Ok, we've updated that for you, check your diff!")
                     }
@@ -92,7 +112,7 @@ fn main() -> Result<(), std::io::Error> {
                 Err(err) => {
                     println!("{}", err);
                     println!("Ok! lets try and fix some of this!");
-                    fixer::fix(&strategy_file, err);
+                    fixer::fix(&strategy_file, err)?;
                     println!("All done, you probably want to run \"check-strategies\" again to make sure");
                 }
             }
@@ -106,7 +126,7 @@ fn main() -> Result<(), std::io::Error> {
                 Ok(()) => println!("All up to date"),
                 Err(err) => {
                     if fixer::can_fix(&err) {
-                        fixer::fix(&strategy_file, err);
+                        fixer::fix(&strategy_file, err)?;
                         println!("All done, you'll need to set a data_type and transformer for those fields");
                     }
                     std::process::exit(1);
@@ -116,7 +136,7 @@ fn main() -> Result<(), std::io::Error> {
         Anonymiser::Uncompress {
             input_file,
             output_file,
-        } => uncompress::uncompress(input_file, output_file).expect("failed to uncompress"),
+        } => anonymiser::uncompress(input_file, output_file)?,
     }
     Ok(())
 }
@@ -136,6 +156,3 @@ fn strategy_differences(
     parsed_strategies.validate_against_db(db_columns)?;
     Ok(())
 }
-
-#[cfg(test)]
-mod test_builders;
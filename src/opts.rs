@@ -1,4 +1,4 @@
-use crate::compression_type::CompressionType;
+use anonymiser::{CompressionType, OutputFormat};
 use std::path::PathBuf;
 use structopt::StructOpt;
 #[derive(Debug, StructOpt)]
@@ -12,8 +12,10 @@ pub struct Opts {
 #[structopt(name = "anonymiser")]
 pub enum Anonymiser {
     Anonymise {
+        /// Path to the dump to anonymise, or '-' to read it from stdin
         #[structopt(short, long, default_value = "./clear_text_dump.sql")]
         input_file: String,
+        /// Path to write the anonymised dump to, or '-' to write it to stdout
         #[structopt(short, long, default_value = "./output.sql")]
         output_file: String,
         /// Path to the strategy.json file
@@ -24,6 +26,14 @@ pub enum Anonymiser {
         /// or with a compression type e.g. '--compress-output zstd' or '--compress-output gzip'
         #[structopt(short, long)]
         compress_output: Option<Option<CompressionType>>,
+        /// Compression used by the input file, either 'zstd' or 'gzip'. Only needed when it can't
+        /// be detected automatically
+        #[structopt(long)]
+        input_compression: Option<CompressionType>,
+        /// Format to write, either 'plain' SQL, a 'custom' pg_dump archive or a 'directory'
+        /// archive. Defaults to the format of the input
+        #[structopt(short = "F", long)]
+        output_format: Option<OutputFormat>,
         /// Does not transform PotentiallPii data types
         #[structopt(long)]
         allow_potential_pii: bool,
@@ -33,6 +43,30 @@ pub enum Anonymiser {
         /// Modifies the "Scramble" transformer to use an underscore for all replaced non-whitespace characters
         #[structopt(long)]
         scramble_blank: bool,
+        /// Secret key used to derive fake values from the original values, so the same input
+        /// always gets the same replacement across tables, runs and machines
+        #[structopt(long, env = "ANONYMISER_DETERMINISTIC_KEY", hide_env_values = true)]
+        deterministic_key: Option<String>,
+        /// Seed for the random number generator, two runs over the same dump with the same seed
+        /// give identical output
+        #[structopt(long)]
+        seed: Option<u64>,
+        /// Follows foreign keys from the tables with filters, keeping the rows they refer to and
+        /// dropping the rows that refer to filtered out ones, so the output restores cleanly.
+        /// Only works on plain dumps read from a file
+        #[structopt(long)]
+        subset: bool,
+        /// Number of threads to transform table data on, defaults to one per core
+        #[structopt(short = "j", long)]
+        threads: Option<usize>,
+        /// Directory to write the rows of tables with shuffled columns to once they don't fit in
+        /// memory, defaults to the system temp directory
+        #[structopt(long, parse(from_os_str))]
+        spill_dir: Option<PathBuf>,
+        /// Keeps the rows of tables with shuffled columns in memory, however big they are,
+        /// rather than writing them to disk
+        #[structopt(long, conflicts_with = "spill-dir")]
+        no_spill: bool,
     },
 
     /// Creates a CSV file of PII or PotentialPII fields
@@ -0,0 +1,21 @@
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Plain,
+    Custom,
+    Directory,
+}
+type ParseError = &'static str;
+
+impl FromStr for OutputFormat {
+    type Err = ParseError;
+    fn from_str(output_format: &str) -> Result<Self, Self::Err> {
+        match output_format {
+            "plain" | "p" => Ok(OutputFormat::Plain),
+            "custom" | "c" => Ok(OutputFormat::Custom),
+            "directory" | "d" => Ok(OutputFormat::Directory),
+            _ => Err("Could not parse output format"),
+        }
+    }
+}
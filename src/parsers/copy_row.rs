@@ -1,3 +1,4 @@
+use crate::errors::AnonymiserError;
 use crate::parsers::sanitiser;
 use crate::parsers::strategies::Strategies;
 use crate::parsers::strategies::TableStrategy;
@@ -17,7 +18,10 @@ pub enum TableTransformers {
     Truncator,
 }
 
-pub fn parse(copy_row: &str, strategies: &Strategies) -> CurrentTableTransforms {
+pub fn parse(
+    copy_row: &str,
+    strategies: &Strategies,
+) -> Result<CurrentTableTransforms, AnonymiserError> {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"COPY (?P<table>.*) \((?P<columns>.*)\)").unwrap();
     }
@@ -30,36 +34,40 @@ pub fn parse(copy_row: &str, strategies: &Strategies) -> CurrentTableTransforms
                 get_current_table_information(table, unsplit_columns, strategies)
             }
 
-            (_, _) => panic!("Invalid Copy row format: {:?}", copy_row),
+            (_, _) => Err(invalid_copy_row(copy_row)),
         }
     } else {
-        panic!("Invalid Copy row format: {:?}", copy_row);
+        Err(invalid_copy_row(copy_row))
     }
 }
 
+fn invalid_copy_row(copy_row: &str) -> AnonymiserError {
+    AnonymiserError::invalid_dump(format!("Invalid Copy row format: {:?}", copy_row))
+}
+
 fn get_current_table_information(
     table: &str,
     unsplit_columns: &str,
     strategies: &Strategies,
-) -> CurrentTableTransforms {
+) -> Result<CurrentTableTransforms, AnonymiserError> {
     let table_name = sanitiser::dequote_column_or_table_name_data(table);
     let column_name_list: Vec<String> = unsplit_columns
         .split(", ")
         .map(sanitiser::dequote_column_or_table_name_data)
         .collect();
-    let table_transformers = table_strategy(strategies, &table_name, &column_name_list);
+    let table_transformers = table_strategy(strategies, &table_name, &column_name_list)?;
 
-    CurrentTableTransforms {
+    Ok(CurrentTableTransforms {
         table_name,
         table_transformers,
-    }
+    })
 }
 
-fn table_strategy(
+pub fn table_strategy(
     strategies: &Strategies,
     table_name: &str,
     column_name_list: &[String],
-) -> TableTransformers {
+) -> Result<TableTransformers, AnonymiserError> {
     let strategies_for_table = strategies.for_table(table_name);
 
     match strategies_for_table {
@@ -67,18 +75,23 @@ fn table_strategy(
             let column_infos = column_name_list
                 .iter()
                 .map(|column_name| match columns_with_names.get(column_name) {
-                    Some(column_info) => column_info.clone(),
-                    None => panic!(
-                        "No transform found for column: {:?} in table: {:?}",
-                        column_name, table_name
-                    ),
+                    Some(column_info) => Ok(column_info.clone()),
+                    None => Err(AnonymiserError::MissingStrategy {
+                        table: table_name.to_string(),
+                        column: Some(column_name.clone()),
+                        line: None,
+                    }),
                 })
-                .collect();
-            TableTransformers::ColumnTransformer(column_infos)
+                .collect::<Result<_, _>>()?;
+            Ok(TableTransformers::ColumnTransformer(column_infos))
         }
 
-        Some(TableStrategy::Truncate) => TableTransformers::Truncator,
-        None => panic!("No transforms found for table: {:?}", table_name),
+        Some(TableStrategy::Truncate) => Ok(TableTransformers::Truncator),
+        None => Err(AnonymiserError::MissingStrategy {
+            table: table_name.to_string(),
+            column: None,
+            line: None,
+        }),
     }
 }
 
@@ -111,11 +124,13 @@ mod tests {
             .iter()
             .map(|column| (column.name.clone(), column.clone()))
             .collect();
-        let strategies = Strategies::new_from("public.users".to_string(), column_infos_with_name);
+        let strategies =
+            Strategies::new_from("public.users".to_string(), column_infos_with_name).unwrap();
         let parsed_copy_row = parse(
             "COPY public.users (id, first_name, last_name) FROM stdin;\n",
             &strategies,
-        );
+        )
+        .unwrap();
 
         let expected = CurrentTableTransforms {
             table_name: "public.users".to_string(),
@@ -135,12 +150,14 @@ mod tests {
         let strategies = Strategies::new_from(
             "public.references".to_string(),
             HashMap::from([("from".to_string(), expected_column.clone())]),
-        );
+        )
+        .unwrap();
 
         let parsed_copy_row = parse(
             "COPY public.\"references\" (\"from\") FROM stdin;\n",
             &strategies,
-        );
+        )
+        .unwrap();
 
         let expected_table_transformers =
             TableTransformers::ColumnTransformer(vec![expected_column]);
@@ -153,40 +170,51 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Invalid Copy row format")]
-    fn panics_if_copy_row_is_not_formatted_correctly() {
+    fn errors_if_copy_row_is_not_formatted_correctly() {
         let expected_transforms = HashMap::from([
             ("id".to_string(), ColumnInfo::builder().build()),
             ("last_name".to_string(), ColumnInfo::builder().build()),
         ]);
-        let strategies = Strategies::new_from("public.users".to_string(), expected_transforms);
-        parse("COPY public.users INTO THE SEA", &strategies);
+        let strategies =
+            Strategies::new_from("public.users".to_string(), expected_transforms).unwrap();
+        let err = parse("COPY public.users INTO THE SEA", &strategies).unwrap_err();
+        assert!(matches!(err, AnonymiserError::InvalidDump { .. }));
+        assert!(err.to_string().starts_with("Invalid Copy row format"));
     }
 
     #[test]
-    #[should_panic(
-        expected = "No transform found for column: \"first_name\" in table: \"public.users\""
-    )]
-    fn panics_if_there_arent_transforms_for_all_columns() {
+    fn errors_if_there_arent_transforms_for_all_columns() {
         let expected_transforms = HashMap::from([
             ("id".to_string(), ColumnInfo::builder().build()),
             ("last_name".to_string(), ColumnInfo::builder().build()),
         ]);
-        let strategies = Strategies::new_from("public.users".to_string(), expected_transforms);
-        parse(
+        let strategies =
+            Strategies::new_from("public.users".to_string(), expected_transforms).unwrap();
+        let err = parse(
             "COPY public.users (id, first_name, last_name) FROM stdin;\n",
             &strategies,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No transform found for column: \"first_name\" in table: \"public.users\""
         );
     }
+
     #[test]
-    #[should_panic(expected = "No transforms found for table: \"public.users\"")]
-    fn panics_if_there_are_no_transforms_for_the_table() {
+    fn errors_if_there_are_no_transforms_for_the_table() {
         let expected_transforms =
             HashMap::from([("id".to_string(), ColumnInfo::builder().build())]);
-        let strategies = Strategies::new_from("public.unrelated".to_string(), expected_transforms);
-        parse(
+        let strategies =
+            Strategies::new_from("public.unrelated".to_string(), expected_transforms).unwrap();
+        let err = parse(
             "COPY public.users (id, first_name, last_name) FROM stdin;\n",
             &strategies,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No transforms found for table: \"public.users\""
         );
     }
 }
@@ -0,0 +1,120 @@
+//! Values in COPY data are written in COPY's text format, where backslashes, newlines, tabs and
+//! carriage returns are escaped with a backslash. Transformers are given (and give back) values
+//! in this format.
+
+/// Converts a value into COPY text
+pub fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Converts COPY text back into the value it represents
+pub fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('b') => result.push('\u{8}'),
+                Some('f') => result.push('\u{c}'),
+                Some('v') => result.push('\u{b}'),
+                Some(other) => result.push(other),
+                None => (),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Removes the escaping Postgres adds to a quoted array element (a backslash before each `"`
+/// and `\`), leaving the element as COPY text like any other value
+pub fn unescape_array_element(element: &str) -> String {
+    let mut result = String::with_capacity(element.len());
+    let mut chars = element.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            // An escaped backslash in COPY text is the array's escape character, so whatever
+            // comes after it is taken literally
+            Some('\\') => match chars.next() {
+                Some('\\') => {
+                    chars.next();
+                    result.push_str("\\\\");
+                }
+                Some(escaped) => result.push(escaped),
+                None => (),
+            },
+            Some(copy_escaped) => {
+                result.push('\\');
+                result.push(copy_escaped);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Escapes COPY text so it can be written as a quoted array element
+pub fn escape_array_element(element: &str) -> String {
+    let mut result = String::with_capacity(element.len());
+    let mut chars = element.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => result.push_str("\\\\\""),
+            '\\' => match chars.next() {
+                Some('\\') => result.push_str("\\\\\\\\"),
+                Some(copy_escaped) => {
+                    result.push('\\');
+                    result.push(copy_escaped);
+                }
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_and_unescapes_copy_text() {
+        let value = "line one\nline\ttwo \\ three";
+        let escaped = escape(value);
+        assert_eq!(escaped, "line one\\nline\\ttwo \\\\ three");
+        assert_eq!(unescape(&escaped), value);
+    }
+
+    #[test]
+    fn unescapes_quotes_and_backslashes_in_array_elements() {
+        // The array element `say \"hi\" \\ bye\nnow` as it appears in COPY text
+        let element = r#"say \\"hi\\" \\\\ bye\nnow"#;
+        assert_eq!(unescape_array_element(element), r#"say "hi" \\ bye\nnow"#);
+    }
+
+    #[test]
+    fn array_element_escaping_round_trips() {
+        let element = r#"{\\"sender\\": \\"pa\\\\blo\\"}\n"#;
+        let unescaped = unescape_array_element(element);
+        assert_eq!(unescaped, r#"{"sender": "pa\\blo"}\n"#);
+        assert_eq!(escape_array_element(&unescaped), element);
+    }
+}
@@ -1,17 +1,22 @@
+use crate::errors::AnonymiserError;
 use crate::parsers::sanitiser;
+
 pub fn is_create_row(line: &str) -> bool {
     (line.starts_with("CREATE TABLE ") || line.starts_with("CREATE UNLOGGED TABLE "))
         && line.ends_with('(')
 }
-pub fn parse(line: &str) -> String {
+pub fn parse(line: &str) -> Result<String, AnonymiserError> {
     let result = line
         .strip_prefix("CREATE TABLE ")
         .or_else(|| line.strip_prefix("CREATE UNLOGGED TABLE "))
         .and_then(|s| s.strip_suffix(" ("));
 
     match result {
-        None => panic!("Create table string doesn't look right??? \"{}\"", line),
-        Some(name) => sanitiser::dequote_column_or_table_name_data(name),
+        None => Err(AnonymiserError::invalid_dump(format!(
+            "Create table string doesn't look right??? \"{}\"",
+            line
+        ))),
+        Some(name) => Ok(sanitiser::dequote_column_or_table_name_data(name)),
     }
 }
 
@@ -43,14 +48,16 @@ mod tests {
     #[test]
     fn create_table_row_is_and_parsed() {
         let create_row = "CREATE TABLE public.candidate_details (";
-        let table_name = parse(create_row);
+        let table_name = parse(create_row).unwrap();
         assert_eq!(table_name, "public.candidate_details".to_string());
     }
 
     #[test]
-    #[should_panic(expected = "Create table string doesn't look right")]
-    fn panics_on_invalid_input() {
+    fn errors_on_invalid_input() {
         let create_row = "CREATE NOTHING public.candidate_details (";
-        parse(create_row);
+        let err = parse(create_row).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Create table string doesn't look right"));
     }
 }
@@ -0,0 +1,335 @@
+//! Exact decimal arithmetic on numbers in the format Postgres writes `integer` and `numeric`
+//! values in, so transforming them doesn't pick up floating point errors.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// `mantissa / 10^scale`, e.g. `-12.50` is a mantissa of -1250 with a scale of 2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// To the nearest, with halves rounded away from zero
+    Nearest,
+    /// Towards negative infinity
+    Down,
+}
+
+/// More digits than an i128 can always hold aren't supported
+const MAX_DIGITS: usize = 38;
+
+impl Decimal {
+    pub fn is_positive(&self) -> bool {
+        self.mantissa > 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    /// The number of digits after the decimal point
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn neg(&self) -> Decimal {
+        Decimal {
+            mantissa: -self.mantissa,
+            scale: self.scale,
+        }
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal {
+            mantissa: self.mantissa.abs(),
+            scale: self.scale,
+        }
+    }
+
+    /// The largest number with `precision` digits, `scale` of them after the decimal point, i.e.
+    /// the largest a `numeric(precision, scale)` can hold
+    pub fn largest(precision: u32, scale: u32) -> Option<Decimal> {
+        Some(Decimal {
+            mantissa: 10i128.checked_pow(precision)? - 1,
+            scale,
+        })
+    }
+
+    pub fn from_i64(value: i64) -> Decimal {
+        Decimal {
+            mantissa: value.into(),
+            scale: 0,
+        }
+    }
+
+    /// `value` rounded to `scale` digits after the decimal point, or None if it isn't a finite
+    /// number or is too large
+    pub fn from_f64(value: f64, scale: u32) -> Option<Decimal> {
+        let scaled = (value * 10f64.powi(scale as i32)).round();
+        (scaled.is_finite() && scaled.abs() < 1e38).then_some(Decimal {
+            mantissa: scaled as i128,
+            scale,
+        })
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        Some(Decimal {
+            mantissa: self
+                .with_scale(scale)?
+                .mantissa
+                .checked_add(other.with_scale(scale)?.mantissa)?,
+            scale,
+        })
+    }
+
+    /// Compares the numbers by value, so `2.50` is equal to `2.5`. The whole parts are compared
+    /// first, so neither number has to be rescaled to a size that might not fit
+    pub fn compare(&self, other: &Decimal) -> Ordering {
+        let whole = |d: &Decimal| d.mantissa / 10i128.pow(d.scale);
+        let scale = self.scale.max(other.scale);
+        let fraction =
+            |d: &Decimal| (d.mantissa % 10i128.pow(d.scale)) * 10i128.pow(scale - d.scale);
+        whole(self)
+            .cmp(&whole(other))
+            .then_with(|| fraction(self).cmp(&fraction(other)))
+    }
+
+    /// The number rounded to `scale` digits after the decimal point, with halves rounded away
+    /// from zero, or None if it doesn't fit
+    pub fn round_to_scale(&self, scale: u32) -> Option<Decimal> {
+        if scale >= self.scale {
+            return self.with_scale(scale);
+        }
+        let divisor = 10i128.pow(self.scale - scale);
+        let quotient = self.mantissa / divisor;
+        let remainder = self.mantissa % divisor;
+        let mantissa = match remainder.abs() * 2 >= divisor {
+            true => quotient + self.mantissa.signum(),
+            false => quotient,
+        };
+        Some(Decimal { mantissa, scale })
+    }
+
+    /// The same number with `scale` digits after the decimal point, or None if that would lose
+    /// digits that aren't 0 (or not fit)
+    pub fn with_scale(&self, scale: u32) -> Option<Decimal> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Some(*self),
+            Ordering::Greater => Some(Decimal {
+                mantissa: self
+                    .mantissa
+                    .checked_mul(10i128.checked_pow(scale - self.scale)?)?,
+                scale,
+            }),
+            Ordering::Less => {
+                let divisor = 10i128.pow(self.scale - scale);
+                (self.mantissa % divisor == 0).then(|| Decimal {
+                    mantissa: self.mantissa / divisor,
+                    scale,
+                })
+            }
+        }
+    }
+
+    /// The multiple of `step` nearest to (or below) this number, with the larger of their scales
+    pub fn round_to_multiple(&self, step: &Decimal, rounding: Rounding) -> Option<Decimal> {
+        let scale = self.scale.max(step.scale);
+        let value = self.with_scale(scale)?.mantissa;
+        let step = step.with_scale(scale)?.mantissa.checked_abs()?;
+        if step == 0 {
+            return None;
+        }
+
+        let quotient = value.div_euclid(step);
+        let remainder = value.rem_euclid(step);
+        let multiple = match rounding {
+            Rounding::Down => quotient,
+            // Halves go away from zero, which for a negative value is rounding down
+            Rounding::Nearest => match (remainder * 2).cmp(&step) {
+                Ordering::Greater => quotient + 1,
+                Ordering::Equal if value >= 0 => quotient + 1,
+                _ => quotient,
+            },
+        };
+        Some(Decimal {
+            mantissa: multiple.checked_mul(step)?,
+            scale,
+        })
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid number found: \"{}\"", text);
+
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if (whole.is_empty() && fraction.is_empty())
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let digits = format!("{}{}", whole.trim_start_matches('0'), fraction);
+        if digits.len() > MAX_DIGITS {
+            return Err(format!(
+                "{}. Numbers with more than {} digits aren't supported",
+                invalid(),
+                MAX_DIGITS
+            ));
+        }
+        let magnitude = match digits.is_empty() {
+            true => 0,
+            false => digits.parse::<i128>().map_err(|_| invalid())?,
+        };
+
+        Ok(Decimal {
+            mantissa: if negative { -magnitude } else { magnitude },
+            scale: fraction.len() as u32,
+        })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+
+        if self.mantissa < 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", whole)?;
+        if scale > 0 {
+            write!(f, ".{}", fraction)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_displays_numbers_exactly() {
+        for number in [
+            "0",
+            "-12.50",
+            "0.001",
+            "123456789012345678901234567890.12345678",
+        ] {
+            assert_eq!(decimal(number).to_string(), number);
+        }
+        assert_eq!(decimal("+007").to_string(), "7");
+        assert_eq!(decimal("-.5").to_string(), "-0.5");
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for text in ["", "-", "1e5", "NaN", "Infinity", "1.2.3", "12a"] {
+            assert!(text.parse::<Decimal>().is_err(), "{}", text);
+        }
+        assert!("1".repeat(39).parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_multiple() {
+        let thousand = decimal("1000");
+        assert_eq!(
+            decimal("52499.99").round_to_multiple(&thousand, Rounding::Nearest),
+            Some(decimal("52000.00"))
+        );
+        assert_eq!(
+            decimal("52500").round_to_multiple(&thousand, Rounding::Nearest),
+            Some(decimal("53000"))
+        );
+        assert_eq!(
+            decimal("-52500").round_to_multiple(&thousand, Rounding::Nearest),
+            Some(decimal("-53000"))
+        );
+        assert_eq!(
+            decimal("12.3456").round_to_multiple(&decimal("0.05"), Rounding::Nearest),
+            Some(decimal("12.3500"))
+        );
+    }
+
+    #[test]
+    fn rounds_down_to_a_multiple() {
+        let five = decimal("5");
+        assert_eq!(
+            decimal("39").round_to_multiple(&five, Rounding::Down),
+            Some(decimal("35"))
+        );
+        assert_eq!(
+            decimal("-1").round_to_multiple(&five, Rounding::Down),
+            Some(decimal("-5"))
+        );
+        assert_eq!(
+            decimal("1").round_to_multiple(&decimal("0"), Rounding::Down),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_scale_only_without_losing_digits() {
+        assert_eq!(decimal("12.50").with_scale(1), Some(decimal("12.5")));
+        assert_eq!(decimal("12.5").with_scale(3), Some(decimal("12.500")));
+        assert_eq!(decimal("12.55").with_scale(1), None);
+    }
+
+    #[test]
+    fn rounds_to_a_scale_with_halves_away_from_zero() {
+        assert_eq!(decimal("12.345").round_to_scale(2), Some(decimal("12.35")));
+        assert_eq!(
+            decimal("-12.345").round_to_scale(2),
+            Some(decimal("-12.35"))
+        );
+        assert_eq!(decimal("12.344").round_to_scale(0), Some(decimal("12")));
+        assert_eq!(decimal("12.3").round_to_scale(2), Some(decimal("12.30")));
+    }
+
+    #[test]
+    fn adds_numbers_with_different_scales() {
+        assert_eq!(
+            decimal("12.5").checked_add(&decimal("-0.75")),
+            Some(decimal("11.75"))
+        );
+        assert_eq!(Decimal::from_f64(-0.125, 2), Some(decimal("-0.13")));
+        assert_eq!(Decimal::from_f64(f64::NAN, 2), None);
+    }
+
+    #[test]
+    fn compares_numbers_by_value() {
+        let compare = |a: &str, b: &str| decimal(a).compare(&decimal(b));
+        assert_eq!(compare("-0.5", "0.25"), Ordering::Less);
+        assert_eq!(compare("-1.5", "-1.25"), Ordering::Less);
+        assert_eq!(compare("2", "1.999"), Ordering::Greater);
+        assert_eq!(compare("2.50", "2.5"), Ordering::Equal);
+        assert_eq!(compare(&"9".repeat(38), "0.5"), Ordering::Greater);
+        assert_eq!(Decimal::largest(5, 2), Some(decimal("999.99")));
+    }
+}
@@ -0,0 +1,875 @@
+//! Filters picking which of a table's rows are kept, written like a SQL `WHERE` clause, e.g.
+//! `created_at >= now() - interval '90 days' AND tenant_id IN (1, 2)`.
+//!
+//! They're checked against the values in the dump, before they're transformed. Like SQL, a
+//! comparison with a NULL is neither true nor false, and only rows the filter is true for are
+//! kept.
+
+use crate::parsers::copy_text;
+use crate::parsers::decimal::Decimal;
+use crate::parsers::types::SubType;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, Utc};
+use itertools::Itertools;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    condition: Condition,
+    /// When the filter was parsed, so `now()` is the same for every row
+    now: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Condition {
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Compare(Operand, Comparison, Operand),
+    IsNull {
+        operand: Operand,
+        negated: bool,
+    },
+    In {
+        operand: Operand,
+        list: Vec<Operand>,
+        negated: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Column(String),
+    Text(String),
+    Number(Decimal),
+    Boolean(bool),
+    Time(NaiveDateTime),
+    Now,
+    CurrentDate,
+    Shifted(Box<Operand>, Interval),
+}
+
+/// An `interval`, already negated if it's being taken away
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Interval {
+    months: i64,
+    seconds: i64,
+}
+
+/// A value to compare, from the row or the filter
+#[derive(Debug, PartialEq)]
+enum Value<'a> {
+    Null,
+    Text(Cow<'a, str>),
+    Number(Decimal),
+    Boolean(bool),
+    Time(NaiveDateTime),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    QuotedName(String),
+    Text(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 12] = [
+    "<=", ">=", "<>", "!=", "=", "<", ">", "(", ")", ",", "+", "-",
+];
+
+/// Words that can't be column names unless they're double quoted
+const KEYWORDS: [&str; 10] = [
+    "and",
+    "or",
+    "not",
+    "in",
+    "is",
+    "null",
+    "true",
+    "false",
+    "current_date",
+    "current_timestamp",
+];
+
+/// Parses a filter, with `now()` as the current time in UTC
+pub fn parse(filter: &str) -> Result<Filter, String> {
+    parse_at(filter, Utc::now().naive_utc())
+}
+
+fn parse_at(filter: &str, now: NaiveDateTime) -> Result<Filter, String> {
+    let invalid = |reason: String| format!("Invalid filter \"{}\": {}", filter, reason);
+
+    let tokens = tokenise(filter).map_err(invalid)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+    };
+    let condition = parser.or().map_err(invalid)?;
+    if let Some(token) = parser.peek() {
+        return Err(invalid(format!(
+            "unexpected {} after the end of the condition",
+            describe(token)
+        )));
+    }
+    Ok(Filter { condition, now })
+}
+
+impl Filter {
+    /// The columns the filter uses, in the order they're first used
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        condition_columns(&self.condition, &mut columns);
+        columns.into_iter().unique().collect()
+    }
+
+    /// Whether a row is kept, given the COPY text `value_of` gives for each column, along with
+    /// its type if it's known
+    pub fn matches<'a>(
+        &'a self,
+        value_of: impl Fn(&str) -> Option<(&'a str, Option<&'a SubType>)>,
+    ) -> Result<bool, String> {
+        Ok(self.evaluate(&self.condition, &value_of)? == Some(true))
+    }
+
+    /// The condition's value, which is None when it's unknown because of a NULL
+    fn evaluate<'a>(
+        &'a self,
+        condition: &'a Condition,
+        value_of: &impl Fn(&str) -> Option<(&'a str, Option<&'a SubType>)>,
+    ) -> Result<Option<bool>, String> {
+        match condition {
+            Condition::And(left, right) => match self.evaluate(left, value_of)? {
+                Some(false) => Ok(Some(false)),
+                left => Ok(match (left, self.evaluate(right, value_of)?) {
+                    (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                }),
+            },
+            Condition::Or(left, right) => match self.evaluate(left, value_of)? {
+                Some(true) => Ok(Some(true)),
+                left => Ok(match (left, self.evaluate(right, value_of)?) {
+                    (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                }),
+            },
+            Condition::Not(condition) => Ok(self.evaluate(condition, value_of)?.map(|is| !is)),
+            Condition::Compare(left, comparison, right) => {
+                let left = self.value(left, value_of)?;
+                let right = self.value(right, value_of)?;
+                Ok(compare(&left, &right)?.map(|ordering| comparison.holds(ordering)))
+            }
+            Condition::IsNull { operand, negated } => {
+                let is_null = self.value(operand, value_of)? == Value::Null;
+                Ok(Some(is_null != *negated))
+            }
+            Condition::In {
+                operand,
+                list,
+                negated,
+            } => {
+                let value = self.value(operand, value_of)?;
+                let mut found = Some(false);
+                for item in list {
+                    match compare(&value, &self.value(item, value_of)?)? {
+                        Some(Ordering::Equal) => {
+                            found = Some(true);
+                            break;
+                        }
+                        Some(_) => (),
+                        None => found = None,
+                    }
+                }
+                Ok(found.map(|found| found != *negated))
+            }
+        }
+    }
+
+    fn value<'a>(
+        &'a self,
+        operand: &'a Operand,
+        value_of: &impl Fn(&str) -> Option<(&'a str, Option<&'a SubType>)>,
+    ) -> Result<Value<'a>, String> {
+        match operand {
+            Operand::Column(column) => {
+                let (value, sub_type) = value_of(column)
+                    .ok_or_else(|| format!("'{}' in the filter isn't in the row", column))?;
+                Ok(column_value(value, sub_type))
+            }
+            Operand::Text(text) => Ok(Value::Text(Cow::Borrowed(text))),
+            Operand::Number(number) => Ok(Value::Number(*number)),
+            Operand::Boolean(boolean) => Ok(Value::Boolean(*boolean)),
+            Operand::Time(time) => Ok(Value::Time(*time)),
+            Operand::Now => Ok(Value::Time(self.now)),
+            Operand::CurrentDate => Ok(Value::Time(self.now.date().and_time(Default::default()))),
+            Operand::Shifted(operand, interval) => match self.value(operand, value_of)? {
+                Value::Null => Ok(Value::Null),
+                Value::Time(time) => shift(time, interval).map(Value::Time),
+                Value::Text(text) => match parse_time(&text) {
+                    Some(time) => shift(time, interval).map(Value::Time),
+                    None => Err(format!(
+                        "'{}' isn't a date, so an interval can't be added to it",
+                        text
+                    )),
+                },
+                other => Err(format!(
+                    "An interval can only be added to a date, not a {}",
+                    kind(&other)
+                )),
+            },
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+fn condition_columns<'a>(condition: &'a Condition, columns: &mut Vec<&'a str>) {
+    match condition {
+        Condition::And(left, right) | Condition::Or(left, right) => {
+            condition_columns(left, columns);
+            condition_columns(right, columns);
+        }
+        Condition::Not(condition) => condition_columns(condition, columns),
+        Condition::Compare(left, _, right) => {
+            operand_columns(left, columns);
+            operand_columns(right, columns);
+        }
+        Condition::IsNull { operand, .. } => operand_columns(operand, columns),
+        Condition::In { operand, list, .. } => {
+            operand_columns(operand, columns);
+            for item in list {
+                operand_columns(item, columns);
+            }
+        }
+    }
+}
+
+fn operand_columns<'a>(operand: &'a Operand, columns: &mut Vec<&'a str>) {
+    match operand {
+        Operand::Column(column) => columns.push(column),
+        Operand::Shifted(operand, _) => operand_columns(operand, columns),
+        _ => (),
+    }
+}
+
+/// A value from the row, as the kind of value its column's type holds. Anything that doesn't
+/// parse as that kind (like a `NaN` numeric) is compared as text
+fn column_value<'a>(value: &'a str, sub_type: Option<&SubType>) -> Value<'a> {
+    if value == "\\N" {
+        return Value::Null;
+    }
+    let typed = match sub_type {
+        Some(SubType::Integer | SubType::BigInt | SubType::SmallInt | SubType::Numeric { .. }) => {
+            value.parse().ok().map(Value::Number)
+        }
+        Some(SubType::Boolean) => parse_boolean(value).map(Value::Boolean),
+        Some(SubType::Date | SubType::Timestamp | SubType::TimestampWithTimeZone) => {
+            parse_time(value).map(Value::Time)
+        }
+        _ => None,
+    };
+    typed.unwrap_or_else(|| match value.contains('\\') {
+        true => Value::Text(Cow::Owned(copy_text::unescape(value))),
+        false => Value::Text(Cow::Borrowed(value)),
+    })
+}
+
+/// Compares two values, or None if either is NULL. Text is compared to a number, boolean or
+/// date by reading it as one, like a quoted literal in SQL
+fn compare(left: &Value, right: &Value) -> Result<Option<Ordering>, String> {
+    let ordering = match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => return Ok(None),
+        (Value::Text(left), Value::Text(right)) => left.cmp(right),
+        (Value::Number(left), Value::Number(right)) => left.compare(right),
+        (Value::Boolean(left), Value::Boolean(right)) => left.cmp(right),
+        (Value::Time(left), Value::Time(right)) => left.cmp(right),
+        (Value::Text(text), other) => return compare(&read_as(text, other)?, other),
+        (other, Value::Text(text)) => return compare(other, &read_as(text, other)?),
+        (left, right) => {
+            return Err(format!(
+                "A {} can't be compared with a {}",
+                kind(left),
+                kind(right)
+            ))
+        }
+    };
+    Ok(Some(ordering))
+}
+
+/// Reads `text` as the same kind of value as `like`
+fn read_as<'a>(text: &str, like: &Value) -> Result<Value<'a>, String> {
+    let value = match like {
+        Value::Number(_) => text.parse().ok().map(Value::Number),
+        Value::Boolean(_) => parse_boolean(text).map(Value::Boolean),
+        Value::Time(_) => parse_time(text).map(Value::Time),
+        Value::Null | Value::Text(_) => None,
+    };
+    value.ok_or_else(|| format!("'{}' can't be compared with a {}", text, kind(like)))
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "NULL",
+        Value::Text(_) => "text value",
+        Value::Number(_) => "number",
+        Value::Boolean(_) => "boolean",
+        Value::Time(_) => "date",
+    }
+}
+
+fn parse_boolean(text: &str) -> Option<bool> {
+    match text.to_lowercase().as_str() {
+        "t" | "true" => Some(true),
+        "f" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads a date or timestamp in the format Postgres writes them in. Timestamps with a time zone
+/// are converted to UTC
+fn parse_time(text: &str) -> Option<NaiveDateTime> {
+    match text {
+        "infinity" => return Some(NaiveDateTime::MAX),
+        "-infinity" => return Some(NaiveDateTime::MIN),
+        _ => (),
+    }
+    if let Some(before_christ) = text.strip_suffix(" BC") {
+        // There's no year 0, so 1 BC is year 0 in chrono
+        let time = parse_time(before_christ)?;
+        return time.with_year(1 - time.year());
+    }
+
+    ["%Y-%m-%d %H:%M:%S%.f%#z", "%Y-%m-%dT%H:%M:%S%.f%#z"]
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(text, format).ok())
+        .map(|time| time.naive_utc())
+        .or_else(|| {
+            ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(Default::default()))
+        })
+}
+
+/// Infinite dates stay infinite, as they do in Postgres
+fn shift(time: NaiveDateTime, interval: &Interval) -> Result<NaiveDateTime, String> {
+    if time == NaiveDateTime::MAX || time == NaiveDateTime::MIN {
+        return Ok(time);
+    }
+    let months = Months::new(interval.months.unsigned_abs() as u32);
+    let shifted = match interval.months < 0 {
+        true => time.checked_sub_months(months),
+        false => time.checked_add_months(months),
+    };
+    shifted
+        .and_then(|shifted| shifted.checked_add_signed(Duration::seconds(interval.seconds)))
+        .ok_or_else(|| format!("Shifting {} by the interval is out of range", time))
+}
+
+/// Reads an interval like `'90 days'` or `'1 year 6 months'`
+fn parse_interval(text: &str) -> Result<Interval, String> {
+    let invalid = || {
+        format!(
+            "invalid interval '{}', expected a number and unit, e.g. '90 days'",
+            text
+        )
+    };
+
+    let mut interval = Interval {
+        months: 0,
+        seconds: 0,
+    };
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    if parts.is_empty() || !parts.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    for (amount, unit) in parts.iter().tuples() {
+        let amount: i64 = amount.parse().map_err(|_| invalid())?;
+        let (months, seconds) = match unit.to_lowercase().trim_end_matches('s') {
+            "year" => (12, 0),
+            "month" | "mon" => (1, 0),
+            "week" => (0, 7 * 24 * 60 * 60),
+            "day" => (0, 24 * 60 * 60),
+            "hour" => (0, 60 * 60),
+            "minute" | "min" => (0, 60),
+            "second" | "sec" => (0, 1),
+            _ => return Err(invalid()),
+        };
+        interval.months = amount
+            .checked_mul(months)
+            .and_then(|months| interval.months.checked_add(months))
+            .ok_or_else(invalid)?;
+        interval.seconds = amount
+            .checked_mul(seconds)
+            .and_then(|seconds| interval.seconds.checked_add(seconds))
+            .ok_or_else(invalid)?;
+    }
+    if interval.months.unsigned_abs() > u32::MAX.into() {
+        return Err(invalid());
+    }
+    Ok(interval)
+}
+
+fn tokenise(filter: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = filter.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, length) = if c == '\'' || c == '"' {
+            let (quoted, length) = quoted(rest, c)?;
+            match c {
+                '\'' => (Token::Text(quoted), length),
+                _ => (Token::QuotedName(quoted), length),
+            }
+        } else if c.is_ascii_digit() || c == '.' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            (Token::Number(rest[..length].to_string()), length)
+        } else if c.is_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (Token::Word(rest[..length].to_string()), length)
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            (Token::Symbol(symbol), symbol.len())
+        } else {
+            return Err(format!("unexpected '{}'", c));
+        };
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// The text in quotes at the start of `text`, where a doubled quote is a literal one, and the
+/// length of it with the quotes
+fn quoted(text: &str, quote: char) -> Result<(String, usize), String> {
+    let mut quoted = String::new();
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((index, c)) = chars.next() {
+        if c != quote {
+            quoted.push(c);
+        } else if chars.next_if(|(_, next)| *next == quote).is_some() {
+            quoted.push(quote);
+        } else {
+            return Ok((quoted, index + 1));
+        }
+    }
+    Err(format!("unclosed {}", quote))
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(text) | Token::Number(text) | Token::Text(text) => format!("'{}'", text),
+        Token::QuotedName(name) => format!("'\"{}\"'", name),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+        matches!(token, Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Moves past the next token if it's `keyword`
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = Self::is_keyword(self.peek(), keyword);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// Moves past the next token if it's `symbol`
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(next)) if *next == symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        match self.symbol(symbol) {
+            true => Ok(()),
+            false => Err(format!("expected '{}', {}", symbol, self.found())),
+        }
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(token) => format!("got {}", describe(token)),
+            None => "but the filter ended".to_string(),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.not()?;
+        while self.keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        match self.keyword("not") {
+            true => Ok(Condition::Not(Box::new(self.not()?))),
+            false => self.predicate(),
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Condition, String> {
+        if self.symbol("(") {
+            let condition = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(condition);
+        }
+
+        let operand = self.operand()?;
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            return match self.keyword("null") {
+                true => Ok(Condition::IsNull { operand, negated }),
+                false => Err(format!("expected NULL after IS, {}", self.found())),
+            };
+        }
+        let negated = Self::is_keyword(self.peek(), "not")
+            && Self::is_keyword(self.tokens.get(self.position + 1), "in");
+        if negated {
+            self.position += 1;
+        }
+        if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut list = vec![self.operand()?];
+            while self.symbol(",") {
+                list.push(self.operand()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(Condition::In {
+                operand,
+                list,
+                negated,
+            });
+        }
+
+        let comparison = match self.peek() {
+            Some(Token::Symbol("=")) => Comparison::Equal,
+            Some(Token::Symbol("!=" | "<>")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            // A column on its own, like `is_active`
+            _ => {
+                return Ok(Condition::Compare(
+                    operand,
+                    Comparison::Equal,
+                    Operand::Boolean(true),
+                ))
+            }
+        };
+        self.position += 1;
+        Ok(Condition::Compare(operand, comparison, self.operand()?))
+    }
+
+    /// A value, with any intervals added to or taken away from it
+    fn operand(&mut self) -> Result<Operand, String> {
+        let mut operand = self.term()?;
+        loop {
+            let sign = match () {
+                _ if self.symbol("+") => 1,
+                _ if self.symbol("-") => -1,
+                _ => return Ok(operand),
+            };
+            if !self.keyword("interval") {
+                return Err(format!(
+                    "only an interval can be added to or taken away from a value, e.g. now() - interval '90 days', {}",
+                    self.found()
+                ));
+            }
+            let Some(Token::Text(text)) = self.next() else {
+                return Err("expected the interval in quotes, e.g. interval '90 days'".to_string());
+            };
+            let interval = parse_interval(text)?;
+            operand = Operand::Shifted(
+                Box::new(operand),
+                Interval {
+                    months: sign * interval.months,
+                    seconds: sign * interval.seconds,
+                },
+            );
+        }
+    }
+
+    fn term(&mut self) -> Result<Operand, String> {
+        let found = self.found();
+        let next = self.tokens.get(self.position + 1).cloned();
+        let expected = || format!("expected a column or value, {}", found);
+        let operand = match self.next().cloned() {
+            Some(Token::QuotedName(name)) => Operand::Column(name),
+            Some(Token::Text(text)) => Operand::Text(text),
+            Some(Token::Number(number)) => Operand::Number(number.parse()?),
+            Some(Token::Symbol("-")) => match self.next() {
+                Some(Token::Number(number)) => Operand::Number(number.parse::<Decimal>()?.neg()),
+                _ => return Err(expected()),
+            },
+            Some(Token::Word(word)) => match (word.to_lowercase().as_str(), next) {
+                ("true", _) => Operand::Boolean(true),
+                ("false", _) => Operand::Boolean(false),
+                ("current_date", _) => Operand::CurrentDate,
+                ("current_timestamp", _) => Operand::Now,
+                ("now", Some(Token::Symbol("("))) => {
+                    self.position += 1;
+                    self.expect_symbol(")")?;
+                    Operand::Now
+                }
+                ("date" | "timestamp", Some(Token::Text(text))) => {
+                    self.position += 1;
+                    Operand::Time(
+                        parse_time(&text).ok_or_else(|| format!("invalid {} '{}'", word, text))?,
+                    )
+                }
+                ("null", _) => {
+                    return Err(
+                        "NULL can only be checked for with IS NULL or IS NOT NULL".to_string()
+                    )
+                }
+                (lowercase, _) if KEYWORDS.contains(&lowercase) => return Err(expected()),
+                _ => Operand::Column(word),
+            },
+            _ => return Err(expected()),
+        };
+        Ok(operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 31)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    fn matches(filter: &str, row: &[(&str, &str, SubType)]) -> Result<bool, String> {
+        let row: HashMap<&str, (&str, &SubType)> = row
+            .iter()
+            .map(|(column, value, sub_type)| (*column, (*value, sub_type)))
+            .collect();
+        parse_at(filter, now()).unwrap().matches(|column| {
+            row.get(column)
+                .map(|(value, sub_type)| (*value, Some(*sub_type)))
+        })
+    }
+
+    #[test]
+    fn compares_values_as_their_column_types() {
+        let row = [
+            ("id", "10", SubType::Integer),
+            (
+                "price",
+                "2.50",
+                SubType::Numeric {
+                    precision: None,
+                    scale: None,
+                },
+            ),
+            ("name", "O'Neil", SubType::Text),
+            ("is_active", "t", SubType::Boolean),
+        ];
+
+        assert_eq!(matches("id > 9", &row), Ok(true));
+        assert_eq!(matches("id >= '10' AND id < 11", &row), Ok(true));
+        assert_eq!(matches("price = 2.5", &row), Ok(true));
+        assert_eq!(matches("price <> 2.5", &row), Ok(false));
+        assert_eq!(matches("name = 'O''Neil'", &row), Ok(true));
+        assert_eq!(matches("name < 'P' AND is_active", &row), Ok(true));
+        assert_eq!(matches("is_active = false OR id = -1", &row), Ok(false));
+        assert_eq!(matches("NOT (id = 10 OR id = 11)", &row), Ok(false));
+    }
+
+    #[test]
+    fn checks_lists_and_nulls() {
+        let row = [
+            ("tenant_id", "3", SubType::Integer),
+            ("deleted_at", "\\N", SubType::TimestampWithTimeZone),
+        ];
+
+        assert_eq!(matches("tenant_id IN (1, 2, 3)", &row), Ok(true));
+        assert_eq!(matches("tenant_id not in (1, 2)", &row), Ok(true));
+        assert_eq!(matches("deleted_at IS NULL", &row), Ok(true));
+        assert_eq!(matches("deleted_at IS NOT NULL", &row), Ok(false));
+    }
+
+    #[test]
+    fn comparisons_with_nulls_are_never_true() {
+        let row = [("deleted_at", "\\N", SubType::Date)];
+
+        assert_eq!(matches("deleted_at < now()", &row), Ok(false));
+        assert_eq!(matches("NOT deleted_at < now()", &row), Ok(false));
+        assert_eq!(
+            matches("deleted_at < now() OR deleted_at IS NULL", &row),
+            Ok(true)
+        );
+        assert_eq!(
+            matches("deleted_at NOT IN ('2024-01-01', '2024-01-02')", &row),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn adds_intervals_to_dates() {
+        let recent = [
+            (
+                "created_at",
+                "2024-01-15 09:30:00+01",
+                SubType::TimestampWithTimeZone,
+            ),
+            ("day", "2024-02-29", SubType::Date),
+        ];
+
+        assert_eq!(
+            matches("created_at >= now() - interval '90 days'", &recent),
+            Ok(true)
+        );
+        assert_eq!(
+            matches("created_at >= now() - interval '2 months'", &recent),
+            Ok(false)
+        );
+        assert_eq!(
+            matches("created_at = '2024-01-15 08:30:00'", &recent),
+            Ok(true)
+        );
+        assert_eq!(
+            matches("day + interval '1 year' = date '2025-02-28'", &recent),
+            Ok(true)
+        );
+        assert_eq!(
+            matches(
+                "day > current_date - interval '1 month 2 days' AND day < '2024-03-01'",
+                &recent
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            matches(
+                "day > now() - interval '90 days'",
+                &[("day", "infinity", SubType::Date)]
+            ),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn errors_for_values_that_cannot_be_compared() {
+        let row = [
+            ("id", "10", SubType::Integer),
+            ("created_at", "2024-01-15", SubType::Date),
+        ];
+
+        assert_eq!(
+            matches("id = 'ten'", &row),
+            Err("'ten' can't be compared with a number".to_string())
+        );
+        assert_eq!(
+            matches("created_at > id", &row),
+            Err("A date can't be compared with a number".to_string())
+        );
+        assert_eq!(
+            matches("name = 'Peter'", &row),
+            Err("'name' in the filter isn't in the row".to_string())
+        );
+    }
+
+    #[test]
+    fn lists_each_column_used_once() {
+        let filter =
+            parse("\"from\" = 'x' AND (tenant_id IN (1, 2) OR from_id > tenant_id)").unwrap();
+
+        assert_eq!(filter.columns(), vec!["from", "tenant_id", "from_id"]);
+    }
+
+    #[test]
+    fn errors_for_invalid_filters() {
+        assert_eq!(
+            parse("id = ").unwrap_err(),
+            "Invalid filter \"id = \": expected a column or value, but the filter ended"
+        );
+        assert_eq!(
+            parse("id = 1 id = 2").unwrap_err(),
+            "Invalid filter \"id = 1 id = 2\": unexpected 'id' after the end of the condition"
+        );
+        assert_eq!(
+            parse("created_at > now() - 90").unwrap_err(),
+            "Invalid filter \"created_at > now() - 90\": only an interval can be added to or taken away from a value, e.g. now() - interval '90 days', got '90'"
+        );
+        for invalid in [
+            "(id = 1",
+            "name = 'Peter",
+            "id = NULL",
+            "id IS 1",
+            "id IN ()",
+            "created_at > now() - interval '90 fortnights'",
+            "id = 1 AND",
+            "id = 1 ; DROP TABLE users",
+            "created_at > date 'yesterday'",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Foreign keys from the `ALTER TABLE ... ADD CONSTRAINT ... FOREIGN KEY` statements pg_dump
+//! writes after the data, e.g.
+//!
+//! ```text
+//! ALTER TABLE ONLY public.orders
+//!     ADD CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES public.users(id);
+//! ```
+
+use crate::parsers::sanitiser;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub table_name: String,
+    pub columns: Vec<String>,
+    pub referenced_table_name: String,
+    pub referenced_columns: Vec<String>,
+}
+
+pub fn is_alter_table(line: &str) -> bool {
+    line.starts_with("ALTER TABLE ")
+}
+
+/// Statements pg_dump writes can span several lines, and are complete when a line ends in `;`
+pub fn is_complete(line: &str) -> bool {
+    line.trim_end().ends_with(';')
+}
+
+/// The foreign key a complete `ALTER TABLE` statement adds, if it adds one
+pub fn parse(statement: &str) -> Option<ForeignKey> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r#"^ALTER TABLE (?:ONLY )?(?P<table>(?:"[^"]*"|[^\s"])+)\s+ADD CONSTRAINT (?:"[^"]*"|\S)+ FOREIGN KEY \((?P<columns>[^)]*)\) REFERENCES (?P<referenced_table>(?:"[^"]*"|[^\s"(])+)\s*\((?P<referenced_columns>[^)]*)\)"#
+        )
+        .unwrap();
+    }
+
+    let captures = RE.captures(statement)?;
+    let names = |name: &str| -> Vec<String> {
+        captures[name]
+            .split(',')
+            .map(|column| sanitiser::dequote_column_or_table_name_data(column.trim()))
+            .collect()
+    };
+    Some(ForeignKey {
+        table_name: sanitiser::dequote_column_or_table_name_data(&captures["table"]),
+        columns: names("columns"),
+        referenced_table_name: sanitiser::dequote_column_or_table_name_data(
+            &captures["referenced_table"],
+        ),
+        referenced_columns: names("referenced_columns"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_foreign_key() {
+        let statement = "ALTER TABLE ONLY public.orders\n    ADD CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES public.users(id);\n";
+
+        assert_eq!(
+            parse(statement),
+            Some(ForeignKey {
+                table_name: "public.orders".to_string(),
+                columns: vec!["user_id".to_string()],
+                referenced_table_name: "public.users".to_string(),
+                referenced_columns: vec!["id".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn parses_quoted_names_and_keys_with_several_columns() {
+        let statement = "ALTER TABLE public.\"Order Items\"\n    ADD CONSTRAINT \"Order Items_fkey\" FOREIGN KEY (tenant_id, \"order\") REFERENCES public.\"Orders\"(tenant_id, id) ON DELETE CASCADE;\n";
+
+        assert_eq!(
+            parse(statement),
+            Some(ForeignKey {
+                table_name: "public.Order Items".to_string(),
+                columns: vec!["tenant_id".to_string(), "order".to_string()],
+                referenced_table_name: "public.Orders".to_string(),
+                referenced_columns: vec!["tenant_id".to_string(), "id".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_other_constraints() {
+        let statement =
+            "ALTER TABLE ONLY public.orders\n    ADD CONSTRAINT orders_pkey PRIMARY KEY (id);\n";
+
+        assert_eq!(parse(statement), None);
+        assert_eq!(
+            parse("ALTER TABLE public.orders OWNER TO postgres;\n"),
+            None
+        );
+    }
+}
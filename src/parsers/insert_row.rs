@@ -0,0 +1,634 @@
+use crate::errors::AnonymiserError;
+use crate::parsers::copy_text;
+use crate::parsers::sanitiser;
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Which kind of quoted text (if any) the end of a partial statement is inside of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quote {
+    None,
+    Literal,
+    EscapedLiteral,
+    Identifier,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InsertStatement {
+    pub table_name: String,
+    pub column_names: Option<Vec<String>>,
+    /// The position of every value in the statement, grouped by row
+    pub rows: Vec<Vec<Range<usize>>>,
+}
+
+pub fn is_insert_row(line: &str) -> bool {
+    line.starts_with("INSERT INTO ")
+}
+
+/// Carries on scanning a statement from the given quote state, returning the quote state at the
+/// end of `text`. A statement is complete once its last line ends with `;` outside of any quotes
+pub fn scan(text: &str, mut quote: Quote) -> Quote {
+    let mut chars = text.chars().peekable();
+    let mut previous = ' ';
+    while let Some(c) = chars.next() {
+        quote = match (quote, c) {
+            (Quote::None, '\'') if previous == 'E' || previous == 'e' => Quote::EscapedLiteral,
+            (Quote::None, '\'') => Quote::Literal,
+            (Quote::None, '"') => Quote::Identifier,
+            (Quote::EscapedLiteral, '\\') => {
+                chars.next();
+                Quote::EscapedLiteral
+            }
+            (Quote::Literal | Quote::EscapedLiteral, '\'') if chars.peek() == Some(&'\'') => {
+                chars.next();
+                quote
+            }
+            (Quote::Literal | Quote::EscapedLiteral, '\'') => Quote::None,
+            (Quote::Identifier, '"') => Quote::None,
+            (quote, _) => quote,
+        };
+        previous = c;
+    }
+    quote
+}
+
+pub fn is_complete(line: &str, quote: Quote) -> bool {
+    quote == Quote::None && line.trim_end().ends_with(';')
+}
+
+/// Carries on scanning a line outside of any data from inside the dollar quote tagged `open` (if
+/// any), returning the tag of the dollar quote the line ends inside of. Function bodies are
+/// dollar quoted, so an INSERT in one is part of the function rather than data
+pub fn dollar_quote(line: &str, mut open: Option<String>) -> Option<String> {
+    let mut position = 0;
+    while position < line.len() {
+        let rest = &line[position..];
+        if let Some(tag) = &open {
+            match rest.find(tag.as_str()) {
+                Some(end) => {
+                    position += end + tag.len();
+                    open = None;
+                    continue;
+                }
+                None => return open,
+            }
+        }
+
+        let previous = line[..position].chars().next_back();
+        let c = rest.chars().next().unwrap_or_default();
+        position += match c {
+            '-' if rest.starts_with("--") => return None,
+            '\'' => {
+                let escaped = matches!(previous, Some('E' | 'e'));
+                match find_closing_quote(&rest[1..], escaped) {
+                    Some(end) => end + 2,
+                    None => return None,
+                }
+            }
+            '"' => match rest[1..].find('"') {
+                Some(end) => end + 2,
+                None => return None,
+            },
+            // `$` can be part of an identifier, or the start of a parameter such as `$1`
+            '$' if !previous.is_some_and(is_identifier_char) => match dollar_quote_tag(rest) {
+                Some(tag) => {
+                    open = Some(tag.to_string());
+                    tag.len()
+                }
+                None => 1,
+            },
+            c => c.len_utf8(),
+        };
+    }
+    open
+}
+
+/// The `$tag$` (or `$$`) `text` starts with, if it starts with one
+fn dollar_quote_tag(text: &str) -> Option<&str> {
+    let end = text[1..].find('$')? + 2;
+    let tag = &text[1..end - 1];
+    let valid = !tag.starts_with(|c: char| c.is_ascii_digit())
+        && tag.chars().all(|c| c != '$' && is_identifier_char(c));
+    valid.then(|| &text[..end])
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Finds the table, the optional column list and the position of every value in a complete
+/// INSERT statement, covering both `--inserts` and `--column-inserts` output and multi-row
+/// statements from `--rows-per-insert`
+pub fn parse(statement: &str) -> Result<InsertStatement, AnonymiserError> {
+    let indent = statement.len() - statement.trim_start().len();
+    let mut scanner = Scanner {
+        text: statement,
+        position: indent + "INSERT INTO ".len(),
+    };
+
+    let table_start = scanner.position;
+    scanner.skip_until(|c| c.is_whitespace() || c == '(');
+    let table_name =
+        sanitiser::dequote_column_or_table_name_data(&statement[table_start..scanner.position]);
+
+    scanner.skip_whitespace();
+    let column_names = if scanner.next_is('(') {
+        scanner.position += 1;
+        let columns_start = scanner.position;
+        scanner.skip_until(|c| c == ')');
+        let columns = statement[columns_start..scanner.position]
+            .split(',')
+            .map(|column| sanitiser::dequote_column_or_table_name_data(column.trim()))
+            .collect();
+        scanner.position += 1;
+        scanner.skip_whitespace();
+        Some(columns)
+    } else {
+        None
+    };
+
+    // Skips clauses such as `OVERRIDING SYSTEM VALUE` which pg_dump adds for identity columns
+    match statement[scanner.position..].find("VALUES") {
+        Some(values_start) => scanner.position += values_start + "VALUES".len(),
+        None => return Err(invalid_insert(statement)),
+    }
+
+    let mut rows = Vec::new();
+    loop {
+        scanner.skip_whitespace();
+        if !scanner.next_is('(') {
+            return Err(invalid_insert(statement));
+        }
+        scanner.position += 1;
+
+        let mut row = Vec::new();
+        loop {
+            scanner.skip_whitespace();
+            let value_start = scanner.position;
+            scanner.skip_value();
+            row.push(
+                value_start
+                    ..value_start + statement[value_start..scanner.position].trim_end().len(),
+            );
+            match scanner.advance() {
+                Some(',') => continue,
+                Some(')') => break,
+                _ => return Err(invalid_insert(statement)),
+            }
+        }
+        rows.push(row);
+
+        scanner.skip_whitespace();
+        if scanner.next_is(',') {
+            scanner.position += 1;
+        } else {
+            break;
+        }
+    }
+
+    Ok(InsertStatement {
+        table_name,
+        column_names,
+        rows,
+    })
+}
+
+fn invalid_insert(statement: &str) -> AnonymiserError {
+    AnonymiserError::invalid_dump(format!("Invalid Insert statement format: {:?}", statement))
+}
+
+/// Rebuilds the statement with the values of each row swapped for their replacements, leaving
+/// everything else exactly as it was. `replacements` is given every value in a row, so it can
+/// use the other values in the row to replace each one
+pub fn replace_values<'statement, E>(
+    statement: &'statement str,
+    parsed: &InsertStatement,
+    mut replacements: impl FnMut(&[&'statement str]) -> Result<Vec<Cow<'statement, str>>, E>,
+) -> Result<String, E> {
+    let mut result = String::with_capacity(statement.len());
+    let mut copied_up_to = 0;
+    for row in &parsed.rows {
+        let values: Vec<&str> = row.iter().map(|value| &statement[value.clone()]).collect();
+        for (value, replacement) in row.iter().zip(replacements(&values)?) {
+            result.push_str(&statement[copied_up_to..value.start]);
+            result.push_str(&replacement);
+            copied_up_to = value.end;
+        }
+    }
+    result.push_str(&statement[copied_up_to..]);
+    Ok(result)
+}
+
+/// Rebuilds the statement with only the rows `keep` is true for, or None if it isn't true for
+/// any of them. `keep` is given every value in a row, like `replace_values`' replacements
+pub fn retain_rows<'statement, E>(
+    statement: &'statement str,
+    parsed: &InsertStatement,
+    mut keep: impl FnMut(&[&'statement str]) -> Result<bool, E>,
+) -> Result<Option<Cow<'statement, str>>, E> {
+    let mut kept = Vec::new();
+    for row in &parsed.rows {
+        let values: Vec<&str> = row.iter().map(|value| &statement[value.clone()]).collect();
+        if keep(&values)? {
+            kept.push(row_span(statement, row));
+        }
+    }
+    if kept.len() == parsed.rows.len() {
+        return Ok(Some(Cow::from(statement)));
+    }
+    if kept.is_empty() {
+        return Ok(None);
+    }
+
+    // Some rows were dropped, so there were at least two. They're separated the same way all
+    // through a statement, so the kept ones are joined with whatever came between the first two
+    let first_row = row_span(statement, &parsed.rows[0]);
+    let last_row = row_span(statement, &parsed.rows[parsed.rows.len() - 1]);
+    let separator = &statement[first_row.end..row_span(statement, &parsed.rows[1]).start];
+    let rows = kept.iter().map(|row| &statement[row.clone()]);
+    Ok(Some(Cow::from(format!(
+        "{}{}{}",
+        &statement[..first_row.start],
+        itertools::join(rows, separator),
+        &statement[last_row.end..]
+    ))))
+}
+
+/// The position of a row in the statement, from its `(` to its `)`
+fn row_span(statement: &str, row: &[Range<usize>]) -> Range<usize> {
+    let (Some(first), Some(last)) = (row.first(), row.last()) else {
+        return 0..0;
+    };
+    let start = statement[..first.start].rfind('(').unwrap_or(first.start);
+    let end = statement[last.end..]
+        .find(')')
+        .map_or(last.end, |close| last.end + close + 1);
+    start..end
+}
+
+/// Converts a SQL value into the same text COPY would have used for it, which is what the
+/// transformers work with
+pub fn to_copy_text(value: &str) -> Cow<'_, str> {
+    match Literal::parse(value) {
+        Some(literal) => Cow::from(copy_text::escape(&literal.unescaped())),
+        None if value.eq_ignore_ascii_case("NULL") => Cow::from("\\N"),
+        None => Cow::from(value),
+    }
+}
+
+/// Converts transformed COPY text back into a SQL value, keeping the original value when the
+/// transformer left it unchanged
+pub fn to_sql<'a>(original: &'a str, transformed: &str) -> Cow<'a, str> {
+    if to_copy_text(original) == transformed {
+        return Cow::from(original);
+    }
+    if transformed == "\\N" {
+        return Cow::from("NULL");
+    }
+
+    let unescaped = copy_text::unescape(transformed);
+    match Literal::parse(original) {
+        Some(Literal { prefix, cast, .. }) if prefix.eq_ignore_ascii_case("E") => {
+            Cow::from(format!("E'{}'{}", escape_backslashes(&unescaped), cast))
+        }
+        Some(Literal { prefix, cast, .. }) => Cow::from(format!(
+            "{}'{}'{}",
+            prefix,
+            unescaped.replace('\'', "''"),
+            cast
+        )),
+        None => Cow::from(format!("'{}'", unescaped.replace('\'', "''"))),
+    }
+}
+
+struct Literal<'a> {
+    prefix: &'a str,
+    content: &'a str,
+    cast: &'a str,
+}
+
+impl<'a> Literal<'a> {
+    fn parse(value: &'a str) -> Option<Literal<'a>> {
+        let quote_start = value.find('\'')?;
+        let prefix = &value[..quote_start];
+        if !prefix.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let escaped = prefix.eq_ignore_ascii_case("E");
+        let quote_end = quote_start + 1 + find_closing_quote(&value[quote_start + 1..], escaped)?;
+        Some(Literal {
+            prefix,
+            content: &value[quote_start + 1..quote_end],
+            cast: &value[quote_end + 1..],
+        })
+    }
+
+    fn unescaped(&self) -> Cow<'a, str> {
+        if self.prefix.eq_ignore_ascii_case("E") {
+            Cow::from(unescape_backslashes(self.content))
+        } else if self.content.contains("''") {
+            Cow::from(self.content.replace("''", "'"))
+        } else {
+            Cow::from(self.content)
+        }
+    }
+}
+
+fn find_closing_quote(text: &str, escaped: bool) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if escaped => {
+                chars.next();
+            }
+            '\'' if chars.peek().map(|(_, next)| *next) == Some('\'') => {
+                chars.next();
+            }
+            '\'' => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn unescape_backslashes(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('b') => result.push('\u{8}'),
+                Some('f') => result.push('\u{c}'),
+                Some(other) => result.push(other),
+                None => (),
+            },
+            '\'' => {
+                chars.next();
+                result.push('\'');
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn escape_backslashes(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+struct Scanner<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next_is(&self, expected: char) -> bool {
+        self.peek() == Some(expected)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+    }
+
+    /// Skips to the next matching character which is not inside a quoted identifier
+    fn skip_until(&mut self, matches: impl Fn(char) -> bool) {
+        let mut in_identifier = false;
+        while let Some(c) = self.peek() {
+            if c == '"' {
+                in_identifier = !in_identifier;
+            } else if !in_identifier && matches(c) {
+                return;
+            }
+            self.advance();
+        }
+    }
+
+    /// Skips over a single value, stopping at the `,` or `)` which ends it. Brackets are tracked
+    /// so casts such as `::numeric(10,2)` stay part of the value
+    fn skip_value(&mut self) {
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                ',' | ')' if depth == 0 => return,
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '\'' => {
+                    let escaped = self.text[..self.position].ends_with(['E', 'e']);
+                    self.advance();
+                    self.position = find_closing_quote(&self.text[self.position..], escaped)
+                        .map_or(self.text.len(), |end| self.position + end);
+                }
+                '"' => {
+                    self.advance();
+                    self.skip_until(|c| c == '"');
+                }
+                _ => (),
+            }
+            self.advance();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<'a>(statement: &'a str, parsed: &InsertStatement) -> Vec<Vec<&'a str>> {
+        parsed
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|value| &statement[value.clone()]).collect())
+            .collect()
+    }
+
+    #[test]
+    fn parses_an_insert_without_a_column_list() {
+        let statement = "INSERT INTO public.users VALUES (1, 'a@b.com', NULL, true);\n";
+        let parsed = parse(statement).unwrap();
+
+        assert_eq!(parsed.table_name, "public.users");
+        assert_eq!(parsed.column_names, None);
+        assert_eq!(
+            values(statement, &parsed),
+            vec![vec!["1", "'a@b.com'", "NULL", "true"]]
+        );
+    }
+
+    #[test]
+    fn parses_an_insert_with_a_column_list() {
+        let statement = "INSERT INTO public.\"Users\" (id, \"first name\") VALUES (1, 'Pete');\n";
+        let parsed = parse(statement).unwrap();
+
+        assert_eq!(parsed.table_name, "public.Users");
+        assert_eq!(
+            parsed.column_names,
+            Some(vec!["id".to_string(), "first name".to_string()])
+        );
+        assert_eq!(values(statement, &parsed), vec![vec!["1", "'Pete'"]]);
+    }
+
+    #[test]
+    fn parses_an_indented_insert() {
+        let statement = "    INSERT INTO public.users VALUES (1, 'Pete');\n";
+        let parsed = parse(statement).unwrap();
+
+        assert_eq!(parsed.table_name, "public.users");
+        assert_eq!(values(statement, &parsed), vec![vec!["1", "'Pete'"]]);
+    }
+
+    #[test]
+    fn parses_an_insert_for_identity_columns() {
+        let statement = "INSERT INTO public.extra_data (id, data) OVERRIDING SYSTEM VALUE VALUES (1, 'jank');\n";
+        let parsed = parse(statement).unwrap();
+
+        assert_eq!(
+            parsed.column_names,
+            Some(vec!["id".to_string(), "data".to_string()])
+        );
+        assert_eq!(values(statement, &parsed), vec![vec!["1", "'jank'"]]);
+    }
+
+    #[test]
+    fn parses_a_multi_row_insert() {
+        let statement =
+            "INSERT INTO public.orders VALUES\n\t(1, 'a, b'),\n\t(2, 'it''s (here)');\n";
+        let parsed = parse(statement).unwrap();
+
+        assert_eq!(
+            values(statement, &parsed),
+            vec![vec!["1", "'a, b'"], vec!["2", "'it''s (here)'"]]
+        );
+    }
+
+    #[test]
+    fn keeps_casts_and_escaped_literals_as_part_of_the_value() {
+        let statement = "INSERT INTO public.products VALUES (E'a\\'b,c', '1.50'::numeric(10,2));\n";
+        let parsed = parse(statement).unwrap();
+
+        assert_eq!(
+            values(statement, &parsed),
+            vec![vec!["E'a\\'b,c'", "'1.50'::numeric(10,2)"]]
+        );
+    }
+
+    #[test]
+    fn scan_tracks_quotes_across_lines() {
+        let quote = scan(
+            "INSERT INTO public.users VALUES (1, 'first line",
+            Quote::None,
+        );
+        assert_eq!(quote, Quote::Literal);
+        assert!(!is_complete("ends with a semicolon;", quote));
+
+        let quote = scan("it''s the second line');", quote);
+        assert_eq!(quote, Quote::None);
+        assert!(is_complete("it''s the second line');\n", quote));
+    }
+
+    #[test]
+    fn dollar_quote_tracks_dollar_quoted_strings_across_lines() {
+        assert_eq!(dollar_quote("    AS $$\n", None), Some("$$".to_string()));
+        assert_eq!(
+            dollar_quote(
+                "CREATE FUNCTION f() RETURNS int AS $body$ SELECT $1 $body$;\n",
+                None
+            ),
+            None
+        );
+        assert_eq!(
+            dollar_quote(
+                "    INSERT INTO audit VALUES ('$$');\n",
+                Some("$_$".to_string())
+            ),
+            Some("$_$".to_string())
+        );
+        assert_eq!(dollar_quote("$_$;\n", Some("$_$".to_string())), None);
+        assert_eq!(
+            dollar_quote("COMMENT ON TABLE t IS 'costs $5$';\n", None),
+            None
+        );
+        assert_eq!(dollar_quote("SELECT a$b$ FROM t; -- $$\n", None), None);
+    }
+
+    #[test]
+    fn converts_sql_values_to_copy_text() {
+        assert_eq!(to_copy_text("NULL"), "\\N");
+        assert_eq!(to_copy_text("42"), "42");
+        assert_eq!(to_copy_text("'it''s'"), "it's");
+        assert_eq!(to_copy_text("'a\\b\nc\td'"), "a\\\\b\\nc\\td");
+        assert_eq!(to_copy_text("E'it\\'s\\n'"), "it's\\n");
+        assert_eq!(to_copy_text("'{a,\"b c\"}'"), "{a,\"b c\"}");
+    }
+
+    #[test]
+    fn converts_copy_text_back_to_sql() {
+        assert_eq!(to_sql("42", "42"), "42");
+        assert_eq!(to_sql("'it''s'", "it's"), "'it''s'");
+        assert_eq!(to_sql("'a@b.com'", "o'neil@c.com"), "'o''neil@c.com'");
+        assert_eq!(to_sql("'a'", "line\\none"), "'line\none'");
+        assert_eq!(to_sql("'a'", "\\N"), "NULL");
+        assert_eq!(to_sql("42", "43"), "'43'");
+        assert_eq!(to_sql("E'a'", "it's\\n"), "E'it\\'s\\n'");
+        assert_eq!(to_sql("'1'::numeric", "2"), "'2'::numeric");
+    }
+
+    #[test]
+    fn replaces_values_leaving_everything_else_alone() {
+        let statement = "INSERT INTO public.orders VALUES\n\t(1, 'a'),\n\t(2, 'b');\n";
+        let parsed = parse(statement).unwrap();
+
+        let replaced = replace_values(statement, &parsed, |values| {
+            Ok::<_, AnonymiserError>(vec![Cow::from(values[0]), Cow::from("'x'")])
+        })
+        .unwrap();
+        assert_eq!(
+            replaced,
+            "INSERT INTO public.orders VALUES\n\t(1, 'x'),\n\t(2, 'x');\n"
+        );
+    }
+
+    #[test]
+    fn retains_only_the_rows_kept() {
+        let statement = "INSERT INTO public.orders VALUES\n\t(1, 'a'),\n\t(2, 'b'),\n\t(3, 'c');\n";
+        let parsed = parse(statement).unwrap();
+        let retain = |kept: &[&str]| {
+            retain_rows(statement, &parsed, |values| {
+                Ok::<_, AnonymiserError>(kept.contains(&values[0]))
+            })
+            .unwrap()
+        };
+
+        assert_eq!(
+            retain(&["1", "3"]),
+            Some(Cow::from(
+                "INSERT INTO public.orders VALUES\n\t(1, 'a'),\n\t(3, 'c');\n"
+            ))
+        );
+        assert_eq!(
+            retain(&["2"]),
+            Some(Cow::from("INSERT INTO public.orders VALUES\n\t(2, 'b');\n"))
+        );
+        assert_eq!(retain(&["1", "2", "3"]), Some(Cow::from(statement)));
+        assert_eq!(retain(&[]), None);
+    }
+}
@@ -0,0 +1,199 @@
+use serde_json::Value;
+
+/// A JSONPath expression, limited to what's needed to pick out the values to transform: `$`
+/// followed by any number of `.key`, `['key']`, `[index]`, `.*` or `[*]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+pub fn parse(path: &str) -> Result<JsonPath, String> {
+    let invalid = |reason: &str| format!("Invalid JSONPath \"{}\": {}", path, reason);
+
+    let mut rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| invalid("it must start with '$'"))?;
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            return Err(invalid("recursive descent ('..') isn't supported"));
+        } else if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            let key = &after_dot[..end];
+            segments.push(match key {
+                "" => return Err(invalid("a '.' must be followed by a key")),
+                "*" => Segment::Wildcard,
+                key => Segment::Key(key.to_string()),
+            });
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let end = closing_bracket(after_bracket).ok_or_else(|| invalid("unclosed '['"))?;
+            segments.push(
+                bracketed_segment(&after_bracket[..end]).ok_or_else(|| {
+                    invalid("brackets must contain a quoted key, an index or '*'")
+                })?,
+            );
+            rest = &after_bracket[end + 1..];
+        } else {
+            return Err(invalid("expected '.' or '['"));
+        }
+    }
+
+    Ok(JsonPath { segments })
+}
+
+/// Finds the `]` closing a bracket, skipping over any in a quoted key
+fn closing_bracket(text: &str) -> Option<usize> {
+    let quote = text.chars().next().filter(|c| *c == '\'' || *c == '"');
+    match quote {
+        Some(quote) => {
+            let closing_quote = 1 + text[1..].find(quote)?;
+            text[closing_quote..]
+                .find(']')
+                .map(|end| closing_quote + end)
+        }
+        None => text.find(']'),
+    }
+}
+
+fn bracketed_segment(contents: &str) -> Option<Segment> {
+    if contents == "*" {
+        return Some(Segment::Wildcard);
+    }
+    if let Ok(index) = contents.parse::<usize>() {
+        return Some(Segment::Index(index));
+    }
+    ['\'', '"']
+        .into_iter()
+        .find_map(|quote| contents.strip_prefix(quote)?.strip_suffix(quote))
+        .map(|key| Segment::Key(key.to_string()))
+}
+
+impl JsonPath {
+    /// Calls `f` with every value in `json` the path matches. Parts of the path that don't exist
+    /// in `json` match nothing
+    pub fn for_each_match<E>(
+        &self,
+        json: &mut Value,
+        f: &mut dyn FnMut(&mut Value) -> Result<(), E>,
+    ) -> Result<(), E> {
+        for_each_match(&self.segments, json, f)
+    }
+}
+
+fn for_each_match<E>(
+    segments: &[Segment],
+    json: &mut Value,
+    f: &mut dyn FnMut(&mut Value) -> Result<(), E>,
+) -> Result<(), E> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return f(json);
+    };
+
+    match (segment, json) {
+        (Segment::Key(key), Value::Object(object)) => match object.get_mut(key) {
+            Some(child) => for_each_match(rest, child, f),
+            None => Ok(()),
+        },
+        (Segment::Index(index), Value::Array(array)) => match array.get_mut(*index) {
+            Some(child) => for_each_match(rest, child, f),
+            None => Ok(()),
+        },
+        (Segment::Wildcard, Value::Object(object)) => object
+            .values_mut()
+            .try_for_each(|child| for_each_match(rest, child, f)),
+        (Segment::Wildcard, Value::Array(array)) => array
+            .iter_mut()
+            .try_for_each(|child| for_each_match(rest, child, f)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn replace_matches(path: &str, mut json: Value) -> Value {
+        parse(path)
+            .unwrap()
+            .for_each_match::<()>(&mut json, &mut |value| {
+                *value = json!("replaced");
+                Ok(())
+            })
+            .unwrap();
+        json
+    }
+
+    #[test]
+    fn parses_keys_indexes_and_wildcards() {
+        assert_eq!(
+            parse("$.contact['home address'][2].*[*]").unwrap(),
+            JsonPath {
+                segments: vec![
+                    Segment::Key("contact".to_string()),
+                    Segment::Key("home address".to_string()),
+                    Segment::Index(2),
+                    Segment::Wildcard,
+                    Segment::Wildcard,
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn errors_for_unsupported_paths() {
+        assert_eq!(
+            parse("contact.email").unwrap_err(),
+            "Invalid JSONPath \"contact.email\": it must start with '$'"
+        );
+        assert!(parse("$..email").is_err());
+        assert!(parse("$.contact.").is_err());
+        assert!(parse("$.addresses[0").is_err());
+        assert!(parse("$.addresses[?(@.postcode)]").is_err());
+    }
+
+    #[test]
+    fn replaces_a_nested_key() {
+        let json = json!({"contact": {"email": "a@b.com", "name": "Alice"}});
+
+        assert_eq!(
+            replace_matches("$.contact.email", json),
+            json!({"contact": {"email": "replaced", "name": "Alice"}})
+        );
+    }
+
+    #[test]
+    fn replaces_every_array_element_with_a_wildcard() {
+        let json = json!({"addresses": [{"postcode": "NW5 1AA"}, {"postcode": "E1 6AN"}, {}]});
+
+        assert_eq!(
+            replace_matches("$.addresses[*].postcode", json),
+            json!({"addresses": [{"postcode": "replaced"}, {"postcode": "replaced"}, {}]})
+        );
+    }
+
+    #[test]
+    fn matches_nothing_when_the_path_does_not_exist() {
+        let json = json!({"contact": "a@b.com", "addresses": []});
+
+        assert_eq!(
+            replace_matches("$.contact.email", json.clone()),
+            json.clone()
+        );
+        assert_eq!(replace_matches("$.addresses[3]", json.clone()), json);
+    }
+
+    #[test]
+    fn the_root_path_matches_the_whole_value() {
+        assert_eq!(replace_matches("$", json!([1, 2])), json!("replaced"));
+    }
+}
@@ -0,0 +1,287 @@
+//! The locales fake names, addresses, cities, companies and phone numbers can be made up in. The
+//! data comes from [faker](https://github.com/cksac/fake-rs), apart from `en_GB`'s, which is
+//! here as faker doesn't have it.
+
+use crate::parsers::strategy_structs::TransformerType;
+use fake::faker::address::raw::*;
+use fake::faker::company::raw::*;
+use fake::faker::name::raw::*;
+use fake::faker::phone_number::raw::*;
+use fake::locales::{Data, AR_SA, EN, FR_FR, JA_JP, PT_BR, ZH_CN, ZH_TW};
+use fake::Fake;
+use rand::rngs::SmallRng;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    EnUs,
+    EnGb,
+    FrFr,
+    JaJp,
+    PtBr,
+    ZhCn,
+    ZhTw,
+    ArSa,
+}
+
+/// Makes up a value with `$faker` in the data for `$locale`
+macro_rules! fake_in {
+    ($locale:expr, $faker:ident, $rng:expr) => {
+        match $locale {
+            Locale::EnUs => $faker(EN).fake_with_rng::<String, _>($rng),
+            Locale::EnGb => $faker(EN_GB).fake_with_rng::<String, _>($rng),
+            Locale::FrFr => $faker(FR_FR).fake_with_rng::<String, _>($rng),
+            Locale::JaJp => $faker(JA_JP).fake_with_rng::<String, _>($rng),
+            Locale::PtBr => $faker(PT_BR).fake_with_rng::<String, _>($rng),
+            Locale::ZhCn => $faker(ZH_CN).fake_with_rng::<String, _>($rng),
+            Locale::ZhTw => $faker(ZH_TW).fake_with_rng::<String, _>($rng),
+            Locale::ArSa => $faker(AR_SA).fake_with_rng::<String, _>($rng),
+        }
+    };
+}
+
+impl Locale {
+    pub const ALL: [Locale; 8] = [
+        Locale::EnUs,
+        Locale::EnGb,
+        Locale::FrFr,
+        Locale::JaJp,
+        Locale::PtBr,
+        Locale::ZhCn,
+        Locale::ZhTw,
+        Locale::ArSa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Locale::EnUs => "en_US",
+            Locale::EnGb => "en_GB",
+            Locale::FrFr => "fr_FR",
+            Locale::JaJp => "ja_JP",
+            Locale::PtBr => "pt_BR",
+            Locale::ZhCn => "zh_CN",
+            Locale::ZhTw => "zh_TW",
+            Locale::ArSa => "ar_SA",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Locale> {
+        Locale::ALL.into_iter().find(|locale| locale.name() == name)
+    }
+
+    /// Whether there's data for `transformer` in this locale. faker falls back to US English for
+    /// anything a locale doesn't have, which isn't what anyone asking for a locale wants
+    pub fn supports(&self, transformer: &TransformerType) -> bool {
+        let is_name = matches!(
+            transformer,
+            TransformerType::FakeFirstName
+                | TransformerType::FakeLastName
+                | TransformerType::FakeFullName
+        );
+        match self {
+            Locale::EnUs | Locale::EnGb => is_localised(transformer),
+            Locale::FrFr => is_name || *transformer == TransformerType::FakePhoneNumber,
+            Locale::JaJp => {
+                is_name
+                    || matches!(
+                        transformer,
+                        TransformerType::FakeCompanyName | TransformerType::FakePhoneNumber
+                    )
+            }
+            Locale::PtBr => {
+                is_name
+                    || matches!(
+                        transformer,
+                        TransformerType::FakeCompanyName
+                            | TransformerType::FakePhoneNumber
+                            | TransformerType::FakeState
+                            | TransformerType::FakeStreetAddress
+                    )
+            }
+            Locale::ZhCn | Locale::ZhTw | Locale::ArSa => is_name,
+        }
+    }
+
+    pub fn first_name(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, FirstName, rng)
+    }
+
+    pub fn last_name(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, LastName, rng)
+    }
+
+    /// A first and last name, in the order they're written in the locale
+    pub fn full_name(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, Name, rng)
+    }
+
+    pub fn city(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, CityName, rng)
+    }
+
+    /// A state, or a county for `en_GB`
+    pub fn state(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, StateName, rng)
+    }
+
+    pub fn street_address(&self, rng: &mut SmallRng) -> String {
+        let building: String = fake_in!(self, BuildingNumber, rng);
+        let street_name: String = fake_in!(self, StreetName, rng);
+        format!("{} {}", building, street_name)
+    }
+
+    pub fn full_address(&self, rng: &mut SmallRng) -> String {
+        let line_1 = self.street_address(rng);
+        let city_name = self.city(rng);
+        let state = self.state(rng);
+        format!("{}, {}, {}", line_1, city_name, state)
+    }
+
+    pub fn company_name(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, CompanyName, rng)
+    }
+
+    pub fn phone_number(&self, rng: &mut SmallRng) -> String {
+        fake_in!(self, PhoneNumber, rng)
+    }
+}
+
+/// Whether `transformer` makes up values that depend on a locale, so takes a `locale` arg
+pub fn is_localised(transformer: &TransformerType) -> bool {
+    matches!(
+        transformer,
+        TransformerType::FakeCity
+            | TransformerType::FakeCompanyName
+            | TransformerType::FakeFirstName
+            | TransformerType::FakeFullAddress
+            | TransformerType::FakeFullName
+            | TransformerType::FakeLastName
+            | TransformerType::FakePhoneNumber
+            | TransformerType::FakeState
+            | TransformerType::FakeStreetAddress
+    )
+}
+
+/// British English, which shares faker's English names but has its own places and phone numbers
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+struct EN_GB;
+
+impl Data for EN_GB {
+    const ADDRESS_CITY_PREFIX: &'static [&'static str] = &[
+        "Great", "Little", "Upper", "Lower", "North", "South", "East", "West", "Old", "Long",
+    ];
+    const ADDRESS_CITY_SUFFIX: &'static [&'static str] = &[
+        "ton", "ham", "bury", "ford", "field", "ley", "wick", "by", "chester", "mouth", "bridge",
+        "stead", "worth", "minster", "thorpe",
+    ];
+    const ADDRESS_CITY_TPL: &'static str = "{CityName}{CitySuffix}";
+    const ADDRESS_CITY_WITH_PREFIX_TPL: &'static str = "{CityPrefix} {CityName}{CitySuffix}";
+    const ADDRESS_STREET_SUFFIX: &'static [&'static str] = &[
+        "Road", "Street", "Lane", "Avenue", "Close", "Crescent", "Drive", "Gardens", "Grove",
+        "Hill", "Mews", "Place", "Rise", "Row", "Square", "Terrace", "Walk", "Way",
+    ];
+    const ADDRESS_STATE: &'static [&'static str] = &[
+        "Bedfordshire",
+        "Berkshire",
+        "Buckinghamshire",
+        "Cambridgeshire",
+        "Cheshire",
+        "Cornwall",
+        "Cumbria",
+        "Derbyshire",
+        "Devon",
+        "Dorset",
+        "Durham",
+        "East Sussex",
+        "Essex",
+        "Gloucestershire",
+        "Greater London",
+        "Greater Manchester",
+        "Hampshire",
+        "Hertfordshire",
+        "Kent",
+        "Lancashire",
+        "Leicestershire",
+        "Lincolnshire",
+        "Merseyside",
+        "Norfolk",
+        "North Yorkshire",
+        "Northamptonshire",
+        "Northumberland",
+        "Nottinghamshire",
+        "Oxfordshire",
+        "Shropshire",
+        "Somerset",
+        "Staffordshire",
+        "Suffolk",
+        "Surrey",
+        "Tyne and Wear",
+        "Warwickshire",
+        "West Midlands",
+        "West Sussex",
+        "West Yorkshire",
+        "Wiltshire",
+        "Worcestershire",
+    ];
+    const ADDRESS_BUILDING_NUMBER_FORMATS: &'static [&'static str] = &["###", "##", "#"];
+    const COMPANY_SUFFIX: &'static [&'static str] = &["Ltd", "plc", "LLP", "& Co", "Group"];
+    // Ofcom's ranges for use in drama, so none of these belong to anyone
+    // https://www.ofcom.org.uk/phones-telecoms-and-internet/information-for-industry/numbering/numbers-for-drama
+    const PHONE_NUMBER_FORMATS: &'static [&'static str] = &[
+        "07700 900###",
+        "020 7946 0###",
+        "0113 496 0###",
+        "0114 496 0###",
+        "0115 496 0###",
+        "0116 496 0###",
+        "0117 496 0###",
+        "0118 496 0###",
+        "0121 496 0###",
+        "0131 496 0###",
+        "0141 496 0###",
+        "0151 496 0###",
+        "0161 496 0###",
+        "028 9649 6###",
+        "029 2018 0###",
+    ];
+    const PHONE_CELL_NUMBER_FORMATS: &'static [&'static str] = &["07700 900###"];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::rng;
+
+    #[test]
+    fn every_locale_has_a_name_it_can_be_found_by() {
+        for locale in Locale::ALL {
+            assert_eq!(Locale::from_name(locale.name()), Some(locale));
+        }
+        assert_eq!(Locale::from_name("de_DE"), None);
+    }
+
+    #[test]
+    fn only_supports_transformers_the_locale_has_data_for() {
+        assert!(Locale::EnGb.supports(&TransformerType::FakeState));
+        assert!(Locale::FrFr.supports(&TransformerType::FakeFullName));
+        assert!(!Locale::FrFr.supports(&TransformerType::FakeCity));
+        assert!(!Locale::ZhCn.supports(&TransformerType::FakePhoneNumber));
+        assert!(!Locale::EnUs.supports(&TransformerType::FakeEmail));
+    }
+
+    #[test]
+    fn en_gb_has_uk_counties_and_phone_numbers() {
+        let mut rng = rng::get();
+        for _ in 0..20 {
+            let county = Locale::EnGb.state(&mut rng);
+            assert!(
+                EN_GB::ADDRESS_STATE.contains(&county.as_str()),
+                "{}",
+                county
+            );
+            let phone_number = Locale::EnGb.phone_number(&mut rng);
+            assert!(phone_number.starts_with('0'), "{}", phone_number);
+        }
+    }
+}
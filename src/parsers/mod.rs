@@ -1,8 +1,16 @@
 pub mod copy_row;
+pub mod copy_text;
 pub mod create_row;
 pub mod data_row;
 pub mod db_schema;
+pub mod decimal;
+pub mod filter;
+pub mod foreign_key;
+pub mod insert_row;
+pub mod json_path;
+pub mod locale;
 pub mod national_insurance_number;
+pub mod pii_detectors;
 pub mod rng;
 pub mod row_parser;
 pub mod sanitiser;
@@ -11,5 +19,7 @@ pub mod strategies;
 pub mod strategy_errors;
 pub mod strategy_file;
 pub mod strategy_structs;
+pub mod subset;
+pub mod template;
 pub mod transformer;
 pub mod types;
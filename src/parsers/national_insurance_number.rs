@@ -1,10 +1,12 @@
+use rand::rngs::SmallRng;
 use rand::seq::SliceRandom;
 
-pub fn random() -> String {
-    NATIONAL_INSURANCE_NUMBERS
-        .choose(&mut rand::thread_rng())
-        .unwrap()
-        .to_string()
+/// The format of a UK National Insurance number, allowing for the spaces it's often written with
+/// (e.g. `QQ 12 34 56 C`)
+pub const FORMAT: &str = r"[A-Z]{2} ?\d{2} ?\d{2} ?\d{2} ?[A-D]";
+
+pub fn random(rng: &mut SmallRng) -> String {
+    NATIONAL_INSURANCE_NUMBERS.choose(rng).unwrap().to_string()
 }
 
 pub const NATIONAL_INSURANCE_NUMBERS: [&str; 1001] = [
@@ -1010,3 +1012,18 @@ pub const NATIONAL_INSURANCE_NUMBERS: [&str; 1001] = [
     "UX105422A",
     "OG121560D",
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn every_number_matches_the_format() {
+        let format = Regex::new(&format!("^{}$", FORMAT)).unwrap();
+        assert!(NATIONAL_INSURANCE_NUMBERS
+            .iter()
+            .all(|number| format.is_match(number)));
+        assert!(format.is_match("QQ 12 34 56 C"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Detectors for the kinds of PII that turn up in free text, and fakes to replace each kind with
+//! something that still looks the same.
+
+use crate::parsers::national_insurance_number;
+use lazy_static::lazy_static;
+use rand::{rngs::SmallRng, Rng};
+use regex::Regex;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detector {
+    Url,
+    Email,
+    Iban,
+    CardNumber,
+    NationalInsuranceNumber,
+    Phone,
+    Postcode,
+}
+
+impl Detector {
+    /// Every detector, in the order they claim text. A URL containing an email address is
+    /// replaced as a URL, and a card number isn't mistaken for a phone number
+    pub const ALL: [Detector; 7] = [
+        Detector::Url,
+        Detector::Email,
+        Detector::Iban,
+        Detector::CardNumber,
+        Detector::NationalInsuranceNumber,
+        Detector::Phone,
+        Detector::Postcode,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Detector::Url => "Url",
+            Detector::Email => "Email",
+            Detector::Iban => "Iban",
+            Detector::CardNumber => "CardNumber",
+            Detector::NationalInsuranceNumber => "NationalInsuranceNumber",
+            Detector::Phone => "Phone",
+            Detector::Postcode => "Postcode",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Detector> {
+        Detector::ALL
+            .into_iter()
+            .find(|detector| detector.name() == name)
+    }
+
+    fn regex(&self) -> &'static Regex {
+        lazy_static! {
+            static ref URL: Regex =
+                Regex::new(r#"\b(?:https?://|www\.)[^\s<>"']*[^\s<>"'.,;:!?)]"#).unwrap();
+            static ref EMAIL: Regex = Regex::new(
+                r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b"
+            )
+            .unwrap();
+            static ref IBAN: Regex =
+                Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b").unwrap();
+            static ref CARD_NUMBER: Regex = Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap();
+            static ref NATIONAL_INSURANCE_NUMBER: Regex =
+                Regex::new(&format!(r"\b{}\b", national_insurance_number::FORMAT)).unwrap();
+            static ref PHONE: Regex =
+                Regex::new(r"(?:\+\d{1,3}[ -]?|\b0)\d{2,4}[ -]?\d{3,4}[ -]?\d{3,4}\b").unwrap();
+            static ref POSTCODE: Regex =
+                Regex::new(r"\b[A-Z]{1,2}\d[A-Z\d]? ?\d[A-Z]{2}\b").unwrap();
+        }
+
+        match self {
+            Detector::Url => &URL,
+            Detector::Email => &EMAIL,
+            Detector::Iban => &IBAN,
+            Detector::CardNumber => &CARD_NUMBER,
+            Detector::NationalInsuranceNumber => &NATIONAL_INSURANCE_NUMBER,
+            Detector::Phone => &PHONE,
+            Detector::Postcode => &POSTCODE,
+        }
+    }
+
+    /// Checks what the regex matched, for detectors with a checksum to cut out false positives
+    fn is_valid(&self, text: &str) -> bool {
+        match self {
+            Detector::Iban => iban_checksum(text) == Some(1),
+            Detector::CardNumber => luhn_sum(text).is_multiple_of(10),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Detection {
+    pub detector: Detector,
+    pub range: Range<usize>,
+}
+
+/// Finds the PII in `text` using `detectors`, returning the detections in the order they appear.
+/// Text is only ever claimed by one detector, the first in `Detector::ALL` to match it
+pub fn detect(text: &str, detectors: &[Detector]) -> Vec<Detection> {
+    let mut detections: Vec<Detection> = Vec::new();
+    for detector in Detector::ALL
+        .into_iter()
+        .filter(|detector| detectors.contains(detector))
+    {
+        for found in detector.regex().find_iter(text) {
+            let overlaps = detections.iter().any(|detection| {
+                found.start() < detection.range.end && detection.range.start < found.end()
+            });
+            if !overlaps && detector.is_valid(found.as_str()) {
+                detections.push(Detection {
+                    detector,
+                    range: found.range(),
+                });
+            }
+        }
+    }
+    detections.sort_by_key(|detection| detection.range.start);
+    detections
+}
+
+/// A random URL on a domain reserved for examples
+pub fn fake_url(rng: &mut SmallRng) -> String {
+    let path: String = (0..8)
+        .map(|_| random_letter(rng).to_ascii_lowercase())
+        .collect();
+    format!("https://example.com/{}", path)
+}
+
+/// A random postcode in the same format as a UK one
+pub fn fake_postcode(rng: &mut SmallRng) -> String {
+    format!(
+        "{}{}{} {}{}{}",
+        random_letter(rng),
+        random_letter(rng),
+        random_digit(rng),
+        random_digit(rng),
+        random_letter(rng),
+        random_letter(rng),
+    )
+}
+
+/// A random IBAN for the same country as `original`, the same length and with valid check digits
+pub fn fake_iban(rng: &mut SmallRng, original: &str) -> String {
+    let country = &original[..2];
+    let bban: String = original[4..]
+        .chars()
+        .map(|c| match c {
+            ' ' => ' ',
+            c if c.is_ascii_digit() => random_digit(rng),
+            _ => random_letter(rng),
+        })
+        .collect();
+    let remainder =
+        iban_checksum(&format!("{}00{}", country, bban)).expect("The fake IBAN is alphanumeric");
+    format!("{}{:02}{}", country, 98 - remainder, bban)
+}
+
+/// A random card number with the same first digit, length and separators as `original`, and a
+/// valid Luhn check digit
+pub fn fake_card_number(rng: &mut SmallRng, original: &str) -> String {
+    let last_digit = original
+        .rfind(|c: char| c.is_ascii_digit())
+        .expect("Card numbers end with a digit");
+    let without_check_digit: String = original[..last_digit]
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            c if i == 0 => c,
+            c if c.is_ascii_digit() => random_digit(rng),
+            c => c,
+        })
+        .collect();
+    // Adding the check digit doubles every other digit before it, so work out the sum as if it
+    // was already there as a 0
+    let sum = luhn_sum(&format!("{}0", without_check_digit));
+    format!("{}{}", without_check_digit, (10 - sum % 10) % 10)
+}
+
+fn random_letter(rng: &mut SmallRng) -> char {
+    rng.gen_range(b'A'..=b'Z') as char
+}
+
+fn random_digit(rng: &mut SmallRng) -> char {
+    rng.gen_range(b'0'..=b'9') as char
+}
+
+/// The Luhn sum of the digits in `text`, which is a multiple of 10 for a valid card number
+fn luhn_sum(text: &str) -> u32 {
+    text.chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum()
+}
+
+/// The IBAN's check number mod 97 (which is 1 for a valid IBAN), or None if it isn't
+/// alphanumeric
+fn iban_checksum(iban: &str) -> Option<u32> {
+    let compact: Vec<char> = iban.chars().filter(|c| *c != ' ').collect();
+    if compact.len() < 5 {
+        return None;
+    }
+    let (start, rest) = compact.split_at(4);
+    rest.iter().chain(start).try_fold(0, |remainder, c| {
+        let value = c.to_digit(36)?;
+        let shift = if value < 10 { 10 } else { 100 };
+        Some((remainder * shift + value) % 97)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::rng;
+
+    fn detected<'a>(text: &'a str, detectors: &[Detector]) -> Vec<(Detector, &'a str)> {
+        detect(text, detectors)
+            .into_iter()
+            .map(|detection| (detection.detector, &text[detection.range]))
+            .collect()
+    }
+
+    #[test]
+    fn detects_each_kind_of_pii() {
+        let text = "Email pete@example.com or call +44 7700 900123 / 020 7946 0000, \
+            NI QQ 12 34 56 C, lives at NW5 1AA, pays from GB82 WEST 1234 5698 7654 32 \
+            with 4111 1111 1111 1111. See https://example.com/pete.";
+
+        assert_eq!(
+            detected(text, &Detector::ALL),
+            vec![
+                (Detector::Email, "pete@example.com"),
+                (Detector::Phone, "+44 7700 900123"),
+                (Detector::Phone, "020 7946 0000"),
+                (Detector::NationalInsuranceNumber, "QQ 12 34 56 C"),
+                (Detector::Postcode, "NW5 1AA"),
+                (Detector::Iban, "GB82 WEST 1234 5698 7654 32"),
+                (Detector::CardNumber, "4111 1111 1111 1111"),
+                (Detector::Url, "https://example.com/pete"),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_uses_the_detectors_it_is_given() {
+        let text = "pete@example.com, NW5 1AA";
+
+        assert_eq!(
+            detected(text, &[Detector::Postcode]),
+            vec![(Detector::Postcode, "NW5 1AA")]
+        );
+    }
+
+    #[test]
+    fn earlier_detectors_claim_text_first() {
+        assert_eq!(
+            detected(
+                "https://example.com/?email=pete@example.com",
+                &Detector::ALL
+            ),
+            vec![(Detector::Url, "https://example.com/?email=pete@example.com")]
+        );
+    }
+
+    #[test]
+    fn numbers_failing_their_checksum_are_not_detected() {
+        assert_eq!(
+            detected(
+                "GB82 WEST 1234 5698 7654 33 and 4111 1111 1111 1112",
+                &[Detector::Iban, Detector::CardNumber]
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn fakes_are_detected_as_the_same_kind() {
+        let mut rng = rng::get();
+        let iban = fake_iban(&mut rng, "GB82 WEST 1234 5698 7654 32");
+        let card_number = fake_card_number(&mut rng, "4111-1111-1111-1111");
+        let postcode = fake_postcode(&mut rng);
+
+        assert_eq!(
+            detected(&iban, &Detector::ALL),
+            vec![(Detector::Iban, iban.as_str())]
+        );
+        assert!(iban.starts_with("GB"));
+        assert_eq!(
+            detected(&card_number, &Detector::ALL),
+            vec![(Detector::CardNumber, card_number.as_str())]
+        );
+        assert!(card_number.starts_with('4'));
+        assert_eq!(card_number.len(), 19);
+        assert_eq!(
+            detected(&postcode, &Detector::ALL),
+            vec![(Detector::Postcode, postcode.as_str())]
+        );
+    }
+}
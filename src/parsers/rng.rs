@@ -1,6 +1,78 @@
+use hmac::{Hmac, Mac};
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
+use sha2::Sha256;
 
 pub fn get() -> SmallRng {
     SmallRng::from_rng(rand::thread_rng()).unwrap_or_else(|_| SmallRng::from_entropy())
 }
+
+pub fn seeded(seed: u64) -> SmallRng {
+    SmallRng::seed_from_u64(seed)
+}
+
+/// An rng seeded from an HMAC of `value` under `key`, so the same value always produces the
+/// same sequence of random draws
+pub fn keyed(key: &str, value: &str) -> SmallRng {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(value.as_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let mut seed = <SmallRng as SeedableRng>::Seed::default();
+    let seed_length = seed.as_ref().len();
+    seed.as_mut().copy_from_slice(&digest[..seed_length]);
+    SmallRng::from_seed(seed)
+}
+
+/// An rng for one part of a seeded run, e.g. a table's data, seeded from a hash of the run's seed,
+/// what the part is and its position in the dump. Unlike adding the position to the seed, no two
+/// parts, of this run or of runs with other seeds, get the same sequence of random draws
+pub fn derived(seed: u64, part: &str, position: u64) -> SmallRng {
+    keyed(&seed.to_string(), &format!("{}:{}", part, position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn seeded_gives_the_same_draws_for_the_same_seed() {
+        let first: Vec<u64> = seeded(42)
+            .sample_iter(rand::distributions::Standard)
+            .take(5)
+            .collect();
+        let second: Vec<u64> = seeded(42)
+            .sample_iter(rand::distributions::Standard)
+            .take(5)
+            .collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn keyed_gives_the_same_draws_for_the_same_key_and_value() {
+        let first: u64 = keyed("secret", "alice@example.com").gen();
+        let second: u64 = keyed("secret", "alice@example.com").gen();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn keyed_gives_different_draws_for_different_keys_or_values() {
+        let original: u64 = keyed("secret", "alice@example.com").gen();
+        let other_value: u64 = keyed("secret", "bob@example.com").gen();
+        let other_key: u64 = keyed("another secret", "alice@example.com").gen();
+        assert_ne!(original, other_value);
+        assert_ne!(original, other_key);
+    }
+
+    #[test]
+    fn derived_gives_different_draws_for_neighbouring_seeds_and_positions() {
+        let draw = |seed, part, position| -> u64 { derived(seed, part, position).gen() };
+
+        assert_eq!(draw(1, "batch", 0), draw(1, "batch", 0));
+        assert_ne!(draw(1, "batch", 0), draw(0, "batch", 1));
+        assert_ne!(draw(1, "batch", 0), draw(1, "shuffle", 0));
+        assert_ne!(draw(1, "batch", 0), seeded(1).gen::<u64>());
+    }
+}
@@ -1,14 +1,16 @@
+use crate::errors::AnonymiserError;
 use crate::parsers::copy_row::{CurrentTableTransforms, TableTransformers};
 use crate::parsers::create_row;
+use crate::parsers::insert_row;
+use crate::parsers::insert_row::{InsertStatement, Quote};
 use crate::parsers::sanitiser;
 use crate::parsers::state::*;
 use crate::parsers::strategies::Strategies;
-use crate::parsers::strategy_structs::ColumnInfo;
-use crate::parsers::transformer;
+use crate::parsers::strategy_structs::{ColumnInfo, TransformerType};
+use crate::parsers::transformer::{self, CompiledTransformer, Draws};
 use crate::parsers::types;
-use crate::parsers::types::Column;
-use crate::parsers::{copy_row, data_row};
-use itertools::Itertools;
+use crate::parsers::types::{Column, Type};
+use crate::parsers::{copy_row, data_row, rng};
 use rand::rngs::SmallRng;
 use std::borrow::Cow;
 
@@ -21,15 +23,24 @@ enum RowType {
     CreateTableStart,
     CreateTableRow,
     CreateTableEnd,
+    InsertStart,
+    InsertRow,
+    DollarQuotedRow,
 }
 
 fn row_type(line: &str, state: &Position) -> RowType {
-    if create_row::is_create_row(line) {
+    if matches!(state, Position::InInsert { .. }) {
+        RowType::InsertRow
+    } else if matches!(state, Position::InDollarQuote { .. }) {
+        RowType::DollarQuotedRow
+    } else if create_row::is_create_row(line) {
         RowType::CreateTableStart
     } else if line.starts_with("COPY ") {
         RowType::CopyBlockStart
     } else if line.starts_with("\\.") {
         RowType::CopyBlockEnd
+    } else if insert_row::is_insert_row(line) && matches!(state, Position::Normal) {
+        RowType::InsertStart
     } else if line.starts_with(");") && matches!(state, Position::InCreateTable { .. }) {
         RowType::CreateTableEnd
     } else if matches!(state, Position::InCopy { .. }) {
@@ -46,16 +57,16 @@ pub fn parse<'line>(
     line: &'line str,
     state: &mut State,
     strategies: &Strategies,
-) -> Cow<'line, str> {
+) -> Result<Cow<'line, str>, AnonymiserError> {
     let sanitised_line = sanitiser::trim(line);
     match (row_type(sanitised_line, &state.position), &state.position) {
         (RowType::CreateTableStart, _position) => {
-            let table_name = create_row::parse(sanitised_line);
+            let table_name = create_row::parse(sanitised_line)?;
             state.update_position(Position::InCreateTable {
                 table_name,
                 types: Vec::new(),
             });
-            Cow::from(line)
+            Ok(Cow::from(line))
         }
         (
             RowType::CreateTableRow,
@@ -68,47 +79,97 @@ pub fn parse<'line>(
                 table_name: table_name.clone(),
                 types: add_create_table_row_to_types(sanitised_line, current_types.to_vec()),
             });
-            Cow::from(line)
+            Ok(Cow::from(line))
         }
-        (RowType::CreateTableEnd, _position) => {
+        (RowType::CreateTableEnd, Position::InCreateTable { table_name, .. }) => {
+            let table_name = table_name.clone();
             state.update_position(Position::Normal);
-            Cow::from(line)
+            if let Some(column_types) = state.types.for_table(&table_name) {
+                let incompatible = strategies.incompatible_transformers(&table_name, column_types);
+                state.incompatible_transformers.extend(incompatible);
+            }
+            Ok(Cow::from(line))
         }
         (RowType::CopyBlockStart, _position) => {
-            let current_table = copy_row::parse(sanitised_line, strategies);
+            state.check_transformers()?;
+            let current_table = copy_row::parse(sanitised_line, strategies)?;
             state.update_position(Position::InCopy { current_table });
-            Cow::from(line)
+            Ok(Cow::from(line))
         }
         (RowType::CopyBlockEnd, _position) => {
             state.update_position(Position::Normal);
-            Cow::from(line)
+            Ok(Cow::from(line))
         }
         (RowType::CopyBlockRow, Position::InCopy { ref current_table }) => {
-            Cow::from(transform_row(rng, line, current_table, &state.types))
+            if !is_kept_by_subset(&mut state.rows_read, &current_table.table_name, strategies) {
+                return Ok(Cow::from(""));
+            }
+            Ok(Cow::from(transform_row(
+                rng,
+                line,
+                current_table,
+                &state.types,
+                strategies,
+            )?))
+        }
+        (RowType::InsertStart, _position) => {
+            state.check_transformers()?;
+            let quote = insert_row::scan(line, Quote::None);
+            if insert_row::is_complete(line, quote) {
+                Ok(Cow::from(transform_insert(rng, line, state, strategies)?))
+            } else {
+                state.update_position(Position::InInsert {
+                    statement: line.to_string(),
+                    quote,
+                });
+                Ok(Cow::from(""))
+            }
         }
+        (RowType::InsertRow, _position) => continue_insert(rng, line, state, strategies),
 
-        (RowType::Normal, Position::Normal) => Cow::from(line),
-        (row_type, position) => {
-            panic!(
-                "omg! invalid combo of rowtype: {:?} and position: {:?}",
-                row_type, position
-            );
+        (RowType::DollarQuotedRow, Position::InDollarQuote { tag }) => {
+            match insert_row::dollar_quote(line, Some(tag.clone())) {
+                Some(tag) => state.update_position(Position::InDollarQuote { tag }),
+                None => state.update_position(Position::Normal),
+            }
+            Ok(Cow::from(line))
+        }
+        (RowType::Normal, Position::Normal) => {
+            if let Some(tag) = insert_row::dollar_quote(line, None) {
+                state.update_position(Position::InDollarQuote { tag });
+            }
+            Ok(Cow::from(line))
         }
+        (row_type, position) => Err(AnonymiserError::invalid_dump(format!(
+            "omg! invalid combo of rowtype: {:?} and position: {:?}",
+            row_type, position
+        ))),
     }
 }
 
-fn transform_row(
+/// Whether `line` is a row of COPY data, which can be transformed without changing `state`
+pub fn is_copy_block_row(line: &str, state: &State) -> bool {
+    row_type(sanitiser::trim(line), &state.position) == RowType::CopyBlockRow
+}
+
+pub fn transform_row(
     rng: &mut SmallRng,
     line: &str,
     current_table: &CurrentTableTransforms,
     types: &Types,
-) -> String {
+    strategies: &Strategies,
+) -> Result<String, AnonymiserError> {
     match current_table.table_transformers {
-        TableTransformers::ColumnTransformer(ref columns) => {
-            transform_row_with_columns(rng, line, &current_table.table_name, columns, types)
-        }
+        TableTransformers::ColumnTransformer(ref columns) => transform_row_with_columns(
+            rng,
+            line,
+            &current_table.table_name,
+            columns,
+            types,
+            strategies,
+        ),
 
-        TableTransformers::Truncator => "".to_string(),
+        TableTransformers::Truncator => Ok("".to_string()),
     }
 }
 
@@ -118,37 +179,371 @@ fn transform_row_with_columns(
     table_name: &str,
     columns: &[ColumnInfo],
     types: &Types,
-) -> String {
-    let column_values = data_row::split(line);
-
-    let mut transformed = column_values.enumerate().map(|(i, value)| {
-        let current_column = &columns[i];
-        let column_type = types
-            //TODO this lookup, we do a double hashmap lookup for every column... already know the
-            //table, so we shouldnt need to do both... can we cache the current tables columns
-            //hashmap?
-            .lookup(table_name, &current_column.name)
-            .unwrap_or_else(|| {
-                panic!(
-                    "No type found for {}.{}\nI did find these for the table: {:?}",
+    strategies: &Strategies,
+) -> Result<String, AnonymiserError> {
+    let column_values: Vec<&str> = data_row::split(line).collect();
+    // The rows a subset drops have already been dropped as they were read, see
+    // `is_kept_by_subset`
+    if strategies.subset().is_none()
+        && !matches_filter(&column_values, table_name, columns, types, strategies)?
+    {
+        return Ok("".to_string());
+    }
+
+    let transformed =
+        transform_values(rng, &column_values, table_name, columns, types, strategies)?;
+
+    let mut joined = transformed.join("\t");
+    joined.push('\n');
+    Ok(joined)
+}
+
+/// Counts a row of `table_name` read from the dump, returning whether the subset, if there is
+/// one, keeps it. Subsets pick out rows by their position in their table, so rows are counted
+/// as they're read rather than by the workers transforming them
+pub fn is_kept_by_subset(
+    rows_read: &mut RowCounts,
+    table_name: &str,
+    strategies: &Strategies,
+) -> bool {
+    match strategies.subset() {
+        Some(subset) => subset.keeps(table_name, rows_read.next(table_name)),
+        None => true,
+    }
+}
+
+/// Whether the table's filter, if it has one, is true for the row with `values`, given as COPY
+/// text in the same order as `columns`
+pub fn matches_filter(
+    values: &[&str],
+    table_name: &str,
+    columns: &[ColumnInfo],
+    types: &Types,
+    strategies: &Strategies,
+) -> Result<bool, AnonymiserError> {
+    let Some(filter) = strategies.filter_for(table_name) else {
+        return Ok(true);
+    };
+    filter
+        .matches(|column_name| {
+            let value = column_value(values, columns, column_name)?;
+            let sub_type = match types.lookup(table_name, column_name) {
+                Some(Type::SingleValue { sub_type }) => Some(sub_type),
+                _ => None,
+            };
+            Some((value, sub_type))
+        })
+        .map_err(|err| {
+            AnonymiserError::transform(table_name, format!("Couldn't apply the filter: {}", err))
+        })
+}
+
+/// Transforms the values of a row, given as COPY text in the same order as `columns`
+fn transform_values<'value>(
+    rng: &mut SmallRng,
+    values: &[&'value str],
+    table_name: &str,
+    columns: &'value [ColumnInfo],
+    types: &Types,
+    strategies: &Strategies,
+) -> Result<Vec<Cow<'value, str>>, AnonymiserError> {
+    let mut transformed = values
+        .iter()
+        .zip(columns)
+        .map(|(value, column)| {
+            let compiled = strategies.compiled_transformer(table_name, column);
+            let transformer = transformer::switch_branch(&compiled, value, |column_name| {
+                column_value(values, columns, column_name)
+            })
+            .map_err(|err| AnonymiserError::transform(table_name, err).in_column(&column.name))?;
+            match transformer::key_column(&transformer) {
+                Some(key_column) => {
+                    // Every row with the same key value gets the same random draws, so (for
+                    // example) all of a user's dates are shifted by the same amount
+                    let key_value = column_value(values, columns, key_column).ok_or_else(|| {
+                        AnonymiserError::invalid_dump(format!(
+                            "The key column '{}' for {}.{} isn't in its data",
+                            key_column, table_name, column.name
+                        ))
+                    })?;
+                    let mut key_rng = rng::keyed(strategies.entity_key(), key_value);
+                    transform_value(
+                        &mut key_rng,
+                        value,
+                        table_name,
+                        column,
+                        &transformer,
+                        types,
+                        Draws::Reproducible,
+                    )
+                }
+                None => transform_value(
+                    rng,
+                    value,
                     table_name,
-                    current_column.name,
-                    types.for_table(table_name)
-                )
-            });
+                    column,
+                    &transformer,
+                    types,
+                    draws_for_run(strategies),
+                ),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-        transformer::transform(
-            rng,
+    let mut rendered = vec![false; columns.len()];
+    for index in 0..columns.len() {
+        render_template(
+            index,
+            values,
+            &mut transformed,
+            &mut rendered,
+            table_name,
+            columns,
+            strategies,
+        )?;
+    }
+    Ok(transformed)
+}
+
+/// Fills in the value of the column at `index` if it has a `Template` transformer, which
+/// `transform_value` leaves as it is, from the transformed values of the columns it uses.
+/// Templates can use other templates' columns, so those are filled in first
+fn render_template(
+    index: usize,
+    values: &[&str],
+    transformed: &mut [Cow<str>],
+    rendered: &mut [bool],
+    table_name: &str,
+    columns: &[ColumnInfo],
+    strategies: &Strategies,
+) -> Result<(), AnonymiserError> {
+    let column = &columns[index];
+    if rendered[index] || column.transformer.name != TransformerType::Template {
+        return Ok(());
+    }
+    rendered[index] = true;
+    if values[index] == "\\N" {
+        return Ok(());
+    }
+
+    let error =
+        |message: String| AnonymiserError::transform(table_name, message).in_column(&column.name);
+    let compiled = strategies.compiled_transformer(table_name, column);
+    let template = transformer::template_arg(&compiled).map_err(error)?;
+    for used_column in template.columns() {
+        if let Some(used_index) = columns.iter().position(|column| column.name == used_column) {
+            render_template(
+                used_index,
+                values,
+                transformed,
+                rendered,
+                table_name,
+                columns,
+                strategies,
+            )?;
+        }
+    }
+    let value = template
+        .render(|used_column| {
+            columns
+                .iter()
+                .position(|column| column.name == used_column)
+                .map(|used_index| transformed[used_index].as_ref())
+        })
+        .map_err(error)?;
+    transformed[index] = Cow::Owned(value);
+    Ok(())
+}
+
+fn column_value<'value>(
+    values: &[&'value str],
+    columns: &[ColumnInfo],
+    column_name: &str,
+) -> Option<&'value str> {
+    columns
+        .iter()
+        .position(|column| column.name == column_name)
+        .and_then(|i| values.get(i).copied())
+}
+
+fn draws_for_run(strategies: &Strategies) -> Draws<'_> {
+    match strategies.deterministic_key() {
+        Some(key) => Draws::Keyed(key),
+        None if strategies.is_seeded() => Draws::Reproducible,
+        None => Draws::Random,
+    }
+}
+
+fn transform_value<'value>(
+    rng: &mut SmallRng,
+    value: &'value str,
+    table_name: &str,
+    current_column: &ColumnInfo,
+    transformer: &CompiledTransformer,
+    types: &Types,
+    draws: Draws,
+) -> Result<Cow<'value, str>, AnonymiserError> {
+    let column_type = types
+        //TODO this lookup, we do a double hashmap lookup for every column... already know the
+        //table, so we shouldnt need to do both... can we cache the current tables columns
+        //hashmap?
+        .lookup(table_name, &current_column.name)
+        .ok_or_else(|| {
+            AnonymiserError::invalid_dump(format!(
+                "No type found for {}.{}\nI did find these for the table: {:?}",
+                table_name,
+                current_column.name,
+                types.for_table(table_name)
+            ))
+        })?;
+
+    let transformed = match draws {
+        Draws::Keyed(key) => transformer::transform_compiled_deterministically(
+            key,
             value,
             column_type,
-            &current_column.transformer,
+            transformer,
             table_name,
-        )
-    });
+        ),
+        Draws::Reproducible => {
+            transformer::transform_reproducibly(rng, value, column_type, transformer, table_name)
+        }
+        Draws::Random => {
+            transformer::transform_compiled(rng, value, column_type, transformer, table_name)
+        }
+    };
+    transformed.map_err(|err| err.in_column(&current_column.name))
+}
 
-    let mut joined = transformed.join("\t");
-    joined.push('\n');
-    joined
+/// Adds a line to a multi-line INSERT statement, transforming it once the statement is complete
+fn continue_insert<'line>(
+    rng: &mut SmallRng,
+    line: &'line str,
+    state: &mut State,
+    strategies: &Strategies,
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    let complete_statement = match state.position {
+        Position::InInsert {
+            ref mut statement,
+            ref mut quote,
+        } => {
+            statement.push_str(line);
+            *quote = insert_row::scan(line, *quote);
+            insert_row::is_complete(line, *quote).then(|| std::mem::take(statement))
+        }
+        _ => None,
+    };
+
+    match complete_statement {
+        Some(statement) => {
+            state.update_position(Position::Normal);
+            Ok(Cow::from(transform_insert(
+                rng, &statement, state, strategies,
+            )?))
+        }
+        None => Ok(Cow::from("")),
+    }
+}
+
+fn transform_insert(
+    rng: &mut SmallRng,
+    statement: &str,
+    state: &mut State,
+    strategies: &Strategies,
+) -> Result<String, AnonymiserError> {
+    let parsed = insert_row::parse(statement)?;
+    match insert_table_transformers(&parsed, state, strategies)? {
+        TableTransformers::ColumnTransformer(columns)
+            if columns
+                .iter()
+                .any(|column| column.transformer.name == TransformerType::Shuffle) =>
+        {
+            Err(AnonymiserError::transform(
+                &parsed.table_name,
+                "Shuffle can only be used on tables dumped with COPY, not INSERT statements"
+                    .to_string(),
+            ))
+        }
+        TableTransformers::ColumnTransformer(columns) => {
+            let drops_rows = strategies.filter_for(&parsed.table_name).is_some()
+                || strategies.subset().is_some();
+            let retained = match drops_rows {
+                true => insert_row::retain_rows(statement, &parsed, |values| {
+                    if strategies.subset().is_some() {
+                        return Ok(is_kept_by_subset(
+                            &mut state.rows_read,
+                            &parsed.table_name,
+                            strategies,
+                        ));
+                    }
+                    let copy_texts: Vec<Cow<str>> = values
+                        .iter()
+                        .map(|value| insert_row::to_copy_text(value))
+                        .collect();
+                    let copy_texts: Vec<&str> = copy_texts.iter().map(AsRef::as_ref).collect();
+                    matches_filter(
+                        &copy_texts,
+                        &parsed.table_name,
+                        &columns,
+                        &state.types,
+                        strategies,
+                    )
+                })?,
+                false => Some(Cow::from(statement)),
+            };
+            let Some(statement) = retained else {
+                return Ok("".to_string());
+            };
+            let parsed = match &statement {
+                Cow::Borrowed(_) => parsed,
+                Cow::Owned(retained) => insert_row::parse(retained)?,
+            };
+
+            insert_row::replace_values(&statement, &parsed, |values| {
+                let copy_texts: Vec<Cow<str>> = values
+                    .iter()
+                    .map(|value| insert_row::to_copy_text(value))
+                    .collect();
+                let copy_texts: Vec<&str> = copy_texts.iter().map(AsRef::as_ref).collect();
+                let transformed = transform_values(
+                    rng,
+                    &copy_texts,
+                    &parsed.table_name,
+                    &columns,
+                    &state.types,
+                    strategies,
+                )?;
+                Ok(values
+                    .iter()
+                    .zip(transformed)
+                    .map(|(value, transformed)| insert_row::to_sql(value, &transformed))
+                    .collect())
+            })
+        }
+        TableTransformers::Truncator => Ok("".to_string()),
+    }
+}
+
+/// The transformers for the columns of an INSERT statement's table, in the order its values are
+/// given in
+pub fn insert_table_transformers(
+    parsed: &InsertStatement,
+    state: &State,
+    strategies: &Strategies,
+) -> Result<TableTransformers, AnonymiserError> {
+    let column_names = match &parsed.column_names {
+        Some(column_names) => column_names,
+        None => state
+            .types
+            .column_names(&parsed.table_name)
+            .ok_or_else(|| {
+                AnonymiserError::invalid_dump(format!(
+                    "No columns found for table: {:?}, its INSERT statements need a column list if it isn't created in the dump",
+                    parsed.table_name
+                ))
+            })?,
+    };
+    copy_row::table_strategy(strategies, &parsed.table_name, column_names)
 }
 
 fn add_create_table_row_to_types(line: &str, mut current_types: Vec<Column>) -> Vec<Column> {
@@ -165,18 +560,22 @@ mod tests {
     use super::*;
     use crate::parsers::copy_row::TableTransformers;
     use crate::parsers::rng;
-    use crate::parsers::strategy_structs::{ColumnInfo, DataCategory, TransformerType};
+    use crate::parsers::strategy_structs::{
+        ColumnInFile, ColumnInfo, DataCategory, StrategyInFile, Transformer, TransformerArg,
+        TransformerOverrides, TransformerType,
+    };
     use crate::parsers::types::{SubType, Type};
     use std::collections::HashMap;
 
     #[test]
     fn create_table_start_row_is_parsed() {
         let create_table_row = "CREATE TABLE public.candidate_details (";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::from([]));
+        let strategies =
+            Strategies::new_from("public.users".to_string(), HashMap::from([])).unwrap();
 
         let mut state = State::new();
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies).unwrap();
         assert_eq!(
             state.position,
             Position::InCreateTable {
@@ -190,11 +589,12 @@ mod tests {
     #[test]
     fn create_unlogged_table_start_row_is_parsed() {
         let create_table_row = "CREATE UNLOGGED TABLE public.candidate_details (";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::from([]));
+        let strategies =
+            Strategies::new_from("public.users".to_string(), HashMap::from([])).unwrap();
 
         let mut state = State::new();
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies).unwrap();
         assert_eq!(
             state.position,
             Position::InCreateTable {
@@ -208,7 +608,8 @@ mod tests {
     #[test]
     fn create_table_mid_row_is_added_to_state() {
         let create_table_row = "password character varying(255)";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::from([]));
+        let strategies =
+            Strategies::new_from("public.users".to_string(), HashMap::from([])).unwrap();
 
         let mut state = State {
             position: Position::InCreateTable {
@@ -219,9 +620,11 @@ mod tests {
                 }],
             },
             types: Types::new(HashMap::default()),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies).unwrap();
 
         assert_eq!(
             state.position,
@@ -245,7 +648,8 @@ mod tests {
     #[test]
     fn non_type_create_table_row_is_ignored() {
         let create_table_row = "PARTITION BY something else";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::from([]));
+        let strategies =
+            Strategies::new_from("public.users".to_string(), HashMap::from([])).unwrap();
 
         let mut state = State {
             position: Position::InCreateTable {
@@ -253,9 +657,11 @@ mod tests {
                 types: vec![],
             },
             types: Types::new(HashMap::default()),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies).unwrap();
 
         assert_eq!(
             state.position,
@@ -270,7 +676,8 @@ mod tests {
     #[test]
     fn end_of_a_create_table_row_changes_state() {
         let create_table_row = ");";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::from([]));
+        let strategies =
+            Strategies::new_from("public.users".to_string(), HashMap::from([])).unwrap();
 
         let mut state = State {
             position: Position::InCreateTable {
@@ -281,20 +688,72 @@ mod tests {
                 }],
             },
             types: Types::new(HashMap::default()),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, create_table_row, &mut state, &strategies).unwrap();
 
         assert_eq!(state.position, Position::Normal);
 
-        let expected_types = Types::new(HashMap::from_iter([(
+        let mut expected_types = Types::new(HashMap::from_iter([(
             "public.users".to_string(),
             HashMap::from_iter([("id".to_string(), Type::integer())]),
         )]));
+        expected_types.insert_column_names("public.users", vec!["id".to_string()]);
         assert_eq!(state.types, expected_types);
         assert_eq!(create_table_row, transformed_row);
     }
 
+    #[test]
+    fn end_of_a_create_table_row_records_transformers_that_do_not_suit_their_column_types() {
+        let strategies = Strategies::new_from(
+            "public.users".to_string(),
+            HashMap::from([
+                (
+                    "id".to_string(),
+                    ColumnInfo::builder()
+                        .with_name("id")
+                        .with_transformer(TransformerType::FakeEmail, None)
+                        .build(),
+                ),
+                (
+                    "settings".to_string(),
+                    ColumnInfo::builder()
+                        .with_name("settings")
+                        .with_transformer(TransformerType::Scramble, None)
+                        .build(),
+                ),
+            ]),
+        )
+        .unwrap();
+
+        let mut state = State::new();
+        let mut rng = rng::get();
+        for line in [
+            "CREATE TABLE public.users (",
+            "    id bigint NOT NULL,",
+            "    settings jsonb",
+            ");",
+        ] {
+            parse(&mut rng, line, &mut state, &strategies).unwrap();
+        }
+        assert_eq!(state.incompatible_transformers.len(), 2);
+
+        let err = parse(
+            &mut rng,
+            "COPY public.users (id, settings) FROM stdin;",
+            &mut state,
+            &strategies,
+        )
+        .unwrap_err();
+
+        assert_eq!(err.exit_code(), 5);
+        let message = err.to_string();
+        assert!(message.contains("public.users => id (FakeEmail can't be used on bigint)"));
+        assert!(message.contains("public.users => settings (Scramble can't be used on json)"));
+    }
+
     #[test]
     fn copy_row_sets_status_to_being_in_copy_and_adds_transforms_in_the_correct_order_for_the_columns(
     ) {
@@ -317,11 +776,11 @@ mod tests {
             ("first_name".to_string(), first_name_column.clone()),
         ]);
 
-        let strategies = Strategies::new_from("public.users".to_string(), column_infos);
+        let strategies = Strategies::new_from("public.users".to_string(), column_infos).unwrap();
 
         let mut state = State::new();
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, copy_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, copy_row, &mut state, &strategies).unwrap();
 
         assert_eq!(copy_row, transformed_row);
 
@@ -367,11 +826,11 @@ mod tests {
                     .build(),
             ),
         ]);
-        let strategies = Strategies::new_from("public.users".to_string(), transforms);
+        let strategies = Strategies::new_from("public.users".to_string(), transforms).unwrap();
 
         let mut state = State::new();
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, end_copy_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, end_copy_row, &mut state, &strategies).unwrap();
         assert!(state.position == Position::Normal);
         assert_eq!(end_copy_row, transformed_row);
     }
@@ -379,11 +838,11 @@ mod tests {
     #[test]
     fn non_table_data_passes_through() {
         let non_table_data_row = "--this is a SQL comment";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new());
+        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new()).unwrap();
 
         let mut state = State::new();
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, non_table_data_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, non_table_data_row, &mut state, &strategies).unwrap();
         assert!(state.position == Position::Normal);
         assert_eq!(non_table_data_row, transformed_row);
     }
@@ -391,7 +850,7 @@ mod tests {
     #[test]
     fn table_data_with_empty_final_column() {
         let table_data_row = "123\tPeter\t\n";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new());
+        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new()).unwrap();
 
         let mut state = State {
             position: Position::InCopy {
@@ -409,16 +868,18 @@ mod tests {
                 .add_type("public.users", "column_2", SubType::Character)
                 .add_type("public.users", "column_3", SubType::Character)
                 .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, table_data_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, table_data_row, &mut state, &strategies).unwrap();
         assert_eq!("123\tPeter\t\n", transformed_row);
     }
 
     #[test]
     fn table_data_is_transformed() {
         let table_data_row = "123\tPeter\tPuckleberry\n";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new());
+        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new()).unwrap();
 
         let mut state = State {
             position: Position::InCopy {
@@ -429,21 +890,21 @@ mod tests {
                             .with_name("column_1")
                             .with_transformer(
                                 TransformerType::Fixed,
-                                Some(HashMap::from([("value".to_string(), "first".to_string())])),
+                                Some(HashMap::from([("value".to_string(), "first".into())])),
                             )
                             .build(),
                         ColumnInfo::builder()
                             .with_name("column_2")
                             .with_transformer(
                                 TransformerType::Fixed,
-                                Some(HashMap::from([("value".to_string(), "second".to_string())])),
+                                Some(HashMap::from([("value".to_string(), "second".into())])),
                             )
                             .build(),
                         ColumnInfo::builder()
                             .with_name("column_3")
                             .with_transformer(
                                 TransformerType::Fixed,
-                                Some(HashMap::from([("value".to_string(), "third".to_string())])),
+                                Some(HashMap::from([("value".to_string(), "third".into())])),
                             )
                             .build(),
                     ]),
@@ -454,15 +915,17 @@ mod tests {
                 .add_type("public.users", "column_2", SubType::Character)
                 .add_type("public.users", "column_3", SubType::Character)
                 .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, table_data_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, table_data_row, &mut state, &strategies).unwrap();
         assert_eq!("first\tsecond\tthird\n", transformed_row);
     }
     #[test]
     fn whitespace_is_not_removed() {
         let table_data_row = "   123\t  Peter   \t  Puckleberry   \n";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new());
+        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new()).unwrap();
 
         let mut state = State {
             position: Position::InCopy {
@@ -489,16 +952,18 @@ mod tests {
                 .add_type("public.users", "column_2", SubType::Character)
                 .add_type("public.users", "column_3", SubType::Character)
                 .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let transformed_row = parse(&mut rng, table_data_row, &mut state, &strategies);
+        let transformed_row = parse(&mut rng, table_data_row, &mut state, &strategies).unwrap();
         assert_eq!(table_data_row, transformed_row);
     }
 
     #[test]
     fn transforms_array_fields() {
         let table_data_row = "{\"My string\"}\n";
-        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new());
+        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new()).unwrap();
 
         let mut state = State {
             position: Position::InCopy {
@@ -515,9 +980,428 @@ mod tests {
             types: Types::builder()
                 .add_array_type("public.users", "column_1", SubType::Character)
                 .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
         let mut rng = rng::get();
-        let processed_row = parse(&mut rng, table_data_row, &mut state, &strategies);
+        let processed_row = parse(&mut rng, table_data_row, &mut state, &strategies).unwrap();
         assert!(table_data_row != processed_row);
     }
+
+    #[test]
+    fn switch_picks_the_transformer_for_the_value_in_another_column() {
+        let strategies =
+            Strategies::new_from("public.contacts".to_string(), HashMap::new()).unwrap();
+        let fixed = |value: &str| TransformerArg::from(fixed_column("value", value).1.transformer);
+        let value = ColumnInfo::builder()
+            .with_name("value")
+            .with_transformer(
+                TransformerType::Switch,
+                Some(HashMap::from([
+                    ("column".to_string(), "kind".into()),
+                    ("email".to_string(), fixed("someone@example.com")),
+                    ("phone".to_string(), fixed("07700 900000")),
+                    ("default".to_string(), fixed("unknown")),
+                ])),
+            )
+            .build();
+        let mut state = State {
+            position: Position::InCopy {
+                current_table: CurrentTableTransforms {
+                    table_name: "public.contacts".to_string(),
+                    table_transformers: TableTransformers::ColumnTransformer(vec![
+                        ColumnInfo::builder().with_name("kind").build(),
+                        value,
+                    ]),
+                },
+            },
+            types: Types::builder()
+                .add_type("public.contacts", "kind", SubType::Character)
+                .add_type("public.contacts", "value", SubType::Character)
+                .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+
+        let transformed: Vec<_> = [
+            "email\tpete@example.com\n",
+            "phone\t020 7946 0000\n",
+            "\\N\t020 7946 0000\n",
+        ]
+        .iter()
+        .map(|row| {
+            parse(&mut rng, row, &mut state, &strategies)
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+
+        assert_eq!(
+            transformed,
+            vec![
+                "email\tsomeone@example.com\n",
+                "phone\t07700 900000\n",
+                "\\N\tunknown\n",
+            ]
+        );
+    }
+
+    fn template_column(name: &str, template: &str) -> ColumnInfo {
+        ColumnInfo::builder()
+            .with_name(name)
+            .with_transformer(
+                TransformerType::Template,
+                Some(HashMap::from([("template".to_string(), template.into())])),
+            )
+            .build()
+    }
+
+    #[test]
+    fn templates_are_filled_in_from_the_transformed_row() {
+        let strategies = Strategies::new_from("public.users".to_string(), HashMap::new()).unwrap();
+        let mut state = State {
+            position: Position::InCopy {
+                current_table: CurrentTableTransforms {
+                    table_name: "public.users".to_string(),
+                    table_transformers: TableTransformers::ColumnTransformer(vec![
+                        template_column("email", "{full_name}@example.com"),
+                        template_column("full_name", "{first_name}\t{last_name}"),
+                        fixed_column("first_name", "Jo").1,
+                        ColumnInfo::builder().with_name("last_name").build(),
+                        template_column("display_name", "{first_name}"),
+                    ]),
+                },
+            },
+            types: Types::builder()
+                .add_type("public.users", "email", SubType::Character)
+                .add_type("public.users", "full_name", SubType::Character)
+                .add_type("public.users", "first_name", SubType::Character)
+                .add_type("public.users", "last_name", SubType::Character)
+                .add_type("public.users", "display_name", SubType::Character)
+                .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+
+        let row = "pete@a.com\tPete Smith\tPete\tSmith\t\\N\n";
+        let transformed = parse(&mut rng, row, &mut state, &strategies).unwrap();
+
+        assert_eq!(
+            transformed,
+            "Jo\\tSmith@example.com\tJo\\tSmith\tJo\tSmith\t\\N\n"
+        );
+    }
+
+    fn shift_keyed_on_user_id(name: &str, transformer: TransformerType) -> ColumnInfo {
+        ColumnInfo::builder()
+            .with_name(name)
+            .with_transformer(
+                transformer,
+                Some(HashMap::from([
+                    ("max_days".to_string(), "1000".into()),
+                    ("key_column".to_string(), "user_id".into()),
+                ])),
+            )
+            .build()
+    }
+
+    fn events_types() -> Types {
+        Types::builder()
+            .add_type("public.events", "user_id", SubType::Integer)
+            .add_type("public.events", "happened_on", SubType::Date)
+            .add_type(
+                "public.events",
+                "happened_at",
+                SubType::TimestampWithTimeZone,
+            )
+            .build()
+    }
+
+    #[test]
+    fn rows_with_the_same_key_value_are_shifted_by_the_same_amount() {
+        let strategies = Strategies::new_from("public.events".to_string(), HashMap::new()).unwrap();
+        let mut state = State {
+            position: Position::InCopy {
+                current_table: CurrentTableTransforms {
+                    table_name: "public.events".to_string(),
+                    table_transformers: TableTransformers::ColumnTransformer(vec![
+                        ColumnInfo::builder().with_name("user_id").build(),
+                        shift_keyed_on_user_id("happened_on", TransformerType::ShiftDate),
+                        shift_keyed_on_user_id("happened_at", TransformerType::ShiftTimestamp),
+                    ]),
+                },
+            },
+            types: events_types(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+
+        let row = "1\t2020-01-10\t2020-01-10 09:30:00.5+01\n";
+        let first = parse(&mut rng, row, &mut state, &strategies).unwrap();
+        let second = parse(&mut rng, row, &mut state, &strategies).unwrap();
+
+        assert_eq!(first, second);
+        let values: Vec<&str> = data_row::split(&first).collect();
+        assert_ne!(values[1], "2020-01-10");
+        assert_eq!(values[2], format!("{} 09:30:00.5+01", values[1]));
+    }
+
+    #[test]
+    fn insert_rows_with_the_same_key_value_are_shifted_by_the_same_amount() {
+        let strategies = Strategies::new_from(
+            "public.events".to_string(),
+            HashMap::from([
+                (
+                    "user_id".to_string(),
+                    ColumnInfo::builder().with_name("user_id").build(),
+                ),
+                (
+                    "happened_on".to_string(),
+                    shift_keyed_on_user_id("happened_on", TransformerType::ShiftDate),
+                ),
+            ]),
+        )
+        .unwrap();
+        let mut state = State {
+            position: Position::Normal,
+            types: events_types(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+
+        let transformed = parse(
+            &mut rng,
+            "INSERT INTO public.events (user_id, happened_on) VALUES (7, '2020-01-10'), (7, '2020-01-10');\n",
+            &mut state,
+            &strategies,
+        )
+        .unwrap();
+
+        let shifted: Vec<&str> = transformed.split('\'').skip(1).step_by(2).collect();
+        assert_eq!(shifted.len(), 2);
+        assert_eq!(shifted[0], shifted[1]);
+        assert_ne!(shifted[0], "2020-01-10");
+    }
+
+    fn events_filtered_by(filter: &str) -> Strategies {
+        let column = |name: &str| ColumnInFile {
+            data_category: DataCategory::General,
+            transformer: Transformer {
+                name: TransformerType::Identity,
+                args: None,
+            },
+            ..ColumnInFile::new(name)
+        };
+        Strategies::from_strategies_in_file(
+            vec![StrategyInFile {
+                table_name: "public.events".to_string(),
+                description: "".to_string(),
+                truncate: false,
+                locale: None,
+                filter: Some(filter.to_string()),
+                columns: vec![
+                    column("user_id"),
+                    column("happened_on"),
+                    column("happened_at"),
+                ],
+            }],
+            &TransformerOverrides::none(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rows_the_filter_is_not_true_for_are_dropped() {
+        let strategies = events_filtered_by(
+            "user_id IN (1, 2) AND (happened_on >= '2020-01-01' OR happened_at IS NULL)",
+        );
+        let mut state = State {
+            position: Position::Normal,
+            types: events_types(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+        parse(
+            &mut rng,
+            "COPY public.events (user_id, happened_on, happened_at) FROM stdin;\n",
+            &mut state,
+            &strategies,
+        )
+        .unwrap();
+
+        let mut transform = |row| parse(&mut rng, row, &mut state, &strategies).unwrap();
+
+        assert_eq!(transform("1\t2020-01-10\t\\N\n"), "1\t2020-01-10\t\\N\n");
+        assert_eq!(transform("2\t2019-12-31\t\\N\n"), "2\t2019-12-31\t\\N\n");
+        assert_eq!(transform("2\t2019-12-31\t2019-12-31 09:30:00+00\n"), "");
+        assert_eq!(transform("3\t2020-01-10\t\\N\n"), "");
+        assert_eq!(transform("\\N\t2020-01-10\t\\N\n"), "");
+    }
+
+    #[test]
+    fn insert_rows_the_filter_is_not_true_for_are_dropped() {
+        let strategies = events_filtered_by("happened_on > '2020-01-01'");
+        let mut state = State {
+            position: Position::Normal,
+            types: events_types(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+        let mut transform =
+            |statement| parse(&mut rng, statement, &mut state, &strategies).unwrap();
+
+        assert_eq!(
+            transform("INSERT INTO public.events (user_id, happened_on) VALUES (1, '2019-12-31'), (2, '2020-01-10'), (3, NULL);\n"),
+            "INSERT INTO public.events (user_id, happened_on) VALUES (2, '2020-01-10');\n"
+        );
+        assert_eq!(
+            transform(
+                "INSERT INTO public.events (user_id, happened_on) VALUES (1, '2019-12-31');\n"
+            ),
+            ""
+        );
+    }
+
+    fn fixed_column(name: &str, value: &str) -> (String, ColumnInfo) {
+        (
+            name.to_string(),
+            ColumnInfo::builder()
+                .with_name(name)
+                .with_transformer(
+                    TransformerType::Fixed,
+                    Some(HashMap::from([("value".to_string(), value.into())])),
+                )
+                .build(),
+        )
+    }
+
+    #[test]
+    fn insert_row_is_transformed() {
+        let insert_row =
+            "INSERT INTO public.users (id, first_name, last_name) VALUES (1, 'Peter', NULL);\n";
+        let strategies = Strategies::new_from(
+            "public.users".to_string(),
+            HashMap::from([
+                (
+                    "id".to_string(),
+                    ColumnInfo::builder().with_name("id").build(),
+                ),
+                fixed_column("first_name", "O'Neil"),
+                fixed_column("last_name", "Puckleberry"),
+            ]),
+        )
+        .unwrap();
+
+        let mut state = State {
+            position: Position::Normal,
+            types: Types::builder()
+                .add_type("public.users", "id", SubType::Integer)
+                .add_type("public.users", "first_name", SubType::Character)
+                .add_type("public.users", "last_name", SubType::Character)
+                .build(),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+        let transformed_row = parse(&mut rng, insert_row, &mut state, &strategies).unwrap();
+        assert_eq!(
+            "INSERT INTO public.users (id, first_name, last_name) VALUES (1, 'O''Neil', NULL);\n",
+            transformed_row
+        );
+        assert_eq!(state.position, Position::Normal);
+    }
+
+    #[test]
+    fn shuffled_columns_error_in_insert_rows() {
+        let insert_row = "INSERT INTO public.users (id, job_title) VALUES (1, 'Chef');\n";
+        let strategies = Strategies::new_from(
+            "public.users".to_string(),
+            HashMap::from([
+                (
+                    "id".to_string(),
+                    ColumnInfo::builder().with_name("id").build(),
+                ),
+                (
+                    "job_title".to_string(),
+                    ColumnInfo::builder()
+                        .with_name("job_title")
+                        .with_transformer(TransformerType::Shuffle, None)
+                        .build(),
+                ),
+            ]),
+        )
+        .unwrap();
+        let mut state = State::new();
+        let mut rng = rng::get();
+
+        let err = parse(&mut rng, insert_row, &mut state, &strategies).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Unable to transform public.users: Shuffle can only be used on tables dumped with COPY, not INSERT statements"
+        );
+    }
+
+    #[test]
+    fn multi_line_insert_is_held_until_it_is_complete() {
+        let strategies = Strategies::new_from(
+            "public.users".to_string(),
+            HashMap::from([
+                (
+                    "id".to_string(),
+                    ColumnInfo::builder().with_name("id").build(),
+                ),
+                fixed_column("first_name", "first"),
+            ]),
+        )
+        .unwrap();
+
+        let mut types = Types::builder()
+            .add_type("public.users", "id", SubType::Integer)
+            .add_type("public.users", "first_name", SubType::Character)
+            .build();
+        types.insert_column_names(
+            "public.users",
+            vec!["id".to_string(), "first_name".to_string()],
+        );
+        let mut state = State {
+            position: Position::Normal,
+            types,
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
+        };
+        let mut rng = rng::get();
+
+        let lines = [
+            "INSERT INTO public.users VALUES\n",
+            "\t(1, 'a name;\n",
+            "over two lines'),\n",
+            "\t(2, 'Peter');\n",
+        ];
+        let transformed: Vec<_> = lines
+            .iter()
+            .map(|line| {
+                parse(&mut rng, line, &mut state, &strategies)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+
+        assert_eq!(
+            transformed,
+            vec![
+                "",
+                "",
+                "",
+                "INSERT INTO public.users VALUES\n\t(1, 'first'),\n\t(2, 'first');\n"
+            ]
+        );
+        assert_eq!(state.position, Position::Normal);
+    }
 }
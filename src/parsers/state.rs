@@ -1,4 +1,7 @@
+use crate::errors::AnonymiserError;
 use crate::parsers::copy_row::CurrentTableTransforms;
+use crate::parsers::insert_row::Quote;
+use crate::parsers::strategy_errors::{IncompatibleTransformer, IncompatibleTransformers};
 use crate::parsers::types::Column;
 use crate::parsers::types::Type;
 use std::collections::HashMap;
@@ -6,17 +9,32 @@ use std::collections::HashMap;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Types {
     types: HashMap<String, HashMap<String, Type>>,
+    column_names: HashMap<String, Vec<String>>,
 }
 
 impl Types {
     pub fn new(initial: HashMap<String, HashMap<String, Type>>) -> Self {
-        Types { types: initial }
+        Types {
+            types: initial,
+            column_names: HashMap::default(),
+        }
     }
 
     pub fn insert(&mut self, table_name: &str, column_types: HashMap<String, Type>) {
         self.types.insert(table_name.to_string(), column_types);
     }
 
+    /// Records the order columns were created in, which is the order values are given in by an
+    /// INSERT without a column list
+    pub fn insert_column_names(&mut self, table_name: &str, column_names: Vec<String>) {
+        self.column_names
+            .insert(table_name.to_string(), column_names);
+    }
+
+    pub fn column_names(&self, table_name: &str) -> Option<&Vec<String>> {
+        self.column_names.get(table_name)
+    }
+
     pub fn lookup(&self, table_name: &str, column_name: &str) -> Option<&Type> {
         self.types
             .get(table_name)
@@ -28,9 +46,36 @@ impl Types {
     }
 }
 
+/// The number of rows of each table read so far
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RowCounts {
+    counts: HashMap<String, usize>,
+}
+
+impl RowCounts {
+    /// Counts a row of `table_name`, returning its position in the table
+    pub fn next(&mut self, table_name: &str) -> usize {
+        match self.counts.get_mut(table_name) {
+            Some(count) => {
+                *count += 1;
+                *count - 1
+            }
+            None => {
+                self.counts.insert(table_name.to_string(), 1);
+                0
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct State {
     pub position: Position,
     pub types: Types,
+    /// Found as each CREATE TABLE is read, and reported together before any data is transformed
+    pub incompatible_transformers: Vec<IncompatibleTransformer>,
+    /// Only counted when subsetting, which picks out rows by their position in their table
+    pub rows_read: RowCounts,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -43,6 +88,14 @@ pub enum Position {
         table_name: String,
         types: Vec<Column>,
     },
+    InInsert {
+        statement: String,
+        quote: Quote,
+    },
+    /// Inside a function body or other dollar quoted string, which ends at the next `tag`
+    InDollarQuote {
+        tag: String,
+    },
 }
 
 impl State {
@@ -50,6 +103,8 @@ impl State {
         State {
             position: Position::Normal,
             types: Types::new(HashMap::default()),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         }
     }
 
@@ -69,10 +124,31 @@ impl State {
                     .map(|c| (c.name.clone(), c.data_type.clone()))
                     .collect::<HashMap<String, Type>>(),
             );
+            self.types.insert_column_names(
+                table_name,
+                table_types.iter().map(|c| c.name.clone()).collect(),
+            );
         }
 
         self.position = new_position
     }
+
+    /// Fails with every column found so far whose transformer can't be used on its type
+    pub fn check_transformers(&self) -> Result<(), AnonymiserError> {
+        if self.incompatible_transformers.is_empty() {
+            Ok(())
+        } else {
+            Err(AnonymiserError::IncompatibleTransformers(
+                IncompatibleTransformers(self.incompatible_transformers.clone()),
+            ))
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -121,20 +197,25 @@ mod tests {
                 ],
             },
             types: Types::new(HashMap::default()),
+            incompatible_transformers: Vec::new(),
+            rows_read: RowCounts::default(),
         };
 
         state.update_position(Position::Normal);
 
-        assert_eq!(state.position, Position::Normal);
-        assert_eq!(
-            state.types,
-            Types::new(HashMap::from_iter([(
-                "table-mc-tableface".to_string(),
-                HashMap::from_iter([
-                    ("column".to_string(), Type::integer()),
-                    ("column_2".to_string(), Type::character())
-                ])
-            )]))
+        let mut expected_types = Types::new(HashMap::from_iter([(
+            "table-mc-tableface".to_string(),
+            HashMap::from_iter([
+                ("column".to_string(), Type::integer()),
+                ("column_2".to_string(), Type::character()),
+            ]),
+        )]));
+        expected_types.insert_column_names(
+            "table-mc-tableface",
+            vec!["column".to_string(), "column_2".to_string()],
         );
+
+        assert_eq!(state.position, Position::Normal);
+        assert_eq!(state.types, expected_types);
     }
 }
@@ -1,14 +1,36 @@
-use crate::parsers::strategy_errors::{DbErrors, ValidationErrors};
+use crate::parsers::filter::{self, Filter};
+use crate::parsers::rng;
+use crate::parsers::strategy_errors::{
+    DbErrors, IncompatibleTransformer, InvalidFilter, InvalidTransformerArgs, ValidationErrors,
+};
 use crate::parsers::strategy_structs::*;
+use crate::parsers::subset::Subset;
+use crate::parsers::template::{self, Template};
+use crate::parsers::transformer::{self, CompiledTransformer};
+use crate::parsers::types::Type;
 use itertools::{Either, Itertools};
+use rand::Rng;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 type ColumnNamesToInfo = HashMap<String, ColumnInfo>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone)]
 pub struct Strategies {
     tables: HashMap<String, TableStrategy>,
+    /// The transformers in `tables` with their args parsed, by table and then column name
+    compiled: HashMap<String, HashMap<String, CompiledTransformer>>,
+    filters: HashMap<String, Filter>,
+    deterministic_key: Option<String>,
+    /// Secret for the random draws shared by every row with the same value in a transformer's
+    /// `key_column`, picked at random unless there's a seed or deterministic key
+    entity_key: String,
+    /// Whether the strategies are for a seeded run, so have to give the same output every time
+    seeded: bool,
+    /// The rows kept by `--subset`, found by reading the whole dump before it's anonymised
+    subset: Option<Arc<Subset>>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -21,25 +43,58 @@ impl Strategies {
     pub fn new() -> Strategies {
         Strategies {
             tables: HashMap::new(),
+            compiled: HashMap::new(),
+            filters: HashMap::new(),
+            deterministic_key: None,
+            entity_key: random_entity_key(),
+            seeded: false,
+            subset: None,
         }
     }
 
+    // Only returned once, when the strategy file is loaded, so its size doesn't matter
+    #[allow(clippy::result_large_err)]
     pub fn from_strategies_in_file(
         strategies_in_file: Vec<StrategyInFile>,
         transformer_overrides: &TransformerOverrides,
     ) -> Result<Strategies, ValidationErrors> {
         let mut transformed_strategies = Strategies::new();
+        transformed_strategies.deterministic_key = transformer_overrides.deterministic_key.clone();
         let mut errors = ValidationErrors::new();
 
         for strategy in strategies_in_file {
+            match table_filter(&strategy) {
+                Ok(Some(filter)) => {
+                    transformed_strategies
+                        .filters
+                        .insert(strategy.table_name.clone(), filter);
+                }
+                Ok(None) => (),
+                Err(message) => errors.invalid_filters.push(InvalidFilter {
+                    table_name: strategy.table_name.clone(),
+                    message,
+                }),
+            }
             if strategy.truncate {
                 transformed_strategies.insert_truncate(strategy.table_name);
             } else {
                 let mut columns = HashMap::<String, ColumnInfo>::new();
-                for column in strategy.columns {
+                let column_names: HashSet<String> = strategy
+                    .columns
+                    .iter()
+                    .map(|column| column.name.clone())
+                    .collect();
+                errors
+                    .invalid_transformer_args
+                    .extend(template_errors(&strategy.table_name, &strategy.columns));
+                for mut column in strategy.columns {
+                    if let Some(locale) = &strategy.locale {
+                        column.transformer =
+                            transformer::with_default_locale(column.transformer, locale);
+                    }
                     if (column.data_category == DataCategory::PotentialPii
                         || column.data_category == DataCategory::Pii)
-                        && column.transformer.name == TransformerType::Identity
+                        && transformer::can_pick_identity(&column.transformer)
                     {
                         errors
                             .unanonymised_pii
@@ -55,6 +110,46 @@ impl Strategies {
                             .error_transformer_types
                             .push(create_simple_column(&strategy.table_name, &column.name));
                     }
+                    if let Err(message) = transformer::validate_args(&column.transformer) {
+                        errors
+                            .invalid_transformer_args
+                            .push(InvalidTransformerArgs {
+                                column: create_simple_column(&strategy.table_name, &column.name),
+                                message,
+                            });
+                    }
+                    if let Some(key_column) = transformer::key_column(&column.transformer) {
+                        if !column_names.contains(key_column) {
+                            errors
+                                .invalid_transformer_args
+                                .push(InvalidTransformerArgs {
+                                    column: create_simple_column(
+                                        &strategy.table_name,
+                                        &column.name,
+                                    ),
+                                    message: format!(
+                                    "'key_column' must be another column in the table, got: '{}'",
+                                    key_column
+                                ),
+                                });
+                        }
+                    }
+                    for switch_column in transformer::switch_columns(&column.transformer) {
+                        if !column_names.contains(&switch_column) || switch_column == column.name {
+                            errors
+                                .invalid_transformer_args
+                                .push(InvalidTransformerArgs {
+                                    column: create_simple_column(
+                                        &strategy.table_name,
+                                        &column.name,
+                                    ),
+                                    message: format!(
+                                        "'column' must be another column in the table, got: '{}'",
+                                        switch_column
+                                    ),
+                                });
+                        }
+                    }
                     let result = columns.insert(
                         column.name.clone(),
                         ColumnInfo {
@@ -71,9 +166,21 @@ impl Strategies {
                     }
                 }
 
-                let result = transformed_strategies.insert(strategy.table_name.clone(), columns);
-                if result.is_some() {
-                    errors.duplicate_tables.push(strategy.table_name);
+                match transformed_strategies.insert(strategy.table_name.clone(), columns) {
+                    Ok(Some(_)) => errors.duplicate_tables.push(strategy.table_name),
+                    Ok(None) => (),
+                    Err(compile_errors) => {
+                        // Most will have been found by `validate_args` already
+                        for error in compile_errors.invalid_transformer_args {
+                            if !errors
+                                .invalid_transformer_args
+                                .iter()
+                                .any(|reported| reported.column == error.column)
+                            {
+                                errors.invalid_transformer_args.push(error);
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -90,18 +197,88 @@ impl Strategies {
         self.tables.get(table_name)
     }
 
+    /// The filter picking which of the table's rows are kept, if it has one
+    pub fn filter_for(&self, table_name: &str) -> Option<&Filter> {
+        self.filters.get(table_name)
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.seeded
+    }
+
+    pub fn subset(&self) -> Option<&Subset> {
+        self.subset.as_deref()
+    }
+
+    /// These strategies, keeping only the rows in `subset`
+    pub fn with_subset(&self, subset: Subset) -> Strategies {
+        Strategies {
+            subset: Some(Arc::new(subset)),
+            ..self.clone()
+        }
+    }
+
+    pub fn deterministic_key(&self) -> Option<&str> {
+        self.deterministic_key.as_deref()
+    }
+
+    pub fn entity_key(&self) -> &str {
+        self.deterministic_key
+            .as_deref()
+            .unwrap_or(&self.entity_key)
+    }
+
+    /// These strategies with their entity key picked using `seed`, so seeded runs give the same
+    /// output
+    pub fn seeded(&self, seed: Option<u64>) -> Cow<'_, Strategies> {
+        match seed {
+            Some(seed) => Cow::Owned(Strategies {
+                entity_key: format!("{:032x}", rng::seeded(seed).gen::<u128>()),
+                seeded: true,
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Adds the transformers for a table's columns, compiling their args (see
+    /// `transformer::compile`). If any of them can't be compiled the table isn't added, and the
+    /// columns are returned in the errors' `invalid_transformer_args`
+    // Only returned while the strategies are built, so its size doesn't matter
+    #[allow(clippy::result_large_err)]
     pub fn insert(
         &mut self,
         table_name: String,
         columns: HashMap<String, ColumnInfo>,
-    ) -> Option<TableStrategy> {
-        self.tables
-            .insert(table_name, TableStrategy::Columns(columns))
+    ) -> Result<Option<TableStrategy>, ValidationErrors> {
+        let compiled = compile_columns(&table_name, &columns)?;
+        self.compiled.insert(table_name.clone(), compiled);
+        Ok(self
+            .tables
+            .insert(table_name, TableStrategy::Columns(columns)))
     }
     pub fn insert_truncate(&mut self, table_name: String) -> Option<TableStrategy> {
+        self.compiled.remove(&table_name);
         self.tables.insert(table_name, TableStrategy::Truncate)
     }
 
+    /// The transformer for `column` of `table_name` with its args compiled when it was added,
+    /// or `column`'s own transformer uncompiled if it wasn't added with these strategies
+    pub(crate) fn compiled_transformer<'a>(
+        &'a self,
+        table_name: &str,
+        column: &ColumnInfo,
+    ) -> Cow<'a, CompiledTransformer> {
+        match self
+            .compiled
+            .get(table_name)
+            .and_then(|columns| columns.get(&column.name))
+        {
+            Some(compiled) => Cow::Borrowed(compiled),
+            None => Cow::Owned(CompiledTransformer::uncompiled(column.transformer.clone())),
+        }
+    }
+
     pub fn validate_against_db(
         &self,
         columns_from_db: HashSet<SimpleColumn>,
@@ -118,9 +295,9 @@ impl Strategies {
         let columns_from_strategy_file: HashSet<SimpleColumn> = columns_by_table
             .iter()
             .flat_map(|(table_name, columns)| {
-                columns.iter().map(|(column_name, _column_info)| {
-                    create_simple_column(table_name, column_name)
-                })
+                columns
+                    .keys()
+                    .map(|column_name| create_simple_column(table_name, column_name))
             })
             .collect();
 
@@ -152,6 +329,32 @@ impl Strategies {
         }
     }
 
+    /// Every column of `table_name` whose transformer can't be used on the column's type. Columns
+    /// (or tables) missing from the strategy file are left for when the table's data is read
+    pub fn incompatible_transformers(
+        &self,
+        table_name: &str,
+        column_types: &HashMap<String, Type>,
+    ) -> Vec<IncompatibleTransformer> {
+        let Some(TableStrategy::Columns(columns)) = self.tables.get(table_name) else {
+            return Vec::new();
+        };
+
+        column_types
+            .iter()
+            .filter_map(|(column_name, column_type)| {
+                let column = columns.get(column_name)?;
+                (!transformer::can_apply(&column.transformer, column_type)).then(|| {
+                    IncompatibleTransformer {
+                        column: create_simple_column(table_name, column_name),
+                        transformer: column.transformer.name.clone(),
+                        column_type: column_type.clone(),
+                    }
+                })
+            })
+            .collect()
+    }
+
     #[allow(dead_code)] //This is used in tests for convenience
     pub fn transformer_for_column<'a>(
         &self,
@@ -168,11 +371,95 @@ impl Strategies {
     }
 
     #[allow(dead_code)] //This is used in tests for convenience
-    pub fn new_from(table_name: String, columns: HashMap<String, ColumnInfo>) -> Strategies {
-        Strategies {
+    #[allow(clippy::result_large_err)]
+    pub fn new_from(
+        table_name: String,
+        columns: HashMap<String, ColumnInfo>,
+    ) -> Result<Strategies, ValidationErrors> {
+        Ok(Strategies {
+            compiled: HashMap::from([(
+                table_name.clone(),
+                compile_columns(&table_name, &columns)?,
+            )]),
             tables: HashMap::from([(table_name, TableStrategy::Columns(columns))]),
+            filters: HashMap::new(),
+            deterministic_key: None,
+            entity_key: random_entity_key(),
+            seeded: false,
+            subset: None,
+        })
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn compile_columns(
+    table_name: &str,
+    columns: &HashMap<String, ColumnInfo>,
+) -> Result<HashMap<String, CompiledTransformer>, ValidationErrors> {
+    let mut compiled = HashMap::new();
+    let mut errors = ValidationErrors::new();
+    for (column_name, column) in columns {
+        match transformer::compile(column.transformer.clone()) {
+            Ok(transformer) => {
+                compiled.insert(column_name.clone(), transformer);
+            }
+            Err(message) => errors
+                .invalid_transformer_args
+                .push(InvalidTransformerArgs {
+                    column: create_simple_column(table_name, column_name),
+                    message,
+                }),
         }
     }
+
+    if ValidationErrors::is_empty(&errors) {
+        Ok(compiled)
+    } else {
+        errors
+            .invalid_transformer_args
+            .sort_by(|a, b| a.column.cmp(&b.column));
+        Err(errors)
+    }
+}
+
+fn random_entity_key() -> String {
+    format!("{:032x}", rng::get().gen::<u128>())
+}
+
+/// The compiled transformers are parsed from `tables`, the entity key and whether the run is
+/// seeded come from the run, and the subset is found from the dump, so they aren't compared
+impl PartialEq for Strategies {
+    fn eq(&self, other: &Self) -> bool {
+        self.tables == other.tables
+            && self.filters == other.filters
+            && self.deterministic_key == other.deterministic_key
+    }
+}
+
+impl Eq for Strategies {}
+
+/// The keys are secrets, so are left out in case the strategies end up in a log or error
+/// message, and the compiled transformers are left out as they're the same as `tables`
+impl std::fmt::Debug for Strategies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Strategies")
+            .field("tables", &self.tables)
+            .field("filters", &self.filters)
+            .field(
+                "deterministic_key",
+                &self.deterministic_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("entity_key", &"<redacted>")
+            .field("seeded", &self.seeded)
+            .field("subset", &self.subset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Strategies {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 fn create_simple_column(table_name: &str, column_name: &str) -> SimpleColumn {
@@ -182,6 +469,85 @@ fn create_simple_column(table_name: &str, column_name: &str) -> SimpleColumn {
     }
 }
 
+/// Parses the table's filter, checking it only uses the table's columns
+fn table_filter(strategy: &StrategyInFile) -> Result<Option<Filter>, String> {
+    let Some(filter) = &strategy.filter else {
+        return Ok(None);
+    };
+    if strategy.truncate {
+        return Err("The table is truncated, so has no rows to filter".to_string());
+    }
+
+    let filter = filter::parse(filter)?;
+    for column in filter.columns() {
+        if !strategy.columns.iter().any(|c| c.name == column) {
+            return Err(format!(
+                "'{}' in the filter isn't a column in the table",
+                column
+            ));
+        }
+    }
+    Ok(Some(filter))
+}
+
+/// Checks the columns `Template` transformers use are other columns in the table, which aren't
+/// shuffled (as that happens after the templates are filled in) and don't lead back to the
+/// template's own column
+fn template_errors(table_name: &str, columns: &[ColumnInFile]) -> Vec<InvalidTransformerArgs> {
+    let templates: Vec<(&str, Template)> = columns
+        .iter()
+        .filter(|column| column.transformer.name == TransformerType::Template)
+        .filter_map(|column| {
+            let template = transformer::parse_template_arg(&column.transformer.args).ok()?;
+            Some((column.name.as_str(), template))
+        })
+        .collect();
+    let error = |column_name: &str, message: String| InvalidTransformerArgs {
+        column: create_simple_column(table_name, column_name),
+        message,
+    };
+
+    let mut errors = Vec::new();
+    for (column_name, template) in &templates {
+        for used_column in template.columns() {
+            match columns.iter().find(|column| column.name == used_column) {
+                None => errors.push(error(
+                    column_name,
+                    format!(
+                        "'{}' in the template isn't a column in the table",
+                        used_column
+                    ),
+                )),
+                Some(column) if column.transformer.name == TransformerType::Shuffle => {
+                    errors.push(error(
+                        column_name,
+                        format!(
+                            "'{}' in the template is shuffled, so can't be used in it",
+                            used_column
+                        ),
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+    }
+
+    let uses: HashMap<&str, Vec<&str>> = templates
+        .iter()
+        .map(|(column_name, template)| (*column_name, template.columns()))
+        .collect();
+    if let Some(cycle) = template::find_cycle(&uses) {
+        errors.push(error(
+            cycle[0],
+            format!(
+                "The template uses its own column's value: {}",
+                cycle.join(" -> ")
+            ),
+        ));
+    }
+    errors
+}
+
 fn apply_transformer_overrides(
     data_category: DataCategory,
     overrides: &TransformerOverrides,
@@ -216,6 +582,7 @@ fn transformer(column: ColumnInFile, overrides: &TransformerOverrides) -> Transf
 mod tests {
     use super::*;
     use crate::parsers::strategy_structs::{ColumnInfo, TransformerType};
+    use crate::parsers::types::SubType;
     use std::collections::HashMap;
 
     #[test]
@@ -325,6 +692,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn incompatible_transformers_lists_every_column_that_does_not_suit_its_type() {
+        let strategies = create_strategy(
+            "public.person",
+            [
+                create_column("id"),
+                create_column_with_data_and_transformer_type(
+                    "age",
+                    DataCategory::Pii,
+                    TransformerType::FakeEmail,
+                ),
+                create_column_with_data_and_transformer_type(
+                    "preferences",
+                    DataCategory::General,
+                    TransformerType::Scramble,
+                ),
+                create_column_with_data_and_transformer_type(
+                    "email",
+                    DataCategory::Pii,
+                    TransformerType::FakeEmail,
+                ),
+            ]
+            .into_iter(),
+        );
+        let column_types = HashMap::from([
+            ("id".to_string(), Type::single_value(SubType::Integer)),
+            ("age".to_string(), Type::single_value(SubType::SmallInt)),
+            ("preferences".to_string(), Type::single_value(SubType::Json)),
+            ("email".to_string(), Type::single_value(SubType::Citext)),
+            (
+                "not_in_strategy".to_string(),
+                Type::single_value(SubType::Json),
+            ),
+        ]);
+
+        let mut incompatible = strategies.incompatible_transformers("public.person", &column_types);
+        incompatible.sort_by(|a, b| a.column.cmp(&b.column));
+
+        assert_eq!(
+            incompatible,
+            vec![
+                IncompatibleTransformer {
+                    column: create_simple_column("public.person", "age"),
+                    transformer: TransformerType::FakeEmail,
+                    column_type: Type::single_value(SubType::SmallInt),
+                },
+                IncompatibleTransformer {
+                    column: create_simple_column("public.person", "preferences"),
+                    transformer: TransformerType::Scramble,
+                    column_type: Type::single_value(SubType::Json),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn incompatible_transformers_ignores_truncated_tables() {
+        let mut strategies = Strategies::new();
+        strategies.insert_truncate("public.location".to_string());
+        let column_types = HashMap::from([("id".to_string(), Type::single_value(SubType::Uuid))]);
+
+        assert!(strategies
+            .incompatible_transformers("public.location", &column_types)
+            .is_empty());
+    }
+
     const TABLE_NAME: &str = "gert_lush_table";
     const PII_COLUMN_NAME: &str = "pii_column";
     const COMMERCIALLY_SENSITIVE_COLUMN_NAME: &str = "commercially_sensitive_column";
@@ -338,6 +771,8 @@ mod tests {
             table_name: TABLE_NAME.to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![column_in_file(
                 DataCategory::Pii,
                 column_name,
@@ -355,7 +790,8 @@ mod tests {
                     .with_transformer(TransformerType::Scramble, None)
                     .build(),
             )]),
-        );
+        )
+        .unwrap();
         let parsed = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
             .expect("we shouldnt have duplicate columns!");
         assert_eq!(expected, parsed);
@@ -373,18 +809,24 @@ mod tests {
                 table_name: TABLE_NAME.to_string(),
                 description: "description".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![],
             },
             StrategyInFile {
                 table_name: TABLE_NAME.to_string(),
                 description: "description".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![],
             },
             StrategyInFile {
                 table_name: table2_name.to_string(),
                 description: "description".to_string(),
                 truncate: false,
+                locale: None,
+                filter: None,
                 columns: vec![duplicated_column.clone(), duplicated_column],
             },
         ];
@@ -399,12 +841,338 @@ mod tests {
         );
     }
 
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_invalid_transformer_args() {
+        let mut column = column_in_file(DataCategory::Pii, "details", TransformerType::JsonPaths);
+        column.transformer.args = Some(HashMap::from([(
+            "contact.email".to_string(),
+            TransformerArg::Transformer(Transformer {
+                name: TransformerType::FakeEmail,
+                args: None,
+            }),
+        )]));
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: None,
+            columns: vec![column],
+        }];
+
+        let error = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
+            .expect_err("We should have an invalid args error");
+
+        assert_eq!(
+            error.invalid_transformer_args,
+            vec![InvalidTransformerArgs {
+                column: create_simple_column(TABLE_NAME, "details"),
+                message: "Invalid JSONPath \"contact.email\": it must start with '$'".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn from_strategies_in_file_compiles_regex_patterns() {
+        let mut column = column_in_file(DataCategory::Pii, "notes", TransformerType::RegexReplace);
+        column.transformer.args = Some(HashMap::from([
+            ("pattern".to_string(), r"\S+@\S+".into()),
+            ("replacement".to_string(), "[email]".into()),
+        ]));
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: None,
+            columns: vec![column],
+        }];
+
+        let strategies =
+            Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none()).unwrap();
+
+        let Some(TableStrategy::Columns(columns)) = strategies.for_table(TABLE_NAME) else {
+            panic!("Expected {} to have column strategies", TABLE_NAME);
+        };
+        assert!(matches!(
+            strategies.compiled_transformer(TABLE_NAME, &columns["notes"]),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn insert_returns_errors_for_args_that_do_not_compile() {
+        let column = ColumnInfo::builder()
+            .with_name("phone")
+            .with_transformer(
+                TransformerType::Mask,
+                Some(HashMap::from([("keep_end".to_string(), "four".into())])),
+            )
+            .build();
+        let mut strategies = Strategies::new();
+
+        let error = strategies
+            .insert(
+                TABLE_NAME.to_string(),
+                HashMap::from([("phone".to_string(), column)]),
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            error.invalid_transformer_args,
+            vec![InvalidTransformerArgs {
+                column: create_simple_column(TABLE_NAME, "phone"),
+                message: "'keep_end' must be a number of characters to keep, got: 'four'"
+                    .to_string(),
+            }]
+        );
+        assert_eq!(strategies.for_table(TABLE_NAME), None);
+    }
+
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_key_columns_not_in_the_table() {
+        let mut column = column_in_file(DataCategory::Pii, "born_on", TransformerType::ShiftDate);
+        column.transformer.args = Some(HashMap::from([
+            ("max_days".to_string(), "30".into()),
+            ("key_column".to_string(), "user_id".into()),
+        ]));
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: None,
+            columns: vec![column],
+        }];
+
+        let error = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
+            .expect_err("We should have an invalid args error");
+
+        assert_eq!(
+            error.invalid_transformer_args,
+            vec![InvalidTransformerArgs {
+                column: create_simple_column(TABLE_NAME, "born_on"),
+                message: "'key_column' must be another column in the table, got: 'user_id'"
+                    .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_columns_not_supported_in_the_table_locale() {
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: Some("fr_FR".to_string()),
+            filter: None,
+            columns: vec![
+                column_in_file(DataCategory::Pii, "name", TransformerType::FakeFullName),
+                column_in_file(DataCategory::Pii, "city", TransformerType::FakeCity),
+                column_in_file(DataCategory::Pii, "email", TransformerType::FakeEmail),
+            ],
+        }];
+
+        let error = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
+            .expect_err("We should have an invalid args error");
+
+        assert_eq!(
+            error.invalid_transformer_args,
+            vec![InvalidTransformerArgs {
+                column: create_simple_column(TABLE_NAME, "city"),
+                message: "FakeCity isn't supported in the fr_FR locale".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn from_strategies_in_file_keeps_table_filters() {
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: Some("id IN (1, 2)".to_string()),
+            columns: vec![column_in_file(
+                DataCategory::General,
+                "id",
+                TransformerType::Identity,
+            )],
+        }];
+
+        let parsed =
+            Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none()).unwrap();
+
+        assert_eq!(parsed.filter_for(TABLE_NAME).unwrap().columns(), vec!["id"]);
+        assert_eq!(parsed.filter_for("public.other"), None);
+    }
+
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_invalid_filters() {
+        let table = |table_name: &str, truncate: bool, filter: &str| StrategyInFile {
+            table_name: table_name.to_string(),
+            description: "description".to_string(),
+            truncate,
+            locale: None,
+            filter: Some(filter.to_string()),
+            columns: vec![column_in_file(
+                DataCategory::General,
+                "id",
+                TransformerType::Identity,
+            )],
+        };
+        let strategies = vec![
+            table("public.a", false, "id IN (1, 2"),
+            table("public.b", false, "tenant_id = 1"),
+            table("public.c", true, "id = 1"),
+        ];
+
+        let error = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
+            .expect_err("We should have an invalid filter error");
+
+        assert_eq!(
+            error.invalid_filters,
+            vec![
+                InvalidFilter {
+                    table_name: "public.a".to_string(),
+                    message: "Invalid filter \"id IN (1, 2\": expected ')', but the filter ended"
+                        .to_string(),
+                },
+                InvalidFilter {
+                    table_name: "public.b".to_string(),
+                    message: "'tenant_id' in the filter isn't a column in the table".to_string(),
+                },
+                InvalidFilter {
+                    table_name: "public.c".to_string(),
+                    message: "The table is truncated, so has no rows to filter".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_switches_on_columns_not_in_the_table() {
+        let mut value = column_in_file(DataCategory::Pii, "value", TransformerType::Switch);
+        value.transformer.args = Some(HashMap::from([
+            ("column".to_string(), "type".into()),
+            (
+                "email".to_string(),
+                Transformer {
+                    name: TransformerType::FakeEmail,
+                    args: None,
+                }
+                .into(),
+            ),
+            (
+                "default".to_string(),
+                Transformer {
+                    name: TransformerType::Scramble,
+                    args: None,
+                }
+                .into(),
+            ),
+        ]));
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: None,
+            columns: vec![
+                column_in_file(DataCategory::General, "kind", TransformerType::Identity),
+                value,
+            ],
+        }];
+
+        let error = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
+            .expect_err("We should have an invalid args error");
+
+        assert_eq!(
+            error.invalid_transformer_args,
+            vec![InvalidTransformerArgs {
+                column: create_simple_column(TABLE_NAME, "value"),
+                message: "'column' must be another column in the table, got: 'type'".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_templates_using_columns_they_cannot() {
+        let template = |name: &str, template: &str| {
+            let mut column = column_in_file(DataCategory::Pii, name, TransformerType::Template);
+            column.transformer.args =
+                Some(HashMap::from([("template".to_string(), template.into())]));
+            column
+        };
+        let strategies = vec![StrategyInFile {
+            table_name: TABLE_NAME.to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: None,
+            columns: vec![
+                column_in_file(
+                    DataCategory::Pii,
+                    "first_name",
+                    TransformerType::FakeFirstName,
+                ),
+                column_in_file(DataCategory::Pii, "job_title", TransformerType::Shuffle),
+                template("full_name", "{first_name} {last_name}"),
+                template("display_name", "{full_name} ({nickname})"),
+                template("nickname", "{display_name}"),
+                template("title", "{job_title}"),
+            ],
+        }];
+
+        let error = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none())
+            .expect_err("We should have an invalid args error");
+
+        assert_eq!(
+            error.invalid_transformer_args,
+            vec![
+                InvalidTransformerArgs {
+                    column: create_simple_column(TABLE_NAME, "full_name"),
+                    message: "'last_name' in the template isn't a column in the table".to_string(),
+                },
+                InvalidTransformerArgs {
+                    column: create_simple_column(TABLE_NAME, "title"),
+                    message: "'job_title' in the template is shuffled, so can't be used in it"
+                        .to_string(),
+                },
+                InvalidTransformerArgs {
+                    column: create_simple_column(TABLE_NAME, "display_name"),
+                    message: "The template uses its own column's value: display_name -> nickname -> display_name".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn seeded_strategies_have_the_same_entity_key_for_the_same_seed() {
+        let strategies = Strategies::new();
+
+        assert_eq!(
+            strategies.seeded(Some(1)).entity_key(),
+            Strategies::new().seeded(Some(1)).entity_key()
+        );
+        assert_ne!(
+            strategies.seeded(Some(1)).entity_key(),
+            strategies.seeded(Some(2)).entity_key()
+        );
+        assert_eq!(
+            strategies.seeded(None).entity_key(),
+            strategies.entity_key()
+        );
+    }
+
     #[test]
     fn from_strategies_in_file_returns_errors_for_columns_missing_data_category() {
         let strategies = vec![StrategyInFile {
             table_name: "public.person".to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![column_in_file(
                 DataCategory::Unknown,
                 "first_name",
@@ -427,6 +1195,8 @@ mod tests {
             table_name: "public.person".to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![column_in_file(
                 DataCategory::General,
                 "first_name",
@@ -449,6 +1219,8 @@ mod tests {
             table_name: "public.person".to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![
                 column_in_file(DataCategory::Pii, "first_name", TransformerType::Identity),
                 column_in_file(
@@ -472,12 +1244,100 @@ mod tests {
         );
     }
 
+    #[test]
+    fn from_strategies_in_file_returns_errors_for_pii_columns_with_switches_that_can_pick_identity()
+    {
+        let switch = |default| {
+            let mut column = column_in_file(DataCategory::Pii, "value", TransformerType::Switch);
+            column.transformer.args = Some(HashMap::from([
+                ("pattern".to_string(), "@".into()),
+                (
+                    "match".to_string(),
+                    Transformer {
+                        name: TransformerType::FakeEmail,
+                        args: None,
+                    }
+                    .into(),
+                ),
+                ("default".to_string(), default),
+            ]));
+            column
+        };
+        let nested_switch = Transformer {
+            name: TransformerType::Switch,
+            args: Some(HashMap::from([
+                ("pattern".to_string(), "^\\+".into()),
+                (
+                    "match".to_string(),
+                    Transformer {
+                        name: TransformerType::FakePhoneNumber,
+                        args: None,
+                    }
+                    .into(),
+                ),
+                (
+                    "default".to_string(),
+                    Transformer {
+                        name: TransformerType::Identity,
+                        args: None,
+                    }
+                    .into(),
+                ),
+            ])),
+        };
+        let strategies = vec![StrategyInFile {
+            table_name: "public.person".to_string(),
+            description: "description".to_string(),
+            truncate: false,
+            locale: None,
+            filter: None,
+            columns: vec![
+                ColumnInFile {
+                    name: "contact".to_string(),
+                    ..switch(
+                        Transformer {
+                            name: TransformerType::Identity,
+                            args: None,
+                        }
+                        .into(),
+                    )
+                },
+                ColumnInFile {
+                    name: "other_contact".to_string(),
+                    ..switch(nested_switch.into())
+                },
+                ColumnInFile {
+                    name: "scrambled_contact".to_string(),
+                    ..switch(
+                        Transformer {
+                            name: TransformerType::Scramble,
+                            args: None,
+                        }
+                        .into(),
+                    )
+                },
+            ],
+        }];
+
+        let result = Strategies::from_strategies_in_file(strategies, &TransformerOverrides::none());
+
+        assert_eq!(
+            result.unwrap_err().unanonymised_pii,
+            vec!(
+                create_simple_column("public.person", "contact"),
+                create_simple_column("public.person", "other_contact")
+            )
+        );
+    }
+
     #[test]
     fn from_strategies_in_file_ignores_transformers_for_potential_pii_if_flag_provided() {
         let strategies = vec![StrategyInFile {
             table_name: TABLE_NAME.to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![
                 column_in_file(
                     DataCategory::PotentialPii,
@@ -521,6 +1381,8 @@ mod tests {
             table_name: TABLE_NAME.to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![
                 column_in_file(
                     DataCategory::PotentialPii,
@@ -565,6 +1427,8 @@ mod tests {
             table_name: TABLE_NAME.to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![column_in_file(
                 DataCategory::General,
                 SCRAMBLED_COLUMN_NAME,
@@ -592,6 +1456,8 @@ mod tests {
             table_name: TABLE_NAME.to_string(),
             description: "description".to_string(),
             truncate: false,
+            locale: None,
+            filter: None,
             columns: vec![
                 column_in_file(
                     DataCategory::PotentialPii,
@@ -612,6 +1478,7 @@ mod tests {
                 allow_potential_pii: true,
                 allow_commercially_sensitive: true,
                 scramble_blank: true,
+                ..Default::default()
             },
         )
         .expect("we shouldnt have duplicate columns!");
@@ -629,6 +1496,39 @@ mod tests {
         assert_eq!(pii_column_transformer.name, TransformerType::Identity);
     }
 
+    #[test]
+    fn debug_output_redacts_the_keys() {
+        let strategies = Strategies::from_strategies_in_file(
+            Vec::new(),
+            &TransformerOverrides {
+                deterministic_key: Some("secret".to_string()),
+                ..TransformerOverrides::none()
+            },
+        )
+        .unwrap();
+
+        let debug = format!("{:?}", strategies);
+        let random = Strategies::new();
+
+        assert!(debug.contains("deterministic_key: Some(\"<redacted>\")"));
+        assert!(!debug.contains("secret"));
+        assert!(!format!("{:?}", random).contains(random.entity_key()));
+    }
+
+    #[test]
+    fn from_strategies_in_file_keeps_the_deterministic_key_if_provided() {
+        let parsed = Strategies::from_strategies_in_file(
+            vec![],
+            &TransformerOverrides {
+                deterministic_key: Some("secret".to_string()),
+                ..Default::default()
+            },
+        )
+        .expect("we shouldnt have any errors!");
+
+        assert_eq!(parsed.deterministic_key(), Some("secret"));
+    }
+
     fn transformer_for_column(column_name: &str, strategies: &Strategies) -> Transformer {
         strategies
             .transformer_for_column(TABLE_NAME, column_name)
@@ -664,7 +1564,9 @@ mod tests {
     where
         I: Iterator<Item = (String, ColumnInfo)>,
     {
-        strategies.insert(table_name.to_string(), HashMap::from_iter(columns));
+        strategies
+            .insert(table_name.to_string(), HashMap::from_iter(columns))
+            .unwrap();
     }
 
     fn create_column(column_name: &str) -> (String, ColumnInfo) {
@@ -1,11 +1,12 @@
 use crate::parsers::strategy_structs::*;
+use crate::parsers::types::Type;
 use itertools::Itertools;
 use std::fmt;
 use std::fmt::Write;
 
 #[derive(Debug)]
 pub enum StrategyFileError {
-    ValidationError(ValidationErrors),
+    ValidationError(Box<ValidationErrors>),
     DbMismatchError(DbErrors),
 }
 impl fmt::Display for StrategyFileError {
@@ -19,7 +20,7 @@ impl fmt::Display for StrategyFileError {
 
 impl From<ValidationErrors> for StrategyFileError {
     fn from(err: ValidationErrors) -> Self {
-        StrategyFileError::ValidationError(err)
+        StrategyFileError::ValidationError(Box::new(err))
     }
 }
 
@@ -73,6 +74,22 @@ pub struct ValidationErrors {
     pub unanonymised_pii: Vec<SimpleColumn>,
     pub duplicate_columns: Vec<SimpleColumn>,
     pub duplicate_tables: Vec<String>,
+    pub invalid_transformer_args: Vec<InvalidTransformerArgs>,
+    pub invalid_filters: Vec<InvalidFilter>,
+}
+
+/// A column whose transformer is missing args it needs, or has args that don't make sense
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidTransformerArgs {
+    pub column: SimpleColumn,
+    pub message: String,
+}
+
+/// A table whose filter can't be parsed, or can't be used on the table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidFilter {
+    pub table_name: String,
+    pub message: String,
 }
 
 impl fmt::Display for ValidationErrors {
@@ -120,15 +137,85 @@ impl fmt::Display for ValidationErrors {
             .unwrap()
         }
 
+        if !self.invalid_transformer_args.is_empty() {
+            let invalid_list = self
+                .invalid_transformer_args
+                .iter()
+                .sorted_by(|a, b| a.column.cmp(&b.column))
+                .map(|invalid| {
+                    format!(
+                        "{} => {}: {}",
+                        invalid.column.table_name, invalid.column.column_name, invalid.message
+                    )
+                })
+                .join("\n\t");
+            write!(
+                message,
+                "Some fields in strategy file have invalid transformer args\n\t{}\nPlease fix the args!\n\n",
+                invalid_list
+            )
+            .unwrap()
+        }
+
+        if !self.invalid_filters.is_empty() {
+            let invalid_list = self
+                .invalid_filters
+                .iter()
+                .sorted_by(|a, b| a.table_name.cmp(&b.table_name))
+                .map(|invalid| format!("{}: {}", invalid.table_name, invalid.message))
+                .join("\n\t");
+            write!(
+                message,
+                "Some tables in strategy file have invalid filters\n\t{}\nPlease fix the filters!\n\n",
+                invalid_list
+            )
+            .unwrap()
+        }
+
         write!(f, "{}", message)
     }
 }
+
+/// A column whose transformer can't give valid values for the column's type in the dump
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncompatibleTransformer {
+    pub column: SimpleColumn,
+    pub transformer: TransformerType,
+    pub column_type: Type,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct IncompatibleTransformers(pub Vec<IncompatibleTransformer>);
+
+impl fmt::Display for IncompatibleTransformers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let incompatible_list = self
+            .0
+            .iter()
+            .sorted_by(|a, b| a.column.cmp(&b.column))
+            .map(|incompatible| {
+                format!(
+                    "{} => {} ({:?} can't be used on {})",
+                    incompatible.column.table_name,
+                    incompatible.column.column_name,
+                    incompatible.transformer,
+                    incompatible.column_type
+                )
+            })
+            .join("\n\t");
+        write!(f,
+            "Some fields in strategy file have transformers that can't be used with their column types\n\t{}\nPlease add valid transformers!\n",
+            incompatible_list
+        )
+    }
+}
+
 fn column_to_message(column: &[SimpleColumn]) -> String {
-    return column
+    column
         .iter()
         .map(|c| format!("{} => {}", &c.table_name, &c.column_name))
         .sorted()
-        .join("\n\t");
+        .join("\n\t")
 }
 
 impl ValidationErrors {
@@ -139,6 +226,8 @@ impl ValidationErrors {
             unanonymised_pii: Vec::new(),
             duplicate_columns: Vec::new(),
             duplicate_tables: Vec::new(),
+            invalid_transformer_args: Vec::new(),
+            invalid_filters: Vec::new(),
         }
     }
     pub fn is_empty(to_check: &ValidationErrors) -> bool {
@@ -147,5 +236,13 @@ impl ValidationErrors {
             && to_check.unanonymised_pii.is_empty()
             && to_check.duplicate_columns.is_empty()
             && to_check.duplicate_tables.is_empty()
+            && to_check.invalid_transformer_args.is_empty()
+            && to_check.invalid_filters.is_empty()
+    }
+}
+
+impl Default for ValidationErrors {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -1,3 +1,4 @@
+use crate::errors::AnonymiserError;
 use crate::parsers::strategy_structs::*;
 use itertools::sorted;
 use itertools::Itertools;
@@ -5,22 +6,30 @@ use serde_json;
 use std::fs;
 use std::io::Write;
 
-pub fn read(file_name: &str) -> Result<Vec<StrategyInFile>, std::io::Error> {
-    let result = fs::read_to_string(file_name).map(|file_contents| {
-        serde_json::from_str::<Vec<StrategyInFile>>(&file_contents).unwrap_or_else(|e| {
-            panic!(
-                "Invalid json found in strategy file at '{}': {:#}",
-                file_name, e
-            )
-        })
-    });
-
-    match result {
-        Ok(_) => result,
-        Err(ref err) => match err.kind() {
-            std::io::ErrorKind::NotFound => result,
-            _ => panic!("Unable to read strategy file at {}: {:?}", file_name, err),
+pub fn read(file_name: &str) -> Result<Vec<StrategyInFile>, AnonymiserError> {
+    let file_contents = fs::read_to_string(file_name).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => AnonymiserError::StrategyFileMissing {
+            path: file_name.to_string(),
+        },
+        _ => AnonymiserError::InvalidStrategyFile {
+            path: file_name.to_string(),
+            message: format!("Unable to read strategy file: {}", err),
         },
+    })?;
+
+    serde_json::from_str::<Vec<StrategyInFile>>(&file_contents).map_err(|err| {
+        AnonymiserError::InvalidStrategyFile {
+            path: file_name.to_string(),
+            message: format!("Invalid json found: {:#}", err),
+        }
+    })
+}
+
+/// Reads the strategy file, or gives no strategies at all if it hasn't been created yet
+pub fn read_or_empty(file_name: &str) -> Result<Vec<StrategyInFile>, AnonymiserError> {
+    match read(file_name) {
+        Err(AnonymiserError::StrategyFileMissing { .. }) => Ok(Vec::new()),
+        result => result,
     }
 }
 
@@ -43,7 +52,7 @@ pub fn write(file_name: &str, mut new_file_contents: Vec<StrategyInFile>) -> std
 fn sort_columns(s: &mut StrategyInFile) {
     s.columns.sort_by(|a, b| a.name.cmp(&b.name))
 }
-pub fn to_csv(strategy_file: &str, csv_output_file: &str) -> std::io::Result<()> {
+pub fn to_csv(strategy_file: &str, csv_output_file: &str) -> Result<(), AnonymiserError> {
     let strategies = read(strategy_file)?;
     let p: Vec<String> = strategies
         .iter()
@@ -71,7 +80,7 @@ pub fn to_csv(strategy_file: &str, csv_output_file: &str) -> std::io::Result<()>
         .create(true)
         .truncate(true)
         .open(csv_output_file)?;
-    file.write_all(to_write.as_bytes()).unwrap();
+    file.write_all(to_write.as_bytes())?;
 
     Ok(())
 }
@@ -51,6 +51,16 @@ pub struct StrategyInFile {
     #[serde(default)]
     pub truncate: bool,
 
+    /// The locale for the table's fake names, addresses, cities, companies and phone numbers,
+    /// unless a column's transformer has a `locale` arg of its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+
+    /// A SQL-like condition on the table's original values, e.g. `tenant_id IN (1, 2)`. Only
+    /// rows it's true for are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+
     pub columns: Vec<ColumnInFile>,
 }
 
@@ -108,6 +118,8 @@ pub enum DataCategory {
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum TransformerType {
+    AddNoise,
+    Bucket,
     EmptyJson,
     Error,
     FakeBase16String,
@@ -130,9 +142,20 @@ pub enum TransformerType {
     FakeUUID,
     Fixed,
     Identity,
+    JsonPaths,
+    Mask,
     ObfuscateDay,
+    RegexReplace,
+    RoundNumber,
     Scramble,
     ScrambleBlank,
+    ScrubPii,
+    ShiftDate,
+    ShiftTimestamp,
+    Shuffle,
+    Switch,
+    Template,
+    TruncateDate,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -140,13 +163,59 @@ pub struct Transformer {
     pub name: TransformerType,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub args: Option<HashMap<String, String>>,
+    pub args: Option<TransformerArgs>,
+}
+
+pub type TransformerArgs = HashMap<String, TransformerArg>;
+
+/// An arg for a transformer, which is either a plain value or (for transformers that apply other
+/// transformers to parts of a value, like `JsonPaths`) another transformer
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TransformerArg {
+    Value(String),
+    Transformer(Transformer),
+}
+
+impl TransformerArg {
+    pub fn as_value(&self) -> Option<&str> {
+        match self {
+            TransformerArg::Value(value) => Some(value),
+            TransformerArg::Transformer(_) => None,
+        }
+    }
+
+    pub fn as_transformer(&self) -> Option<&Transformer> {
+        match self {
+            TransformerArg::Transformer(transformer) => Some(transformer),
+            TransformerArg::Value(_) => None,
+        }
+    }
+}
+
+impl From<&str> for TransformerArg {
+    fn from(value: &str) -> Self {
+        TransformerArg::Value(value.to_string())
+    }
+}
+
+impl From<String> for TransformerArg {
+    fn from(value: String) -> Self {
+        TransformerArg::Value(value)
+    }
+}
+
+impl From<Transformer> for TransformerArg {
+    fn from(transformer: Transformer) -> Self {
+        TransformerArg::Transformer(transformer)
+    }
 }
 
 pub struct TransformerOverrides {
     pub allow_potential_pii: bool,
     pub allow_commercially_sensitive: bool,
     pub scramble_blank: bool,
+    pub deterministic_key: Option<String>,
 }
 
 impl TransformerOverrides {
@@ -155,6 +224,7 @@ impl TransformerOverrides {
             allow_potential_pii: false,
             allow_commercially_sensitive: false,
             scramble_blank: false,
+            deterministic_key: None,
         }
     }
 }
@@ -0,0 +1,673 @@
+//! Subsets of a dump that keep its foreign keys intact, so it still restores with all of its
+//! constraints. Tables with a `filter` keep the rows it's true for, along with any rows those
+//! rows refer to, all the way down. Every other table keeps all of its rows, apart from those
+//! that refer to a row that isn't kept.
+//!
+//! pg_dump writes the foreign keys after the data, so `find` reads the dump through twice before
+//! it's anonymised: once for its foreign keys, and again for the values of the columns in them,
+//! to work out which rows are kept. `Subset::keeps` then picks them out by their position in
+//! their table as the dump is anonymised. Only the key columns of each row are held, each
+//! distinct value once, as numbers that are compared rather than hashes so that two values can
+//! never be mistaken for each other.
+
+use crate::errors::AnonymiserError;
+use crate::parsers::copy_row::TableTransformers;
+use crate::parsers::data_row;
+use crate::parsers::foreign_key::{self, ForeignKey};
+use crate::parsers::insert_row::{self, Quote};
+use crate::parsers::rng;
+use crate::parsers::row_parser;
+use crate::parsers::sanitiser;
+use crate::parsers::state::{Position, State, Types};
+use crate::parsers::strategies::Strategies;
+use crate::parsers::strategy_structs::ColumnInfo;
+use rand::rngs::SmallRng;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+/// The rows a subset of a dump keeps
+#[derive(Debug)]
+pub struct Subset {
+    /// Whether each row is kept, by its position in its table, for every table that's filtered
+    /// or has foreign keys to or from it
+    kept_rows: HashMap<String, Vec<bool>>,
+}
+
+/// The values of a row's key columns, as the number of each in `Values`
+type Key = Box<[u64]>;
+
+/// The number of a NULL, which no other value has
+const NULL: u64 = 0;
+
+/// Every distinct value read from a key column, as COPY text, and the number it's been given
+#[derive(Default)]
+struct Values {
+    numbers: HashMap<String, u64>,
+}
+
+impl Values {
+    fn add(&mut self, value: &str) -> u64 {
+        if value == "\\N" {
+            return NULL;
+        }
+        if let Some(number) = self.numbers.get(value) {
+            return *number;
+        }
+        let number = self.numbers.len() as u64 + 1;
+        self.numbers.insert(value.to_string(), number);
+        number
+    }
+}
+
+/// The rows of a table read from the dump, with the values of the columns its foreign keys use
+struct TableRows {
+    /// The columns in foreign keys to or from the table
+    columns: Vec<String>,
+    values: Vec<u64>,
+    count: usize,
+    /// Whether each row matched the table's filter, if it has one
+    matched: Option<Vec<bool>>,
+}
+
+/// A foreign key between two tables with rows in the dump
+struct Link {
+    table: usize,
+    columns: Vec<usize>,
+    /// The referenced table and columns, or None if the referenced table has no rows in the
+    /// dump (e.g. it's truncated)
+    referenced: Option<(usize, Vec<usize>)>,
+    /// The row each referenced key is in
+    referenced_rows: HashMap<Key, usize>,
+    /// The rows referring to each key
+    referring_rows: HashMap<Key, Vec<usize>>,
+}
+
+impl Subset {
+    /// Whether the row at `row` in `table_name`, counting from 0 in the order rows are read, is
+    /// kept
+    pub fn keeps(&self, table_name: &str, row: usize) -> bool {
+        self.kept_rows
+            .get(table_name)
+            .is_none_or(|rows| rows.get(row).copied().unwrap_or(false))
+    }
+}
+
+/// Reads a plain dump through to the end twice, working out which of its rows are kept.
+/// `open` is called for each read, and gives a reader from the start of the dump
+pub fn find<'a>(
+    open: &mut dyn FnMut() -> Result<Box<dyn BufRead + 'a>, AnonymiserError>,
+    strategies: &Strategies,
+) -> Result<Subset, AnonymiserError> {
+    let foreign_keys = read(open()?.as_mut(), strategies, None)?.foreign_keys;
+    let mut key_columns: HashMap<String, Vec<String>> = HashMap::new();
+    for foreign_key in &foreign_keys {
+        for (table_name, columns) in [
+            (&foreign_key.table_name, &foreign_key.columns),
+            (
+                &foreign_key.referenced_table_name,
+                &foreign_key.referenced_columns,
+            ),
+        ] {
+            let key = key_columns.entry(table_name.clone()).or_default();
+            for column in columns {
+                if !key.contains(column) {
+                    key.push(column.clone());
+                }
+            }
+        }
+    }
+
+    let dump = read(open()?.as_mut(), strategies, Some(&key_columns))?;
+    let tables = dump.rows.map(|rows| rows.tables).unwrap_or_default();
+    Ok(Subset {
+        kept_rows: kept_rows(tables, &dump.foreign_keys),
+    })
+}
+
+/// Reads the dump, keeping the rows of the tables in `key_columns` or with a filter, or only
+/// its foreign keys if `key_columns` is None
+fn read<'a>(
+    reader: &mut dyn BufRead,
+    strategies: &'a Strategies,
+    key_columns: Option<&'a HashMap<String, Vec<String>>>,
+) -> Result<DumpReader<'a>, AnonymiserError> {
+    let mut dump = DumpReader {
+        strategies,
+        state: State::new(),
+        alter_table: None,
+        rows: key_columns.map(|key_columns| KeyRows {
+            key_columns,
+            values: Values::default(),
+            tables: HashMap::new(),
+        }),
+        foreign_keys: Vec::new(),
+    };
+    let mut rng = rng::get();
+    let mut line = String::new();
+    let mut line_number = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+        dump.read(&mut rng, &line)
+            .map_err(|err| err.at_line(line_number))?;
+    }
+    Ok(dump)
+}
+
+struct DumpReader<'a> {
+    strategies: &'a Strategies,
+    state: State,
+    /// An `ALTER TABLE` statement that hasn't ended yet
+    alter_table: Option<String>,
+    /// None when only the foreign keys are being read
+    rows: Option<KeyRows<'a>>,
+    foreign_keys: Vec<ForeignKey>,
+}
+
+/// The rows of the tables in foreign keys or with a filter
+struct KeyRows<'a> {
+    /// The columns in foreign keys to or from each table
+    key_columns: &'a HashMap<String, Vec<String>>,
+    values: Values,
+    tables: HashMap<String, TableRows>,
+}
+
+impl DumpReader<'_> {
+    fn read(&mut self, rng: &mut SmallRng, line: &str) -> Result<(), AnonymiserError> {
+        let sanitised_line = sanitiser::trim(line);
+        if let Some(statement) = &mut self.alter_table {
+            statement.push_str(line);
+            if foreign_key::is_complete(line) {
+                let statement = std::mem::take(statement);
+                self.alter_table = None;
+                self.foreign_keys.extend(foreign_key::parse(&statement));
+            }
+            return Ok(());
+        }
+        if row_parser::is_copy_block_row(line, &self.state) {
+            if let (Some(rows), Position::InCopy { current_table }) =
+                (&mut self.rows, &self.state.position)
+            {
+                if let TableTransformers::ColumnTransformer(columns) =
+                    &current_table.table_transformers
+                {
+                    let values: Vec<&str> = data_row::split(line).collect();
+                    rows.add(
+                        &current_table.table_name,
+                        columns,
+                        &values,
+                        &self.state.types,
+                        self.strategies,
+                    )?;
+                }
+            }
+            return Ok(());
+        }
+
+        match &mut self.state.position {
+            Position::InInsert { statement, quote } => {
+                statement.push_str(line);
+                *quote = insert_row::scan(line, *quote);
+                if insert_row::is_complete(line, *quote) {
+                    let statement = std::mem::take(statement);
+                    self.state.update_position(Position::Normal);
+                    self.read_insert(&statement)?;
+                }
+            }
+            Position::Normal if insert_row::is_insert_row(sanitised_line) => {
+                let quote = insert_row::scan(line, Quote::None);
+                if insert_row::is_complete(line, quote) {
+                    self.read_insert(line)?;
+                } else {
+                    self.state.update_position(Position::InInsert {
+                        statement: line.to_string(),
+                        quote,
+                    });
+                }
+            }
+            Position::Normal if foreign_key::is_alter_table(sanitised_line) => {
+                match foreign_key::is_complete(line) {
+                    true => self.foreign_keys.extend(foreign_key::parse(line)),
+                    false => self.alter_table = Some(line.to_string()),
+                }
+            }
+            _ => {
+                row_parser::parse(rng, line, &mut self.state, self.strategies)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_insert(&mut self, statement: &str) -> Result<(), AnonymiserError> {
+        let Some(rows) = &mut self.rows else {
+            return Ok(());
+        };
+        let parsed = insert_row::parse(statement)?;
+        let TableTransformers::ColumnTransformer(columns) =
+            row_parser::insert_table_transformers(&parsed, &self.state, self.strategies)?
+        else {
+            return Ok(());
+        };
+        for row in &parsed.rows {
+            let copy_texts: Vec<Cow<str>> = row
+                .iter()
+                .map(|value| insert_row::to_copy_text(&statement[value.clone()]))
+                .collect();
+            let copy_texts: Vec<&str> = copy_texts.iter().map(AsRef::as_ref).collect();
+            rows.add(
+                &parsed.table_name,
+                &columns,
+                &copy_texts,
+                &self.state.types,
+                self.strategies,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl KeyRows<'_> {
+    /// Adds a row with `values`, in the same order as `columns`, if its table is in a foreign
+    /// key or has a filter
+    fn add(
+        &mut self,
+        table_name: &str,
+        columns: &[ColumnInfo],
+        values: &[&str],
+        types: &Types,
+        strategies: &Strategies,
+    ) -> Result<(), AnonymiserError> {
+        let key_columns = self.key_columns.get(table_name);
+        let filtered = strategies.filter_for(table_name).is_some();
+        if key_columns.is_none() && !filtered {
+            return Ok(());
+        }
+
+        let matched = match filtered {
+            true => Some(row_parser::matches_filter(
+                values, table_name, columns, types, strategies,
+            )?),
+            false => None,
+        };
+        let rows = self
+            .tables
+            .entry(table_name.to_string())
+            .or_insert_with(|| TableRows {
+                columns: key_columns.cloned().unwrap_or_default(),
+                values: Vec::new(),
+                count: 0,
+                matched: matched.map(|_| Vec::new()),
+            });
+
+        // A column an INSERT leaves out gets its default, which can't be known here, so it's
+        // taken as NULL
+        for key_column in &rows.columns {
+            let value = columns
+                .iter()
+                .position(|column| column.name == *key_column)
+                .and_then(|position| values.get(position))
+                .map_or(NULL, |value| self.values.add(value));
+            rows.values.push(value);
+        }
+        rows.count += 1;
+        if let (Some(matched_rows), Some(matched)) = (&mut rows.matched, matched) {
+            matched_rows.push(matched);
+        }
+        Ok(())
+    }
+}
+
+impl TableRows {
+    /// The row's values in `columns`, or None if any of them are NULL, as then it doesn't refer
+    /// to anything
+    fn key(&self, row: usize, columns: &[usize]) -> Option<Key> {
+        let width = self.columns.len();
+        let values = &self.values[row * width..(row + 1) * width];
+        let key: Key = columns.iter().map(|column| values[*column]).collect();
+        (!key.contains(&NULL)).then_some(key)
+    }
+}
+
+/// Works out which rows of each table are kept, given every table's rows and the foreign keys
+/// between them
+fn kept_rows(
+    tables: HashMap<String, TableRows>,
+    foreign_keys: &[ForeignKey],
+) -> HashMap<String, Vec<bool>> {
+    let (names, tables): (Vec<String>, Vec<TableRows>) = tables.into_iter().unzip();
+    let index: HashMap<&str, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(table, name)| (name.as_str(), table))
+        .collect();
+    let links: Vec<Link> = foreign_keys
+        .iter()
+        .filter_map(|foreign_key| link(foreign_key, &index, &tables))
+        .collect();
+
+    // Rows the filters are true for are kept, and so is every row they refer to, all the way
+    // down
+    let mut required: Vec<Vec<bool>> = tables
+        .iter()
+        .map(|rows| {
+            rows.matched
+                .clone()
+                .unwrap_or_else(|| vec![false; rows.count])
+        })
+        .collect();
+    let mut pending = rows_where(&required);
+    while let Some((table, row)) = pending.pop() {
+        for link in links.iter().filter(|link| link.table == table) {
+            let Some((referenced, _)) = link.referenced else {
+                continue;
+            };
+            let referenced_row = tables[table]
+                .key(row, &link.columns)
+                .and_then(|key| link.referenced_rows.get(&key));
+            if let Some(&referenced_row) = referenced_row {
+                if !required[referenced][referenced_row] {
+                    required[referenced][referenced_row] = true;
+                    pending.push((referenced, referenced_row));
+                }
+            }
+        }
+    }
+
+    // Filtered rows that aren't required are dropped, as are rows referring to a row that isn't
+    // in the dump, and then every row referring to a dropped row
+    let mut dropped: Vec<Vec<bool>> = tables
+        .iter()
+        .zip(&required)
+        .map(|(rows, required)| match rows.matched {
+            Some(_) => required.iter().map(|required| !required).collect(),
+            None => vec![false; rows.count],
+        })
+        .collect();
+    for link in &links {
+        for (row, dropped) in dropped[link.table].iter_mut().enumerate() {
+            if let Some(key) = tables[link.table].key(row, &link.columns) {
+                if !link.referenced_rows.contains_key(&key) {
+                    *dropped = true;
+                }
+            }
+        }
+    }
+    let mut pending = rows_where(&dropped);
+    while let Some((table, row)) = pending.pop() {
+        for link in &links {
+            let Some((referenced, referenced_columns)) = &link.referenced else {
+                continue;
+            };
+            if *referenced != table {
+                continue;
+            }
+            let Some(key) = tables[table].key(row, referenced_columns) else {
+                continue;
+            };
+            for &referring_row in link.referring_rows.get(&key).into_iter().flatten() {
+                if !dropped[link.table][referring_row] {
+                    dropped[link.table][referring_row] = true;
+                    pending.push((link.table, referring_row));
+                }
+            }
+        }
+    }
+
+    let subset_tables: HashSet<usize> = links
+        .iter()
+        .flat_map(|link| std::iter::once(link.table).chain(link.referenced.as_ref().map(|r| r.0)))
+        .chain((0..tables.len()).filter(|table| tables[*table].matched.is_some()))
+        .collect();
+    subset_tables
+        .into_iter()
+        .map(|table| {
+            let kept = dropped[table].iter().map(|dropped| !dropped).collect();
+            (names[table].clone(), kept)
+        })
+        .collect()
+}
+
+/// The foreign key between the tables' rows, or None if the referring table has no rows in the
+/// dump
+fn link(
+    foreign_key: &ForeignKey,
+    index: &HashMap<&str, usize>,
+    tables: &[TableRows],
+) -> Option<Link> {
+    let table = *index.get(foreign_key.table_name.as_str())?;
+    let columns = positions(&tables[table].columns, &foreign_key.columns)?;
+    let referenced = index
+        .get(foreign_key.referenced_table_name.as_str())
+        .and_then(|referenced| {
+            let referenced_columns = positions(
+                &tables[*referenced].columns,
+                &foreign_key.referenced_columns,
+            )?;
+            Some((*referenced, referenced_columns))
+        });
+
+    let referenced_rows = match &referenced {
+        Some((referenced, referenced_columns)) => (0..tables[*referenced].count)
+            .filter_map(|row| Some((tables[*referenced].key(row, referenced_columns)?, row)))
+            .collect(),
+        None => HashMap::new(),
+    };
+    let mut referring_rows: HashMap<Key, Vec<usize>> = HashMap::new();
+    for row in 0..tables[table].count {
+        if let Some(key) = tables[table].key(row, &columns) {
+            referring_rows.entry(key).or_default().push(row);
+        }
+    }
+
+    Some(Link {
+        table,
+        columns,
+        referenced,
+        referenced_rows,
+        referring_rows,
+    })
+}
+
+fn positions(columns: &[String], names: &[String]) -> Option<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| columns.iter().position(|column| column == name))
+        .collect()
+}
+
+fn rows_where(flags: &[Vec<bool>]) -> Vec<(usize, usize)> {
+    flags
+        .iter()
+        .enumerate()
+        .flat_map(|(table, rows)| {
+            rows.iter()
+                .enumerate()
+                .filter(|(_, flag)| **flag)
+                .map(move |(row, _)| (table, row))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anonymiser::AnonymiseOptions;
+    use crate::file_reader::{self, Output};
+    use crate::parsers::strategy_structs::{ColumnInFile, StrategyInFile, TransformerOverrides};
+    use std::io::Cursor;
+
+    const DUMP: &str = "\
+CREATE TABLE public.users (
+    id integer NOT NULL,
+    manager_id integer
+);
+
+CREATE TABLE public.orders (
+    id integer NOT NULL,
+    user_id integer
+);
+
+CREATE TABLE public.order_items (
+    id integer NOT NULL,
+    order_id integer
+);
+
+COPY public.users (id, manager_id) FROM stdin;
+1\t\\N
+2\t1
+3\t\\N
+\\.
+
+COPY public.orders (id, user_id) FROM stdin;
+10\t2
+11\t3
+12\t\\N
+13\t9
+\\.
+
+INSERT INTO public.order_items VALUES (100, 10), (101, 11),
+\t(102, NULL), (103, 13);
+
+ALTER TABLE ONLY public.users
+    ADD CONSTRAINT users_pkey PRIMARY KEY (id);
+
+ALTER TABLE ONLY public.users
+    ADD CONSTRAINT fk_manager FOREIGN KEY (manager_id) REFERENCES public.users(id);
+
+ALTER TABLE ONLY public.orders
+    ADD CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES public.users(id);
+
+ALTER TABLE ONLY public.order_items
+    ADD CONSTRAINT fk_order FOREIGN KEY (order_id) REFERENCES public.orders(id);
+";
+
+    fn strategies(filters: &[(&str, &str)]) -> Strategies {
+        let table = |table_name: &str, columns: [&str; 2]| {
+            let mut strategy = StrategyInFile::builder().with_table_name(table_name);
+            for column in columns {
+                strategy = strategy.with_column(ColumnInFile::builder().with_name(column).build());
+            }
+            StrategyInFile {
+                filter: filters
+                    .iter()
+                    .find(|(filtered, _)| *filtered == table_name)
+                    .map(|(_, filter)| filter.to_string()),
+                ..strategy.build()
+            }
+        };
+        Strategies::from_strategies_in_file(
+            vec![
+                table("public.users", ["id", "manager_id"]),
+                table("public.orders", ["id", "user_id"]),
+                table("public.order_items", ["id", "order_id"]),
+            ],
+            &TransformerOverrides::none(),
+        )
+        .unwrap()
+    }
+
+    fn find_in_dump(strategies: &Strategies) -> Subset {
+        find(&mut || Ok(Box::new(Cursor::new(DUMP))), strategies).unwrap()
+    }
+
+    /// The ids of the kept rows, given the ids of every row in the order they're in the dump
+    fn kept(subset: &Subset, table_name: &str, ids: &[&str]) -> Vec<String> {
+        ids.iter()
+            .enumerate()
+            .filter(|(row, _)| subset.keeps(table_name, *row))
+            .map(|(_, id)| id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_filtered_rows_and_the_rows_they_refer_to() {
+        let subset = find_in_dump(&strategies(&[("public.users", "id = 2")]));
+
+        assert_eq!(
+            kept(&subset, "public.users", &["1", "2", "3"]),
+            vec!["1", "2"]
+        );
+        assert_eq!(
+            kept(&subset, "public.orders", &["10", "11", "12", "13"]),
+            vec!["10", "12"]
+        );
+        assert_eq!(
+            kept(&subset, "public.order_items", &["100", "101", "102", "103"]),
+            vec!["100", "102"]
+        );
+    }
+
+    #[test]
+    fn drops_rows_referring_to_rows_that_are_not_in_the_dump() {
+        let subset = find_in_dump(&strategies(&[]));
+
+        assert_eq!(
+            kept(&subset, "public.users", &["1", "2", "3"]),
+            vec!["1", "2", "3"]
+        );
+        assert_eq!(
+            kept(&subset, "public.orders", &["10", "11", "12", "13"]),
+            vec!["10", "11", "12"]
+        );
+        assert_eq!(
+            kept(&subset, "public.order_items", &["100", "101", "102", "103"]),
+            vec!["100", "101", "102"]
+        );
+    }
+
+    #[test]
+    fn rows_referred_to_by_kept_rows_are_kept_even_if_their_filter_is_false() {
+        let subset = find_in_dump(&strategies(&[
+            ("public.users", "id = 3"),
+            ("public.orders", "id = 10"),
+        ]));
+
+        assert_eq!(
+            kept(&subset, "public.users", &["1", "2", "3"]),
+            vec!["1", "2", "3"]
+        );
+        assert_eq!(
+            kept(&subset, "public.orders", &["10", "11", "12", "13"]),
+            vec!["10"]
+        );
+    }
+
+    #[test]
+    fn does_not_keep_rows_that_were_not_in_the_dump() {
+        let subset = find_in_dump(&strategies(&[("public.users", "id = 2")]));
+
+        assert!(subset.keeps("public.users", 1));
+        assert!(!subset.keeps("public.users", 2));
+        assert!(!subset.keeps("public.users", 3));
+    }
+
+    #[test]
+    fn anonymising_drops_the_copy_and_insert_rows_that_are_not_kept() {
+        let strategies = strategies(&[("public.users", "id = 2")]);
+        let strategies = strategies.with_subset(find_in_dump(&strategies));
+        let mut output = Vec::new();
+        file_reader::transform(
+            Box::new(DUMP.as_bytes()),
+            Output::Stream(Box::new(&mut output)),
+            &strategies,
+            &AnonymiseOptions {
+                subset: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(
+            output.contains("COPY public.users (id, manager_id) FROM stdin;\n1\t\\N\n2\t1\n\\.\n")
+        );
+        assert!(
+            output.contains("COPY public.orders (id, user_id) FROM stdin;\n10\t2\n12\t\\N\n\\.\n")
+        );
+        assert!(output.contains("INSERT INTO public.order_items VALUES (100, 10), (102, NULL);\n"));
+    }
+}
@@ -0,0 +1,218 @@
+//! Templates for values made up from the other columns in the same row, e.g.
+//! `{first_name}.{last_name}@example.com`.
+
+use crate::parsers::copy_text;
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Column(String),
+}
+
+/// Parses a template, where `{column}` is replaced with the column's value and `{{` and `}}` are
+/// a literal `{` and `}`
+pub fn parse(template: &str) -> Result<Template, String> {
+    let invalid = |reason: &str| format!("Invalid template \"{}\": {}", template, reason);
+
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        text.push_str(&rest[..start]);
+        let brace = &rest[start..];
+        if let Some(after) = brace.strip_prefix("{{") {
+            text.push('{');
+            rest = after;
+        } else if let Some(after) = brace.strip_prefix("}}") {
+            text.push('}');
+            rest = after;
+        } else if let Some(after_brace) = brace.strip_prefix('{') {
+            let end = after_brace
+                .find('}')
+                .ok_or_else(|| invalid("unclosed '{'"))?;
+            let column = &after_brace[..end];
+            if column.is_empty() || column.contains('{') {
+                return Err(invalid("'{' must be followed by a column name and '}'"));
+            }
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(Part::Column(column.to_string()));
+            rest = &after_brace[end + 1..];
+        } else {
+            return Err(invalid("unmatched '}', use '}}' for a literal '}'"));
+        }
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+
+    Ok(Template { parts })
+}
+
+impl Template {
+    /// The columns the template uses, in the order they're first used
+    pub fn columns(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Column(column) => Some(column.as_str()),
+                Part::Text(_) => None,
+            })
+            .unique()
+            .collect()
+    }
+
+    /// Fills in the template with the values `value_of` gives for each column, as COPY text,
+    /// giving back COPY text. NULLs are filled in as empty strings
+    pub fn render<'a>(&self, value_of: impl Fn(&str) -> Option<&'a str>) -> Result<String, String> {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(&copy_text::escape(text)),
+                Part::Column(column) => match value_of(column) {
+                    Some("\\N") => (),
+                    Some(value) => rendered.push_str(value),
+                    None => return Err(format!("'{}' in the template isn't in the row", column)),
+                },
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+/// Finds a column whose template uses its own value, directly or through other templates, given
+/// the columns each template column uses. The cycle is given as the columns in it, starting and
+/// ending with the same one
+pub fn find_cycle<'a>(uses: &HashMap<&'a str, Vec<&'a str>>) -> Option<Vec<&'a str>> {
+    let mut finished = HashSet::new();
+    uses.keys().sorted().find_map(|column| {
+        let mut path = Vec::new();
+        visit(column, uses, &mut path, &mut finished)
+    })
+}
+
+fn visit<'a>(
+    column: &'a str,
+    uses: &HashMap<&'a str, Vec<&'a str>>,
+    path: &mut Vec<&'a str>,
+    finished: &mut HashSet<&'a str>,
+) -> Option<Vec<&'a str>> {
+    if let Some(start) = path.iter().position(|visited| *visited == column) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(column);
+        return Some(cycle);
+    }
+    let used = uses.get(column)?;
+    if finished.contains(column) {
+        return None;
+    }
+
+    path.push(column);
+    let cycle = used
+        .iter()
+        .find_map(|used_column| visit(used_column, uses, path, finished));
+    path.pop();
+    finished.insert(column);
+    cycle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, values: &[(&str, &'static str)]) -> Result<String, String> {
+        let values: HashMap<&str, &str> = values.iter().copied().collect();
+        parse(template)
+            .unwrap()
+            .render(|column| values.get(column).copied())
+    }
+
+    #[test]
+    fn parses_columns_and_escaped_braces() {
+        assert_eq!(
+            parse("{{{first_name}}} {last_name}").unwrap(),
+            Template {
+                parts: vec![
+                    Part::Text("{".to_string()),
+                    Part::Column("first_name".to_string()),
+                    Part::Text("} ".to_string()),
+                    Part::Column("last_name".to_string()),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn errors_for_unmatched_braces() {
+        assert_eq!(
+            parse("{first_name").unwrap_err(),
+            "Invalid template \"{first_name\": unclosed '{'"
+        );
+        assert!(parse("{}").is_err());
+        assert!(parse("{first{name}").is_err());
+        assert!(parse("first_name}").is_err());
+    }
+
+    #[test]
+    fn lists_each_column_used_once() {
+        let template = parse("{first_name}.{last_name}{first_name}").unwrap();
+
+        assert_eq!(template.columns(), vec!["first_name", "last_name"]);
+    }
+
+    #[test]
+    fn renders_copy_text() {
+        assert_eq!(
+            render(
+                "{first_name}.{last_name}@example.com",
+                &[("first_name", "Peter"), ("last_name", "O'Neil")]
+            ),
+            Ok("Peter.O'Neil@example.com".to_string())
+        );
+        assert_eq!(
+            render(
+                "{first_name}\t{last_name}",
+                &[("first_name", "a\\\\b"), ("last_name", "\\N")]
+            ),
+            Ok("a\\\\b\\t".to_string())
+        );
+        assert_eq!(
+            render("{first_name}", &[]),
+            Err("'first_name' in the template isn't in the row".to_string())
+        );
+    }
+
+    #[test]
+    fn finds_cycles_between_templates() {
+        let uses = HashMap::from([
+            ("full_name", vec!["first_name", "last_name"]),
+            ("display_name", vec!["full_name", "nickname"]),
+            ("nickname", vec!["display_name"]),
+        ]);
+
+        assert_eq!(
+            find_cycle(&uses),
+            Some(vec!["display_name", "nickname", "display_name"])
+        );
+        assert_eq!(
+            find_cycle(&HashMap::from([("name", vec!["name"])])),
+            Some(vec!["name", "name"])
+        );
+        assert_eq!(
+            find_cycle(&HashMap::from([
+                ("full_name", vec!["first_name"]),
+                ("display_name", vec!["full_name"]),
+            ])),
+            None
+        );
+    }
+}
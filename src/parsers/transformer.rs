@@ -1,25 +1,35 @@
+use crate::errors::AnonymiserError;
+use crate::parsers::copy_text;
+use crate::parsers::decimal::{Decimal, Rounding};
+use crate::parsers::json_path::{self, JsonPath};
+use crate::parsers::locale::{self, Locale};
 use crate::parsers::national_insurance_number;
-use crate::parsers::strategy_structs::{Transformer, TransformerType};
+use crate::parsers::pii_detectors::{self, Detector};
+use crate::parsers::rng;
+use crate::parsers::strategy_structs::{
+    Transformer, TransformerArg, TransformerArgs, TransformerType,
+};
+use crate::parsers::template::{self, Template};
 use crate::parsers::types::Type::Array;
 use crate::parsers::types::Type::SingleValue;
 use crate::parsers::types::*;
 use base16;
 use base32::Alphabet;
-use chrono::{Datelike, NaiveDate};
+use chrono::{Datelike, NaiveDate, NaiveTime};
 use core::ops::Range;
-use fake::faker::address::en::*;
-use fake::faker::company::en::*;
 use fake::faker::internet::en::*;
-use fake::faker::name::en::*;
 use fake::Fake;
+use itertools::Itertools;
 use log::trace;
-use rand::SeedableRng;
 use rand::{rngs::SmallRng, Rng};
+use regex::{Captures, Regex};
+use serde_json::Value;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use uuid::Uuid;
+use std::sync::Arc;
+use uuid::Builder;
 
 static UNIQUE_INTEGER: AtomicUsize = AtomicUsize::new(0);
 
@@ -27,101 +37,373 @@ fn get_unique() -> usize {
     UNIQUE_INTEGER.fetch_add(1, Ordering::SeqCst)
 }
 
+/// Where the random draws for a value come from
+#[derive(Clone, Copy)]
+pub enum Draws<'a> {
+    /// An HMAC of the value under the deterministic key
+    Keyed(&'a str),
+    /// The rng, which is seeded, so everything else that has to be the same each run comes from
+    /// it too
+    Reproducible,
+    Random,
+}
+
+// A counter gives prefixes in whatever order the threads get to them, and can't give the same
+// prefix for the same value in different tables or runs, so for reproducible output the prefix
+// comes from the (seeded or keyed) rng instead
+fn unique_prefix(rng: &mut SmallRng, draws: Draws) -> usize {
+    match draws {
+        Draws::Random => get_unique(),
+        Draws::Reproducible | Draws::Keyed(_) => rng.gen::<usize>(),
+    }
+}
+
+/// Transforms `value` with `transformer`, parsing its args for this value alone. To transform many
+/// values, `compile` the transformer once and use `CompiledTransformer::transform`
 pub fn transform<'line>(
     rng: &mut SmallRng,
     value: &'line str,
     column_type: &Type,
-    transformer: &'line Transformer,
+    transformer: &Transformer,
     table_name: &str,
-) -> Cow<'line, str> {
-    if ["\\N", "deleted"].contains(&value) {
-        return Cow::from(value);
-    }
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    let transformer = CompiledTransformer::uncompiled(transformer.clone());
+    transform_compiled(rng, value, column_type, &transformer, table_name)
+}
 
-    if transformer.name == TransformerType::Identity {
-        return Cow::from(value);
-    }
+/// Transforms `value` using an rng derived from an HMAC of the value under `key`, so the same
+/// input always maps to the same output, across tables and across runs. Like `transform`, the
+/// transformer's args are parsed for this value alone
+pub fn transform_deterministically<'line>(
+    key: &str,
+    value: &'line str,
+    column_type: &Type,
+    transformer: &Transformer,
+    table_name: &str,
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    let transformer = CompiledTransformer::uncompiled(transformer.clone());
+    transform_compiled_deterministically(key, value, column_type, &transformer, table_name)
+}
 
-    if let Array {
-        sub_type: underlying_type,
-    } = column_type
-    {
-        return transform_array(rng, value, underlying_type, transformer, table_name);
-    }
+/// Transforms `value` the same as `transform`, with a transformer compiled by `compile`
+pub fn transform_compiled<'line>(
+    rng: &mut SmallRng,
+    value: &'line str,
+    column_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    transform_value(
+        rng,
+        value,
+        column_type,
+        transformer,
+        table_name,
+        Draws::Random,
+    )
+}
 
-    let unique = get_unique();
+/// Transforms `value` the same as `transform_deterministically`, with a transformer compiled by
+/// `compile`
+pub fn transform_compiled_deterministically<'line>(
+    key: &str,
+    value: &'line str,
+    column_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    let mut rng = rng::keyed(key, value);
+    transform_value(
+        &mut rng,
+        value,
+        column_type,
+        transformer,
+        table_name,
+        Draws::Keyed(key),
+    )
+}
 
-    //TODO error if inappropriate transformer for type is used e.g. scramble for json should give
-    //nice error rather than making invalid sql
+/// Transforms `value` the same as `transform_compiled`, apart from taking the prefix of `unique`
+/// values from `rng` rather than a counter shared by every thread, so a seeded rng gives the same
+/// output whichever thread the value is transformed on
+pub fn transform_reproducibly<'line>(
+    rng: &mut SmallRng,
+    value: &'line str,
+    column_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    transform_value(
+        rng,
+        value,
+        column_type,
+        transformer,
+        table_name,
+        Draws::Reproducible,
+    )
+}
 
-    match transformer.name {
+fn transform_value<'line>(
+    rng: &mut SmallRng,
+    value: &'line str,
+    column_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+    draws: Draws,
+) -> Result<Cow<'line, str>, AnonymiserError> {
+    if ["\\N", "deleted"].contains(&value) {
+        return Ok(Cow::from(value));
+    }
+
+    let transformed = match transformer.name {
+        // Shuffled values are moved between rows once the whole COPY block has been read (see
+        // `shuffle`), and templates are filled in from the rest of the transformed row (see
+        // `row_parser`)
+        TransformerType::Identity | TransformerType::Shuffle | TransformerType::Template => {
+            Cow::from(value)
+        }
+        _ if matches!(column_type, Array { .. }) => transform_array(
+            rng,
+            value,
+            column_type.sub_type(),
+            transformer,
+            table_name,
+            draws,
+        )?,
         TransformerType::Error => {
-            panic!("Error transform still in place for table: {}", table_name)
+            return Err(AnonymiserError::transform(
+                table_name,
+                "Error transform still in place".to_string(),
+            ))
         }
+        TransformerType::AddNoise => {
+            Cow::from(add_noise(rng, value, column_type, transformer, table_name)?)
+        }
+        TransformerType::Bucket | TransformerType::RoundNumber => Cow::from(generalise_number(
+            value,
+            column_type,
+            transformer,
+            table_name,
+        )?),
         TransformerType::EmptyJson => Cow::from("{}"),
-        TransformerType::FakeBase16String => Cow::from(fake_base16_string()),
-        TransformerType::FakeBase32String => Cow::from(fake_base32_string()),
-        TransformerType::FakeCity => Cow::from(CityName().fake::<String>()),
-        TransformerType::FakeCompanyName => Cow::from(fake_company_name(&transformer.args, unique)),
-        TransformerType::FakeEmail => Cow::from(fake_email(&transformer.args, unique)),
+        TransformerType::FakeBase16String => Cow::from(fake_base16_string(rng)),
+        TransformerType::FakeBase32String => Cow::from(fake_base32_string(rng)),
+        TransformerType::FakeCity => Cow::from(locale(transformer, table_name)?.city(rng)),
+        TransformerType::FakeCompanyName => {
+            let unique = unique_prefix(rng, draws);
+            Cow::from(fake_company_name(
+                rng,
+                locale(transformer, table_name)?,
+                &transformer.args,
+                unique,
+            ))
+        }
+        TransformerType::FakeEmail => {
+            let unique = unique_prefix(rng, draws);
+            Cow::from(fake_email(rng, &transformer.args, unique))
+        }
         TransformerType::FakeEmailOrPhone => {
-            Cow::from(fake_email_or_phone(value, &transformer.args, unique))
-        }
-        TransformerType::FakeFirstName => Cow::from(FirstName().fake::<String>()),
-        TransformerType::FakeFullAddress => Cow::from(fake_full_address()),
-        TransformerType::FakeFullName => Cow::from(fake_full_name()),
-        TransformerType::FakeIPv4 => Cow::from(IPv4().fake::<String>()),
-        TransformerType::FakeLastName => Cow::from(LastName().fake::<String>()),
-        TransformerType::FakeNationalIdentityNumber => Cow::from(fake_national_identity_number()),
+            let unique = unique_prefix(rng, draws);
+            Cow::from(fake_email_or_phone(rng, value, &transformer.args, unique))
+        }
+        TransformerType::FakeFirstName => {
+            Cow::from(locale(transformer, table_name)?.first_name(rng))
+        }
+        TransformerType::FakeFullAddress => {
+            Cow::from(locale(transformer, table_name)?.full_address(rng))
+        }
+        TransformerType::FakeFullName => Cow::from(locale(transformer, table_name)?.full_name(rng)),
+        TransformerType::FakeIPv4 => Cow::from(IPv4().fake_with_rng::<String, _>(rng)),
+        TransformerType::FakeLastName => Cow::from(locale(transformer, table_name)?.last_name(rng)),
+        TransformerType::FakeNationalIdentityNumber => {
+            Cow::from(fake_national_identity_number(rng))
+        }
         TransformerType::FakePostCode => Cow::from(fake_postcode(value)),
-        TransformerType::FakePhoneNumber => Cow::from(fake_phone_number(value)),
-        TransformerType::FakeStreetAddress => Cow::from(fake_street_address()),
-        TransformerType::FakeState => Cow::from(StateName().fake::<String>()),
-        TransformerType::FakeUsername => Cow::from(fake_username(&transformer.args, unique)),
+        TransformerType::FakePhoneNumber => match locale_arg(transformer) {
+            Ok(Some(locale)) => Cow::from(locale.phone_number(rng)),
+            Ok(None) => Cow::from(fake_phone_number(rng, value)),
+            Err(err) => return Err(AnonymiserError::transform(table_name, err)),
+        },
+        TransformerType::FakeStreetAddress => {
+            Cow::from(locale(transformer, table_name)?.street_address(rng))
+        }
+        TransformerType::FakeState => Cow::from(locale(transformer, table_name)?.state(rng)),
+        TransformerType::FakeUsername => {
+            let unique = unique_prefix(rng, draws);
+            Cow::from(fake_username(rng, &transformer.args, unique))
+        }
         TransformerType::Scramble => Cow::from(scramble(rng, value)),
         TransformerType::ScrambleBlank => Cow::from(scramble_blank(value)),
-        TransformerType::ObfuscateDay => Cow::from(obfuscate_day(value, table_name)),
-        TransformerType::Fixed => fixed(&transformer.args, table_name),
-        TransformerType::Identity => Cow::from(value),
+        TransformerType::ScrubPii => Cow::from(scrub_pii(rng, value, transformer, table_name)?),
+        TransformerType::ShiftDate | TransformerType::ShiftTimestamp => {
+            Cow::from(shift_date(rng, value, transformer, table_name)?)
+        }
+        TransformerType::TruncateDate => Cow::from(truncate_date(value, transformer, table_name)?),
+        TransformerType::Mask => Cow::from(mask(value, transformer, table_name)?),
+        TransformerType::ObfuscateDay => Cow::from(obfuscate_day(value, table_name)?),
+        TransformerType::RegexReplace => {
+            Cow::from(regex_replace(rng, value, transformer, table_name, draws)?)
+        }
+        TransformerType::Fixed => Cow::from(fixed(&transformer.args, table_name)?),
+        // Switches on another column are resolved by `row_parser`, which has the rest of the row
+        TransformerType::Switch => {
+            let branch = switch_branch(transformer, value, |_| None)
+                .map_err(|err| AnonymiserError::transform(table_name, err))?;
+            transform_value(rng, value, column_type, &branch, table_name, draws)?
+        }
+        TransformerType::JsonPaths => {
+            Cow::from(json_paths(rng, value, transformer, table_name, draws)?)
+        }
         //TODO not tested VV
-        TransformerType::FakeUUID => Cow::from(Uuid::new_v4().to_string()),
+        TransformerType::FakeUUID => Cow::from(fake_uuid(rng)),
+    };
+    Ok(transformed)
+}
+
+/// Whether `transformer` gives values that are valid for a column of `column_type`, or for the
+/// elements of it if it's an array. Types the anonymiser doesn't know are assumed to be fine
+pub fn can_transform(transformer: &TransformerType, column_type: &Type) -> bool {
+    let sub_type = column_type.sub_type();
+    let is_text = matches!(
+        sub_type,
+        SubType::Character | SubType::Text | SubType::Citext
+    );
+
+    if let SubType::Unknown { .. } = sub_type {
+        return true;
+    }
+
+    match transformer {
+        // The transformers a Switch picks between are checked by `can_apply`
+        TransformerType::Error
+        | TransformerType::Fixed
+        | TransformerType::Identity
+        | TransformerType::Shuffle
+        | TransformerType::Switch => true,
+        TransformerType::EmptyJson | TransformerType::JsonPaths => {
+            is_text || *sub_type == SubType::Json
+        }
+        TransformerType::FakeIPv4 => is_text || *sub_type == SubType::Inet,
+        TransformerType::FakeUUID => is_text || *sub_type == SubType::Uuid,
+        TransformerType::ObfuscateDay | TransformerType::ShiftDate => {
+            is_text || *sub_type == SubType::Date
+        }
+        TransformerType::ShiftTimestamp => {
+            is_text
+                || *sub_type == SubType::Timestamp
+                || *sub_type == SubType::TimestampWithTimeZone
+        }
+        TransformerType::TruncateDate => {
+            is_text
+                || *sub_type == SubType::Date
+                || *sub_type == SubType::Timestamp
+                || *sub_type == SubType::TimestampWithTimeZone
+        }
+        TransformerType::AddNoise | TransformerType::Bucket | TransformerType::RoundNumber => {
+            is_text
+                || matches!(
+                    sub_type,
+                    SubType::Integer
+                        | SubType::BigInt
+                        | SubType::SmallInt
+                        | SubType::Numeric { .. }
+                )
+        }
+        TransformerType::Scramble => {
+            is_text
+                || matches!(
+                    sub_type,
+                    SubType::Integer | SubType::BigInt | SubType::SmallInt
+                )
+        }
+        TransformerType::Template => is_text && !matches!(column_type, Array { .. }),
+        TransformerType::FakeBase16String
+        | TransformerType::FakeBase32String
+        | TransformerType::FakeCity
+        | TransformerType::FakeCompanyName
+        | TransformerType::FakeEmail
+        | TransformerType::FakeEmailOrPhone
+        | TransformerType::FakeFirstName
+        | TransformerType::FakeFullAddress
+        | TransformerType::FakeFullName
+        | TransformerType::FakeLastName
+        | TransformerType::FakeNationalIdentityNumber
+        | TransformerType::FakePhoneNumber
+        | TransformerType::FakePostCode
+        | TransformerType::FakeState
+        | TransformerType::FakeStreetAddress
+        | TransformerType::FakeUsername
+        | TransformerType::Mask
+        | TransformerType::RegexReplace
+        | TransformerType::ScrambleBlank
+        | TransformerType::ScrubPii => is_text,
+    }
+}
+
+/// Whether `transformer`, and every transformer a `Switch` might pick, can be used on a column of
+/// `column_type`
+pub fn can_apply(transformer: &Transformer, column_type: &Type) -> bool {
+    if transformer.name != TransformerType::Switch {
+        return can_transform(&transformer.name, column_type);
     }
+    parse_switch_args(transformer).map_or(true, |args| {
+        args.branches().into_iter().all(|name| {
+            switch_branch_named(transformer, name)
+                .map_or(true, |branch| can_apply(branch, column_type))
+        })
+    })
 }
 
 fn transform_array<'value>(
     rng: &mut SmallRng,
     value: &'value str,
     underlying_type: &SubType,
-    transformer: &Transformer,
+    transformer: &CompiledTransformer,
     table_name: &str,
-) -> Cow<'value, str> {
-    let quoted_types = vec![SubType::Character, SubType::Json];
-    let requires_quotes = quoted_types.contains(underlying_type);
+    draws: Draws,
+) -> Result<Cow<'value, str>, AnonymiserError> {
+    if value.len() <= 2 {
+        // An empty array has no elements, rather than one empty one
+        return Ok(Cow::from(value));
+    }
 
     let sub_type = SingleValue {
         sub_type: underlying_type.clone(),
     };
 
-    let transformed_array = if requires_quotes {
-        transform_quoted_array(rng, value, &sub_type, transformer, table_name)
+    let transformed_array = if underlying_type.has_quoted_array_elements() {
+        transform_quoted_array(rng, value, &sub_type, transformer, table_name, draws)?
     } else {
         let unsplit_array = &value[1..value.len() - 1];
         unsplit_array
-            .split(", ")
-            .map(|list_item| transform(rng, list_item, &sub_type, transformer, table_name))
-            .collect::<Vec<Cow<str>>>()
+            .split(',')
+            .map(|list_item| {
+                if list_item.trim() == "NULL" {
+                    return Ok(Cow::from(list_item.trim()));
+                }
+                transform_element(
+                    rng,
+                    list_item.trim(),
+                    &sub_type,
+                    transformer,
+                    table_name,
+                    draws,
+                )
+            })
+            .collect::<Result<Vec<Cow<str>>, AnonymiserError>>()?
             .join(",")
     };
-    Cow::from(format!("{{{}}}", transformed_array))
+    Ok(Cow::from(format!("{{{}}}", transformed_array)))
 }
 
 fn transform_quoted_array(
     rng: &mut SmallRng,
     value: &str,
     sub_type: &Type,
-    transformer: &Transformer,
+    transformer: &CompiledTransformer,
     table_name: &str,
-) -> String {
+    draws: Draws,
+) -> Result<String, AnonymiserError> {
     let mut inside_word = false;
     let mut word_is_quoted = false;
     let mut current_word: String = "".to_string();
@@ -144,10 +426,25 @@ fn transform_quoted_array(
                 || (!word_is_quoted && c == '}'))
         {
             inside_word = false;
-            word_is_quoted = false;
-            let transformed = transform(rng, &current_word, sub_type, transformer, table_name);
-            write!(word_acc, "\"{}\",", &transformed)
+            if !word_is_quoted && current_word == "NULL" {
+                word_acc.push_str("NULL,");
+            } else {
+                // Elements are transformed as COPY text, the same as values that aren't in arrays
+                let element = if word_is_quoted {
+                    copy_text::unescape_array_element(&current_word)
+                } else {
+                    current_word.clone()
+                };
+                let transformed =
+                    transform_element(rng, &element, sub_type, transformer, table_name, draws)?;
+                write!(
+                    word_acc,
+                    "\"{}\",",
+                    copy_text::escape_array_element(&transformed)
+                )
                 .expect("Should be able to apppend to word_acc");
+            }
+            word_is_quoted = false;
             current_word = "".to_string();
             trace!("its the end of a word");
         } else {
@@ -168,18 +465,36 @@ fn transform_quoted_array(
     trace!("\noutput - {:?}", word_acc);
     //Remove the trailing comma from line: 145!
     word_acc.pop();
-    word_acc
+    Ok(word_acc)
+}
+
+/// Transforms an element of an array. A keyed element is keyed on its own value rather than the
+/// whole array's, so it's given the same output as the same value in a column that isn't an array
+fn transform_element<'element>(
+    rng: &mut SmallRng,
+    element: &'element str,
+    sub_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+    draws: Draws,
+) -> Result<Cow<'element, str>, AnonymiserError> {
+    match draws {
+        Draws::Keyed(key) => {
+            let mut rng = rng::keyed(key, element);
+            transform_value(&mut rng, element, sub_type, transformer, table_name, draws)
+        }
+        Draws::Random | Draws::Reproducible => {
+            transform_value(rng, element, sub_type, transformer, table_name, draws)
+        }
+    }
 }
 
 fn prepend_unique_if_present(
     new_value: String,
-    args: &Option<HashMap<String, String>>,
+    args: &Option<TransformerArgs>,
     unique: usize,
 ) -> String {
-    let unique_value = args
-        .as_ref()
-        .and_then(|a| a.get("unique"))
-        .map_or_else(|| false, |u| u == "true");
+    let unique_value = arg(args, "unique").map_or_else(|| false, |u| u == "true");
 
     if unique_value {
         format!("{}-{}", unique, new_value)
@@ -188,653 +503,3555 @@ fn prepend_unique_if_present(
     }
 }
 
-fn fake_base16_string() -> String {
-    let random_bytes = SmallRng::from_rng(rand::thread_rng())
-        .unwrap_or_else(|_| SmallRng::from_entropy())
-        .gen::<[u8; 16]>();
-    base16::encode_lower(&random_bytes)
+fn arg<'a>(args: &'a Option<TransformerArgs>, name: &str) -> Option<&'a str> {
+    args.as_ref()
+        .and_then(|args| args.get(name))
+        .and_then(|arg| arg.as_value())
 }
 
-fn fake_base32_string() -> String {
-    let random_bytes = SmallRng::from_rng(rand::thread_rng())
-        .unwrap_or_else(|_| SmallRng::from_entropy())
-        .gen::<[u8; 16]>();
-    base32::encode(Alphabet::RFC4648 { padding: true }, &random_bytes)
+/// Checks the args a transformer needs are there and make sense, so mistakes are found when the
+/// strategy file is loaded rather than part way through a dump
+pub fn validate_args(transformer: &Transformer) -> Result<(), String> {
+    locale_arg(transformer)?;
+    match transformer.name {
+        TransformerType::AddNoise => parse_add_noise_args(&transformer.args).map(|_| ()),
+        TransformerType::JsonPaths => parse_json_path_transformers(transformer).map(|_| ()),
+        TransformerType::Mask => parse_mask_args(&transformer.args).map(|_| ()),
+        TransformerType::RegexReplace => parse_regex_replace_args(transformer).map(|_| ()),
+        TransformerType::ScrubPii => parse_pii_detectors_arg(&transformer.args).map(|_| ()),
+        TransformerType::ShiftDate | TransformerType::ShiftTimestamp => {
+            parse_shift_date_args(transformer).map(|_| ())
+        }
+        TransformerType::Switch => validate_switch_args(transformer),
+        TransformerType::Bucket | TransformerType::RoundNumber => {
+            parse_number_step_arg(transformer).map(|_| ())
+        }
+        TransformerType::Template => parse_template_arg(&transformer.args).map(|_| ()),
+        TransformerType::TruncateDate => parse_truncate_date_arg(&transformer.args).map(|_| ()),
+        _ => Ok(()),
+    }
 }
 
-fn fake_company_name(args: &Option<HashMap<String, String>>, unique: usize) -> String {
-    let new_company_name = CompanyName().fake();
-    prepend_unique_if_present(new_company_name, args, unique)
+/// Checks a transformer nested in another one's args. Shuffles, templates and switches on
+/// another column work on whole column values in a row, so there's nothing for them to do with
+/// part of a value
+fn validate_nested(nested: &Transformer) -> Result<(), String> {
+    if matches!(
+        nested.name,
+        TransformerType::Shuffle | TransformerType::Template
+    ) {
+        return Err(format!(
+            "{:?} can't be nested in another transformer",
+            nested.name
+        ));
+    }
+    if !switch_columns(nested).is_empty() {
+        return Err("A Switch on a 'column' can't be nested in another transformer".to_string());
+    }
+    validate_args(nested)
 }
 
-fn fake_email(optional_args: &Option<HashMap<String, String>>, unique: usize) -> String {
-    let new_email = FreeEmail().fake();
-    prepend_unique_if_present(new_email, optional_args, unique)
+/// The template in a `Template` transformer's args
+pub fn template_arg(transformer: &CompiledTransformer) -> Result<Cow<'_, Template>, String> {
+    match compiled_args(transformer) {
+        Some(Args::Template(template)) => Ok(Cow::Borrowed(template)),
+        _ => parse_template_arg(&transformer.args).map(Cow::Owned),
+    }
 }
 
-fn fake_email_or_phone(
-    current_value: &str,
-    optional_args: &Option<HashMap<String, String>>,
-    unique: usize,
-) -> String {
-    if current_value.starts_with('+') && !current_value.contains('@') {
-        fake_phone_number(current_value)
-    } else {
-        fake_email(optional_args, unique)
+pub fn parse_template_arg(args: &Option<TransformerArgs>) -> Result<Template, String> {
+    let text = arg(args, "template").ok_or_else(|| {
+        "Template needs a 'template' arg, e.g. \"{first_name} {last_name}\"".to_string()
+    })?;
+    template::parse(text)
+}
+
+/// The locale in the `locale` arg, if there is one, checking there's data in it for the
+/// transformer
+fn locale_arg(transformer: &Transformer) -> Result<Option<Locale>, String> {
+    let Some(name) = arg(&transformer.args, "locale") else {
+        return Ok(None);
+    };
+    let locale = Locale::from_name(name).ok_or_else(|| {
+        format!(
+            "Unknown locale '{}', expected one of: {}",
+            name,
+            Locale::ALL.iter().map(|locale| locale.name()).join(", ")
+        )
+    })?;
+    if !locale::is_localised(&transformer.name) {
+        return Err(format!(
+            "{:?} doesn't take a 'locale' arg",
+            transformer.name
+        ));
+    }
+    if !locale.supports(&transformer.name) {
+        return Err(format!(
+            "{:?} isn't supported in the {} locale",
+            transformer.name, name
+        ));
     }
+    Ok(Some(locale))
 }
 
-fn fake_street_address() -> String {
-    let building: String = BuildingNumber().fake();
-    let street_name: String = StreetName().fake();
-    format!("{} {}", building, street_name)
+/// The locale to make up a value in, which is US English unless there's a `locale` arg
+fn locale(transformer: &Transformer, table_name: &str) -> Result<Locale, AnonymiserError> {
+    locale_arg(transformer)
+        .map(Option::unwrap_or_default)
+        .map_err(|err| AnonymiserError::transform(table_name, err))
 }
 
-fn fake_full_address() -> String {
-    let line_1 = fake_street_address();
-    let city_name: String = CityName().fake();
-    let state: String = StateName().fake();
-    format!("{}, {}, {}", line_1, city_name, state)
+/// Gives `transformer` (and the transformers nested in it) a `locale` arg of `locale` if it makes
+/// up values that depend on one and doesn't already have one, for a table's default locale
+pub fn with_default_locale(mut transformer: Transformer, locale: &str) -> Transformer {
+    if locale::is_localised(&transformer.name) {
+        transformer
+            .args
+            .get_or_insert_with(HashMap::new)
+            .entry("locale".to_string())
+            .or_insert_with(|| locale.into());
+    }
+    if let Some(args) = transformer.args.as_mut() {
+        for arg in args.values_mut() {
+            if let TransformerArg::Transformer(nested) = arg {
+                *nested = with_default_locale(nested.clone(), locale);
+            }
+        }
+    }
+    transformer
 }
 
-fn fake_full_name() -> String {
-    let first: String = FirstName().fake();
-    let last: String = LastName().fake();
-    format!("{} {}", first, last)
+/// The column whose value picks the random draws for `transformer`, so every row with the same
+/// value in it is transformed the same way
+pub fn key_column(transformer: &Transformer) -> Option<&str> {
+    match transformer.name {
+        TransformerType::ShiftDate | TransformerType::ShiftTimestamp => {
+            arg(&transformer.args, "key_column")
+        }
+        _ => None,
+    }
 }
 
-fn fake_national_identity_number() -> String {
-    //TODO currently this is free text so they can enter anything at all,
-    //so im not bothering with us vs uk,
-    //there dont seem to be any us social sec numbers in the DB currently
-    national_insurance_number::random()
+/// A transformer with its args parsed once, by `compile`, rather than for every value. The
+/// transformers nested in its args are compiled too
+#[derive(Clone, Debug)]
+pub struct CompiledTransformer {
+    transformer: Transformer,
+    parsed_args: Option<Arc<Args>>,
 }
 
-//https://www.ofcom.org.uk/phones-telecoms-and-internet/information-for-industry/numbering/numbers-for-drama
-static UK_FAKE_MOBILE_RANGE: Range<i32> = 900000..960999;
+impl CompiledTransformer {
+    /// `transformer` without its args parsed, so they're parsed each time they're used
+    pub fn uncompiled(transformer: Transformer) -> Self {
+        CompiledTransformer {
+            transformer,
+            parsed_args: None,
+        }
+    }
 
-fn fake_phone_number(current_value: &str) -> String {
-    let mut rng =
-        SmallRng::from_rng(rand::thread_rng()).unwrap_or_else(|_| SmallRng::from_entropy());
-    if current_value.starts_with("+447") {
-        let random = rng.gen_range(UK_FAKE_MOBILE_RANGE.clone());
-        format!("+447700{0}", random)
-    } else {
-        let area_code = rng.gen_range(200..999);
-        let rest = rng.gen_range(1000000..9999999);
-        format!("+1{}{}", area_code, rest)
+    /// Transforms `value` the same as `transform`, without parsing the args again
+    pub fn transform<'line>(
+        &self,
+        rng: &mut SmallRng,
+        value: &'line str,
+        column_type: &Type,
+        table_name: &str,
+    ) -> Result<Cow<'line, str>, AnonymiserError> {
+        transform_compiled(rng, value, column_type, self, table_name)
     }
-}
 
-fn fake_postcode(current_value: &str) -> String {
-    //TODO not sure this is unicode safe...
-    let mut truncated_value = current_value.to_string();
-    truncated_value.truncate(3);
-    truncated_value.to_string()
+    /// Transforms `value` the same as `transform_deterministically`, without parsing the args
+    /// again
+    pub fn transform_deterministically<'line>(
+        &self,
+        key: &str,
+        value: &'line str,
+        column_type: &Type,
+        table_name: &str,
+    ) -> Result<Cow<'line, str>, AnonymiserError> {
+        transform_compiled_deterministically(key, value, column_type, self, table_name)
+    }
 }
 
-fn fake_username(args: &Option<HashMap<String, String>>, unique: usize) -> String {
-    let username = Username().fake();
-    prepend_unique_if_present(username, args, unique)
+impl std::ops::Deref for CompiledTransformer {
+    type Target = Transformer;
+
+    fn deref(&self) -> &Transformer {
+        &self.transformer
+    }
 }
 
-fn fixed<'a>(args: &'a Option<HashMap<String, String>>, table_name: &str) -> Cow<'a, str> {
-    let value = args
-        .as_ref()
-        .and_then(|a| a.get("value"))
-        .unwrap_or_else(|| {
-            panic!(
-                "'value' must be present in args for a fixed transformer in table: '{}'\ngot: '{:?}'",
-                table_name, args,
-            )
-        });
-    Cow::from(value)
+#[derive(Debug)]
+enum Args {
+    JsonPaths(Vec<(JsonPath, CompiledTransformer)>),
+    RegexReplace(RegexReplaceArgs),
+    ScrubPii(Vec<Detector>),
+    Mask(MaskArgs),
+    ShiftDate(ShiftDateArgs),
+    GeneraliseNumber { step: Decimal },
+    TruncateDate { to: DatePart },
+    AddNoise(AddNoiseArgs),
+    Switch(SwitchArgs, HashMap<String, CompiledTransformer>),
+    Template(Template),
 }
 
-fn obfuscate_day(value: &str, table_name: &str) -> String {
-    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
-        Ok(date) => {
-            let new_date = date.with_day(1).unwrap();
-            new_date.to_string()
-        }
-        Err(err) => {
-            return value
-                .strip_suffix(" BC")
-                .and_then(|trimmed| {
-                    NaiveDate::parse_from_str(trimmed, "%Y-%m-%d")
-                        .ok()
-                        .map(|re_parsed| format!("{} BC", re_parsed.with_day(1).unwrap()))
-                })
-                .unwrap_or_else(|| {
-                    panic!(
-                        "Invalid date found: \"{}\" in table: \"{}\". Error: \"{}\"",
-                        value, table_name, err
-                    )
+/// Parses the args of the transformers that have args worth parsing ahead of time
+fn parse_args(transformer: &Transformer) -> Result<Option<Args>, String> {
+    let args = match transformer.name {
+        TransformerType::JsonPaths => Args::JsonPaths(
+            parse_json_path_transformers(transformer)?
+                .into_iter()
+                .map(|(json_path, nested)| Ok((json_path, compile(nested)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        TransformerType::RegexReplace => {
+            let args = parse_regex_replace_args(transformer)?;
+            Args::RegexReplace(RegexReplaceArgs {
+                group_transformers: args
+                    .group_transformers
+                    .into_iter()
+                    .map(|(index, nested)| Ok((index, compile(nested.transformer)?)))
+                    .collect::<Result<_, String>>()?,
+                ..args
+            })
+        }
+        TransformerType::ScrubPii => Args::ScrubPii(parse_pii_detectors_arg(&transformer.args)?),
+        TransformerType::Mask => Args::Mask(parse_mask_args(&transformer.args)?),
+        TransformerType::ShiftDate | TransformerType::ShiftTimestamp => {
+            Args::ShiftDate(parse_shift_date_args(transformer)?)
+        }
+        TransformerType::Bucket | TransformerType::RoundNumber => Args::GeneraliseNumber {
+            step: parse_number_step_arg(transformer)?,
+        },
+        TransformerType::TruncateDate => Args::TruncateDate {
+            to: parse_truncate_date_arg(&transformer.args)?,
+        },
+        TransformerType::AddNoise => Args::AddNoise(parse_add_noise_args(&transformer.args)?),
+        TransformerType::Template => Args::Template(parse_template_arg(&transformer.args)?),
+        TransformerType::Switch => {
+            let args = parse_switch_args(transformer)?;
+            let branches = args
+                .branches()
+                .into_iter()
+                .map(|name| {
+                    let branch = switch_branch_named(transformer, name)?;
+                    Ok((name.to_string(), compile(branch.clone())?))
                 })
+                .collect::<Result<_, String>>()?;
+            Args::Switch(args, branches)
         }
+        _ => return Ok(None),
+    };
+    Ok(Some(args))
+}
+
+/// Parses the args of `transformer` (and the transformers nested in it), compiling any regexes
+/// in them, so it's done once when the strategies are loaded rather than for every value
+pub fn compile(transformer: Transformer) -> Result<CompiledTransformer, String> {
+    let parsed_args = parse_args(&transformer)?.map(Arc::new);
+    Ok(CompiledTransformer {
+        transformer,
+        parsed_args,
+    })
+}
+
+/// The args of `transformer` parsed by `compile`, if it's been compiled
+fn compiled_args(transformer: &CompiledTransformer) -> Option<&Args> {
+    transformer.parsed_args.as_deref()
+}
+
+/// The JSONPaths in a `JsonPaths` transformer's args with the transformer for each, in order of
+/// the paths so that seeded runs are repeatable
+fn json_path_transformers(
+    transformer: &CompiledTransformer,
+) -> Result<Cow<'_, [(JsonPath, CompiledTransformer)]>, String> {
+    match compiled_args(transformer) {
+        Some(Args::JsonPaths(json_path_transformers)) => Ok(Cow::Borrowed(json_path_transformers)),
+        _ => parse_json_path_transformers(transformer).map(|json_path_transformers| {
+            json_path_transformers
+                .into_iter()
+                .map(|(json_path, nested)| (json_path, CompiledTransformer::uncompiled(nested)))
+                .collect()
+        }),
     }
 }
 
-fn scramble(rng: &mut SmallRng, original_value: &str) -> String {
-    let mut last_was_backslash = false;
-    original_value
-        .chars()
-        .map(|c| {
-            if last_was_backslash {
-                last_was_backslash = false;
-                c
-            } else if c == '\\' {
-                last_was_backslash = true;
-                c
-            } else if c == ' ' {
-                c
-            } else if c.is_ascii_digit() {
-                rng.gen_range(b'0'..=b'9') as char
-            } else {
-                rng.gen_range(b'a'..=b'z') as char
-            }
+fn parse_json_path_transformers(
+    transformer: &Transformer,
+) -> Result<Vec<(JsonPath, Transformer)>, String> {
+    let args = match &transformer.args {
+        Some(args) if !args.is_empty() => args,
+        _ => {
+            return Err(
+                "JsonPaths needs args mapping JSONPaths to the transformer for each".to_string(),
+            )
+        }
+    };
+
+    args.iter()
+        .sorted_by_key(|(path, _)| *path)
+        .map(|(path, arg)| {
+            let json_path = json_path::parse(path)?;
+            let nested = arg.as_transformer().ok_or_else(|| {
+                format!(
+                    "The arg for \"{}\" must be a transformer, e.g. {{\"name\": \"FakeEmail\"}}",
+                    path
+                )
+            })?;
+            validate_nested(nested).map_err(|err| format!("{}: {}", path, err))?;
+            Ok((json_path, nested.clone()))
         })
-        .collect::<String>()
+        .collect()
 }
 
-fn scramble_blank(original_value: &str) -> String {
-    let mut last_was_backslash = false;
-    original_value
-        .chars()
-        .map(|c| {
-            if last_was_backslash {
-                last_was_backslash = false;
-                c
-            } else if c == '\\' {
-                last_was_backslash = true;
-                c
-            } else if c == ' ' {
-                c
-            } else {
-                '_'
-            }
-        })
-        .collect::<String>()
+#[derive(Clone, Debug)]
+struct RegexReplaceArgs {
+    regex: Regex,
+    replacement: String,
+    group_transformers: Vec<(usize, CompiledTransformer)>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::parsers::rng;
-    use regex::Regex;
+fn regex_replace_args(
+    transformer: &CompiledTransformer,
+) -> Result<Cow<'_, RegexReplaceArgs>, String> {
+    match compiled_args(transformer) {
+        Some(Args::RegexReplace(args)) => Ok(Cow::Borrowed(args)),
+        _ => parse_regex_replace_args(transformer).map(Cow::Owned),
+    }
+}
 
-    const TABLE_NAME: &str = "gert_lush_table";
-    #[test]
-    fn null_is_not_transformed() {
-        let null = "\\N";
-        let mut rng = rng::get();
-        let new_null = transform(
-            &mut rng,
-            null,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::Scramble,
-                args: None,
-            },
-            TABLE_NAME,
-        );
-        assert_eq!(new_null, null);
+/// The pattern and replacement for a `RegexReplace` transformer, and the transformers for any
+/// capture groups, which are the other args, named after the group's number or name
+fn parse_regex_replace_args(transformer: &Transformer) -> Result<RegexReplaceArgs, String> {
+    let args = transformer.args.as_ref();
+    let regex = match args.and_then(|args| args.get("pattern")) {
+        Some(TransformerArg::Value(pattern)) => {
+            Regex::new(pattern).map_err(|err| format!("Invalid pattern: {}", err))?
+        }
+        _ => return Err("RegexReplace needs a 'pattern' arg".to_string()),
+    };
+    let replacement = arg(&transformer.args, "replacement")
+        .ok_or_else(|| "RegexReplace needs a 'replacement' arg".to_string())?;
+
+    let group_transformers = args
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| *name != "pattern" && *name != "replacement")
+        .map(|(group, arg)| {
+            let index = group_index(&regex, group)
+                .ok_or_else(|| format!("'{}' isn't a capture group in the pattern", group))?;
+            let nested = arg.as_transformer().ok_or_else(|| {
+                format!(
+                    "The arg for group '{}' must be a transformer, e.g. {{\"name\": \"FakeEmail\"}}",
+                    group
+                )
+            })?;
+            validate_nested(nested).map_err(|err| format!("group '{}': {}", group, err))?;
+            Ok((index, CompiledTransformer::uncompiled(nested.clone())))
+        })
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .sorted_by_key(|(index, _)| *index)
+        .collect();
+
+    Ok(RegexReplaceArgs {
+        regex,
+        replacement: replacement.to_string(),
+        group_transformers,
+    })
+}
+
+fn group_index(regex: &Regex, group: &str) -> Option<usize> {
+    match group.parse::<usize>() {
+        Ok(index) if index < regex.captures_len() => Some(index),
+        Ok(_) => None,
+        Err(_) => regex.capture_names().position(|name| name == Some(group)),
     }
+}
 
-    #[test]
-    fn deleted_is_not_transformed() {
-        let deleted = "deleted";
-        let mut rng = rng::get();
-        let new_deleted = transform(
-            &mut rng,
-            deleted,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::Scramble,
-                args: None,
-            },
-            TABLE_NAME,
-        );
-        assert_eq!(new_deleted, deleted);
+/// The args for a `Switch` transformer. The transformers it picks between stay in its args,
+/// which `switch_branch` returns them from, so only the names of their args are kept here
+#[derive(Clone, Debug)]
+enum SwitchArgs {
+    /// Picks the transformer for the value in another column of the row, from the args named
+    /// after its values
+    Column { column: String, cases: Vec<String> },
+    /// Picks the `match` transformer for values the pattern matches
+    Pattern(Regex),
+}
+
+impl SwitchArgs {
+    /// The name of the arg of every transformer the switch can pick
+    fn branches(&self) -> Vec<&str> {
+        let mut branches = match self {
+            SwitchArgs::Column { cases, .. } => cases.iter().map(String::as_str).collect(),
+            SwitchArgs::Pattern(_) => vec!["match"],
+        };
+        branches.push("default");
+        branches
     }
+}
 
-    #[test]
-    fn identity() {
-        let first_name = "any first name";
-        let mut rng = rng::get();
-        let new_first_name = transform(
-            &mut rng,
-            first_name,
+/// The transformer in the arg called `name` of a `Switch`, if it has one
+fn switch_case<'a>(
+    transformer: &'a Transformer,
+    name: &str,
+) -> Result<Option<&'a Transformer>, String> {
+    transformer
+        .args
+        .as_ref()
+        .and_then(|args| args.get(name))
+        .map(|arg| {
+            arg.as_transformer().ok_or_else(|| {
+                format!(
+                    "The arg for '{}' must be a transformer, e.g. {{\"name\": \"FakeEmail\"}}",
+                    name
+                )
+            })
+        })
+        .transpose()
+}
+
+/// The transformer in the arg called `name` of a `Switch`, which its args have been checked to
+/// have
+fn switch_branch_named<'a>(
+    transformer: &'a Transformer,
+    name: &str,
+) -> Result<&'a Transformer, String> {
+    switch_case(transformer, name)?.ok_or_else(|| format!("Switch has no '{}' arg", name))
+}
+
+/// The args for a `Switch` transformer, which switches either on the value of another `column`
+/// or on whether the value matches a `pattern`, and always has a `default`
+fn parse_switch_args(transformer: &Transformer) -> Result<SwitchArgs, String> {
+    let args = transformer.args.as_ref();
+    switch_case(transformer, "default")?.ok_or_else(|| {
+        "Switch needs a 'default' arg with the transformer to use when nothing else matches"
+            .to_string()
+    })?;
+    match (
+        arg(&transformer.args, "column"),
+        args.and_then(|args| args.get("pattern")),
+    ) {
+        (Some(column), None) => {
+            let cases = args
+                .into_iter()
+                .flatten()
+                .map(|(name, _)| name)
+                .filter(|name| *name != "column" && *name != "default")
+                .sorted()
+                .map(|name| {
+                    switch_case(transformer, name)?;
+                    Ok(name.clone())
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            if cases.is_empty() {
+                return Err(format!(
+                    "Switch needs an arg for at least one value of '{}', with the transformer to use for it",
+                    column
+                ));
+            }
+            Ok(SwitchArgs::Column {
+                column: column.to_string(),
+                cases,
+            })
+        }
+        (None, Some(pattern)) => {
+            let regex = match pattern {
+                TransformerArg::Value(pattern) => {
+                    Regex::new(pattern).map_err(|err| format!("Invalid pattern: {}", err))?
+                }
+                TransformerArg::Transformer(_) => {
+                    return Err("The arg for 'pattern' must be a regex".to_string())
+                }
+            };
+            switch_case(transformer, "match")?.ok_or_else(|| {
+                "Switch needs a 'match' arg with the transformer for values matching its 'pattern'"
+                    .to_string()
+            })?;
+            if let Some(other) = args
+                .into_iter()
+                .flatten()
+                .map(|(name, _)| name)
+                .filter(|name| !["pattern", "match", "default"].contains(&name.as_str()))
+                .min()
+            {
+                return Err(format!(
+                    "Switch on a 'pattern' only takes 'match' and 'default' args, got: '{}'",
+                    other
+                ));
+            }
+            Ok(SwitchArgs::Pattern(regex))
+        }
+        _ => Err("Switch needs either a 'column' arg, to switch on the value of another column, or a 'pattern' arg, to switch on whether the value matches it".to_string()),
+    }
+}
+
+fn validate_switch_args(transformer: &Transformer) -> Result<(), String> {
+    for name in parse_switch_args(transformer)?.branches() {
+        let branch = switch_branch_named(transformer, name)?;
+        if matches!(
+            branch.name,
+            TransformerType::Shuffle | TransformerType::Template
+        ) {
+            return Err(format!(
+                "'{}': {:?} can't be picked by a Switch",
+                name, branch.name
+            ));
+        }
+        validate_args(branch).map_err(|err| format!("'{}': {}", name, err))?;
+    }
+    Ok(())
+}
+
+/// The transformer to use on `value`, which is `transformer` unless it's a `Switch`, in which
+/// case it's the one the switch picks (and so on if that's a switch too). `column_value` gives
+/// the value of another column in the row, as COPY text
+pub fn switch_branch<'a, 'value>(
+    transformer: &'a CompiledTransformer,
+    value: &str,
+    column_value: impl Fn(&str) -> Option<&'value str>,
+) -> Result<Cow<'a, CompiledTransformer>, String> {
+    if transformer.name != TransformerType::Switch {
+        return Ok(Cow::Borrowed(transformer));
+    }
+
+    let parsed_args;
+    let (args, branches) = match compiled_args(transformer) {
+        Some(Args::Switch(args, branches)) => (args, Some(branches)),
+        _ => {
+            parsed_args = parse_switch_args(transformer)?;
+            (&parsed_args, None)
+        }
+    };
+    let picked = match args {
+        SwitchArgs::Column { column, cases } => {
+            let switch_value = column_value(column)
+                .ok_or_else(|| format!("The column '{}' to switch on isn't in the row", column))?;
+            // NULLs always get the default
+            let switch_value = (switch_value != "\\N").then(|| copy_text::unescape(switch_value));
+            cases
+                .iter()
+                .find(|case| switch_value.as_deref() == Some(case.as_str()))
+                .map(String::as_str)
+        }
+        SwitchArgs::Pattern(regex) => regex
+            .is_match(&copy_text::unescape(value))
+            .then_some("match"),
+    }
+    .unwrap_or("default");
+
+    // The branch picked might be a switch too
+    match branches {
+        Some(branches) => {
+            let branch = branches
+                .get(picked)
+                .ok_or_else(|| format!("Switch has no '{}' arg", picked))?;
+            switch_branch(branch, value, column_value)
+        }
+        None => {
+            let branch =
+                CompiledTransformer::uncompiled(switch_branch_named(transformer, picked)?.clone());
+            switch_branch(&branch, value, column_value)
+                .map(|branch| Cow::Owned(branch.into_owned()))
+        }
+    }
+}
+
+/// The other columns whose values `transformer` switches on, if it's a `Switch`, including those
+/// of any switches it can pick
+pub fn switch_columns(transformer: &Transformer) -> Vec<String> {
+    if transformer.name != TransformerType::Switch {
+        return Vec::new();
+    }
+    let Ok(args) = parse_switch_args(transformer) else {
+        return Vec::new();
+    };
+    let column = match &args {
+        SwitchArgs::Column { column, .. } => Some(column.clone()),
+        SwitchArgs::Pattern(_) => None,
+    };
+    column
+        .into_iter()
+        .chain(
+            args.branches()
+                .into_iter()
+                .filter_map(|name| switch_branch_named(transformer, name).ok())
+                .flat_map(switch_columns),
+        )
+        .collect()
+}
+
+/// Whether `transformer` can leave a value as it is, because it's `Identity` or a `Switch` that can
+/// pick `Identity` (or pick a switch that can)
+pub fn can_pick_identity(transformer: &Transformer) -> bool {
+    match transformer.name {
+        TransformerType::Identity => true,
+        TransformerType::Switch => parse_switch_args(transformer).is_ok_and(|args| {
+            args.branches()
+                .into_iter()
+                .filter_map(|name| switch_branch_named(transformer, name).ok())
+                .any(can_pick_identity)
+        }),
+        _ => false,
+    }
+}
+
+fn regex_replace(
+    rng: &mut SmallRng,
+    value: &str,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+    draws: Draws,
+) -> Result<String, AnonymiserError> {
+    let args = regex_replace_args(transformer)
+        .map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let text = copy_text::unescape(value);
+
+    let mut result = String::with_capacity(text.len());
+    let mut last_match_end = 0;
+    for captures in args.regex.captures_iter(&text) {
+        let whole_match = captures.get(0).expect("Group 0 is always the whole match");
+        result.push_str(&text[last_match_end..whole_match.start()]);
+
+        let mut transformed_groups = HashMap::new();
+        for (index, nested) in &args.group_transformers {
+            if let Some(group) = captures.get(*index) {
+                let group_text = copy_text::escape(group.as_str());
+                let transformed = transform_value(
+                    rng,
+                    &group_text,
+                    &Type::single_value(SubType::Text),
+                    nested,
+                    table_name,
+                    draws,
+                )?;
+                transformed_groups.insert(*index, copy_text::unescape(&transformed));
+            }
+        }
+
+        expand_replacement(
+            &args.replacement,
+            &args.regex,
+            &captures,
+            &transformed_groups,
+            &mut result,
+        );
+        last_match_end = whole_match.end();
+    }
+    result.push_str(&text[last_match_end..]);
+
+    Ok(copy_text::escape(&result))
+}
+
+/// Writes `replacement` with each `$group` or `${group}` in it replaced by the group's
+/// transformed value if it has one, or what it matched if not (the same syntax as
+/// `Regex::replace`, with `$$` for a literal `$`)
+fn expand_replacement(
+    replacement: &str,
+    regex: &Regex,
+    captures: &Captures,
+    transformed_groups: &HashMap<usize, String>,
+    result: &mut String,
+) {
+    let mut rest = replacement;
+    while let Some(dollar) = rest.find('$') {
+        result.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+        let (group, after) = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            Some((group, after)) => (group, after),
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        if group.is_empty() {
+            result.push('$');
+            continue;
+        }
+
+        if let Some(index) = group_index(regex, group) {
+            match transformed_groups.get(&index) {
+                Some(transformed) => result.push_str(transformed),
+                None => result.push_str(captures.get(index).map_or("", |m| m.as_str())),
+            }
+        }
+        rest = after;
+    }
+    result.push_str(rest);
+}
+
+fn json_paths(
+    rng: &mut SmallRng,
+    value: &str,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+    draws: Draws,
+) -> Result<String, AnonymiserError> {
+    let json_path_transformers = json_path_transformers(transformer)
+        .map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let mut json: Value = serde_json::from_str(&copy_text::unescape(value)).map_err(|err| {
+        AnonymiserError::transform(
+            table_name,
+            format!("Invalid JSON found: \"{}\". Error: \"{}\"", value, err),
+        )
+    })?;
+
+    for (json_path, nested) in json_path_transformers.iter() {
+        json_path.for_each_match(&mut json, &mut |node| {
+            transform_json_node(rng, node, nested, table_name, draws)
+        })?;
+    }
+    Ok(copy_text::escape(&json.to_string()))
+}
+
+/// Transforms a value inside a JSON document. Strings are given to the transformer as text, and
+/// anything else as its JSON, which is kept as JSON if the transformed value is still valid
+fn transform_json_node(
+    rng: &mut SmallRng,
+    node: &mut Value,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+    draws: Draws,
+) -> Result<(), AnonymiserError> {
+    let (text, sub_type) = match node {
+        Value::Null => return Ok(()),
+        Value::String(string) => (copy_text::escape(string), SubType::Text),
+        Value::Number(number) => (number.to_string(), SubType::numeric()),
+        Value::Bool(boolean) => (boolean.to_string(), SubType::Boolean),
+        Value::Array(_) | Value::Object(_) => (copy_text::escape(&node.to_string()), SubType::Json),
+    };
+
+    let transformed = copy_text::unescape(&transform_value(
+        rng,
+        &text,
+        &Type::single_value(sub_type),
+        transformer,
+        table_name,
+        draws,
+    )?);
+
+    *node = match node {
+        Value::String(_) => Value::String(transformed),
+        _ => serde_json::from_str(&transformed).unwrap_or(Value::String(transformed)),
+    };
+    Ok(())
+}
+
+fn fake_base16_string(rng: &mut SmallRng) -> String {
+    let random_bytes = rng.gen::<[u8; 16]>();
+    base16::encode_lower(&random_bytes)
+}
+
+fn fake_base32_string(rng: &mut SmallRng) -> String {
+    let random_bytes = rng.gen::<[u8; 16]>();
+    base32::encode(Alphabet::RFC4648 { padding: true }, &random_bytes)
+}
+
+fn fake_company_name(
+    rng: &mut SmallRng,
+    locale: Locale,
+    args: &Option<TransformerArgs>,
+    unique: usize,
+) -> String {
+    let new_company_name = locale.company_name(rng);
+    prepend_unique_if_present(new_company_name, args, unique)
+}
+
+fn fake_email(
+    rng: &mut SmallRng,
+    optional_args: &Option<TransformerArgs>,
+    unique: usize,
+) -> String {
+    let new_email = FreeEmail().fake_with_rng(rng);
+    prepend_unique_if_present(new_email, optional_args, unique)
+}
+
+fn fake_email_or_phone(
+    rng: &mut SmallRng,
+    current_value: &str,
+    optional_args: &Option<TransformerArgs>,
+    unique: usize,
+) -> String {
+    if current_value.starts_with('+') && !current_value.contains('@') {
+        fake_phone_number(rng, current_value)
+    } else {
+        fake_email(rng, optional_args, unique)
+    }
+}
+
+fn fake_national_identity_number(rng: &mut SmallRng) -> String {
+    //TODO currently this is free text so they can enter anything at all,
+    //so im not bothering with us vs uk,
+    //there dont seem to be any us social sec numbers in the DB currently
+    national_insurance_number::random(rng)
+}
+
+//https://www.ofcom.org.uk/phones-telecoms-and-internet/information-for-industry/numbering/numbers-for-drama
+static UK_FAKE_MOBILE_RANGE: Range<i32> = 900000..960999;
+
+fn fake_phone_number(rng: &mut SmallRng, current_value: &str) -> String {
+    if current_value.starts_with("+447") {
+        let random = rng.gen_range(UK_FAKE_MOBILE_RANGE.clone());
+        format!("+447700{0}", random)
+    } else {
+        let area_code = rng.gen_range(200..999);
+        let rest = rng.gen_range(1000000..9999999);
+        format!("+1{}{}", area_code, rest)
+    }
+}
+
+fn fake_postcode(current_value: &str) -> String {
+    current_value.chars().take(3).collect()
+}
+
+fn fake_username(rng: &mut SmallRng, args: &Option<TransformerArgs>, unique: usize) -> String {
+    let username = Username().fake_with_rng(rng);
+    prepend_unique_if_present(username, args, unique)
+}
+
+fn fake_uuid(rng: &mut SmallRng) -> String {
+    Builder::from_random_bytes(rng.gen())
+        .into_uuid()
+        .to_string()
+}
+
+fn fixed(args: &Option<TransformerArgs>, table_name: &str) -> Result<String, AnonymiserError> {
+    let value = arg(args, "value").ok_or_else(|| {
+        AnonymiserError::transform(
+            table_name,
+            format!(
+                "'value' must be present in args for a fixed transformer\ngot: '{:?}'",
+                args
+            ),
+        )
+    })?;
+    Ok(value.to_string())
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MaskArgs {
+    keep_start: usize,
+    keep_end: usize,
+    mask_char: char,
+}
+
+fn mask_args(transformer: &CompiledTransformer) -> Result<MaskArgs, String> {
+    match compiled_args(transformer) {
+        Some(Args::Mask(args)) => Ok(*args),
+        _ => parse_mask_args(&transformer.args),
+    }
+}
+
+fn parse_mask_args(args: &Option<TransformerArgs>) -> Result<MaskArgs, String> {
+    let count = |name| {
+        arg(args, name).map_or(Ok(0), |count: &str| {
+            count.parse::<usize>().map_err(|_| {
+                format!(
+                    "'{}' must be a number of characters to keep, got: '{}'",
+                    name, count
+                )
+            })
+        })
+    };
+    let mask_char = match arg(args, "mask_char") {
+        None => '*',
+        Some(mask_char) => {
+            let mut chars = mask_char.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return Err(format!(
+                        "'mask_char' must be a single character, got: '{}'",
+                        mask_char
+                    ))
+                }
+            }
+        }
+    };
+
+    Ok(MaskArgs {
+        keep_start: count("keep_start")?,
+        keep_end: count("keep_end")?,
+        mask_char,
+    })
+}
+
+/// Replaces every character with `mask_char` apart from the first `keep_start` and last
+/// `keep_end`. Counts characters rather than bytes, and an escaped character in the COPY text
+/// (e.g. `\n`) counts as one. Values too short to hide anything are masked completely
+fn mask(
+    value: &str,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<String, AnonymiserError> {
+    let args = mask_args(transformer).map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let text = copy_text::unescape(value);
+    let length = text.chars().count();
+
+    let masked: String = if length <= args.keep_start + args.keep_end {
+        text.chars().map(|_| args.mask_char).collect()
+    } else {
+        text.chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if i < args.keep_start || i >= length - args.keep_end {
+                    c
+                } else {
+                    args.mask_char
+                }
+            })
+            .collect()
+    };
+    Ok(copy_text::escape(&masked))
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ShiftDateArgs {
+    max_days: i64,
+    /// The most seconds a timestamp's time of day is moved by either way, 0 to keep it
+    max_seconds: i64,
+}
+
+fn shift_date_args(transformer: &CompiledTransformer) -> Result<ShiftDateArgs, String> {
+    match compiled_args(transformer) {
+        Some(Args::ShiftDate(args)) => Ok(*args),
+        _ => parse_shift_date_args(transformer),
+    }
+}
+
+fn parse_shift_date_args(transformer: &Transformer) -> Result<ShiftDateArgs, String> {
+    let max_days = arg(&transformer.args, "max_days").ok_or_else(|| {
+        "'max_days' must be present in args, with the most days a value can be moved by".to_string()
+    })?;
+    let max_days = match max_days.parse::<i64>() {
+        Ok(days) if (1..=MAX_SHIFT_DAYS).contains(&days) => days,
+        _ => {
+            return Err(format!(
+                "'max_days' must be a whole number of days from 1 to {}, got: '{}'",
+                MAX_SHIFT_DAYS, max_days
+            ))
+        }
+    };
+
+    let max_seconds = match arg(&transformer.args, "max_seconds") {
+        None => 0,
+        Some(_) if transformer.name == TransformerType::ShiftDate => {
+            return Err("'max_seconds' can only be used with ShiftTimestamp".to_string())
+        }
+        Some(max_seconds) => match max_seconds.parse::<i64>() {
+            Ok(seconds) if (1..SECONDS_IN_A_DAY).contains(&seconds) => seconds,
+            _ => {
+                return Err(format!(
+                    "'max_seconds' must be a whole number of seconds from 1 to {}, got: '{}'",
+                    SECONDS_IN_A_DAY - 1,
+                    max_seconds
+                ))
+            }
+        },
+    };
+    Ok(ShiftDateArgs {
+        max_days,
+        max_seconds,
+    })
+}
+
+/// About 1000 years, well within what chrono can add to a date
+const MAX_SHIFT_DAYS: i64 = 365_250;
+
+const SECONDS_IN_A_DAY: i64 = 24 * 60 * 60;
+
+/// Moves a date, or the date part of a timestamp, by a random number of days (never 0) up to
+/// `max_days` either way. With `max_seconds` a timestamp's time of day is moved too, by up to that
+/// many seconds either way, after the days are picked so it's moved by the same number of days as
+/// a date with the same key. Fractional seconds and time zones are kept as they are. Postgres'
+/// `infinity` and `-infinity` aren't moved, and BC dates are moved across the BC/AD boundary
+/// correctly
+fn shift_date(
+    rng: &mut SmallRng,
+    value: &str,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<String, AnonymiserError> {
+    if value == "infinity" || value == "-infinity" {
+        return Ok(value.to_string());
+    }
+    let args =
+        shift_date_args(transformer).map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let (date, time) = parse_date_value(value).ok_or_else(|| invalid_date(value, table_name))?;
+
+    let days = rng.gen_range(1..=args.max_days) * if rng.gen::<bool>() { 1 } else { -1 };
+    let shifted = date
+        .checked_add_signed(chrono::Duration::days(days))
+        .ok_or_else(|| invalid_date(value, table_name))?;
+
+    match time {
+        Some(time) if args.max_seconds > 0 => {
+            let seconds = rng.gen_range(-args.max_seconds..=args.max_seconds);
+            shift_time(shifted, time, seconds).ok_or_else(|| invalid_date(value, table_name))
+        }
+        _ => Ok(format_date_value(shifted, time)),
+    }
+}
+
+/// Moves a timestamp's time of day by `seconds`, moving its date too if that crosses midnight.
+/// Only the whole seconds (the `HH:MM:SS` at the start of `time`) change
+fn shift_time(date: NaiveDate, time: &str, seconds: i64) -> Option<String> {
+    let (whole_seconds, rest) = (time.get(..8)?, &time[8..]);
+    let shifted = date
+        .and_time(NaiveTime::parse_from_str(whole_seconds, "%H:%M:%S").ok()?)
+        .checked_add_signed(chrono::Duration::seconds(seconds))?;
+    let time = format!("{}{}", shifted.format("%H:%M:%S"), rest);
+    Some(format_date_value(shifted.date(), Some(&time)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DatePart {
+    Year,
+    Month,
+    Day,
+    Hour,
+}
+
+fn truncate_date_arg(transformer: &CompiledTransformer) -> Result<DatePart, String> {
+    match compiled_args(transformer) {
+        Some(Args::TruncateDate { to }) => Ok(*to),
+        _ => parse_truncate_date_arg(&transformer.args),
+    }
+}
+
+fn parse_truncate_date_arg(args: &Option<TransformerArgs>) -> Result<DatePart, String> {
+    match arg(args, "to") {
+        Some("year") => Ok(DatePart::Year),
+        Some("month") => Ok(DatePart::Month),
+        Some("day") => Ok(DatePart::Day),
+        Some("hour") => Ok(DatePart::Hour),
+        other => Err(format!(
+            "'to' must be one of 'year', 'month', 'day' or 'hour', got: {:?}",
+            other
+        )),
+    }
+}
+
+/// Sets everything in a date or timestamp smaller than the `to` arg to its start, e.g.
+/// truncating `2020-12-12 10:11:12+01` to the month gives `2020-12-01 00:00:00+01`
+fn truncate_date(
+    value: &str,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<String, AnonymiserError> {
+    if value == "infinity" || value == "-infinity" {
+        return Ok(value.to_string());
+    }
+    let to = truncate_date_arg(transformer)
+        .map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let (date, time) = parse_date_value(value).ok_or_else(|| invalid_date(value, table_name))?;
+
+    let truncated_date = match to {
+        DatePart::Year => date.with_day(1).and_then(|date| date.with_month(1)),
+        DatePart::Month => date.with_day(1),
+        DatePart::Day | DatePart::Hour => Some(date),
+    }
+    .expect("The first day of a month or year always exists");
+    let truncated_time = time
+        .map(|time| {
+            // The time zone (if there is one) is the first sign after the time
+            let (time, time_zone) = time.split_at(time.find(['+', '-']).unwrap_or(time.len()));
+            let hour = match to {
+                DatePart::Hour => time
+                    .get(..2)
+                    .ok_or_else(|| invalid_date(value, table_name))?,
+                _ => "00",
+            };
+            Ok::<_, AnonymiserError>(format!("{}:00:00{}", hour, time_zone))
+        })
+        .transpose()?;
+
+    Ok(format_date_value(truncated_date, truncated_time.as_deref()))
+}
+
+/// Splits a date or timestamp written by Postgres into the date and the time (with its time
+/// zone, if it has one). BC dates are returned as years before 1, Postgres has no year 0 so
+/// 1 BC is chrono's year 0
+fn parse_date_value(value: &str) -> Option<(NaiveDate, Option<&str>)> {
+    let (value_without_era, is_bc) = match value.strip_suffix(" BC") {
+        Some(ad) => (ad, true),
+        None => (value, false),
+    };
+    let (date, time) = match value_without_era.split_once(' ') {
+        Some((date, time)) => (date, Some(time)),
+        None => (value_without_era, None),
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let date = match is_bc {
+        true => date.with_year(1 - date.year())?,
+        false => date,
+    };
+    Some((date, time))
+}
+
+fn format_date_value(date: NaiveDate, time: Option<&str>) -> String {
+    let (year, era) = match date.year() {
+        year if year <= 0 => (1 - year, " BC"),
+        year => (year, ""),
+    };
+    let mut result = format!("{:04}-{:02}-{:02}", year, date.month(), date.day());
+    if let Some(time) = time {
+        result.push(' ');
+        result.push_str(time);
+    }
+    result.push_str(era);
+    result
+}
+
+fn invalid_date(value: &str, table_name: &str) -> AnonymiserError {
+    AnonymiserError::transform(
+        table_name,
+        format!("Invalid date or timestamp found: \"{}\"", value),
+    )
+}
+
+fn number_step_arg(transformer: &CompiledTransformer) -> Result<Decimal, String> {
+    match compiled_args(transformer) {
+        Some(Args::GeneraliseNumber { step }) => Ok(*step),
+        _ => parse_number_step_arg(transformer),
+    }
+}
+
+/// The step a `RoundNumber` rounds to, or the size of a `Bucket`'s buckets
+fn parse_number_step_arg(transformer: &Transformer) -> Result<Decimal, String> {
+    let name = match transformer.name {
+        TransformerType::Bucket => "size",
+        _ => "to",
+    };
+    let step = arg(&transformer.args, name)
+        .ok_or_else(|| format!("'{}' must be present in args", name))?;
+    match step.parse::<Decimal>() {
+        Ok(decimal) if decimal.is_positive() => Ok(decimal),
+        _ => Err(format!(
+            "'{}' must be a number greater than 0, got: '{}'",
+            name, step
+        )),
+    }
+}
+
+/// Rounds a number to the nearest multiple of the `to` arg (`RoundNumber`), or down to the
+/// bottom of its bucket (`Bucket`). Numbers keep their decimal places, and integer columns stay
+/// whole numbers. Postgres' `NaN`, `Infinity` and `-Infinity` aren't changed
+fn generalise_number(
+    value: &str,
+    column_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<String, AnonymiserError> {
+    if ["NaN", "Infinity", "-Infinity"].contains(&value) {
+        return Ok(value.to_string());
+    }
+    let step =
+        number_step_arg(transformer).map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let number = value
+        .parse::<Decimal>()
+        .map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let rounding = match transformer.name {
+        TransformerType::Bucket => Rounding::Down,
+        _ => Rounding::Nearest,
+    };
+
+    let is_integer = matches!(
+        column_type.sub_type(),
+        SubType::Integer | SubType::BigInt | SubType::SmallInt
+    );
+    let step = match is_integer {
+        true => step.with_scale(0).ok_or_else(|| {
+            AnonymiserError::transform(
+                table_name,
+                format!("integer values can't be rounded to multiples of {}", step),
+            )
+        })?,
+        false => step,
+    };
+
+    let mut generalised = number.round_to_multiple(&step, rounding).ok_or_else(|| {
+        AnonymiserError::transform(table_name, format!("{} is too large to round", value))
+    })?;
+
+    // Rounding up can go past the largest value the column holds, e.g. 999.99 to the nearest 10
+    // in a numeric(5,2), so it's kept to the column's limits the same as `add_noise`
+    let (column_min, column_max) = column_limits(column_type.sub_type());
+    if let Some(min) = column_min.filter(|min| generalised.compare(min).is_lt()) {
+        generalised = min;
+    }
+    if let Some(max) = column_max.filter(|max| generalised.compare(max).is_gt()) {
+        generalised = max;
+    }
+    Ok(generalised.to_string())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Distribution {
+    Gaussian,
+    Uniform,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct AddNoiseArgs {
+    distribution: Distribution,
+    amount: f64,
+    relative: bool,
+    keep_sign: bool,
+    min: Option<Decimal>,
+    max: Option<Decimal>,
+}
+
+fn add_noise_args(transformer: &CompiledTransformer) -> Result<AddNoiseArgs, String> {
+    match compiled_args(transformer) {
+        Some(Args::AddNoise(args)) => Ok(*args),
+        _ => parse_add_noise_args(&transformer.args),
+    }
+}
+
+fn parse_add_noise_args(args: &Option<TransformerArgs>) -> Result<AddNoiseArgs, String> {
+    let distribution = match arg(args, "distribution") {
+        None | Some("gaussian") => Distribution::Gaussian,
+        Some("uniform") => Distribution::Uniform,
+        Some(other) => {
+            return Err(format!(
+                "'distribution' must be 'gaussian' or 'uniform', got: '{}'",
+                other
+            ))
+        }
+    };
+    let amount = arg(args, "amount").ok_or_else(|| {
+        "'amount' must be present in args, with the size of the noise to add".to_string()
+    })?;
+    let amount = match amount.parse::<Decimal>() {
+        Ok(decimal) if decimal.is_positive() => decimal.to_f64(),
+        _ => {
+            return Err(format!(
+                "'amount' must be a number greater than 0, got: '{}'",
+                amount
+            ))
+        }
+    };
+    let flag = |name| match arg(args, name) {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(other) => Err(format!(
+            "'{}' must be 'true' or 'false', got: '{}'",
+            name, other
+        )),
+    };
+    let limit = |name| {
+        arg(args, name)
+            .map(|limit: &str| {
+                limit
+                    .parse::<Decimal>()
+                    .map_err(|_| format!("'{}' must be a number, got: '{}'", name, limit))
+            })
+            .transpose()
+    };
+
+    let (min, max) = (limit("min")?, limit("max")?);
+    if let (Some(min), Some(max)) = (min, max) {
+        if min.compare(&max).is_gt() {
+            return Err(format!("'min' ({}) is greater than 'max' ({})", min, max));
+        }
+    }
+
+    Ok(AddNoiseArgs {
+        distribution,
+        amount,
+        relative: flag("relative")?,
+        keep_sign: flag("keep_sign")?,
+        min,
+        max,
+    })
+}
+
+/// Adds random noise to a number: gaussian noise with a standard deviation of `amount`, or noise
+/// picked uniformly from `-amount` to `amount`. If `relative`, `amount` is a fraction of the
+/// value instead (e.g. `0.05` for 5%). The result is rounded to the column's scale (or the
+/// value's decimal places if it has no scale), kept within `min` and `max` and whatever the
+/// column can hold, and flipped back if `keep_sign` and the noise changed its sign. Postgres'
+/// `NaN`, `Infinity` and `-Infinity` aren't changed
+fn add_noise(
+    rng: &mut SmallRng,
+    value: &str,
+    column_type: &Type,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<String, AnonymiserError> {
+    if ["NaN", "Infinity", "-Infinity"].contains(&value) {
+        return Ok(value.to_string());
+    }
+    let args =
+        add_noise_args(transformer).map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let number = value
+        .parse::<Decimal>()
+        .map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let too_large = || {
+        AnonymiserError::transform(
+            table_name,
+            format!("{} is too large to add noise to", value),
+        )
+    };
+
+    let amount = match args.relative {
+        true => args.amount * number.abs().to_f64(),
+        false => args.amount,
+    };
+    let noise = match args.distribution {
+        Distribution::Gaussian => amount * standard_normal(rng),
+        Distribution::Uniform => rng.gen_range(-amount..=amount),
+    };
+
+    let sub_type = column_type.sub_type();
+    let scale = match sub_type {
+        SubType::Integer | SubType::BigInt | SubType::SmallInt => 0,
+        SubType::Numeric {
+            scale: Some(scale), ..
+        } => *scale,
+        _ => number.scale(),
+    };
+    let noise = Decimal::from_f64(noise, scale).ok_or_else(too_large)?;
+    let mut noisy = number.checked_add(&noise).ok_or_else(too_large)?;
+
+    if args.keep_sign && noisy.is_negative() != number.is_negative() {
+        noisy = noisy.neg();
+    }
+
+    let (column_min, column_max) = column_limits(sub_type);
+    for min in [args.min, column_min].into_iter().flatten() {
+        if noisy.compare(&min).is_lt() {
+            noisy = min;
+        }
+    }
+    for max in [args.max, column_max].into_iter().flatten() {
+        if noisy.compare(&max).is_gt() {
+            noisy = max;
+        }
+    }
+
+    Ok(noisy
+        .round_to_scale(scale)
+        .ok_or_else(too_large)?
+        .to_string())
+}
+
+/// The smallest and largest values a column of `sub_type` can hold, if it has limits
+fn column_limits(sub_type: &SubType) -> (Option<Decimal>, Option<Decimal>) {
+    match sub_type {
+        SubType::SmallInt => (
+            Some(Decimal::from_i64(i16::MIN.into())),
+            Some(Decimal::from_i64(i16::MAX.into())),
+        ),
+        SubType::Integer => (
+            Some(Decimal::from_i64(i32::MIN.into())),
+            Some(Decimal::from_i64(i32::MAX.into())),
+        ),
+        SubType::BigInt => (
+            Some(Decimal::from_i64(i64::MIN)),
+            Some(Decimal::from_i64(i64::MAX)),
+        ),
+        SubType::Numeric {
+            precision: Some(precision),
+            scale: Some(scale),
+        } => {
+            let largest = Decimal::largest(*precision, *scale);
+            (largest.map(|largest| largest.neg()), largest)
+        }
+        _ => (None, None),
+    }
+}
+
+/// A number from the standard normal distribution, using the Box-Muller transform
+fn standard_normal(rng: &mut SmallRng) -> f64 {
+    // gen gives [0, 1), and the log of 0 is infinite, so flip it to (0, 1]
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+fn obfuscate_day(value: &str, table_name: &str) -> Result<String, AnonymiserError> {
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => {
+            let new_date = date.with_day(1).unwrap();
+            Ok(new_date.to_string())
+        }
+        Err(err) => value
+            .strip_suffix(" BC")
+            .and_then(|trimmed| {
+                NaiveDate::parse_from_str(trimmed, "%Y-%m-%d")
+                    .ok()
+                    .map(|re_parsed| format!("{} BC", re_parsed.with_day(1).unwrap()))
+            })
+            .ok_or_else(|| {
+                AnonymiserError::transform(
+                    table_name,
+                    format!("Invalid date found: \"{}\". Error: \"{}\"", value, err),
+                )
+            }),
+    }
+}
+
+fn scramble(rng: &mut SmallRng, original_value: &str) -> String {
+    let mut last_was_backslash = false;
+    original_value
+        .chars()
+        .map(|c| {
+            if last_was_backslash {
+                last_was_backslash = false;
+                c
+            } else if c == '\\' {
+                last_was_backslash = true;
+                c
+            } else if c == ' ' {
+                c
+            } else if c.is_ascii_digit() {
+                rng.gen_range(b'0'..=b'9') as char
+            } else {
+                rng.gen_range(b'a'..=b'z') as char
+            }
+        })
+        .collect::<String>()
+}
+
+fn pii_detectors_arg(transformer: &CompiledTransformer) -> Result<Cow<'_, [Detector]>, String> {
+    match compiled_args(transformer) {
+        Some(Args::ScrubPii(detectors)) => Ok(Cow::Borrowed(detectors)),
+        _ => parse_pii_detectors_arg(&transformer.args).map(Cow::Owned),
+    }
+}
+
+/// The detectors in the comma separated `detectors` arg, or all of them if it isn't given
+fn parse_pii_detectors_arg(args: &Option<TransformerArgs>) -> Result<Vec<Detector>, String> {
+    let Some(names) = arg(args, "detectors") else {
+        return Ok(Detector::ALL.to_vec());
+    };
+    names
+        .split(',')
+        .map(|name| {
+            Detector::from_name(name.trim()).ok_or_else(|| {
+                format!(
+                    "Unknown PII detector '{}', expected one of: {}",
+                    name.trim(),
+                    Detector::ALL.iter().map(Detector::name).join(", ")
+                )
+            })
+        })
+        .collect()
+}
+
+fn scrub_pii(
+    rng: &mut SmallRng,
+    value: &str,
+    transformer: &CompiledTransformer,
+    table_name: &str,
+) -> Result<String, AnonymiserError> {
+    let detectors = pii_detectors_arg(transformer)
+        .map_err(|err| AnonymiserError::transform(table_name, err))?;
+    let text = copy_text::unescape(value);
+
+    let mut result = String::with_capacity(text.len());
+    let mut last_detection_end = 0;
+    for detection in pii_detectors::detect(&text, &detectors) {
+        let found = &text[detection.range.clone()];
+        result.push_str(&text[last_detection_end..detection.range.start]);
+        result.push_str(&match detection.detector {
+            Detector::Url => pii_detectors::fake_url(rng),
+            Detector::Email => fake_email(rng, &None, 0),
+            Detector::Iban => pii_detectors::fake_iban(rng, found),
+            Detector::CardNumber => pii_detectors::fake_card_number(rng, found),
+            Detector::NationalInsuranceNumber => fake_national_identity_number(rng),
+            Detector::Phone if found.starts_with('0') => {
+                format!("07700{}", rng.gen_range(UK_FAKE_MOBILE_RANGE.clone()))
+            }
+            Detector::Phone => fake_phone_number(rng, &found.replace([' ', '-'], "")),
+            Detector::Postcode => pii_detectors::fake_postcode(rng),
+        });
+        last_detection_end = detection.range.end;
+    }
+    result.push_str(&text[last_detection_end..]);
+
+    Ok(copy_text::escape(&result))
+}
+
+fn scramble_blank(original_value: &str) -> String {
+    let mut last_was_backslash = false;
+    original_value
+        .chars()
+        .map(|c| {
+            if last_was_backslash {
+                last_was_backslash = false;
+                c
+            } else if c == '\\' {
+                last_was_backslash = true;
+                c
+            } else if c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::rng;
+    use regex::Regex;
+
+    const TABLE_NAME: &str = "gert_lush_table";
+    #[test]
+    fn null_is_not_transformed() {
+        let null = "\\N";
+        let mut rng = rng::get();
+        let new_null = transform(
+            &mut rng,
+            null,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::Scramble,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(new_null, null);
+    }
+
+    #[test]
+    fn deleted_is_not_transformed() {
+        let deleted = "deleted";
+        let mut rng = rng::get();
+        let new_deleted = transform(
+            &mut rng,
+            deleted,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::Scramble,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(new_deleted, deleted);
+    }
+
+    #[test]
+    fn identity() {
+        let first_name = "any first name";
+        let mut rng = rng::get();
+        let new_first_name = transform(
+            &mut rng,
+            first_name,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::Identity,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_first_name == first_name);
+    }
+
+    #[test]
+    fn fake_base16_string() {
+        let verification_key = "1702a4eddd53d6fa79ed4a677e64c002";
+        let mut rng = rng::get();
+        let new_verification_key = transform(
+            &mut rng,
+            verification_key,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeBase16String,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_verification_key != verification_key);
+        assert_eq!(new_verification_key.len(), 32);
+    }
+
+    #[test]
+    fn fake_base32_string() {
+        let verification_key = "EMVXWNTUKRVAODPQ7KIBBQQTWY======";
+        let mut rng = rng::get();
+        let new_verification_key = transform(
+            &mut rng,
+            verification_key,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeBase32String,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_verification_key != verification_key);
+        assert_eq!(new_verification_key.len(), 32);
+    }
+
+    #[test]
+    fn fake_company_name() {
+        let company_name = "any company name";
+        let mut rng = rng::get();
+        let new_company_name = transform(
+            &mut rng,
+            company_name,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeCompanyName,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_company_name != company_name);
+    }
+    #[test]
+    fn fake_company_name_with_unique_arg() {
+        let company_name = "any company name";
+        let mut rng = rng::get();
+        let transformer = &Transformer {
+            name: TransformerType::FakeCompanyName,
+            args: Some(HashMap::from([("unique".to_string(), "true".into())])),
+        };
+        let new_company_name = transform(
+            &mut rng,
+            company_name,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_company_name != company_name);
+    }
+
+    #[test]
+    fn fake_email() {
+        let email = "any email";
+        let mut rng = rng::get();
+        let new_email = transform(
+            &mut rng,
+            email,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeEmail,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_email != email);
+
+        let re = Regex::new(r"^[0-9]+-.*@.*\..*").unwrap();
+        assert!(
+            !re.is_match(&new_email),
+            "Email {:?} should not have the unique prefix",
+            new_email
+        );
+    }
+
+    #[test]
+    fn fake_email_with_unique_arg() {
+        let email = "rupert@example.com";
+        let mut rng = rng::get();
+        let transformer = &Transformer {
+            name: TransformerType::FakeEmail,
+            args: Some(HashMap::from([("unique".to_string(), "true".into())])),
+        };
+        let new_email = transform(
+            &mut rng,
+            email,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_email != email);
+        let re = Regex::new(r"^[0-9]+-.*@.*\..*").unwrap();
+        assert!(
+            re.is_match(&new_email),
+            "Email {:?} does not have the unique prefix",
+            new_email
+        );
+    }
+
+    #[test]
+    fn deterministic_transform_gives_the_same_output_for_the_same_input() {
+        let transformer = &Transformer {
+            name: TransformerType::FakeEmail,
+            args: Some(HashMap::from([("unique".to_string(), "true".into())])),
+        };
+        let column_type = Type::SingleValue {
+            sub_type: SubType::Character,
+        };
+        let first = transform_deterministically(
+            "secret",
+            "alice@example.com",
+            &column_type,
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        let second = transform_deterministically(
+            "secret",
+            "alice@example.com",
+            &column_type,
+            transformer,
+            "another_table",
+        )
+        .unwrap();
+        assert_eq!(first, second);
+        assert!(first != "alice@example.com");
+    }
+
+    #[test]
+    fn deterministic_transform_gives_different_output_for_different_inputs_or_keys() {
+        let transformer = &Transformer {
+            name: TransformerType::FakeFullName,
+            args: None,
+        };
+        let column_type = Type::SingleValue {
+            sub_type: SubType::Character,
+        };
+        let alice = transform_deterministically(
+            "secret",
+            "Alice Smith",
+            &column_type,
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        let bob = transform_deterministically(
+            "secret",
+            "Bob Jones",
+            &column_type,
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        let alice_with_other_key = transform_deterministically(
+            "another secret",
+            "Alice Smith",
+            &column_type,
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(alice != bob);
+        assert!(alice != alice_with_other_key);
+    }
+
+    #[test]
+    fn compiled_transformers_transform_values_the_same_as_uncompiled_ones() {
+        let transformer = Transformer {
+            name: TransformerType::Mask,
+            args: Some(HashMap::from([("keep_end".to_string(), "4".into())])),
+        };
+        let compiled = compile(transformer.clone()).unwrap();
+        let column_type = Type::single_value(SubType::Text);
+        let card = "4111111111111111";
+
+        let transformed = compiled
+            .transform(&mut rng::get(), card, &column_type, TABLE_NAME)
+            .unwrap();
+        assert_eq!(transformed, "************1111");
+        assert_eq!(
+            transformed,
+            transform(
+                &mut rng::get(),
+                card,
+                &column_type,
+                &transformer,
+                TABLE_NAME
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            compiled
+                .transform_deterministically("secret", card, &column_type, TABLE_NAME)
+                .unwrap(),
+            transform_deterministically("secret", card, &column_type, &transformer, TABLE_NAME)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn deterministic_transform_gives_array_elements_the_same_output_as_single_values() {
+        let transformer = &Transformer {
+            name: TransformerType::FakeFullName,
+            args: None,
+        };
+        let alice = transform_deterministically(
+            "secret",
+            "Alice Smith",
+            &Type::single_value(SubType::Text),
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        let bob = transform_deterministically(
+            "secret",
+            "Bob Jones",
+            &Type::single_value(SubType::Text),
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        let array = transform_deterministically(
+            "secret",
+            "{\"Alice Smith\",\"Bob Jones\"}",
+            &Type::array(SubType::Text),
+            transformer,
+            "another_table",
+        )
+        .unwrap();
+        assert_eq!(array, format!("{{\"{}\",\"{}\"}}", alice, bob));
+    }
+
+    #[test]
+    fn fake_first_name() {
+        let first_name = "any first name";
+        let mut rng = rng::get();
+        let new_first_name = transform(
+            &mut rng,
+            first_name,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeFirstName,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_first_name != first_name);
+    }
+
+    #[test]
+    fn fake_full_name() {
+        let full_name = "any full name";
+        let mut rng = rng::get();
+        let new_full_name = transform(
+            &mut rng,
+            full_name,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeFullName,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_full_name != full_name);
+    }
+
+    #[test]
+    fn fake_last_name() {
+        let last_name = "any last name";
+        let mut rng = rng::get();
+        let new_last_name = transform(
+            &mut rng,
+            last_name,
             &Type::SingleValue {
                 sub_type: SubType::Character,
             },
             &Transformer {
-                name: TransformerType::Identity,
+                name: TransformerType::FakeLastName,
                 args: None,
             },
             TABLE_NAME,
-        );
-        assert!(new_first_name == first_name);
+        )
+        .unwrap();
+        assert!(new_last_name != last_name);
     }
 
     #[test]
-    fn fake_base16_string() {
-        let verification_key = "1702a4eddd53d6fa79ed4a677e64c002";
+    fn fake_full_address() {
+        let street_address = "any street_address";
         let mut rng = rng::get();
-        let new_verification_key = transform(
+        let new_street_address = transform(
             &mut rng,
-            verification_key,
+            street_address,
             &Type::SingleValue {
                 sub_type: SubType::Character,
             },
             &Transformer {
-                name: TransformerType::FakeBase16String,
+                name: TransformerType::FakeFullAddress,
                 args: None,
             },
             TABLE_NAME,
-        );
-        assert!(new_verification_key != verification_key);
-        assert_eq!(new_verification_key.len(), 32);
+        )
+        .unwrap();
+        assert!(new_street_address != street_address);
     }
 
     #[test]
-    fn fake_base32_string() {
-        let verification_key = "EMVXWNTUKRVAODPQ7KIBBQQTWY======";
+    fn fake_national_identity_number() {
+        let national_identity_number = "JR 55 55 55 E";
         let mut rng = rng::get();
-        let new_verification_key = transform(
+        let new_national_identity_number = transform(
             &mut rng,
-            verification_key,
+            national_identity_number,
             &Type::SingleValue {
                 sub_type: SubType::Character,
             },
             &Transformer {
-                name: TransformerType::FakeBase32String,
+                name: TransformerType::FakeNationalIdentityNumber,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_national_identity_number != national_identity_number);
+        assert!(national_insurance_number::NATIONAL_INSURANCE_NUMBERS
+            .contains(&new_national_identity_number.as_ref()));
+    }
+
+    #[test]
+    fn fake_email_or_phone_with_phone() {
+        let phone_number = "+447822222222";
+        let mut rng = rng::get();
+        let new_phone_number = transform(
+            &mut rng,
+            phone_number,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeEmailOrPhone,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_phone_number != phone_number);
+        assert!(new_phone_number.starts_with("+4477009"));
+        assert_eq!(new_phone_number.len(), 13);
+    }
+    #[test]
+    fn fake_email_or_phone_with_email() {
+        let email = "peter@peterson.com";
+        let mut rng = rng::get();
+        let new_email = transform(
+            &mut rng,
+            email,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakeEmailOrPhone,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_email != email);
+        assert!(new_email.contains('@'));
+    }
+
+    #[test]
+    fn fake_phone_number_gb() {
+        let phone_number = "+447822222222";
+        let mut rng = rng::get();
+        let new_phone_number = transform(
+            &mut rng,
+            phone_number,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakePhoneNumber,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_phone_number != phone_number);
+        assert!(new_phone_number.starts_with("+4477009"));
+        assert_eq!(new_phone_number.len(), 13);
+    }
+
+    #[test]
+    fn fake_phone_number_uses_the_locale_if_there_is_one() {
+        let transformer = Transformer {
+            name: TransformerType::FakePhoneNumber,
+            args: Some(HashMap::from([("locale".to_string(), "en_GB".into())])),
+        };
+        let mut rng = rng::get();
+        let new_phone_number = transform(
+            &mut rng,
+            "+16505130514",
+            &Type::character(),
+            &transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_phone_number.starts_with('0'), "{}", new_phone_number);
+    }
+
+    #[test]
+    fn fake_full_name_is_written_in_the_locale_order() {
+        let transformer = Transformer {
+            name: TransformerType::FakeFullName,
+            args: Some(HashMap::from([("locale".to_string(), "zh_CN".into())])),
+        };
+        let mut rng = rng::get();
+        let new_full_name = transform(
+            &mut rng,
+            "any full name",
+            &Type::character(),
+            &transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(!new_full_name.contains(' '), "{}", new_full_name);
+        assert!(!new_full_name.is_ascii(), "{}", new_full_name);
+    }
+
+    #[test]
+    fn fake_phone_number_us() {
+        let phone_number = "+16505130514";
+        let mut rng = rng::get();
+        let new_phone_number = transform(
+            &mut rng,
+            phone_number,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakePhoneNumber,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_phone_number != phone_number);
+        assert!(new_phone_number.starts_with("+1"));
+        assert_eq!(new_phone_number.len(), 12);
+    }
+
+    #[test]
+    fn fake_postcode() {
+        let postcode = "NW5 3QQ";
+        let mut rng = rng::get();
+        let new_postcode = transform(
+            &mut rng,
+            postcode,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            &Transformer {
+                name: TransformerType::FakePostCode,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(new_postcode, "NW5");
+    }
+
+    #[test]
+    fn fake_postcode_keeps_whole_characters() {
+        let mut rng = rng::get();
+        let new_postcode = transform(
+            &mut rng,
+            "ÅÖ5 3QQ",
+            &Type::single_value(SubType::Text),
+            &Transformer {
+                name: TransformerType::FakePostCode,
                 args: None,
             },
             TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(new_postcode, "ÅÖ5");
+    }
+
+    fn mask_value(value: &str, args: &[(&str, &str)]) -> String {
+        let transformer = Transformer {
+            name: TransformerType::Mask,
+            args: Some(
+                args.iter()
+                    .map(|(name, value)| (name.to_string(), (*value).into()))
+                    .collect(),
+            ),
+        };
+        let mut rng = rng::get();
+        transform(
+            &mut rng,
+            value,
+            &Type::single_value(SubType::Text),
+            &transformer,
+            TABLE_NAME,
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    #[test]
+    fn mask_keeps_the_start_and_end() {
+        assert_eq!(
+            mask_value("+447777777771", &[("keep_end", "4")]),
+            "*********7771"
         );
-        assert!(new_verification_key != verification_key);
-        assert_eq!(new_verification_key.len(), 32);
+        assert_eq!(
+            mask_value("Peterson", &[("keep_start", "1"), ("mask_char", "x")]),
+            "Pxxxxxxx"
+        );
+        assert_eq!(mask_value("secret", &[]), "******");
     }
 
     #[test]
-    fn fake_company_name() {
-        let company_name = "any company name";
+    fn mask_counts_characters_not_bytes() {
+        assert_eq!(
+            mask_value(
+                "Ångström",
+                &[("keep_start", "1"), ("keep_end", "1"), ("mask_char", "•")]
+            ),
+            "Å••••••m"
+        );
+    }
+
+    #[test]
+    fn mask_treats_copy_escapes_as_one_character() {
+        assert_eq!(
+            mask_value("ab\\n\\\\cd", &[("keep_start", "2"), ("keep_end", "2")]),
+            "ab**cd"
+        );
+        assert_eq!(
+            mask_value("a\\tb", &[("keep_start", "1"), ("mask_char", "\\")]),
+            "a\\\\\\\\"
+        );
+    }
+
+    #[test]
+    fn mask_hides_values_too_short_to_keep_anything() {
+        assert_eq!(
+            mask_value("1234", &[("keep_start", "2"), ("keep_end", "2")]),
+            "****"
+        );
+    }
+
+    #[test]
+    fn fake_user_name() {
+        let user_name = "any user_name";
         let mut rng = rng::get();
-        let new_company_name = transform(
+        let new_user_name = transform(
             &mut rng,
-            company_name,
+            user_name,
             &Type::SingleValue {
                 sub_type: SubType::Character,
             },
             &Transformer {
-                name: TransformerType::FakeCompanyName,
+                name: TransformerType::FakeUsername,
                 args: None,
             },
             TABLE_NAME,
+        )
+        .unwrap();
+        assert!(new_user_name != user_name);
+    }
+
+    #[test]
+    fn fake_user_name_supports_unique_arg() {
+        let user_name = "any user_name";
+        let mut rng = rng::get();
+        let transformer = &Transformer {
+            name: TransformerType::FakeUsername,
+            args: Some(HashMap::from([("unique".to_string(), "true".into())])),
+        };
+        let new_user_name = transform(
+            &mut rng,
+            user_name,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+
+        assert!(new_user_name != user_name);
+        let re = Regex::new(r"^[0-9]+-.*").unwrap();
+        assert!(
+            re.is_match(&new_user_name),
+            "Username {:?} does not have the unique prefix",
+            new_user_name
         );
-        assert!(new_company_name != company_name);
     }
+
     #[test]
-    fn fake_company_name_with_unique_arg() {
-        let company_name = "any company name";
+    fn fixed() {
+        let url = "any web address";
+        let fixed_url = "a very fixed web address";
         let mut rng = rng::get();
         let transformer = &Transformer {
-            name: TransformerType::FakeCompanyName,
-            args: Some(HashMap::from([("unique".to_string(), "true".to_string())])),
+            name: TransformerType::Fixed,
+            args: Some(HashMap::from([("value".to_string(), fixed_url.into())])),
         };
-        let new_company_name = transform(
+        let new_url = transform(
+            &mut rng,
+            url,
+            &Type::SingleValue {
+                sub_type: SubType::Character,
+            },
+            transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(new_url, fixed_url);
+    }
+    #[test]
+    fn fixed_errors_if_value_not_provided() {
+        let mut rng = rng::get();
+        let url = "any web address";
+        let result = transform(
             &mut rng,
-            company_name,
+            url,
             &Type::SingleValue {
                 sub_type: SubType::Character,
             },
-            transformer,
+            &Transformer {
+                name: TransformerType::Fixed,
+                args: None,
+            },
             TABLE_NAME,
         );
-        assert!(new_company_name != company_name);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("'value' must be present in args for a fixed transformer"));
     }
 
     #[test]
-    fn fake_email() {
-        let email = "any email";
+    fn error_transformer_returns_an_error() {
         let mut rng = rng::get();
-        let new_email = transform(
+        let result = transform(
             &mut rng,
-            email,
+            "any value",
             &Type::SingleValue {
                 sub_type: SubType::Character,
             },
             &Transformer {
-                name: TransformerType::FakeEmail,
+                name: TransformerType::Error,
                 args: None,
             },
             TABLE_NAME,
         );
-        assert!(new_email != email);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unable to transform gert_lush_table: Error transform still in place"
+        );
+    }
 
-        let re = Regex::new(r"^[0-9]+-.*@.*\..*").unwrap();
-        assert!(
-            !re.is_match(&new_email),
-            "Email {:?} should not have the unique prefix",
-            new_email
+    #[test]
+    fn compile_parses_the_args_of_transformers_and_the_transformers_nested_in_them() {
+        let transformer = |name, args: Vec<(&str, TransformerArg)>| Transformer {
+            name,
+            args: Some(
+                args.into_iter()
+                    .map(|(name, arg)| (name.to_string(), arg))
+                    .collect(),
+            ),
+        };
+        let mask = transformer(TransformerType::Mask, vec![("keep_end", "4".into())]);
+        fn is_mask(nested: &CompiledTransformer) -> bool {
+            matches!(compiled_args(nested), Some(Args::Mask(_)))
+        }
+
+        type IsExpected = fn(Option<&Args>) -> bool;
+        let cases: Vec<(Transformer, IsExpected)> = vec![
+            (
+                transformer(TransformerType::FakeEmail, vec![("unique", "true".into())]),
+                |args| args.is_none(),
+            ),
+            (mask.clone(), |args| matches!(args, Some(Args::Mask(_)))),
+            (
+                transformer(TransformerType::ShiftDate, vec![("max_days", "30".into())]),
+                |args| {
+                    matches!(
+                        args,
+                        Some(Args::ShiftDate(ShiftDateArgs {
+                            max_days: 30,
+                            max_seconds: 0
+                        }))
+                    )
+                },
+            ),
+            (
+                transformer(TransformerType::RoundNumber, vec![("to", "10".into())]),
+                |args| matches!(args, Some(Args::GeneraliseNumber { .. })),
+            ),
+            (
+                transformer(TransformerType::TruncateDate, vec![("to", "month".into())]),
+                |args| {
+                    matches!(
+                        args,
+                        Some(Args::TruncateDate {
+                            to: DatePart::Month
+                        })
+                    )
+                },
+            ),
+            (
+                transformer(TransformerType::AddNoise, vec![("amount", "5".into())]),
+                |args| matches!(args, Some(Args::AddNoise(_))),
+            ),
+            (
+                transformer(
+                    TransformerType::Template,
+                    vec![("template", "{first_name}".into())],
+                ),
+                |args| {
+                    matches!(args, Some(Args::Template(template))
+                        if template.columns() == ["first_name"])
+                },
+            ),
+            (
+                transformer(
+                    TransformerType::ScrubPii,
+                    vec![("detectors", "Postcode".into())],
+                ),
+                |args| {
+                    matches!(args, Some(Args::ScrubPii(detectors))
+                        if detectors == &[Detector::Postcode])
+                },
+            ),
+            (
+                transformer(
+                    TransformerType::RegexReplace,
+                    vec![
+                        ("pattern", "(a)".into()),
+                        ("replacement", "$1".into()),
+                        ("1", mask.clone().into()),
+                    ],
+                ),
+                |args| {
+                    matches!(args, Some(Args::RegexReplace(args))
+                        if args.group_transformers.len() == 1
+                            && is_mask(&args.group_transformers[0].1))
+                },
+            ),
+            (
+                transformer(
+                    TransformerType::JsonPaths,
+                    vec![("$.phone", mask.clone().into())],
+                ),
+                |args| {
+                    matches!(args, Some(Args::JsonPaths(paths))
+                        if paths.len() == 1 && is_mask(&paths[0].1))
+                },
+            ),
+            (
+                transformer(
+                    TransformerType::Switch,
+                    vec![
+                        ("pattern", "@".into()),
+                        ("match", mask.clone().into()),
+                        ("default", mask.clone().into()),
+                    ],
+                ),
+                |args| {
+                    matches!(args, Some(Args::Switch(SwitchArgs::Pattern(_), branches))
+                        if branches.len() == 2 && branches.values().all(is_mask))
+                },
+            ),
+        ];
+
+        for (transformer, expected) in cases {
+            let compiled = compile(transformer.clone()).unwrap();
+            assert!(
+                expected(compiled_args(&compiled)),
+                "{:?} compiled to {:?}",
+                transformer,
+                compiled_args(&compiled)
+            );
+            assert_eq!(*compiled, transformer);
+        }
+        assert_eq!(
+            compile(transformer(
+                TransformerType::JsonPaths,
+                vec![(
+                    "$.phone",
+                    transformer(TransformerType::Mask, vec![("keep_end", "four".into())]).into()
+                )]
+            ))
+            .unwrap_err(),
+            "$.phone: 'keep_end' must be a number of characters to keep, got: 'four'"
         );
     }
 
     #[test]
-    fn fake_email_with_unique_arg() {
-        let email = "rupert@example.com";
+    fn validate_args_reports_args_that_are_missing_or_invalid() {
+        let transformer = |name, args: Vec<(&str, TransformerArg)>| Transformer {
+            name,
+            args: Some(
+                args.into_iter()
+                    .map(|(name, arg)| (name.to_string(), arg))
+                    .collect(),
+            ),
+        };
+        let no_args = |name| Transformer { name, args: None };
+        let email = || TransformerArg::from(no_args(TransformerType::FakeEmail));
+
+        let cases: Vec<(Transformer, Result<(), &str>)> = vec![
+            (
+                transformer(
+                    TransformerType::Mask,
+                    vec![("keep_end", "4".into()), ("mask_char", "#".into())],
+                ),
+                Ok(()),
+            ),
+            (
+                transformer(TransformerType::Mask, vec![("keep_end", "four".into())]),
+                Err("'keep_end' must be a number of characters to keep, got: 'four'"),
+            ),
+            (
+                transformer(TransformerType::Mask, vec![("mask_char", "**".into())]),
+                Err("'mask_char' must be a single character, got: '**'"),
+            ),
+            (
+                switch(vec![
+                    ("column", "kind".into()),
+                    ("email", email()),
+                    ("default", email()),
+                ]),
+                Ok(()),
+            ),
+            (
+                switch(vec![("column", "kind".into()), ("email", email())]),
+                Err("Switch needs a 'default' arg with the transformer to use when nothing else matches"),
+            ),
+            (
+                switch(vec![("default", email())]),
+                Err("Switch needs either a 'column' arg, to switch on the value of another column, or a 'pattern' arg, to switch on whether the value matches it"),
+            ),
+            (
+                switch(vec![
+                    ("column", "kind".into()),
+                    ("email", "FakeEmail".into()),
+                    ("default", email()),
+                ]),
+                Err("The arg for 'email' must be a transformer, e.g. {\"name\": \"FakeEmail\"}"),
+            ),
+            (
+                switch(vec![
+                    ("pattern", "@".into()),
+                    ("match", email()),
+                    ("phone", email()),
+                    ("default", email()),
+                ]),
+                Err("Switch on a 'pattern' only takes 'match' and 'default' args, got: 'phone'"),
+            ),
+            (
+                switch(vec![
+                    ("pattern", "@".into()),
+                    ("match", fixed_transformer("x").into()),
+                    ("default", no_args(TransformerType::ShiftDate).into()),
+                ]),
+                Err("'default': 'max_days' must be present in args, with the most days a value can be moved by"),
+            ),
+            (
+                switch(vec![
+                    ("pattern", "@".into()),
+                    ("match", email()),
+                    ("default", no_args(TransformerType::Shuffle).into()),
+                ]),
+                Err("'default': Shuffle can't be picked by a Switch"),
+            ),
+            (
+                json_paths_transformer(&[(
+                    "$.addresses[*].postcode",
+                    TransformerType::FakePostCode,
+                    None,
+                )]),
+                Ok(()),
+            ),
+            (
+                no_args(TransformerType::JsonPaths),
+                Err("JsonPaths needs args mapping JSONPaths to the transformer for each"),
+            ),
+            (
+                transformer(TransformerType::JsonPaths, vec![("$.email", "FakeEmail".into())]),
+                Err("The arg for \"$.email\" must be a transformer, e.g. {\"name\": \"FakeEmail\"}"),
+            ),
+            (
+                json_paths_transformer(&[("$.job_title", TransformerType::Shuffle, None)]),
+                Err("$.job_title: Shuffle can't be nested in another transformer"),
+            ),
+            (
+                json_paths_transformer(&[("$.name", TransformerType::Template, None)]),
+                Err("$.name: Template can't be nested in another transformer"),
+            ),
+            (
+                regex_replace_transformer(
+                    "(a)(?P<b>b)",
+                    "$2",
+                    &[("1", TransformerType::FakeEmail, None)],
+                ),
+                Ok(()),
+            ),
+            (
+                no_args(TransformerType::RegexReplace),
+                Err("RegexReplace needs a 'pattern' arg"),
+            ),
+            (
+                regex_replace_transformer("(a)", "$1", &[("2", TransformerType::FakeEmail, None)]),
+                Err("'2' isn't a capture group in the pattern"),
+            ),
+            (
+                regex_replace_transformer("(a)", "$1", &[("1", TransformerType::JsonPaths, None)]),
+                Err("group '1': JsonPaths needs args mapping JSONPaths to the transformer for each"),
+            ),
+            (
+                transformer(TransformerType::ScrubPii, vec![("detectors", "Email,Iban".into())]),
+                Ok(()),
+            ),
+            (
+                transformer(TransformerType::ScrubPii, vec![("detectors", "Email,Address".into())]),
+                Err("Unknown PII detector 'Address', expected one of: Url, Email, Iban, CardNumber, NationalInsuranceNumber, Phone, Postcode"),
+            ),
+            (
+                transformer(TransformerType::ShiftTimestamp, vec![("max_days", "30".into())]),
+                Ok(()),
+            ),
+            (
+                no_args(TransformerType::ShiftTimestamp),
+                Err("'max_days' must be present in args, with the most days a value can be moved by"),
+            ),
+            (
+                transformer(TransformerType::ShiftTimestamp, vec![("max_days", "0".into())]),
+                Err("'max_days' must be a whole number of days from 1 to 365250, got: '0'"),
+            ),
+            (
+                transformer(
+                    TransformerType::ShiftTimestamp,
+                    vec![("max_days", "30".into()), ("max_seconds", "3600".into())],
+                ),
+                Ok(()),
+            ),
+            (
+                transformer(
+                    TransformerType::ShiftTimestamp,
+                    vec![("max_days", "30".into()), ("max_seconds", "86400".into())],
+                ),
+                Err("'max_seconds' must be a whole number of seconds from 1 to 86399, got: '86400'"),
+            ),
+            (
+                transformer(
+                    TransformerType::ShiftDate,
+                    vec![("max_days", "30".into()), ("max_seconds", "3600".into())],
+                ),
+                Err("'max_seconds' can only be used with ShiftTimestamp"),
+            ),
+            (
+                transformer(TransformerType::RoundNumber, vec![("to", "0.01".into())]),
+                Ok(()),
+            ),
+            (
+                transformer(TransformerType::Bucket, vec![("to", "5".into())]),
+                Err("'size' must be present in args"),
+            ),
+            (
+                transformer(TransformerType::Bucket, vec![("size", "-5".into())]),
+                Err("'size' must be a number greater than 0, got: '-5'"),
+            ),
+            (
+                transformer(TransformerType::TruncateDate, vec![("to", "week".into())]),
+                Err("'to' must be one of 'year', 'month', 'day' or 'hour', got: Some(\"week\")"),
+            ),
+            (
+                transformer(
+                    TransformerType::Template,
+                    vec![("template", "{first_name}.{last_name}@example.com".into())],
+                ),
+                Ok(()),
+            ),
+            (
+                no_args(TransformerType::Template),
+                Err("Template needs a 'template' arg, e.g. \"{first_name} {last_name}\""),
+            ),
+            (
+                transformer(TransformerType::Template, vec![("template", "{first_name".into())]),
+                Err("Invalid template \"{first_name\": unclosed '{'"),
+            ),
+            (
+                transformer(TransformerType::FakeState, vec![("locale", "en_GB".into())]),
+                Ok(()),
+            ),
+            (
+                transformer(TransformerType::FakeFirstName, vec![("locale", "de_DE".into())]),
+                Err("Unknown locale 'de_DE', expected one of: en_US, en_GB, fr_FR, ja_JP, pt_BR, zh_CN, zh_TW, ar_SA"),
+            ),
+            (
+                transformer(TransformerType::FakeCity, vec![("locale", "fr_FR".into())]),
+                Err("FakeCity isn't supported in the fr_FR locale"),
+            ),
+            (
+                transformer(TransformerType::FakeEmail, vec![("locale", "fr_FR".into())]),
+                Err("FakeEmail doesn't take a 'locale' arg"),
+            ),
+            (
+                transformer(
+                    TransformerType::AddNoise,
+                    vec![("amount", "0.05".into()), ("relative", "true".into())],
+                ),
+                Ok(()),
+            ),
+            (
+                transformer(TransformerType::AddNoise, vec![("distribution", "uniform".into())]),
+                Err("'amount' must be present in args, with the size of the noise to add"),
+            ),
+            (
+                transformer(
+                    TransformerType::AddNoise,
+                    vec![("amount", "5".into()), ("distribution", "normal".into())],
+                ),
+                Err("'distribution' must be 'gaussian' or 'uniform', got: 'normal'"),
+            ),
+            (
+                transformer(
+                    TransformerType::AddNoise,
+                    vec![("amount", "5".into()), ("keep_sign", "yes".into())],
+                ),
+                Err("'keep_sign' must be 'true' or 'false', got: 'yes'"),
+            ),
+            (
+                transformer(
+                    TransformerType::AddNoise,
+                    vec![
+                        ("amount", "5".into()),
+                        ("min", "10".into()),
+                        ("max", "1".into()),
+                    ],
+                ),
+                Err("'min' (10) is greater than 'max' (1)"),
+            ),
+        ];
+
+        for (transformer, expected) in cases {
+            assert_eq!(
+                validate_args(&transformer).as_ref().map_err(String::as_str),
+                expected.as_ref().map_err(|message| *message),
+                "{:?}",
+                transformer
+            );
+        }
+        assert!(validate_args(&regex_replace_transformer("(a", "", &[]))
+            .unwrap_err()
+            .starts_with("Invalid pattern: "));
+    }
+
+    #[test]
+    fn can_transform_only_with_transformers_that_suit_the_type() {
+        let text = Type::single_value(SubType::Text);
+        let json = Type::single_value(SubType::Json);
+        let integers = Type::array(SubType::Integer);
+
+        assert!(can_transform(&TransformerType::FakeEmail, &text));
+        assert!(can_transform(&TransformerType::EmptyJson, &json));
+        assert!(can_transform(&TransformerType::Scramble, &integers));
+        assert!(can_transform(
+            &TransformerType::ObfuscateDay,
+            &Type::single_value(SubType::Date)
+        ));
+        assert!(can_transform(
+            &TransformerType::Fixed,
+            &Type::single_value(SubType::Boolean)
+        ));
+
+        assert!(!can_transform(&TransformerType::Scramble, &json));
+        assert!(!can_transform(&TransformerType::FakeEmail, &integers));
+        assert!(!can_transform(
+            &TransformerType::ObfuscateDay,
+            &Type::single_value(SubType::TimestampWithTimeZone)
+        ));
+        assert!(!can_transform(
+            &TransformerType::FakeUUID,
+            &Type::single_value(SubType::Bytea)
+        ));
+    }
+
+    fn fixed_transformer(value: &str) -> Transformer {
+        Transformer {
+            name: TransformerType::Fixed,
+            args: Some(HashMap::from([("value".to_string(), value.into())])),
+        }
+    }
+
+    fn switch(args: Vec<(&str, TransformerArg)>) -> Transformer {
+        Transformer {
+            name: TransformerType::Switch,
+            args: Some(
+                args.into_iter()
+                    .map(|(name, arg)| (name.to_string(), arg))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn switch_on_a_pattern_picks_match_for_values_matching_it() {
+        let transformer = switch(vec![
+            ("pattern", "@".into()),
+            ("match", fixed_transformer("someone@example.com").into()),
+            ("default", fixed_transformer("07700 900000").into()),
+        ]);
         let mut rng = rng::get();
-        let transformer = &Transformer {
-            name: TransformerType::FakeEmail,
-            args: Some(HashMap::from([("unique".to_string(), "true".to_string())])),
+        let mut switch = |value| {
+            transform(
+                &mut rng,
+                value,
+                &Type::single_value(SubType::Text),
+                &transformer,
+                TABLE_NAME,
+            )
+            .unwrap()
+            .to_string()
         };
-        let new_email = transform(
+
+        assert_eq!(switch("pete@example.com"), "someone@example.com");
+        assert_eq!(switch("020 7946 0000"), "07700 900000");
+        assert_eq!(switch("\\N"), "\\N");
+    }
+
+    #[test]
+    fn switch_branch_picks_the_case_for_the_other_column() {
+        let transformer = switch(vec![
+            ("column", "kind".into()),
+            ("email", fixed_transformer("someone@example.com").into()),
+            ("phone", fixed_transformer("07700 900000").into()),
+            ("default", fixed_transformer("").into()),
+        ]);
+        let transformer = compile(transformer).unwrap();
+        let branch = |kind| {
+            switch_branch(&transformer, "value", |column| {
+                (column == "kind").then_some(kind)
+            })
+            .unwrap()
+            .transformer
+            .clone()
+        };
+
+        assert_eq!(branch("phone"), fixed_transformer("07700 900000"));
+        assert_eq!(branch("email"), fixed_transformer("someone@example.com"));
+        assert_eq!(branch("fax"), fixed_transformer(""));
+        assert_eq!(branch("\\N"), fixed_transformer(""));
+        assert_eq!(
+            switch_branch(&transformer, "value", |_| None).unwrap_err(),
+            "The column 'kind' to switch on isn't in the row"
+        );
+    }
+
+    #[test]
+    fn can_apply_checks_every_transformer_a_switch_can_pick() {
+        let transformer = switch(vec![
+            ("pattern", "@".into()),
+            (
+                "match",
+                Transformer {
+                    name: TransformerType::FakeEmail,
+                    args: None,
+                }
+                .into(),
+            ),
+            ("default", fixed_transformer("0").into()),
+        ]);
+
+        assert!(can_apply(&transformer, &Type::single_value(SubType::Text)));
+        assert!(!can_apply(
+            &transformer,
+            &Type::single_value(SubType::Integer)
+        ));
+    }
+
+    #[test]
+    fn can_transform_types_it_does_not_know_with_anything() {
+        assert!(can_transform(
+            &TransformerType::FakeEmail,
+            &Type::unknown("point".to_string())
+        ));
+    }
+
+    fn json_paths_transformer(paths: &[(&str, TransformerType, Option<&str>)]) -> Transformer {
+        Transformer {
+            name: TransformerType::JsonPaths,
+            args: Some(
+                paths
+                    .iter()
+                    .map(|(path, name, value)| {
+                        let nested = Transformer {
+                            name: name.clone(),
+                            args: value
+                                .map(|value| HashMap::from([("value".to_string(), value.into())])),
+                        };
+                        (path.to_string(), nested.into())
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn json_paths_transforms_only_the_matching_values() {
+        let json = r#"{"contact": {"email": "a@b.com", "name": "Alice"}, "addresses": [{"postcode": "NW5 1AA"}, {"postcode": "E1 6AN"}], "count": 3}"#;
+        let transformer = json_paths_transformer(&[
+            (
+                "$.contact.email",
+                TransformerType::Fixed,
+                Some("x@example.com"),
+            ),
+            (
+                "$.addresses[*].postcode",
+                TransformerType::FakePostCode,
+                None,
+            ),
+        ]);
+
+        let mut rng = rng::get();
+        let transformed = transform(
             &mut rng,
-            email,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            transformer,
+            json,
+            &Type::single_value(SubType::Json),
+            &transformer,
             TABLE_NAME,
-        );
-        assert!(new_email != email);
-        let re = Regex::new(r"^[0-9]+-.*@.*\..*").unwrap();
-        assert!(
-            re.is_match(&new_email),
-            "Email {:?} does not have the unique prefix",
-            new_email
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Value>(&transformed).unwrap(),
+            serde_json::json!({"contact": {"email": "x@example.com", "name": "Alice"}, "addresses": [{"postcode": "NW5"}, {"postcode": "E1 "}], "count": 3})
         );
     }
 
     #[test]
-    fn fake_first_name() {
-        let first_name = "any first name";
+    fn json_paths_keeps_copy_escapes_and_exact_numbers() {
+        let json = r#"{"note": "line one\\nline two \\\\ end", "amount": 12345678901234567890.123456789, "email": "a@b.com"}"#;
+        let transformer =
+            json_paths_transformer(&[("$.email", TransformerType::Fixed, Some("x@example.com"))]);
+
         let mut rng = rng::get();
-        let new_first_name = transform(
+        let transformed = transform(
             &mut rng,
-            first_name,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeFirstName,
-                args: None,
-            },
+            json,
+            &Type::single_value(SubType::Json),
+            &transformer,
             TABLE_NAME,
+        )
+        .unwrap();
+
+        assert_eq!(
+            transformed,
+            r#"{"note":"line one\\nline two \\\\ end","amount":12345678901234567890.123456789,"email":"x@example.com"}"#
         );
-        assert!(new_first_name != first_name);
     }
 
     #[test]
-    fn fake_full_name() {
-        let full_name = "any full name";
+    fn json_paths_keeps_the_order_of_keys() {
+        // Postgres keeps the keys of a json (rather than jsonb) value in the order they were written
+        let json = r#"{"zebra": 1, "email": "a@b.com", "apple": {"yak": 2, "bee": 3}}"#;
+        let transformer =
+            json_paths_transformer(&[("$.email", TransformerType::Fixed, Some("x@example.com"))]);
+
         let mut rng = rng::get();
-        let new_full_name = transform(
+        let transformed = transform(
             &mut rng,
-            full_name,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeFullName,
-                args: None,
-            },
+            json,
+            &Type::single_value(SubType::Json),
+            &transformer,
             TABLE_NAME,
+        )
+        .unwrap();
+
+        assert_eq!(
+            transformed,
+            r#"{"zebra":1,"email":"x@example.com","apple":{"yak":2,"bee":3}}"#
         );
-        assert!(new_full_name != full_name);
     }
 
     #[test]
-    fn fake_last_name() {
-        let last_name = "any last name";
+    fn json_paths_transforms_values_inside_json_arrays() {
+        let json = r#"{"{\\"email\\": \\"a@b.com\\"}",NULL,"{\\"email\\": \\"c@d.com\\"}"}"#;
+        let transformer =
+            json_paths_transformer(&[("$.email", TransformerType::Fixed, Some("x@example.com"))]);
+
         let mut rng = rng::get();
-        let new_last_name = transform(
+        let transformed = transform(
             &mut rng,
-            last_name,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeLastName,
-                args: None,
-            },
+            json,
+            &Type::array(SubType::Json),
+            &transformer,
             TABLE_NAME,
+        )
+        .unwrap();
+
+        assert_eq!(
+            transformed,
+            r#"{"{\\"email\\":\\"x@example.com\\"}",NULL,"{\\"email\\":\\"x@example.com\\"}"}"#
         );
-        assert!(new_last_name != last_name);
     }
 
     #[test]
-    fn fake_full_address() {
-        let street_address = "any street_address";
+    fn json_paths_errors_with_invalid_json() {
+        let transformer =
+            json_paths_transformer(&[("$.email", TransformerType::Fixed, Some("x@example.com"))]);
+
         let mut rng = rng::get();
-        let new_street_address = transform(
+        let result = transform(
             &mut rng,
-            street_address,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeFullAddress,
-                args: None,
-            },
+            "{not json",
+            &Type::single_value(SubType::Json),
+            &transformer,
             TABLE_NAME,
         );
-        assert!(new_street_address != street_address);
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unable to transform gert_lush_table: Invalid JSON found: \"{not json\""));
     }
 
-    #[test]
-    fn fake_national_identity_number() {
-        let national_identity_number = "JR 55 55 55 E";
+    fn regex_replace_transformer(
+        pattern: &str,
+        replacement: &str,
+        groups: &[(&str, TransformerType, Option<&str>)],
+    ) -> Transformer {
+        let mut args: TransformerArgs = HashMap::from([
+            ("pattern".to_string(), pattern.into()),
+            ("replacement".to_string(), replacement.into()),
+        ]);
+        for (group, name, value) in groups {
+            let nested = Transformer {
+                name: name.clone(),
+                args: value.map(|value| HashMap::from([("value".to_string(), value.into())])),
+            };
+            args.insert(group.to_string(), nested.into());
+        }
+        Transformer {
+            name: TransformerType::RegexReplace,
+            args: Some(args),
+        }
+    }
+
+    fn regex_replace_value(value: &str, transformer: &Transformer) -> String {
         let mut rng = rng::get();
-        let new_national_identity_number = transform(
+        transform(
             &mut rng,
-            national_identity_number,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeNationalIdentityNumber,
-                args: None,
-            },
+            value,
+            &Type::single_value(SubType::Text),
+            transformer,
             TABLE_NAME,
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    #[test]
+    fn regex_replace_replaces_every_match_using_capture_groups() {
+        let transformer = regex_replace_transformer(r"(\d{4})-(\d{2})", "$2/${1}$$", &[]);
+
+        assert_eq!(
+            regex_replace_value("from 2020-12 to 2021-01.", &transformer),
+            "from 12/2020$ to 01/2021$."
         );
-        assert!(new_national_identity_number != national_identity_number);
-        assert!(national_insurance_number::NATIONAL_INSURANCE_NUMBERS
-            .contains(&new_national_identity_number.as_ref()));
     }
 
     #[test]
-    fn fake_email_or_phone_with_phone() {
-        let phone_number = "+447822222222";
+    fn regex_replace_transforms_capture_groups_with_their_transformers() {
+        let transformer = regex_replace_transformer(
+            r"^(ref:\d+) (?P<email>\S+)$",
+            "$1 $email",
+            &[("email", TransformerType::Fixed, Some("x@example.com"))],
+        );
+
+        assert_eq!(
+            regex_replace_value("ref:1234 a@b.com", &transformer),
+            "ref:1234 x@example.com"
+        );
+        assert_eq!(
+            regex_replace_value("no match a@b.com", &transformer),
+            "no match a@b.com"
+        );
+    }
+
+    #[test]
+    fn regex_replace_matches_the_value_rather_than_its_copy_text() {
+        let transformer = regex_replace_transformer(r"\n", " ", &[]);
+
+        assert_eq!(
+            regex_replace_value("line one\\nline two \\\\", &transformer),
+            "line one line two \\\\"
+        );
+    }
+
+    fn scrub_pii_value(value: &str, detectors: Option<&str>) -> String {
+        let transformer = Transformer {
+            name: TransformerType::ScrubPii,
+            args: detectors
+                .map(|detectors| HashMap::from([("detectors".to_string(), detectors.into())])),
+        };
         let mut rng = rng::get();
-        let new_phone_number = transform(
+        transform(
             &mut rng,
-            phone_number,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeEmailOrPhone,
-                args: None,
-            },
+            value,
+            &Type::single_value(SubType::Text),
+            &transformer,
             TABLE_NAME,
-        );
-        assert!(new_phone_number != phone_number);
-        assert!(new_phone_number.starts_with("+4477009"));
-        assert_eq!(new_phone_number.len(), 13);
+        )
+        .unwrap()
+        .to_string()
     }
+
     #[test]
-    fn fake_email_or_phone_with_email() {
-        let email = "peter@peterson.com";
+    fn scrub_pii_replaces_pii_but_keeps_the_rest_of_the_text() {
+        let note = "Called pete@example.com on 07777 777771,\\nNI QQ 12 34 56 C, NW5 1AA";
+
+        let scrubbed = scrub_pii_value(note, None);
+
+        let re = Regex::new(
+            r"^Called \S+@\S+ on 07700\d{6},\\nNI [A-Z]{2}\d{6}[A-D], [A-Z]{2}\d \d[A-Z]{2}$",
+        )
+        .unwrap();
+        assert!(re.is_match(&scrubbed), "{}", scrubbed);
+        assert!(!scrubbed.contains("pete@example.com"));
+        assert!(!scrubbed.contains("NW5 1AA"));
+    }
+
+    #[test]
+    fn scrub_pii_only_uses_the_detectors_in_its_args() {
+        let note = "pete@example.com, NW5 1AA";
+
+        let scrubbed = scrub_pii_value(note, Some("Postcode, Url"));
+
+        assert!(scrubbed.starts_with("pete@example.com, "));
+        assert!(!scrubbed.ends_with("NW5 1AA"));
+    }
+
+    fn shift(value: &str, transformer: TransformerType, max_days: &str) -> String {
         let mut rng = rng::get();
-        let new_email = transform(
+        transform(
             &mut rng,
-            email,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
+            value,
+            &Type::single_value(SubType::Text),
             &Transformer {
-                name: TransformerType::FakeEmailOrPhone,
-                args: None,
+                name: transformer,
+                args: Some(HashMap::from([("max_days".to_string(), max_days.into())])),
             },
             TABLE_NAME,
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    #[test]
+    fn shift_date_moves_dates_by_up_to_max_days() {
+        let date = NaiveDate::from_ymd_opt(2020, 12, 12).unwrap();
+        for _ in 0..50 {
+            let shifted = shift("2020-12-12", TransformerType::ShiftDate, "3");
+            let days = (NaiveDate::parse_from_str(&shifted, "%Y-%m-%d").unwrap() - date).num_days();
+            assert!((1..=3).contains(&days.abs()), "{}", shifted);
+        }
+    }
+
+    #[test]
+    fn shift_timestamp_keeps_the_time_and_time_zone() {
+        let shifted = shift(
+            "2020-12-12 10:11:12.123456+05:30",
+            TransformerType::ShiftTimestamp,
+            "10",
+        );
+
+        assert!(!shifted.starts_with("2020-12-12"));
+        assert!(shifted.ends_with(" 10:11:12.123456+05:30"), "{}", shifted);
+    }
+
+    #[test]
+    fn shift_timestamp_can_move_the_time_of_day_by_up_to_max_seconds() {
+        let timestamp =
+            chrono::NaiveDateTime::parse_from_str("2020-12-12 23:59:00", "%Y-%m-%d %H:%M:%S")
+                .unwrap();
+        for _ in 0..50 {
+            let shifted = transform_with_args(
+                "2020-12-12 23:59:00.123456+05:30",
+                SubType::Text,
+                TransformerType::ShiftTimestamp,
+                &[("max_days", "1"), ("max_seconds", "120")],
+            )
+            .unwrap();
+
+            assert!(shifted.ends_with(".123456+05:30"), "{}", shifted);
+            let shifted_timestamp =
+                chrono::NaiveDateTime::parse_from_str(&shifted[..19], "%Y-%m-%d %H:%M:%S").unwrap();
+            let seconds = (shifted_timestamp - timestamp).num_seconds().abs();
+            assert!(
+                (24 * 60 * 60 - 120..=24 * 60 * 60 + 120).contains(&seconds),
+                "{}",
+                shifted
+            );
+        }
+    }
+
+    #[test]
+    fn shift_timestamp_moves_by_the_same_days_as_shift_date_with_the_same_key() {
+        let shift_keyed = |value, transformer, args: &[(&str, &str)]| {
+            let transformer = Transformer {
+                name: transformer,
+                args: Some(
+                    args.iter()
+                        .map(|(name, value)| (name.to_string(), (*value).into()))
+                        .collect(),
+                ),
+            };
+            let mut rng = rng::keyed("secret", "user 1");
+            transform(
+                &mut rng,
+                value,
+                &Type::single_value(SubType::Text),
+                &transformer,
+                TABLE_NAME,
+            )
+            .unwrap()
+            .to_string()
+        };
+
+        let date = shift_keyed(
+            "2020-12-12",
+            TransformerType::ShiftDate,
+            &[("max_days", "30")],
+        );
+        let timestamp = shift_keyed(
+            "2020-12-12 12:00:00",
+            TransformerType::ShiftTimestamp,
+            &[("max_days", "30"), ("max_seconds", "3600")],
+        );
+        assert!(timestamp.starts_with(&date), "{} {}", date, timestamp);
+    }
+
+    #[test]
+    fn shift_date_does_not_move_infinity() {
+        assert_eq!(
+            shift("infinity", TransformerType::ShiftTimestamp, "10"),
+            "infinity"
+        );
+        assert_eq!(
+            shift("-infinity", TransformerType::ShiftDate, "10"),
+            "-infinity"
         );
-        assert!(new_email != email);
-        assert!(new_email.contains('@'));
     }
 
     #[test]
-    fn fake_phone_number_gb() {
-        let phone_number = "+447822222222";
-        let mut rng = rng::get();
-        let new_phone_number = transform(
-            &mut rng,
-            phone_number,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakePhoneNumber,
-                args: None,
-            },
-            TABLE_NAME,
+    fn shift_date_handles_bc_dates() {
+        let shifted = shift("0001-12-31 BC", TransformerType::ShiftDate, "1");
+        assert!(
+            ["0001-01-01", "0001-12-30 BC"].contains(&shifted.as_str()),
+            "{}",
+            shifted
+        );
+
+        let shifted = shift(
+            "0044-03-15 12:00:00 BC",
+            TransformerType::ShiftTimestamp,
+            "1",
+        );
+        assert!(
+            ["0044-03-14 12:00:00 BC", "0044-03-16 12:00:00 BC"].contains(&shifted.as_str()),
+            "{}",
+            shifted
         );
-        assert!(new_phone_number != phone_number);
-        assert!(new_phone_number.starts_with("+4477009"));
-        assert_eq!(new_phone_number.len(), 13);
     }
 
     #[test]
-    fn fake_phone_number_us() {
-        let phone_number = "+16505130514";
+    fn shift_date_errors_with_invalid_dates() {
+        let transformer = Transformer {
+            name: TransformerType::ShiftDate,
+            args: Some(HashMap::from([("max_days".to_string(), "10".into())])),
+        };
         let mut rng = rng::get();
-        let new_phone_number = transform(
+        let result = transform(
             &mut rng,
-            phone_number,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakePhoneNumber,
-                args: None,
-            },
+            "12/12/2020",
+            &Type::single_value(SubType::Text),
+            &transformer,
             TABLE_NAME,
         );
-        assert!(new_phone_number != phone_number);
-        assert!(new_phone_number.starts_with("+1"));
-        assert_eq!(new_phone_number.len(), 12);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unable to transform gert_lush_table: Invalid date or timestamp found: \"12/12/2020\""
+        );
     }
 
-    #[test]
-    fn fake_postcode() {
-        let postcode = "NW5 3QQ";
+    fn transform_with_args(
+        value: &str,
+        sub_type: SubType,
+        transformer: TransformerType,
+        args: &[(&str, &str)],
+    ) -> Result<String, AnonymiserError> {
+        let transformer = Transformer {
+            name: transformer,
+            args: Some(
+                args.iter()
+                    .map(|(name, value)| (name.to_string(), (*value).into()))
+                    .collect(),
+            ),
+        };
         let mut rng = rng::get();
-        let new_postcode = transform(
+        transform(
             &mut rng,
-            postcode,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakePostCode,
-                args: None,
-            },
+            value,
+            &Type::single_value(sub_type),
+            &transformer,
             TABLE_NAME,
+        )
+        .map(|transformed| transformed.to_string())
+    }
+
+    #[test]
+    fn round_number_rounds_to_the_nearest_multiple() {
+        let round = |value, sub_type, to| {
+            transform_with_args(value, sub_type, TransformerType::RoundNumber, &[("to", to)])
+                .unwrap()
+        };
+
+        assert_eq!(round("52499", SubType::Integer, "1000"), "52000");
+        assert_eq!(round("52500", SubType::Integer, "1000"), "53000");
+        assert_eq!(round("-52500", SubType::Integer, "1000"), "-53000");
+        assert_eq!(round("52499.99", SubType::numeric(), "1000"), "52000.00");
+        assert_eq!(round("12.3456", SubType::numeric(), "0.05"), "12.3500");
+        assert_eq!(round("NaN", SubType::numeric(), "10"), "NaN");
+    }
+
+    #[test]
+    fn round_number_keeps_integers_whole() {
+        assert_eq!(
+            transform_with_args(
+                "7",
+                SubType::Integer,
+                TransformerType::RoundNumber,
+                &[("to", "2.0")]
+            )
+            .unwrap(),
+            "8"
+        );
+        assert_eq!(
+            transform_with_args(
+                "7",
+                SubType::Integer,
+                TransformerType::RoundNumber,
+                &[("to", "0.5")]
+            )
+            .unwrap_err()
+            .to_string(),
+            "Unable to transform gert_lush_table: integer values can't be rounded to multiples of 0.5"
         );
-        assert_eq!(new_postcode, "NW5");
     }
 
     #[test]
-    fn fake_user_name() {
-        let user_name = "any user_name";
-        let mut rng = rng::get();
-        let new_user_name = transform(
-            &mut rng,
-            user_name,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            &Transformer {
-                name: TransformerType::FakeUsername,
-                args: None,
-            },
-            TABLE_NAME,
+    fn bucket_rounds_down_to_the_start_of_the_bucket() {
+        let bucket = |value, sub_type| {
+            transform_with_args(value, sub_type, TransformerType::Bucket, &[("size", "5")]).unwrap()
+        };
+
+        assert_eq!(bucket("37", SubType::SmallInt), "35");
+        assert_eq!(bucket("40", SubType::SmallInt), "40");
+        assert_eq!(bucket("-1", SubType::Integer), "-5");
+        assert_eq!(bucket("39.9", SubType::numeric()), "35.0");
+    }
+
+    #[test]
+    fn round_number_and_bucket_keep_values_within_the_column_limits() {
+        let generalise = |value, sub_type, transformer, arg| {
+            transform_with_args(value, sub_type, transformer, &[arg]).unwrap()
+        };
+
+        assert_eq!(
+            generalise(
+                "999.99",
+                numeric(5, 2),
+                TransformerType::RoundNumber,
+                ("to", "10")
+            ),
+            "999.99"
+        );
+        assert_eq!(
+            generalise(
+                "-999.99",
+                numeric(5, 2),
+                TransformerType::RoundNumber,
+                ("to", "10")
+            ),
+            "-999.99"
+        );
+        assert_eq!(
+            generalise(
+                "32767",
+                SubType::SmallInt,
+                TransformerType::RoundNumber,
+                ("to", "10")
+            ),
+            "32767"
+        );
+        assert_eq!(
+            generalise(
+                "-32768",
+                SubType::SmallInt,
+                TransformerType::Bucket,
+                ("size", "5")
+            ),
+            "-32768"
         );
-        assert!(new_user_name != user_name);
     }
 
     #[test]
-    fn fake_user_name_supports_unique_arg() {
-        let user_name = "any user_name";
-        let mut rng = rng::get();
-        let transformer = &Transformer {
-            name: TransformerType::FakeUsername,
-            args: Some(HashMap::from([("unique".to_string(), "true".to_string())])),
+    fn round_number_transforms_integer_arrays() {
+        let transformer = Transformer {
+            name: TransformerType::RoundNumber,
+            args: Some(HashMap::from([("to".to_string(), "10".into())])),
         };
-        let new_user_name = transform(
+        let mut rng = rng::get();
+        let transformed = transform(
             &mut rng,
-            user_name,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            transformer,
+            "{14,NULL,15}",
+            &Type::array(SubType::Integer),
+            &transformer,
             TABLE_NAME,
+        )
+        .unwrap();
+
+        assert_eq!(transformed, "{10,NULL,20}");
+    }
+
+    #[test]
+    fn round_number_errors_with_invalid_numbers() {
+        assert_eq!(
+            transform_with_args(
+                "12,5",
+                SubType::Text,
+                TransformerType::RoundNumber,
+                &[("to", "10")]
+            )
+            .unwrap_err()
+            .to_string(),
+            "Unable to transform gert_lush_table: Invalid number found: \"12,5\""
         );
+    }
 
-        assert!(new_user_name != user_name);
-        let re = Regex::new(r"^[0-9]+-.*").unwrap();
-        assert!(
-            re.is_match(&new_user_name),
-            "Username {:?} does not have the unique prefix",
-            new_user_name
+    fn numeric(precision: u32, scale: u32) -> SubType {
+        SubType::Numeric {
+            precision: Some(precision),
+            scale: Some(scale),
+        }
+    }
+
+    #[test]
+    fn add_noise_keeps_the_scale_of_the_column() {
+        for _ in 0..100 {
+            let noisy = transform_with_args(
+                "1234.50",
+                numeric(10, 2),
+                TransformerType::AddNoise,
+                &[("amount", "100")],
+            )
+            .unwrap();
+            let (_, decimals) = noisy.split_once('.').unwrap();
+            assert_eq!(decimals.len(), 2, "{}", noisy);
+
+            let noisy = transform_with_args(
+                "1234",
+                SubType::Integer,
+                TransformerType::AddNoise,
+                &[("amount", "100"), ("distribution", "uniform")],
+            )
+            .unwrap();
+            let noisy = noisy.parse::<i64>().unwrap();
+            assert!((1134..=1334).contains(&noisy), "{}", noisy);
+        }
+    }
+
+    #[test]
+    fn add_noise_keeps_values_within_the_limits_and_precision() {
+        for _ in 0..100 {
+            let noisy = transform_with_args(
+                "995.00",
+                numeric(5, 2),
+                TransformerType::AddNoise,
+                &[("amount", "50"), ("min", "900")],
+            )
+            .unwrap();
+            let noisy = noisy.parse::<f64>().unwrap();
+            assert!((900.0..=999.99).contains(&noisy), "{}", noisy);
+
+            let noisy = transform_with_args(
+                "32760",
+                SubType::SmallInt,
+                TransformerType::AddNoise,
+                &[("amount", "1000")],
+            )
+            .unwrap();
+            assert!(noisy.parse::<i16>().is_ok(), "{}", noisy);
+
+            let noisy = transform_with_args(
+                "2147483640",
+                SubType::Integer,
+                TransformerType::AddNoise,
+                &[("amount", "1000")],
+            )
+            .unwrap();
+            assert!(noisy.parse::<i32>().is_ok(), "{}", noisy);
+
+            let noisy = transform_with_args(
+                "2147483640",
+                SubType::BigInt,
+                TransformerType::AddNoise,
+                &[("amount", "1000"), ("min", "2147483648")],
+            )
+            .unwrap();
+            assert!(noisy.parse::<i64>().unwrap() > i32::MAX.into(), "{}", noisy);
+        }
+    }
+
+    #[test]
+    fn add_noise_can_keep_the_sign() {
+        for _ in 0..100 {
+            let noisy = transform_with_args(
+                "-2.5",
+                SubType::Text,
+                TransformerType::AddNoise,
+                &[("amount", "10"), ("keep_sign", "true")],
+            )
+            .unwrap();
+            assert!(noisy.starts_with('-') || noisy == "0.0", "{}", noisy);
+        }
+    }
+
+    #[test]
+    fn add_noise_can_be_relative_to_the_value() {
+        for _ in 0..100 {
+            let noisy = transform_with_args(
+                "200000",
+                numeric(12, 2),
+                TransformerType::AddNoise,
+                &[
+                    ("amount", "0.1"),
+                    ("relative", "true"),
+                    ("distribution", "uniform"),
+                ],
+            )
+            .unwrap();
+            let noisy = noisy.parse::<f64>().unwrap();
+            assert!((180000.0..=220000.0).contains(&noisy), "{}", noisy);
+        }
+        assert_eq!(
+            transform_with_args(
+                "NaN",
+                numeric(12, 2),
+                TransformerType::AddNoise,
+                &[("amount", "0.1"), ("relative", "true")],
+            )
+            .unwrap(),
+            "NaN"
         );
     }
 
     #[test]
-    fn fixed() {
-        let url = "any web address";
-        let fixed_url = "a very fixed web address";
-        let mut rng = rng::get();
-        let transformer = &Transformer {
-            name: TransformerType::Fixed,
-            args: Some(HashMap::from([(
-                "value".to_string(),
-                fixed_url.to_string(),
-            )])),
+    fn truncate_date_truncates_dates_and_timestamps() {
+        let truncate = |value, sub_type, to| {
+            transform_with_args(
+                value,
+                sub_type,
+                TransformerType::TruncateDate,
+                &[("to", to)],
+            )
+            .unwrap()
         };
-        let new_url = transform(
-            &mut rng,
-            url,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
-            },
-            transformer,
-            TABLE_NAME,
+
+        assert_eq!(truncate("2020-12-12", SubType::Date, "month"), "2020-12-01");
+        assert_eq!(truncate("2020-12-12", SubType::Date, "year"), "2020-01-01");
+        assert_eq!(
+            truncate("2020-12-12 10:11:12.5", SubType::Timestamp, "day"),
+            "2020-12-12 00:00:00"
         );
-        assert_eq!(new_url, fixed_url);
+        assert_eq!(
+            truncate(
+                "2020-12-12 10:11:12.123+05:30",
+                SubType::TimestampWithTimeZone,
+                "hour"
+            ),
+            "2020-12-12 10:00:00+05:30"
+        );
+        assert_eq!(
+            truncate(
+                "0044-03-15 12:00:00-08 BC",
+                SubType::TimestampWithTimeZone,
+                "year"
+            ),
+            "0044-01-01 00:00:00-08 BC"
+        );
+        assert_eq!(truncate("infinity", SubType::Date, "year"), "infinity");
     }
+
     #[test]
-    #[should_panic(expected = "'value' must be present in args for a fixed transformer")]
-    fn fixed_panics_if_value_not_provided() {
-        let mut rng = rng::get();
-        let url = "any web address";
-        transform(
-            &mut rng,
-            url,
-            &Type::SingleValue {
-                sub_type: SubType::Character,
+    fn with_default_locale_only_sets_the_locale_where_there_is_not_one() {
+        let city = with_default_locale(
+            Transformer {
+                name: TransformerType::FakeCity,
+                args: None,
             },
-            &Transformer {
-                name: TransformerType::Fixed,
+            "en_GB",
+        );
+        let name = with_default_locale(
+            Transformer {
+                name: TransformerType::FakeFullName,
+                args: Some(HashMap::from([("locale".to_string(), "fr_FR".into())])),
+            },
+            "en_GB",
+        );
+        let email = with_default_locale(
+            Transformer {
+                name: TransformerType::FakeEmail,
                 args: None,
             },
-            TABLE_NAME,
+            "en_GB",
         );
+
+        assert_eq!(arg(&city.args, "locale"), Some("en_GB"));
+        assert_eq!(arg(&name.args, "locale"), Some("fr_FR"));
+        assert_eq!(email.args, None);
     }
 
     #[test]
@@ -854,18 +4071,16 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert_eq!(obfuscated_date, "2020-12-01");
     }
 
     #[test]
-    #[should_panic(
-        expected = "Invalid date found: \"2020-OHMYGOSH-12\" in table: \"gert_lush_table\". Error: \"input contains invalid characters\""
-    )]
-    fn obfuscate_day_panics_with_invalid_date() {
+    fn obfuscate_day_errors_with_invalid_date() {
         let date = "2020-OHMYGOSH-12";
         let mut rng = rng::get();
-        transform(
+        let result = transform(
             &mut rng,
             date,
             &Type::SingleValue {
@@ -879,6 +4094,10 @@ mod tests {
             },
             TABLE_NAME,
         );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unable to transform gert_lush_table: Invalid date found: \"2020-OHMYGOSH-12\". Error: \"input contains invalid characters\""
+        );
     }
 
     #[test]
@@ -898,7 +4117,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert_eq!(result, "0001-08-01 BC");
     }
 
@@ -919,7 +4139,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         assert_eq!(new_value.chars().count(), initial_value.chars().count());
 
@@ -944,7 +4165,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         let re = Regex::new(r"^[a-z][a-z]\\.\\?").unwrap();
         assert!(
             !re.is_match(&new_value),
@@ -969,7 +4191,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         let re = Regex::new(r"^[a-z]{2} [0-9]{2} [a-z][0-9][a-z][0-9]").unwrap();
         assert!(
             re.is_match(&new_value),
@@ -994,7 +4217,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         assert_eq!(new_value.chars().count(), initial_value.chars().count());
     }
@@ -1015,7 +4239,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         //TODO finish this test
     }
@@ -1036,7 +4261,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         assert!(!new_value.contains("Second line"));
         assert!(!new_value.contains("Third line"));
@@ -1058,7 +4284,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         let re = Regex::new(r"^[0-9]{9}$").unwrap();
         assert!(
@@ -1083,7 +4310,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         let re = Regex::new(r#"^\{"[a-z]","[a-z]","[a-z] [a-z]{2} [a-z]"\}$"#).unwrap();
         assert!(
@@ -1093,6 +4321,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_scramble_array_text_fields() {
+        let initial_value = r#"{a,"b, or c"}"#;
+        let mut rng = rng::get();
+        let new_value = transform(
+            &mut rng,
+            initial_value,
+            &Type::Array {
+                sub_type: SubType::Text,
+            },
+            &Transformer {
+                name: TransformerType::Scramble,
+                args: None,
+            },
+            TABLE_NAME,
+        )
+        .unwrap();
+        let re = Regex::new(r#"^\{"[a-z]","[a-z]{2} [a-z]{2} [a-z]"\}$"#).unwrap();
+        assert!(
+            re.is_match(&new_value),
+            "new value: \"{}\" does not contain same digit / alphabet structure as input",
+            new_value
+        );
+    }
+
+    #[test]
+    fn can_transform_array_timestamp_fields() {
+        let initial_value = r#"{"2020-01-01 10:00:00+00","2021-06-01 12:30:00+00"}"#;
+        let transformer = Transformer {
+            name: TransformerType::Fixed,
+            args: Some(HashMap::from([(
+                "value".to_string(),
+                "2000-01-01 00:00:00+00".into(),
+            )])),
+        };
+        let mut rng = rng::get();
+        let new_value = transform(
+            &mut rng,
+            initial_value,
+            &Type::Array {
+                sub_type: SubType::TimestampWithTimeZone,
+            },
+            &transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(
+            new_value,
+            r#"{"2000-01-01 00:00:00+00","2000-01-01 00:00:00+00"}"#
+        );
+    }
+
     #[test]
     fn can_deal_with_commas_inside_values() {
         let initial_value = r#"{"A, or B",C}"#;
@@ -1108,7 +4388,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         let re = Regex::new(r#"^\{"[a-z]{2} [a-z]{2} [a-z]","[a-z]"\}$"#).unwrap();
         assert!(
@@ -1134,7 +4415,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert_eq!(new_value, initial_value);
     }
 
@@ -1153,7 +4435,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert!(new_value != initial_value);
         let re = Regex::new(r#"^\{[0-9],[0-9]{2},[0-9]{3},[0-9]{4}\}$"#).unwrap();
         assert!(
@@ -1163,6 +4446,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn empty_arrays_are_left_empty() {
+        let transformer = Transformer {
+            name: TransformerType::Fixed,
+            args: Some(HashMap::from([("value".to_string(), "1".into())])),
+        };
+        let mut rng = rng::get();
+        let new_value = transform(
+            &mut rng,
+            "{}",
+            &Type::Array {
+                sub_type: SubType::Integer,
+            },
+            &transformer,
+            TABLE_NAME,
+        )
+        .unwrap();
+        assert_eq!(new_value, "{}");
+    }
+
     #[test]
     fn scramble_blank_maintains_word_boundaries() {
         let initial_value = "Sample Text";
@@ -1179,7 +4482,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
 
         assert!(new_value == "______ ____");
     }
@@ -1200,7 +4504,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
 
         assert!(new_value == r#"___\n___\n_____"#);
     }
@@ -1220,7 +4525,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert_eq!(new_json, "{\"{}\",\"{}\"}");
     }
 
@@ -1241,7 +4547,8 @@ mod tests {
                 args: None,
             },
             TABLE_NAME,
-        );
+        )
+        .unwrap();
         assert_eq!(new_json, "{}");
     }
 }
@@ -1,4 +1,5 @@
 use crate::parsers::sanitiser;
+use std::fmt;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Column {
@@ -20,14 +21,110 @@ impl Type {
     pub fn single_value(sub_type: SubType) -> Self {
         Type::SingleValue { sub_type }
     }
+
+    pub fn sub_type(&self) -> &SubType {
+        match self {
+            Type::SingleValue { sub_type } | Type::Array { sub_type } => sub_type,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::SingleValue { sub_type } => write!(f, "{}", sub_type),
+            Type::Array {
+                sub_type: SubType::Unknown { underlying_type },
+            } => write!(f, "{}", underlying_type),
+            Type::Array { sub_type } => write!(f, "{}[]", sub_type),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SubType {
+    /// `json` or `jsonb`
     Json,
+    /// `character` or `character varying`
     Character,
+    Text,
+    Citext,
     Integer,
-    Unknown { underlying_type: String },
+    BigInt,
+    SmallInt,
+    /// `numeric`, with the precision and scale from `numeric(precision, scale)` if it has them
+    Numeric {
+        precision: Option<u32>,
+        scale: Option<u32>,
+    },
+    Boolean,
+    Uuid,
+    Date,
+    /// `timestamp without time zone`
+    Timestamp,
+    TimestampWithTimeZone,
+    Inet,
+    Bytea,
+    Unknown {
+        underlying_type: String,
+    },
+}
+
+impl fmt::Display for SubType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let SubType::Numeric {
+            precision: Some(precision),
+            scale,
+        } = self
+        {
+            return write!(f, "numeric({},{})", precision, scale.unwrap_or(0));
+        }
+        let name = match self {
+            SubType::Json => "json",
+            SubType::Character => "character varying",
+            SubType::Text => "text",
+            SubType::Citext => "citext",
+            SubType::Integer => "integer",
+            SubType::BigInt => "bigint",
+            SubType::SmallInt => "smallint",
+            SubType::Numeric { .. } => "numeric",
+            SubType::Boolean => "boolean",
+            SubType::Uuid => "uuid",
+            SubType::Date => "date",
+            SubType::Timestamp => "timestamp without time zone",
+            SubType::TimestampWithTimeZone => "timestamp with time zone",
+            SubType::Inet => "inet",
+            SubType::Bytea => "bytea",
+            SubType::Unknown { underlying_type } => underlying_type,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SubType {
+    /// A `numeric` without a precision or scale, which holds any number
+    pub fn numeric() -> Self {
+        SubType::Numeric {
+            precision: None,
+            scale: None,
+        }
+    }
+
+    /// Whether the elements of an array of this type can be quoted in a dump, because they can
+    /// contain spaces, commas, quotes or backslashes
+    pub fn has_quoted_array_elements(&self) -> bool {
+        matches!(
+            self,
+            SubType::Json
+                | SubType::Character
+                | SubType::Text
+                | SubType::Citext
+                | SubType::Date
+                | SubType::Timestamp
+                | SubType::TimestampWithTimeZone
+                | SubType::Bytea
+        )
+    }
 }
 
 fn is_non_column_definition(first_word: &str) -> bool {
@@ -96,25 +193,86 @@ pub fn parse(line: &str) -> Option<Column> {
 }
 
 fn string_to_type(type_string: String) -> Type {
-    let sub_type = if type_string.starts_with("character") {
-        SubType::Character
-    } else if type_string.starts_with("bigint") || type_string.starts_with("integer") {
-        SubType::Integer
-    } else if type_string.starts_with("jsonb") {
-        SubType::Json
-    } else {
-        SubType::Unknown {
+    let (element_type, is_array) = match type_string.strip_suffix("[]") {
+        Some(element_type) => (element_type, true),
+        None => (type_string.as_str(), false),
+    };
+
+    let sub_type = match type_name(element_type).as_str() {
+        "character" | "character varying" => SubType::Character,
+        "text" => SubType::Text,
+        "citext" => SubType::Citext,
+        "integer" => SubType::Integer,
+        "bigint" => SubType::BigInt,
+        "smallint" => SubType::SmallInt,
+        "numeric" => numeric_type(element_type),
+        "boolean" => SubType::Boolean,
+        "uuid" => SubType::Uuid,
+        "date" => SubType::Date,
+        "timestamp" | "timestamp without time zone" => SubType::Timestamp,
+        "timestamp with time zone" => SubType::TimestampWithTimeZone,
+        "inet" => SubType::Inet,
+        "bytea" => SubType::Bytea,
+        "json" | "jsonb" => SubType::Json,
+        _ => SubType::Unknown {
             underlying_type: type_string.clone(),
-        }
+        },
     };
 
-    if type_string.ends_with("[]") {
+    if is_array {
         Type::array(sub_type)
     } else {
         Type::single_value(sub_type)
     }
 }
 
+/// A `numeric` with the modifiers in `numeric(precision, scale)` or `numeric(precision)`, where
+/// the scale defaults to 0
+fn numeric_type(type_string: &str) -> SubType {
+    let modifiers = type_string
+        .split_once('(')
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(modifiers, _)| modifiers);
+    let Some(modifiers) = modifiers else {
+        return SubType::numeric();
+    };
+
+    let numbers: Result<Vec<u32>, _> = modifiers.split(',').map(|n| n.trim().parse()).collect();
+    match numbers.as_deref() {
+        Ok([precision]) => SubType::Numeric {
+            precision: Some(*precision),
+            scale: Some(0),
+        },
+        Ok([precision, scale]) => SubType::Numeric {
+            precision: Some(*precision),
+            scale: Some(*scale),
+        },
+        // A negative scale (rounding to tens, hundreds etc.) isn't supported, so the number is
+        // treated as unconstrained
+        _ => SubType::numeric(),
+    }
+}
+
+/// The name of a type without its modifiers or schema, so `character varying(255)` is
+/// `character varying`, `timestamp(3) with time zone` is `timestamp with time zone` and
+/// `public.citext` (as extension types are written) is `citext`
+fn type_name(type_string: &str) -> String {
+    let mut name = String::with_capacity(type_string.len());
+    let mut in_modifiers = false;
+    for c in type_string.chars() {
+        match c {
+            '(' => in_modifiers = true,
+            ')' => in_modifiers = false,
+            c if !in_modifiers => name.push(c),
+            _ => {}
+        }
+    }
+    match name.rsplit_once('.') {
+        Some((_schema, name)) => name.to_string(),
+        None => name,
+    }
+}
+
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
@@ -138,10 +296,10 @@ mod tests {
 
     #[test]
     fn parses_other_type_as_unknown() {
-        let row = "check boolean,";
+        let row = "location point,";
         let parsed = parse(row).expect("Expected a column back! but got None");
-        assert_eq!(parsed.name, "check");
-        assert_eq!(parsed.data_type, Type::unknown("boolean".to_string()));
+        assert_eq!(parsed.name, "location");
+        assert_eq!(parsed.data_type, Type::unknown("point".to_string()));
     }
 
     #[test]
@@ -151,7 +309,99 @@ mod tests {
         assert_eq!(parsed.name, "inserted_at");
         assert_eq!(
             parsed.data_type,
-            Type::unknown("timestamp with time zone".to_string())
+            Type::single_value(SubType::TimestampWithTimeZone)
+        );
+    }
+
+    #[test]
+    fn parses_each_known_type() {
+        let types = [
+            ("character(2)", SubType::Character),
+            ("text", SubType::Text),
+            ("public.citext", SubType::Citext),
+            ("integer", SubType::Integer),
+            ("bigint", SubType::BigInt),
+            ("smallint", SubType::SmallInt),
+            ("numeric", SubType::numeric()),
+            (
+                "numeric(10,2)",
+                SubType::Numeric {
+                    precision: Some(10),
+                    scale: Some(2),
+                },
+            ),
+            (
+                "numeric(5)",
+                SubType::Numeric {
+                    precision: Some(5),
+                    scale: Some(0),
+                },
+            ),
+            ("boolean", SubType::Boolean),
+            ("uuid", SubType::Uuid),
+            ("date", SubType::Date),
+            ("timestamp(6) without time zone", SubType::Timestamp),
+            (
+                "timestamp(3) with time zone",
+                SubType::TimestampWithTimeZone,
+            ),
+            ("inet", SubType::Inet),
+            ("bytea", SubType::Bytea),
+            ("json", SubType::Json),
+            ("jsonb", SubType::Json),
+        ];
+
+        for (type_string, sub_type) in types {
+            let parsed = parse(&format!("a_column {},", type_string))
+                .expect("Expected a column back! but got None");
+            assert_eq!(
+                parsed.data_type,
+                Type::single_value(sub_type),
+                "{}",
+                type_string
+            );
+        }
+    }
+
+    #[test]
+    fn types_are_displayed_as_postgres_names() {
+        assert_eq!(Type::single_value(SubType::Uuid).to_string(), "uuid");
+        assert_eq!(Type::array(SubType::Text).to_string(), "text[]");
+        assert_eq!(
+            Type::single_value(SubType::Numeric {
+                precision: Some(10),
+                scale: Some(2)
+            })
+            .to_string(),
+            "numeric(10,2)"
+        );
+        assert_eq!(Type::unknown("point".to_string()).to_string(), "point");
+        assert_eq!(
+            Type::array(SubType::Unknown {
+                underlying_type: "point[]".to_string()
+            })
+            .to_string(),
+            "point[]"
+        );
+    }
+
+    #[test]
+    fn parses_array_of_text_type() {
+        let row = "tags text[] DEFAULT '{}'::text[] NOT NULL,";
+        let parsed = parse(row).expect("Expected a column back! but got None");
+        assert_eq!(parsed.name, "tags");
+        assert_eq!(parsed.data_type, Type::array(SubType::Text));
+    }
+
+    #[test]
+    fn keeps_the_whole_type_of_unknown_arrays() {
+        let row = "locations point[],";
+        let parsed = parse(row).expect("Expected a column back! but got None");
+        assert_eq!(
+            parsed.data_type,
+            Type::array(SubType::Unknown {
+                underlying_type: "point[]".to_string()
+            })
         );
     }
 
@@ -178,7 +428,7 @@ mod tests {
         let row = "id bigint COMPRESSION pglz,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -186,7 +436,7 @@ mod tests {
         let row = "id bigint COLLATE \"es_ES\",";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -194,7 +444,7 @@ mod tests {
         let row = "id bigint NOT NULL,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -202,7 +452,7 @@ mod tests {
         let row = "id bigint NULL,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -210,7 +460,7 @@ mod tests {
         let row = "id bigint CHECK (id >= 0),";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -218,7 +468,7 @@ mod tests {
         let row = "is_great_fun boolean DEFAULT false,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "is_great_fun");
-        assert_eq!(parsed.data_type, Type::unknown("boolean".to_string()));
+        assert_eq!(parsed.data_type, Type::single_value(SubType::Boolean));
     }
 
     #[test]
@@ -226,7 +476,7 @@ mod tests {
         let row = "id bigint GENERATED ALWAYS AS IDENTIFY,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -234,7 +484,7 @@ mod tests {
         let row = "id bigint UNIQUE,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -242,7 +492,7 @@ mod tests {
         let row = "id bigint PRIMARY KEY,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -250,7 +500,7 @@ mod tests {
         let row = "id bigint REFERENCES products (product_no),";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -258,7 +508,7 @@ mod tests {
         let row = "id bigint DEFERRABLE,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -266,14 +516,14 @@ mod tests {
         let row = "id bigint NOT DEFERRABLE,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
     #[test]
     fn with_column_constraint_INITIALLY_DEFERRED_modifier() {
         let row = "id bigint INITIALLY DEFERRED,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -281,7 +531,7 @@ mod tests {
         let row = "id bigint INITIALLY IMMEDIATE,";
         let parsed = parse(row).expect("Expected a column back! but got None");
         assert_eq!(parsed.name, "id");
-        assert_eq!(parsed.data_type, Type::integer());
+        assert_eq!(parsed.data_type, Type::bigint());
     }
 
     #[test]
@@ -396,6 +646,12 @@ mod tests {
             }
         }
 
+        pub fn bigint() -> Self {
+            Type::SingleValue {
+                sub_type: SubType::BigInt,
+            }
+        }
+
         pub fn character() -> Self {
             Type::SingleValue {
                 sub_type: SubType::Character,
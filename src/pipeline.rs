@@ -0,0 +1,631 @@
+use crate::errors::AnonymiserError;
+use crate::parsers::copy_row::CurrentTableTransforms;
+use crate::parsers::rng;
+use crate::parsers::row_parser;
+use crate::parsers::state::{Position, State, Types};
+use crate::parsers::strategies::Strategies;
+use crate::shuffle::{self, Shuffler};
+use rand::rngs::SmallRng;
+use std::collections::{BTreeMap, HashMap};
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ScopedJoinHandle};
+
+/// Number of COPY rows handed to a worker at a time
+const BATCH_SIZE: usize = 1000;
+
+/// Lines that don't need a worker are collected up to roughly this many bytes before being
+/// passed to the writer
+const PASSTHROUGH_SIZE: usize = 64 * 1024;
+
+/// Transformed output, or the error that stopped it, tagged with its place in the dump
+type Output = (u64, Result<Chunk, AnonymiserError>);
+
+enum Chunk {
+    /// Transformed lines, ready to be written
+    Text(String),
+    /// The rows from here until `EndShuffle` are a COPY block with these columns shuffled, so
+    /// are held back until the end of it
+    StartShuffle(Vec<usize>),
+    EndShuffle,
+}
+
+/// A run of rows from one COPY block, numbered so the writer can put it back in order
+struct Batch {
+    sequence: u64,
+    first_line: usize,
+    current_table: Arc<CurrentTableTransforms>,
+    types: Arc<Types>,
+    rows: Vec<String>,
+}
+
+/// The number of worker threads to use, defaulting to one per core
+pub fn threads(threads: Option<usize>) -> usize {
+    threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()))
+        .max(1)
+}
+
+/// Transforms a plain dump from `reader` to `writer`.
+///
+/// The calling thread reads the dump and keeps track of where it is in it, COPY rows are sent
+/// in batches to a pool of `threads` workers and a writer thread puts the transformed batches
+/// back in their original order. Each batch gets its own rng seeded from `seed` and its position
+/// in the dump, so seeded output doesn't depend on the number of threads. The reader can only
+/// get so far ahead of the writer, so a slow batch doesn't leave the ones after it piling up in
+/// memory. If transforming fails the output stops at the first error in the dump, which is
+/// returned
+pub fn transform(
+    reader: &mut dyn BufRead,
+    writer: &mut (dyn Write + Send),
+    strategies: &Strategies,
+    threads: usize,
+    seed: Option<u64>,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    let (batch_sender, batch_receiver) = mpsc::sync_channel::<Batch>(threads * 2);
+    let (output_sender, output_receiver) = mpsc::sync_channel::<Output>(threads * 4);
+    // A permit is sent for every batch or chunk of output before it's sent, and taken back once
+    // it's been written, so there are never more than this many waiting to be written. That's
+    // enough to fill both channels with every worker busy
+    let (permit_sender, permit_receiver) = mpsc::sync_channel::<()>(threads * 8);
+    let batch_receiver = Arc::new(Mutex::new(batch_receiver));
+
+    thread::scope(|scope| {
+        let write_thread = scope.spawn(move || {
+            write_in_order(output_receiver, permit_receiver, writer, seed, spill_dir)
+        });
+
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let batch_receiver = Arc::clone(&batch_receiver);
+                let output_sender = output_sender.clone();
+                scope.spawn(move || {
+                    transform_batches(&batch_receiver, output_sender, strategies, seed)
+                })
+            })
+            .collect();
+        drop(batch_receiver);
+
+        let read = read_batches(
+            reader,
+            batch_sender,
+            output_sender,
+            permit_sender,
+            strategies,
+            seed,
+        );
+
+        workers.into_iter().for_each(join);
+        let written = join(write_thread);
+        // Anything the writer failed on came before the point the reader stopped at
+        written.and(read)
+    })
+}
+
+fn read_batches(
+    reader: &mut dyn BufRead,
+    batch_sender: SyncSender<Batch>,
+    output_sender: SyncSender<Output>,
+    permit_sender: SyncSender<()>,
+    strategies: &Strategies,
+    seed: Option<u64>,
+) -> Result<(), AnonymiserError> {
+    let mut rng = seed.map_or_else(rng::get, rng::seeded);
+    let mut state = State::new();
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut sequence = 0;
+    let mut passthrough = String::new();
+    let mut batch: Option<Batch> = None;
+    let mut shuffling = false;
+
+    loop {
+        let bytes_read = reader.read_line(&mut line)?;
+        if bytes_read == 0 {
+            break;
+        }
+        line_number += 1;
+
+        if row_parser::is_copy_block_row(&line, &state) {
+            if let Position::InCopy { current_table } = &state.position {
+                if !row_parser::is_kept_by_subset(
+                    &mut state.rows_read,
+                    &current_table.table_name,
+                    strategies,
+                ) {
+                    line.clear();
+                    continue;
+                }
+            }
+            if !send_passthrough(
+                &mut passthrough,
+                &mut sequence,
+                &output_sender,
+                &permit_sender,
+            ) {
+                return Ok(());
+            }
+            let current_batch = batch.get_or_insert_with(|| {
+                let next_batch = new_batch(&state, sequence, line_number);
+                sequence += 1;
+                next_batch
+            });
+            current_batch.rows.push(std::mem::take(&mut line));
+            if current_batch.rows.len() == BATCH_SIZE
+                && !send_batch(&mut batch, &batch_sender, &permit_sender)
+            {
+                return Ok(());
+            }
+        } else {
+            if !send_batch(&mut batch, &batch_sender, &permit_sender) {
+                return Ok(());
+            }
+            let transformed_row = match row_parser::parse(&mut rng, &line, &mut state, strategies) {
+                Ok(transformed_row) => transformed_row,
+                Err(err) => {
+                    // Everything before the line that failed is still written out
+                    send_passthrough(
+                        &mut passthrough,
+                        &mut sequence,
+                        &output_sender,
+                        &permit_sender,
+                    );
+                    return Err(err.at_line(line_number));
+                }
+            };
+            if shuffling && !matches!(state.position, Position::InCopy { .. }) {
+                shuffling = false;
+                if !send_chunk(
+                    Chunk::EndShuffle,
+                    &mut sequence,
+                    &output_sender,
+                    &permit_sender,
+                ) {
+                    return Ok(());
+                }
+            }
+            passthrough.push_str(&transformed_row);
+            if let Position::InCopy { current_table } = &state.position {
+                let columns = shuffle::shuffled_columns(current_table);
+                if !shuffling && !columns.is_empty() {
+                    shuffling = true;
+                    if !send_passthrough(
+                        &mut passthrough,
+                        &mut sequence,
+                        &output_sender,
+                        &permit_sender,
+                    ) || !send_chunk(
+                        Chunk::StartShuffle(columns),
+                        &mut sequence,
+                        &output_sender,
+                        &permit_sender,
+                    ) {
+                        return Ok(());
+                    }
+                }
+            }
+            if passthrough.len() >= PASSTHROUGH_SIZE
+                && !send_passthrough(
+                    &mut passthrough,
+                    &mut sequence,
+                    &output_sender,
+                    &permit_sender,
+                )
+            {
+                return Ok(());
+            }
+            line.clear();
+        }
+    }
+    send_batch(&mut batch, &batch_sender, &permit_sender);
+    if shuffling {
+        send_chunk(
+            Chunk::EndShuffle,
+            &mut sequence,
+            &output_sender,
+            &permit_sender,
+        );
+    }
+    send_passthrough(
+        &mut passthrough,
+        &mut sequence,
+        &output_sender,
+        &permit_sender,
+    );
+    Ok(())
+}
+
+/// Sends the batch being filled, if there is one. Returns false if every worker or the writer
+/// has stopped, the reason why comes back when they are joined
+fn send_batch(
+    batch: &mut Option<Batch>,
+    batch_sender: &SyncSender<Batch>,
+    permit_sender: &SyncSender<()>,
+) -> bool {
+    match batch.take() {
+        Some(batch) => permit_sender.send(()).is_ok() && batch_sender.send(batch).is_ok(),
+        None => true,
+    }
+}
+
+/// Sends the lines read since the last batch straight to the writer. Returns false if the writer
+/// has stopped
+fn send_passthrough(
+    passthrough: &mut String,
+    sequence: &mut u64,
+    output_sender: &SyncSender<Output>,
+    permit_sender: &SyncSender<()>,
+) -> bool {
+    if passthrough.is_empty() {
+        return true;
+    }
+    send_chunk(
+        Chunk::Text(std::mem::take(passthrough)),
+        sequence,
+        output_sender,
+        permit_sender,
+    )
+}
+
+/// Sends `chunk` to the writer. Returns false if the writer has stopped
+fn send_chunk(
+    chunk: Chunk,
+    sequence: &mut u64,
+    output_sender: &SyncSender<Output>,
+    permit_sender: &SyncSender<()>,
+) -> bool {
+    let sent = permit_sender.send(()).is_ok() && output_sender.send((*sequence, Ok(chunk))).is_ok();
+    *sequence += 1;
+    sent
+}
+
+fn new_batch(state: &State, sequence: u64, first_line: usize) -> Batch {
+    let current_table = match &state.position {
+        Position::InCopy { current_table } => current_table,
+        position => panic!("Expected to be in a COPY block but was in {:?}", position),
+    };
+
+    let mut types = Types::new(HashMap::default());
+    if let Some(column_types) = state.types.for_table(&current_table.table_name) {
+        types.insert(&current_table.table_name, column_types.clone());
+    }
+
+    Batch {
+        sequence,
+        first_line,
+        current_table: Arc::new(current_table.clone()),
+        types: Arc::new(types),
+        rows: Vec::with_capacity(BATCH_SIZE),
+    }
+}
+
+fn transform_batches(
+    batch_receiver: &Mutex<Receiver<Batch>>,
+    output_sender: SyncSender<Output>,
+    strategies: &Strategies,
+    seed: Option<u64>,
+) {
+    let mut rng = rng::get();
+    loop {
+        let received = batch_receiver
+            .lock()
+            .expect("Another worker panicked")
+            .recv();
+        let Ok(batch) = received else {
+            return;
+        };
+
+        if let Some(seed) = seed {
+            rng = rng::derived(seed, "batch", batch.sequence);
+        }
+        let transformed = transform_batch(&mut rng, &batch, strategies);
+        if output_sender
+            .send((batch.sequence, transformed.map(Chunk::Text)))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn transform_batch(
+    rng: &mut SmallRng,
+    batch: &Batch,
+    strategies: &Strategies,
+) -> Result<String, AnonymiserError> {
+    batch
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row_parser::transform_row(rng, row, &batch.current_table, &batch.types, strategies)
+                .map_err(|err| err.at_line(batch.first_line + i))
+        })
+        .collect()
+}
+
+/// Writes the output in order. The rows of COPY blocks with shuffled columns are held back until
+/// the end of the block, when they're shuffled with an rng seeded from `seed` and the block's
+/// position in the dump, and spilled to `spill_dir` if there are too many to keep in memory
+fn write_in_order(
+    output_receiver: Receiver<Output>,
+    permit_receiver: Receiver<()>,
+    writer: &mut (dyn Write + Send),
+    seed: Option<u64>,
+    spill_dir: Option<&Path>,
+) -> Result<(), AnonymiserError> {
+    let mut next_sequence = 0;
+    let mut waiting = BTreeMap::new();
+    let mut shuffler: Option<Shuffler> = None;
+
+    for (sequence, output) in output_receiver {
+        waiting.insert(sequence, output);
+        while let Some(output) = waiting.remove(&next_sequence) {
+            match output? {
+                Chunk::Text(text) => match shuffler.as_mut() {
+                    Some(shuffler) => shuffler.push(&text)?,
+                    None => writer.write_all(text.as_bytes())?,
+                },
+                Chunk::StartShuffle(columns) => {
+                    let rng = seed.map_or_else(rng::get, |seed| {
+                        rng::derived(seed, "shuffle", next_sequence)
+                    });
+                    shuffler = Some(Shuffler::new(columns, rng, spill_dir));
+                }
+                Chunk::EndShuffle => {
+                    if let Some(shuffler) = shuffler.take() {
+                        shuffler.finish(writer)?;
+                    }
+                }
+            }
+            // Lets the reader send something else. The permit was sent before this output was,
+            // so it's already there
+            let _ = permit_receiver.recv();
+            next_sequence += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn join<T>(handle: ScopedJoinHandle<T>) -> T {
+    handle
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::strategy_structs::{
+        ColumnInfo, DataCategory, TransformerArgs, TransformerType,
+    };
+    use itertools::Itertools;
+    use std::io::Cursor;
+
+    fn transform_with(input: &str, threads: usize, seed: Option<u64>) -> String {
+        let mut output = Vec::new();
+        transform(
+            &mut Cursor::new(input),
+            &mut output,
+            &users_strategies(TransformerType::Scramble),
+            threads,
+            seed,
+            None,
+        )
+        .expect("Transforming should not fail");
+        String::from_utf8(output).unwrap()
+    }
+
+    fn users_strategies(name_transformer: TransformerType) -> Strategies {
+        users_strategies_with_args(name_transformer, None)
+    }
+
+    fn users_strategies_with_args(
+        name_transformer: TransformerType,
+        args: Option<TransformerArgs>,
+    ) -> Strategies {
+        let mut strategies = Strategies::new();
+        strategies
+            .insert(
+                "public.users".to_string(),
+                HashMap::from([
+                    (
+                        "id".to_string(),
+                        ColumnInfo::builder().with_name("id").build(),
+                    ),
+                    (
+                        "name".to_string(),
+                        ColumnInfo::builder()
+                            .with_name("name")
+                            .with_data_category(DataCategory::Pii)
+                            .with_transformer(name_transformer, args)
+                            .build(),
+                    ),
+                ]),
+            )
+            .unwrap();
+        strategies
+    }
+
+    fn users_dump(rows: usize) -> String {
+        let mut dump = "CREATE TABLE public.users (\n    id integer NOT NULL,\n    name text\n);\n\nCOPY public.users (id, name) FROM stdin;\n".to_string();
+        for id in 0..rows {
+            dump.push_str(&format!("{id}\tname {id}\n"));
+        }
+        dump.push_str("\\.\n\n");
+        dump
+    }
+
+    #[test]
+    fn rows_are_written_in_their_original_order() {
+        let dump = users_dump(BATCH_SIZE * 5 + 3);
+
+        let transformed = transform_with(&dump, 4, None);
+
+        let ids: Vec<&str> = transformed
+            .lines()
+            .skip_while(|line| !line.starts_with("COPY "))
+            .skip(1)
+            .take_while(|line| *line != "\\.")
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        let expected: Vec<String> = (0..BATCH_SIZE * 5 + 3).map(|id| id.to_string()).collect();
+        assert_eq!(ids, expected);
+        assert!(transformed.starts_with("CREATE TABLE public.users ("));
+        assert!(transformed.ends_with("\\.\n\n"));
+    }
+
+    #[test]
+    fn seeded_output_is_the_same_for_any_number_of_threads() {
+        let dump = users_dump(BATCH_SIZE * 3);
+
+        let single_threaded = transform_with(&dump, 1, Some(7));
+        let multi_threaded = transform_with(&dump, 4, Some(7));
+
+        assert_eq!(single_threaded, multi_threaded);
+        assert_ne!(single_threaded, dump);
+    }
+
+    #[test]
+    fn writes_every_batch_when_the_reader_has_to_wait_for_the_writer() {
+        // More batches than the writer lets the reader get ahead by
+        let dump = users_dump(BATCH_SIZE * 20);
+
+        let single_threaded = transform_with(&dump, 1, Some(7));
+        let multi_threaded = transform_with(&dump, 2, Some(7));
+
+        assert_eq!(single_threaded.lines().count(), dump.lines().count());
+        assert_eq!(single_threaded, multi_threaded);
+    }
+
+    #[test]
+    fn seeded_unique_values_are_the_same_every_run() {
+        let dump = users_dump(BATCH_SIZE * 3);
+        let strategies = users_strategies_with_args(
+            TransformerType::FakeEmail,
+            Some(HashMap::from([("unique".to_string(), "true".into())])),
+        );
+        let strategies = strategies.seeded(Some(7));
+        let run = || {
+            let mut output = Vec::new();
+            transform(
+                &mut Cursor::new(&dump),
+                &mut output,
+                &strategies,
+                4,
+                Some(7),
+                None,
+            )
+            .expect("Transforming should not fail");
+            output
+        };
+
+        let first = run();
+        let second = run();
+
+        assert_eq!(first, second);
+        assert_ne!(first, dump.as_bytes());
+    }
+
+    #[test]
+    fn errors_have_the_line_they_were_found_on() {
+        let dump = users_dump(BATCH_SIZE * 2);
+        let mut output = Vec::new();
+
+        let err = transform(
+            &mut Cursor::new(dump),
+            &mut output,
+            &users_strategies(TransformerType::Fixed),
+            4,
+            None,
+            None,
+        )
+        .unwrap_err();
+
+        assert_eq!(err.exit_code(), 8);
+        assert!(err.to_string().starts_with(
+            "Unable to transform public.users.name on line 7: 'value' must be present"
+        ));
+    }
+
+    #[test]
+    fn errors_stop_the_output_at_the_line_before() {
+        let dump = format!(
+            "{}COPY public.orders (id) FROM stdin;\n1\n\\.\n",
+            users_dump(3)
+        );
+        let mut output = Vec::new();
+
+        let err = transform(
+            &mut Cursor::new(dump.clone()),
+            &mut output,
+            &users_strategies(TransformerType::Scramble),
+            2,
+            None,
+            None,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "No transforms found for table: \"public.orders\" on line 12"
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().count(),
+            dump.lines().count() - 3
+        );
+    }
+
+    #[test]
+    fn shuffled_columns_are_shuffled_across_the_whole_copy_block() {
+        let dump = users_dump(BATCH_SIZE * 3);
+        let strategies = users_strategies(TransformerType::Shuffle);
+        let shuffle = |threads| {
+            let mut output = Vec::new();
+            transform(
+                &mut Cursor::new(&dump),
+                &mut output,
+                &strategies,
+                threads,
+                Some(7),
+                None,
+            )
+            .expect("Transforming should not fail");
+            String::from_utf8(output).unwrap()
+        };
+
+        let single_threaded = shuffle(1);
+        let multi_threaded = shuffle(4);
+
+        assert_eq!(single_threaded, multi_threaded);
+        assert!(single_threaded.ends_with("\\.\n\n"));
+        let rows = |dump: &str| -> Vec<(String, String)> {
+            dump.lines()
+                .skip_while(|line| !line.starts_with("COPY "))
+                .skip(1)
+                .take_while(|line| *line != "\\.")
+                .map(|line| line.split_once('\t').unwrap())
+                .map(|(id, name)| (id.to_string(), name.to_string()))
+                .collect()
+        };
+        let (original_ids, original_names): (Vec<_>, Vec<_>) = rows(&dump).into_iter().unzip();
+        let (ids, names): (Vec<_>, Vec<_>) = rows(&single_threaded).into_iter().unzip();
+        assert_eq!(ids, original_ids);
+        assert_ne!(names, original_names);
+        assert_eq!(
+            names.into_iter().sorted().collect::<Vec<_>>(),
+            original_names.into_iter().sorted().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn threads_defaults_to_at_least_one() {
+        assert_eq!(threads(Some(3)), 3);
+        assert_eq!(threads(Some(0)), 1);
+        assert!(threads(None) >= 1);
+    }
+}
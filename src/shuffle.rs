@@ -0,0 +1,363 @@
+//! Shuffles columns' values between the rows of a COPY block, so the column keeps its real
+//! distribution but none of its values stay with the row they came from. Nothing can be written
+//! until the last row has been read, so the rows are held in memory, and spilled to temporary
+//! files once there are too many of them, unless spilling is turned off.
+
+use crate::anonymiser::AnonymiseOptions;
+use crate::errors::AnonymiserError;
+use crate::parsers::copy_row::{CurrentTableTransforms, TableTransformers};
+use crate::parsers::data_row;
+use crate::parsers::rng;
+use crate::parsers::state::{Position, State};
+use crate::parsers::strategy_structs::TransformerType;
+use itertools::Itertools;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Rows are kept in memory until they add up to roughly this many bytes
+const SPILL_SIZE: usize = 64 * 1024 * 1024;
+
+/// The number of files the shuffled values are dealt between once they've been spilled. Each one
+/// is read back and shuffled in memory on its own, so there need to be enough of them for that
+/// to fit
+const PILES: usize = 64;
+
+/// Collects the transformed rows of a COPY block, then writes them back out in their original
+/// order with the values in the shuffled columns permuted between them
+pub struct Shuffler {
+    columns: Vec<usize>,
+    rng: SmallRng,
+    spill_size: usize,
+    /// Where the rows are spilled to, or `None` to keep them all in memory
+    spill_dir: Option<PathBuf>,
+    buffered: Buffered,
+}
+
+enum Buffered {
+    InMemory {
+        rows: Vec<String>,
+        values: Vec<String>,
+        size: usize,
+    },
+    /// The rows are in one file in order, and the shuffled values are dealt at random between
+    /// the piles. Shuffling each pile and reading them one after the other is then a uniformly
+    /// random permutation of the values (Rao's method), without ever holding them all in memory
+    Spilled {
+        rows: TempFile,
+        piles: Vec<TempFile>,
+    },
+}
+
+/// The positions of the columns in `current_table` that are shuffled
+pub fn shuffled_columns(current_table: &CurrentTableTransforms) -> Vec<usize> {
+    match &current_table.table_transformers {
+        TableTransformers::ColumnTransformer(columns) => columns
+            .iter()
+            .positions(|column| column.transformer.name == TransformerType::Shuffle)
+            .collect(),
+        TableTransformers::Truncator => Vec::new(),
+    }
+}
+
+/// The directory shufflers spill their rows to, or `None` if spilling is turned off
+pub fn spill_dir(options: &AnonymiseOptions) -> Option<PathBuf> {
+    match options.no_spill {
+        true => None,
+        false => Some(options.spill_dir.clone().unwrap_or_else(std::env::temp_dir)),
+    }
+}
+
+/// A shuffler for the COPY block `state` is in, if any of its columns are shuffled
+pub fn for_copy_block(
+    state: &State,
+    rng: &mut SmallRng,
+    spill_dir: Option<&Path>,
+) -> Option<Shuffler> {
+    let Position::InCopy { current_table } = &state.position else {
+        return None;
+    };
+    let columns = shuffled_columns(current_table);
+    (!columns.is_empty()).then(|| Shuffler::new(columns, rng::seeded(rng.gen()), spill_dir))
+}
+
+impl Shuffler {
+    pub fn new(columns: Vec<usize>, rng: SmallRng, spill_dir: Option<&Path>) -> Shuffler {
+        Shuffler {
+            columns,
+            rng,
+            spill_size: SPILL_SIZE,
+            spill_dir: spill_dir.map(Path::to_path_buf),
+            buffered: Buffered::InMemory {
+                rows: Vec::new(),
+                values: Vec::new(),
+                size: 0,
+            },
+        }
+    }
+
+    /// Adds transformed COPY rows, each ending in a newline
+    pub fn push(&mut self, rows: &str) -> Result<(), AnonymiserError> {
+        for row in rows.split_inclusive('\n') {
+            let values = self.values(row);
+            match &mut self.buffered {
+                Buffered::InMemory {
+                    rows,
+                    values: buffered_values,
+                    size,
+                } => {
+                    *size += row.len() + values.len();
+                    rows.push(row.to_string());
+                    buffered_values.push(values);
+                    if *size >= self.spill_size && self.spill_dir.is_some() {
+                        self.spill()?;
+                    }
+                }
+                Buffered::Spilled { rows, piles } => {
+                    rows.write_line(row.strip_suffix('\n').unwrap_or(row))?;
+                    piles[self.rng.gen_range(0..PILES)].write_line(&values)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every row pushed, in the order they were pushed, with the shuffled values
+    pub fn finish(mut self, writer: &mut dyn Write) -> Result<(), AnonymiserError> {
+        match &mut self.buffered {
+            Buffered::InMemory { rows, values, .. } => {
+                values.shuffle(&mut self.rng);
+                for (row, values) in rows.iter().zip(values.iter()) {
+                    write_row(writer, row, values, &self.columns)?;
+                }
+            }
+            Buffered::Spilled { rows, piles } => {
+                let mut rows = rows.reader()?.lines();
+                for pile in piles {
+                    let mut values = pile.reader()?.lines().collect::<io::Result<Vec<_>>>()?;
+                    values.shuffle(&mut self.rng);
+                    for values in values {
+                        let row = rows.next().ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "Fewer rows than shuffled values were read back",
+                            )
+                        })??;
+                        write_row(writer, &row, &values, &self.columns)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The values of the shuffled columns in `row`, tab separated as in the row. COPY escapes
+    /// tabs and newlines in values, so these can be split apart again
+    fn values(&self, row: &str) -> String {
+        let fields: Vec<&str> = data_row::split(row).collect();
+        self.columns
+            .iter()
+            .map(|index| fields.get(*index).copied().unwrap_or_default())
+            .join("\t")
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let (Buffered::InMemory { rows, values, .. }, Some(spill_dir)) =
+            (&self.buffered, &self.spill_dir)
+        else {
+            return Ok(());
+        };
+        let mut rows_file = TempFile::new(spill_dir)?;
+        let mut piles = (0..PILES)
+            .map(|_| TempFile::new(spill_dir))
+            .collect::<io::Result<Vec<_>>>()?;
+        for (row, values) in rows.iter().zip(values) {
+            rows_file.write_line(row.strip_suffix('\n').unwrap_or(row))?;
+            piles[self.rng.gen_range(0..PILES)].write_line(values)?;
+        }
+        self.buffered = Buffered::Spilled {
+            rows: rows_file,
+            piles,
+        };
+        Ok(())
+    }
+}
+
+/// Writes `row` with the values of `columns` replaced by `values`
+fn write_row(writer: &mut dyn Write, row: &str, values: &str, columns: &[usize]) -> io::Result<()> {
+    let mut values = values.split('\t');
+    let fields =
+        data_row::split(row)
+            .enumerate()
+            .map(|(index, field)| match columns.contains(&index) {
+                true => values.next().unwrap_or(field),
+                false => field,
+            });
+    writeln!(writer, "{}", fields.format("\t"))
+}
+
+/// A file only the current user can read, which is deleted when it's dropped. The rows hold
+/// the dump's data, so the name is random to stop other users guessing it and getting there
+/// first
+struct TempFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl TempFile {
+    fn new(dir: &Path) -> io::Result<TempFile> {
+        let path = dir.join(format!(
+            "anonymiser-shuffle-{:032x}",
+            rand::random::<u128>()
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        Ok(TempFile {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Reads back everything written so far, from the start
+    fn reader(&mut self) -> io::Result<BufReader<File>> {
+        self.writer.flush()?;
+        Ok(BufReader::new(File::open(&self.path)?))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(count: usize) -> String {
+        (0..count)
+            .map(|id| format!("{id}\tname {id}\tjob {id}\n"))
+            .collect()
+    }
+
+    fn shuffler(spill_size: usize, spill_dir: Option<&Path>) -> Shuffler {
+        Shuffler {
+            spill_size,
+            ..Shuffler::new(vec![2], rng::seeded(3), spill_dir)
+        }
+    }
+
+    fn shuffle(rows: &str, spill_size: usize) -> String {
+        let mut shuffler = shuffler(spill_size, Some(&std::env::temp_dir()));
+        shuffler.push(rows).unwrap();
+        let mut output = Vec::new();
+        shuffler.finish(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn column(rows: &str, index: usize) -> Vec<&str> {
+        rows.lines()
+            .map(|row| row.split('\t').nth(index).unwrap())
+            .collect()
+    }
+
+    fn assert_shuffled(original: &str, shuffled: &str) {
+        assert_eq!(column(shuffled, 0), column(original, 0));
+        assert_eq!(column(shuffled, 1), column(original, 1));
+        assert_ne!(column(shuffled, 2), column(original, 2));
+        assert_eq!(
+            column(shuffled, 2).into_iter().sorted().collect::<Vec<_>>(),
+            column(original, 2).into_iter().sorted().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn shuffles_only_the_shuffled_columns_between_rows() {
+        let original = rows(100);
+
+        let shuffled = shuffle(&original, SPILL_SIZE);
+
+        assert_shuffled(&original, &shuffled);
+    }
+
+    #[test]
+    fn shuffles_rows_spilled_to_disk() {
+        let original = rows(2000);
+
+        let shuffled = shuffle(&original, 1024);
+
+        assert_shuffled(&original, &shuffled);
+    }
+
+    #[test]
+    fn spills_to_files_only_the_current_user_can_read_which_are_deleted_afterwards() {
+        let spill_dir = std::env::temp_dir().join("anonymiser-shuffle-test-spill-dir");
+        let _ = fs::remove_dir_all(&spill_dir);
+        fs::create_dir(&spill_dir).unwrap();
+        let spilled_files = || {
+            fs::read_dir(&spill_dir)
+                .unwrap()
+                .collect::<io::Result<Vec<_>>>()
+        };
+        let mut shuffler = shuffler(1024, Some(&spill_dir));
+
+        shuffler.push(&rows(2000)).unwrap();
+
+        let files = spilled_files().unwrap();
+        assert_eq!(files.len(), PILES + 1);
+        #[cfg(unix)]
+        for file in files {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(file.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        shuffler.finish(&mut Vec::new()).unwrap();
+        assert!(spilled_files().unwrap().is_empty());
+        fs::remove_dir(&spill_dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_every_row_in_memory_when_spilling_is_turned_off() {
+        let original = rows(2000);
+        let mut shuffler = shuffler(1024, None);
+
+        shuffler.push(&original).unwrap();
+
+        assert!(matches!(shuffler.buffered, Buffered::InMemory { .. }));
+        let mut shuffled = Vec::new();
+        shuffler.finish(&mut shuffled).unwrap();
+        assert_shuffled(&original, &String::from_utf8(shuffled).unwrap());
+    }
+
+    #[test]
+    fn keeps_empty_values_and_nulls() {
+        let original = "1\ta\t\n2\tb\t\\N\n3\tc\tx\n";
+
+        let shuffled = shuffle(original, SPILL_SIZE);
+
+        assert_eq!(column(&shuffled, 1), vec!["a", "b", "c"]);
+        assert_eq!(
+            column(&shuffled, 2)
+                .into_iter()
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec!["", "\\N", "x"]
+        );
+    }
+
+    #[test]
+    fn writes_nothing_for_an_empty_copy_block() {
+        assert_eq!(shuffle("", 1024), "");
+    }
+}
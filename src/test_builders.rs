@@ -2,7 +2,8 @@
 pub mod builders {
     use crate::parsers::state::Types;
     use crate::parsers::strategy_structs::{
-        ColumnInFile, ColumnInfo, DataCategory, StrategyInFile, Transformer, TransformerType,
+        ColumnInFile, ColumnInfo, DataCategory, StrategyInFile, Transformer, TransformerArgs,
+        TransformerType,
     };
     use crate::parsers::types::{SubType, Type};
     use std::collections::HashMap;
@@ -18,7 +19,7 @@ pub mod builders {
         name: String,
         data_category: Option<DataCategory>,
         transformer_type: Option<TransformerType>,
-        transformer_args: Option<HashMap<String, String>>,
+        transformer_args: Option<TransformerArgs>,
     }
 
     impl ColumnInfoBuilder {
@@ -30,7 +31,7 @@ pub mod builders {
         pub fn with_transformer(
             mut self,
             transformer_type: TransformerType,
-            transformer_args: Option<HashMap<String, String>>,
+            transformer_args: Option<TransformerArgs>,
         ) -> ColumnInfoBuilder {
             self.transformer_type = Some(transformer_type);
             self.transformer_args = transformer_args;
@@ -82,6 +83,8 @@ pub mod builders {
             StrategyInFile {
                 table_name: self.table_name,
                 truncate: false,
+                locale: None,
+                filter: None,
                 description: self
                     .description
                     .unwrap_or_else(|| "Any description".to_string()),
@@ -95,7 +98,7 @@ pub mod builders {
         description: Option<String>,
         data_category: Option<DataCategory>,
         transformer_type: Option<TransformerType>,
-        transformer_args: Option<HashMap<String, String>>,
+        transformer_args: Option<TransformerArgs>,
     }
 
     impl ColumnInFile {
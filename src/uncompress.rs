@@ -13,7 +13,10 @@ pub fn uncompress(input_file: PathBuf, output_file: Option<PathBuf>) -> Result<(
 mod tests {
     use std::path::PathBuf;
 
-    use crate::{anonymiser::anonymise, parsers::strategy_structs::TransformerOverrides};
+    use crate::{
+        anonymiser::{anonymise, AnonymiseOptions},
+        parsers::strategy_structs::TransformerOverrides,
+    };
 
     use super::uncompress;
 
@@ -26,8 +29,8 @@ mod tests {
             "test_files/dump_file.sql".to_string(),
             "test_files/compress/results.sql".to_string(),
             "test_files/strategy.json".to_string(),
-            None,
             TransformerOverrides::none(),
+            &AnonymiseOptions::default(),
         )
         .unwrap();
 
@@ -35,8 +38,11 @@ mod tests {
             "test_files/dump_file.sql".to_string(),
             "test_files/compress/results.sql.zst".to_string(),
             "test_files/strategy.json".to_string(),
-            Some(None),
             TransformerOverrides::none(),
+            &AnonymiseOptions {
+                compress_output: Some(None),
+                ..Default::default()
+            },
         )
         .unwrap();
 